@@ -20,11 +20,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::array;
+use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
 use std::num::ParseIntError;
 use std::str::FromStr;
 
+use bc::secp256k1::SecretKey;
 use bc::{
     CompressedPk, ControlBlock, InternalPk, LeafScript, LegacyPk, RedeemScript, ScriptPubkey,
     TapNodeHash, WitnessScript, XOnlyPk,
@@ -34,7 +38,7 @@ use invoice::AddressError;
 
 use crate::{
     Address, AddressNetwork, AddressParseError, ControlBlockFactory, DerivationIndex, Idx, IdxBase,
-    IndexParseError, NormalIndex, TapTree, XpubDerivable, XpubSpec,
+    IndexParseError, KeyOrigin, NormalIndex, TapTree, XprivDerivable, XpubDerivable, XpubSpec,
 };
 
 #[derive(Wrapper, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Default, Debug, Display, From)]
@@ -64,6 +68,46 @@ impl Keychain {
     pub const fn with(idx: u8) -> Self { Keychain(idx) }
 }
 
+/// Set of exactly `N` distinct keychains, which size is known at compile time.
+///
+/// Can be obtained from a descriptor with [`Derive::fixed_keychains`], which fails if the
+/// descriptor uses a different number of keychains.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Keychains<const N: usize>([Keychain; N]);
+
+impl Keychains<2> {
+    /// Standard external (receiving) and internal (change) keychains.
+    pub const STANDARD: Self = Keychains([Keychain::OUTER, Keychain::INNER]);
+}
+
+impl<const N: usize> Keychains<N> {
+    /// Constructs the set from a keychain array, failing if the keychains are not distinct.
+    pub fn with(mut keychains: [Keychain; N]) -> Option<Self> {
+        keychains.sort_unstable();
+        if keychains.windows(2).any(|pair| pair[0] == pair[1]) {
+            return None;
+        }
+        Some(Keychains(keychains))
+    }
+
+    /// Number of keychains in the set.
+    #[inline]
+    pub const fn count(&self) -> usize { N }
+
+    #[inline]
+    pub fn contains(&self, keychain: Keychain) -> bool { self.0.contains(&keychain) }
+
+    #[inline]
+    pub fn as_array(&self) -> &[Keychain; N] { &self.0 }
+}
+
+impl<const N: usize> IntoIterator for Keychains<N> {
+    type Item = Keychain;
+    type IntoIter = array::IntoIter<Keychain, N>;
+
+    fn into_iter(self) -> Self::IntoIter { self.0.into_iter() }
+}
+
 impl IdxBase for Keychain {
     #[inline]
     fn is_hardened(&self) -> bool { false }
@@ -75,13 +119,17 @@ impl IdxBase for Keychain {
     fn index(&self) -> u32 { self.0 as u32 }
 }
 
+/// Last two normal derivation steps of a key: keychain and the index within the keychain.
+///
+/// Displayed in the `KEYCHAIN/INDEX` form. The parser also accepts the `&KEYCHAIN/INDEX` form
+/// used by the earlier versions.
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Display)]
 #[cfg_attr(
     feature = "serde",
     derive(serde::Serialize, serde::Deserialize),
     serde(crate = "serde_crate", rename_all = "camelCase")
 )]
-#[display("&{keychain}/{index}")]
+#[display("{keychain}/{index}")]
 pub struct Terminal {
     pub keychain: Keychain,
     pub index: NormalIndex,
@@ -95,14 +143,36 @@ impl Terminal {
         }
     }
     pub fn change(index: NormalIndex) -> Self { Self::new(1, index) }
+
+    /// Iterates over `count` terminals of the `keychain` starting with the `start` index. The
+    /// iteration stops at [`NormalIndex::MAX`], thus less than `count` terminals may be returned.
+    pub fn range(
+        keychain: impl Into<Keychain>,
+        start: NormalIndex,
+        count: u32,
+    ) -> impl Iterator<Item = Terminal> {
+        let keychain = keychain.into();
+        (0..count)
+            .map_while(move |offset| start.checked_add(offset))
+            .map(move |index| Terminal::new(keychain, index))
+    }
+
+    /// Returns terminal with the next index in the same keychain, or `None` if the index is
+    /// already [`NormalIndex::MAX`].
+    pub fn next(&self) -> Option<Terminal> {
+        self.index.checked_inc().map(|index| Terminal::new(self.keychain, index))
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
 #[display(doc_comments)]
 pub enum TerminalParseError {
-    /// terminal derivation path must start with keychain index prefixed with '&'.
+    /// terminal derivation path must start with keychain index.
     NoKeychain,
 
+    /// terminal derivation path '{0}' must not contain hardened indexes.
+    Hardened(String),
+
     /// keychain index in terminal derivation path is not a number.
     #[from]
     InvalidKeychain(ParseIntError),
@@ -119,22 +189,34 @@ impl FromStr for Terminal {
     type Err = TerminalParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut iter = s.split('/');
+        let mut iter = s.strip_prefix('&').unwrap_or(s).split('/');
         match (iter.next(), iter.next(), iter.next()) {
+            (Some(""), Some(_), None) => Err(TerminalParseError::NoKeychain),
             (Some(keychain), Some(index), None) => {
-                if !keychain.starts_with('&') {
-                    return Err(TerminalParseError::NoKeychain);
+                if [keychain, index].iter().any(|c| c.ends_with(['h', 'H', '\''])) {
+                    return Err(TerminalParseError::Hardened(s.to_owned()));
+                }
+                // Only the canonical decimal form is accepted, so the parsed terminal is always
+                // displayed the same way
+                if [keychain, index].iter().any(|c| {
+                    c.is_empty()
+                        || !c.bytes().all(|b| b.is_ascii_digit())
+                        || (c.len() > 1 && c.starts_with('0'))
+                }) {
+                    return Err(TerminalParseError::InvalidComponents(s.to_owned()));
                 }
-                Ok(Terminal::new(
-                    Keychain::from_str(keychain.trim_start_matches('&'))?,
-                    index.parse()?,
-                ))
+                Ok(Terminal::new(Keychain::from_str(keychain)?, index.parse()?))
             }
             _ => Err(TerminalParseError::InvalidComponents(s.to_owned())),
         }
     }
 }
 
+/// Script derived from a descriptor, carrying the information required to spend it.
+///
+/// Equality and hashing are structural, i.e. they take into account the spending information and
+/// not only the resulting [`ScriptPubkey`]. Use [`DerivedSpk`] to deduplicate derived scripts by
+/// their script pubkeys.
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 #[non_exhaustive]
 pub enum DerivedScript {
@@ -143,9 +225,59 @@ pub enum DerivedScript {
     Segwit(WitnessScript),
     Nested(WitnessScript),
     TaprootKeyOnly(InternalPk),
+    /// Taproot output spendable with the key path, which commits to a script tree known only by
+    /// its merkle root.
+    TaprootKeyTweaked(InternalPk, TapNodeHash),
     TaprootScript(InternalPk, TapTree),
 }
 
+/// Derived script together with its script pubkey, which is computed once on construction.
+///
+/// Equality and hashing are defined by the script pubkey only: two derived scripts producing the
+/// same script pubkey are equal, even if they differ in the spending information they carry (for
+/// instance, a taproot script tree and its merkle root). This allows using `DerivedSpk` in hash
+/// sets and maps, which can also be queried with a [`ScriptPubkey`].
+#[derive(Clone, Debug)]
+pub struct DerivedSpk {
+    script: DerivedScript,
+    script_pubkey: ScriptPubkey,
+}
+
+impl From<DerivedScript> for DerivedSpk {
+    fn from(script: DerivedScript) -> Self {
+        let script_pubkey = script.to_script_pubkey();
+        DerivedSpk {
+            script,
+            script_pubkey,
+        }
+    }
+}
+
+impl PartialEq for DerivedSpk {
+    fn eq(&self, other: &Self) -> bool { self.script_pubkey == other.script_pubkey }
+}
+
+impl Eq for DerivedSpk {}
+
+impl Hash for DerivedSpk {
+    fn hash<H: Hasher>(&self, state: &mut H) { self.script_pubkey.hash(state) }
+}
+
+impl Borrow<ScriptPubkey> for DerivedSpk {
+    fn borrow(&self) -> &ScriptPubkey { &self.script_pubkey }
+}
+
+impl DerivedSpk {
+    #[inline]
+    pub fn script_pubkey(&self) -> &ScriptPubkey { &self.script_pubkey }
+
+    #[inline]
+    pub fn derived_script(&self) -> &DerivedScript { &self.script }
+
+    #[inline]
+    pub fn into_derived_script(self) -> DerivedScript { self.script }
+}
+
 impl DerivedScript {
     pub fn to_script_pubkey(&self) -> ScriptPubkey {
         match self {
@@ -158,12 +290,18 @@ impl DerivedScript {
             DerivedScript::TaprootKeyOnly(internal_key) => {
                 ScriptPubkey::p2tr_key_only(*internal_key)
             }
+            DerivedScript::TaprootKeyTweaked(internal_pk, merkle_root) => {
+                internal_pk.to_output_pk(Some(*merkle_root)).0.to_script_pubkey()
+            }
             DerivedScript::TaprootScript(internal_pk, tap_tree) => {
                 internal_pk.to_output_pk(Some(tap_tree.merkle_root())).0.to_script_pubkey()
             }
         }
     }
 
+    /// Returns redeem script which has to be put into the script sig when spending P2SH outputs:
+    /// the BIP-13 script itself, or the P2WSH script pubkey for the nested segwit outputs.
+    /// Returns `None` for non-P2SH outputs.
     pub fn to_redeem_script(&self) -> Option<RedeemScript> {
         match self {
             DerivedScript::Bare(_) => None,
@@ -171,9 +309,14 @@ impl DerivedScript {
             DerivedScript::Segwit(_) => None,
             DerivedScript::Nested(witness_script) => Some(witness_script.to_redeem_script()),
             DerivedScript::TaprootKeyOnly(_) => None,
+            DerivedScript::TaprootKeyTweaked(_, _) => None,
             DerivedScript::TaprootScript(_, _) => None,
         }
     }
+
+    /// Returns witness script which has to be put into the witness when spending P2WSH outputs,
+    /// either native or nested into P2SH. Returns `None` for other outputs, including P2WPKH and
+    /// taproot ones.
     pub fn as_witness_script(&self) -> Option<&WitnessScript> {
         match self {
             DerivedScript::Bare(_) => None,
@@ -182,9 +325,12 @@ impl DerivedScript {
                 Some(witness_script)
             }
             DerivedScript::TaprootKeyOnly(_) => None,
+            DerivedScript::TaprootKeyTweaked(_, _) => None,
             DerivedScript::TaprootScript(_, _) => None,
         }
     }
+
+    /// Returns a copy of the witness script; see [`Self::as_witness_script`].
     pub fn to_witness_script(&self) -> Option<WitnessScript> { self.as_witness_script().cloned() }
 
     pub fn to_internal_pk(&self) -> Option<InternalPk> {
@@ -194,6 +340,7 @@ impl DerivedScript {
             | DerivedScript::Segwit(_)
             | DerivedScript::Nested(_) => None,
             DerivedScript::TaprootKeyOnly(internal_key) => Some(*internal_key),
+            DerivedScript::TaprootKeyTweaked(internal_key, _) => Some(*internal_key),
             DerivedScript::TaprootScript(internal_key, _) => Some(*internal_key),
         }
     }
@@ -204,7 +351,8 @@ impl DerivedScript {
             | DerivedScript::Bip13(_)
             | DerivedScript::Segwit(_)
             | DerivedScript::Nested(_)
-            | DerivedScript::TaprootKeyOnly(_) => None,
+            | DerivedScript::TaprootKeyOnly(_)
+            | DerivedScript::TaprootKeyTweaked(_, _) => None,
             DerivedScript::TaprootScript(_, tap_tree) => Some(tap_tree),
         }
     }
@@ -219,9 +367,13 @@ impl DerivedScript {
         ControlBlockFactory::with(internal_pk, tap_tree).collect()
     }
 
-    #[inline]
+    /// Returns merkle root of the taproot script tree, which is either computed from the known
+    /// tree or provided for the outputs committing to an unknown tree.
     pub fn to_tap_root(&self) -> Option<TapNodeHash> {
-        self.to_tap_tree().as_ref().map(TapTree::merkle_root)
+        match self {
+            DerivedScript::TaprootKeyTweaked(_, merkle_root) => Some(*merkle_root),
+            _ => self.as_tap_tree().map(TapTree::merkle_root),
+        }
     }
 }
 
@@ -231,7 +383,7 @@ impl DerivedScript {
     derive(serde::Serialize, serde::Deserialize),
     serde(crate = "serde_crate", rename_all = "camelCase")
 )]
-#[display("{addr}{terminal}")]
+#[display("{addr}&{terminal}")]
 pub struct DerivedAddr {
     pub addr: Address,
     #[cfg_attr(feature = "serde", serde(flatten))]
@@ -276,11 +428,22 @@ impl FromStr for DerivedAddr {
         let (addr, terminal) = s.split_at(pos);
         Ok(DerivedAddr {
             addr: addr.parse()?,
-            terminal: terminal.parse()?,
+            terminal: terminal[1..].parse()?,
         })
     }
 }
 
+/// Errors during key or script derivation.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum DeriveError {
+    /// keychain {0} is not known to the descriptor or key.
+    UnknownKeychain(Keychain),
+
+    /// descriptor or key uses {found} keychains, while {expected} keychains were expected.
+    KeychainCount { expected: usize, found: usize },
+}
+
 pub trait Derive<D> {
     fn default_keychain(&self) -> Keychain;
 
@@ -288,6 +451,35 @@ pub trait Derive<D> {
 
     fn derive(&self, keychain: impl Into<Keychain>, index: impl Into<NormalIndex>) -> D;
 
+    /// Returns [`Self::keychains`] as a fixed-size set, failing if the number of keychains
+    /// differs from `N`.
+    ///
+    /// Use this method to bind wallets with a fixed keychain layout (like the standard receiving
+    /// and change keychains) to the number of keychains at the type level.
+    fn fixed_keychains<const N: usize>(&self) -> Result<Keychains<N>, DeriveError> {
+        let keychains = self.keychains();
+        let found = keychains.len();
+        let array = <[Keychain; N]>::try_from(keychains.into_iter().collect::<Vec<_>>())
+            .map_err(|_| DeriveError::KeychainCount { expected: N, found })?;
+        Ok(Keychains(array))
+    }
+
+    /// Derives for the given keychain and index, failing for keychains which are not a part of
+    /// [`Self::keychains`] instead of producing data which can't be spent by the wallet.
+    ///
+    /// Use this method when the keychain comes from a user input.
+    fn try_derive(
+        &self,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+    ) -> Result<D, DeriveError> {
+        let keychain = keychain.into();
+        if !self.keychains().contains(&keychain) {
+            return Err(DeriveError::UnknownKeychain(keychain));
+        }
+        Ok(self.derive(keychain, index))
+    }
+
     fn derive_batch(
         &self,
         keychain: impl Into<Keychain>,
@@ -308,8 +500,26 @@ pub trait Derive<D> {
     }
 }
 
+/// Keys derived from an extended public key or a key which does not need derivation.
 pub trait DeriveKey<D>: Derive<D> {
-    fn xpub_spec(&self) -> &XpubSpec;
+    /// Returns extended public key specification the keys are derived from, or `None` for
+    /// non-derivable (single) keys.
+    fn try_xpub_spec(&self) -> Option<&XpubSpec>;
+
+    /// Returns keys and origins of the individual participants for the keys aggregated from
+    /// multiple extended keys (like MuSig2). Empty for non-aggregated keys.
+    fn participants(&self, _terminal: Terminal) -> Vec<(D, KeyOrigin)> { vec![] }
+
+    /// Returns origin of the key derived for the `terminal`. Single (non-derivable) keys have an
+    /// empty origin; aggregated keys have no origin, since the origins belong to their
+    /// [`DeriveKey::participants`].
+    fn key_origin(&self, terminal: Terminal) -> Option<KeyOrigin> {
+        match self.try_xpub_spec() {
+            Some(spec) => Some(KeyOrigin::with(spec.origin(), terminal)),
+            None if self.participants(terminal).is_empty() => Some(KeyOrigin::default()),
+            None => None,
+        }
+    }
 }
 
 pub trait DeriveLegacy: DeriveKey<LegacyPk> {}
@@ -345,19 +555,30 @@ pub trait DeriveScripts: Derive<DerivedScript> {
             .map(|spk| Address::with(&spk, network))
             .collect()
     }
+
+    /// Derives scripts with the same index for each of the [`Derive::keychains`], in the keychain
+    /// order. Extended keys derive from their cached keychain keys, thus each of the scripts
+    /// requires a single derivation step per key.
+    fn derive_multi(&self, index: impl Into<NormalIndex>) -> Vec<(Keychain, DerivedScript)> {
+        let index = index.into();
+        self.keychains()
+            .into_iter()
+            .map(|keychain| (keychain, self.derive(keychain, index)))
+            .collect()
+    }
 }
 impl<T: Derive<DerivedScript>> DeriveScripts for T {}
 
 impl DeriveKey<LegacyPk> for XpubDerivable {
-    fn xpub_spec(&self) -> &XpubSpec { self.spec() }
+    fn try_xpub_spec(&self) -> Option<&XpubSpec> { Some(self.spec()) }
 }
 
 impl DeriveKey<CompressedPk> for XpubDerivable {
-    fn xpub_spec(&self) -> &XpubSpec { self.spec() }
+    fn try_xpub_spec(&self) -> Option<&XpubSpec> { Some(self.spec()) }
 }
 
 impl DeriveKey<XOnlyPk> for XpubDerivable {
-    fn xpub_spec(&self) -> &XpubSpec { self.spec() }
+    fn try_xpub_spec(&self) -> Option<&XpubSpec> { Some(self.spec()) }
 }
 
 impl Derive<LegacyPk> for XpubDerivable {
@@ -368,7 +589,7 @@ impl Derive<LegacyPk> for XpubDerivable {
     fn keychains(&self) -> BTreeSet<Keychain> { self.keychains.to_set() }
 
     fn derive(&self, keychain: impl Into<Keychain>, index: impl Into<NormalIndex>) -> LegacyPk {
-        self.xpub().derive_pub([keychain.into().into(), index.into()]).to_legacy_pub()
+        self.keychain_xpub(keychain.into()).ckd_pub(index.into()).to_legacy_pub()
     }
 }
 
@@ -380,7 +601,7 @@ impl Derive<CompressedPk> for XpubDerivable {
     fn keychains(&self) -> BTreeSet<Keychain> { self.keychains.to_set() }
 
     fn derive(&self, keychain: impl Into<Keychain>, index: impl Into<NormalIndex>) -> CompressedPk {
-        self.xpub().derive_pub([keychain.into().into(), index.into()]).to_compr_pub()
+        self.keychain_xpub(keychain.into()).ckd_pub(index.into()).to_compr_pub()
     }
 }
 
@@ -392,7 +613,95 @@ impl Derive<XOnlyPk> for XpubDerivable {
     fn keychains(&self) -> BTreeSet<Keychain> { self.keychains.to_set() }
 
     fn derive(&self, keychain: impl Into<Keychain>, index: impl Into<NormalIndex>) -> XOnlyPk {
-        self.xpub().derive_pub([keychain.into().into(), index.into()]).to_xonly_pub()
+        self.keychain_xpub(keychain.into()).ckd_pub(index.into()).to_xonly_pub()
+    }
+}
+
+/// Single non-derivable public key, which is returned for any terminal. The key uses a single
+/// [`Keychain::OUTER`] keychain; the derivation index is ignored.
+impl Derive<CompressedPk> for CompressedPk {
+    #[inline]
+    fn default_keychain(&self) -> Keychain { Keychain::OUTER }
+
+    #[inline]
+    fn keychains(&self) -> BTreeSet<Keychain> { bset![Keychain::OUTER] }
+
+    #[inline]
+    fn derive(&self, _: impl Into<Keychain>, _: impl Into<NormalIndex>) -> CompressedPk { *self }
+}
+
+impl DeriveKey<CompressedPk> for CompressedPk {
+    #[inline]
+    fn try_xpub_spec(&self) -> Option<&XpubSpec> { None }
+}
+
+/// Single non-derivable BIP340 public key, which is returned for any terminal. The key uses a
+/// single [`Keychain::OUTER`] keychain; the derivation index is ignored.
+impl Derive<XOnlyPk> for XOnlyPk {
+    #[inline]
+    fn default_keychain(&self) -> Keychain { Keychain::OUTER }
+
+    #[inline]
+    fn keychains(&self) -> BTreeSet<Keychain> { bset![Keychain::OUTER] }
+
+    #[inline]
+    fn derive(&self, _: impl Into<Keychain>, _: impl Into<NormalIndex>) -> XOnlyPk { *self }
+}
+
+impl DeriveKey<XOnlyPk> for XOnlyPk {
+    #[inline]
+    fn try_xpub_spec(&self) -> Option<&XpubSpec> { None }
+}
+
+impl DeriveKey<LegacyPk> for XprivDerivable {
+    fn try_xpub_spec(&self) -> Option<&XpubSpec> { Some(self.as_xpub_derivable().spec()) }
+}
+
+impl DeriveKey<CompressedPk> for XprivDerivable {
+    fn try_xpub_spec(&self) -> Option<&XpubSpec> { Some(self.as_xpub_derivable().spec()) }
+}
+
+impl DeriveKey<XOnlyPk> for XprivDerivable {
+    fn try_xpub_spec(&self) -> Option<&XpubSpec> { Some(self.as_xpub_derivable().spec()) }
+}
+
+/// Derives public keys matching the extended private key.
+impl<D> Derive<D> for XprivDerivable
+where XpubDerivable: Derive<D>
+{
+    #[inline]
+    fn default_keychain(&self) -> Keychain {
+        <XpubDerivable as Derive<D>>::default_keychain(self.as_xpub_derivable())
+    }
+
+    #[inline]
+    fn keychains(&self) -> BTreeSet<Keychain> {
+        <XpubDerivable as Derive<D>>::keychains(self.as_xpub_derivable())
+    }
+
+    #[inline]
+    fn derive(&self, keychain: impl Into<Keychain>, index: impl Into<NormalIndex>) -> D {
+        <XpubDerivable as Derive<D>>::derive(self.as_xpub_derivable(), keychain, index)
+    }
+}
+
+/// Keys which can derive private keys matching the public keys produced by [`Derive`].
+pub trait DeriveSecret {
+    fn derive_secret(
+        &self,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+    ) -> SecretKey;
+}
+
+impl DeriveSecret for XprivDerivable {
+    fn derive_secret(
+        &self,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+    ) -> SecretKey {
+        let path = [DerivationIndex::from(keychain.into()), DerivationIndex::from(index.into())];
+        self.xpriv().derive_priv(path).to_private_ecdsa()
     }
 }
 
@@ -407,3 +716,159 @@ impl DeriveSet for XpubDerivable {
     type Compr = XpubDerivable;
     type XOnly = XpubDerivable;
 }
+
+impl DeriveSet for XprivDerivable {
+    type Legacy = XprivDerivable;
+    type Compr = XprivDerivable;
+    type XOnly = XprivDerivable;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn terminal_range() {
+        let terminals = Terminal::range(1, NormalIndex::from(5u8), 3).collect::<Vec<_>>();
+        assert_eq!(terminals, vec![
+            Terminal::change(NormalIndex::from(5u8)),
+            Terminal::change(NormalIndex::from(6u8)),
+            Terminal::change(NormalIndex::from(7u8)),
+        ]);
+        assert_eq!(terminals[0].next(), Some(terminals[1]));
+
+        let last = NormalIndex::MAX.checked_sub(1u8).unwrap();
+        let terminals = Terminal::range(Keychain::OUTER, last, 10).collect::<Vec<_>>();
+        assert_eq!(terminals, vec![
+            Terminal::new(Keychain::OUTER, last),
+            Terminal::new(Keychain::OUTER, NormalIndex::MAX),
+        ]);
+        assert_eq!(terminals[1].next(), None);
+    }
+
+    #[test]
+    fn try_derive() {
+        let xpub = XpubDerivable::from_str("[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*").unwrap();
+        assert_eq!(
+            Derive::<CompressedPk>::try_derive(&xpub, 1u8, 5u8),
+            Ok(Derive::<CompressedPk>::derive(&xpub, 1u8, 5u8))
+        );
+        assert_eq!(
+            Derive::<CompressedPk>::try_derive(&xpub, 2u8, 5u8),
+            Err(DeriveError::UnknownKeychain(Keychain::from(2u8)))
+        );
+    }
+
+    #[test]
+    fn terminal_str() {
+        let terminal = Terminal::from_str("1/5").unwrap();
+        assert_eq!(terminal, Terminal::change(NormalIndex::from(5u8)));
+        assert_eq!(terminal.to_string(), "1/5");
+
+        let max = format!("0/{}", NormalIndex::MAX);
+        assert_eq!(Terminal::from_str(&max).unwrap().to_string(), max);
+
+        assert_eq!(
+            Terminal::from_str("0/5h"),
+            Err(TerminalParseError::Hardened("0/5h".to_owned()))
+        );
+        assert_eq!(
+            Terminal::from_str("1'/5"),
+            Err(TerminalParseError::Hardened("1'/5".to_owned()))
+        );
+        assert_eq!(
+            Terminal::from_str("+0/5"),
+            Err(TerminalParseError::InvalidComponents("+0/5".to_owned()))
+        );
+        assert_eq!(
+            Terminal::from_str("0/05"),
+            Err(TerminalParseError::InvalidComponents("0/05".to_owned()))
+        );
+        assert!(matches!(Terminal::from_str("0/2147483648"), Err(TerminalParseError::Index(_))));
+        assert!(matches!(Terminal::from_str("256/0"), Err(TerminalParseError::InvalidKeychain(_))));
+        assert!(matches!(
+            Terminal::from_str("0/1/2"),
+            Err(TerminalParseError::InvalidComponents(_))
+        ));
+    }
+
+    #[test]
+    fn terminal_legacy_str() {
+        let terminal = Terminal::from_str("&1/5").unwrap();
+        assert_eq!(terminal, Terminal::change(NormalIndex::from(5u8)));
+        assert_eq!(terminal.to_string(), "1/5");
+
+        assert_eq!(Terminal::from_str("/5"), Err(TerminalParseError::NoKeychain));
+        assert_eq!(Terminal::from_str("&/5"), Err(TerminalParseError::NoKeychain));
+        assert!(matches!(
+            Terminal::from_str("&&1/5"),
+            Err(TerminalParseError::InvalidComponents(_))
+        ));
+    }
+
+    #[test]
+    fn fixed_keychains() {
+        let xpub = XpubDerivable::from_str("[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*").unwrap();
+        let keychains = Derive::<CompressedPk>::fixed_keychains::<2>(&xpub).unwrap();
+        assert_eq!(keychains, Keychains::STANDARD);
+        assert_eq!(keychains.into_iter().collect::<Vec<_>>(), vec![
+            Keychain::OUTER,
+            Keychain::INNER
+        ]);
+        assert_eq!(
+            Derive::<CompressedPk>::fixed_keychains::<1>(&xpub),
+            Err(DeriveError::KeychainCount {
+                expected: 1,
+                found: 2
+            })
+        );
+
+        assert_eq!(Keychains::with([Keychain::INNER, Keychain::OUTER]), Some(Keychains::STANDARD));
+        assert_eq!(Keychains::with([Keychain::OUTER, Keychain::OUTER]), None);
+    }
+
+    #[test]
+    fn derived_script_accessors() {
+        let witness_script = WitnessScript::from_unsafe(vec![0x51]);
+        let redeem_script = RedeemScript::from_unsafe(vec![0x51]);
+
+        let segwit = DerivedScript::Segwit(witness_script.clone());
+        assert_eq!(segwit.to_redeem_script(), None);
+        assert_eq!(segwit.as_witness_script(), Some(&witness_script));
+        assert!(segwit.to_script_pubkey().is_p2wsh());
+
+        let nested = DerivedScript::Nested(witness_script.clone());
+        assert_eq!(nested.to_redeem_script(), Some(witness_script.to_redeem_script()));
+        assert_eq!(nested.to_witness_script(), Some(witness_script.clone()));
+        assert!(nested.to_script_pubkey().is_p2sh());
+
+        let bip13 = DerivedScript::Bip13(redeem_script.clone());
+        assert_eq!(bip13.to_redeem_script(), Some(redeem_script));
+        assert_eq!(bip13.as_witness_script(), None);
+
+        let bare = DerivedScript::Bare(witness_script.to_script_pubkey());
+        assert_eq!(bare.to_redeem_script(), None);
+        assert_eq!(bare.as_witness_script(), None);
+    }
+
+    #[test]
+    fn derived_script_eq() {
+        use std::collections::HashSet;
+
+        let witness_script = WitnessScript::from_unsafe(vec![0x51]);
+        let segwit = DerivedScript::Segwit(witness_script.clone());
+        let nested = DerivedScript::Nested(witness_script.clone());
+        let bare = DerivedScript::Bare(witness_script.to_script_pubkey());
+        assert_ne!(segwit, bare);
+        assert_ne!(segwit, nested);
+        assert_eq!(DerivedSpk::from(segwit.clone()), DerivedSpk::from(bare.clone()));
+        assert_ne!(DerivedSpk::from(segwit.clone()), DerivedSpk::from(nested.clone()));
+
+        let set = HashSet::<_>::from_iter([segwit, nested, bare].map(DerivedSpk::from));
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&witness_script.to_script_pubkey()));
+        assert!(
+            set.contains(&DerivedSpk::from(DerivedScript::Bare(witness_script.to_script_pubkey())))
+        );
+    }
+}
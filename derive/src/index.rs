@@ -160,7 +160,7 @@ pub trait Idx: IdxBase {
 
     /// Mutates the self by decrementing the index on one step; fails if the index
     /// value is already maximum value.
-    fn wrapping_dec_assign(&mut self) { *self = self.wrapping_inc(); }
+    fn wrapping_dec_assign(&mut self) { *self = self.wrapping_dec(); }
 
     /// Adds value the index; fails if the index value overflow happens.
     #[must_use]
@@ -197,11 +197,11 @@ pub trait Idx: IdxBase {
     }
 
     /// Mutates the self by adding value the index; fails if the index value
-    /// overflow happens.
+    /// overflow happens, leaving the self unchanged.
     fn checked_add_assign(&mut self, add: impl Into<u32>) -> Option<Self>;
 
     /// Mutates the self by subtracting value the index; fails if the index
-    /// value overflow happens.
+    /// value overflow happens, leaving the self unchanged.
     fn checked_sub_assign(&mut self, sub: impl Into<u32>) -> Option<Self>;
 
     /// Mutates the self by adding value the index saturating it at the
@@ -231,11 +231,12 @@ pub trait Idx: IdxBase {
 
 fn checked_add_assign(index: &mut u32, add: impl Into<u32>) -> Option<u32> {
     let add: u32 = add.into();
-    *index = index.checked_add(add)?;
-    if *index >= HARDENED_INDEX_BOUNDARY {
+    let res = index.checked_add(add)?;
+    if res >= HARDENED_INDEX_BOUNDARY {
         return None;
     }
-    Some(*index)
+    *index = res;
+    Some(res)
 }
 
 fn checked_sub_assign(index: &mut u32, sub: impl Into<u32>) -> Option<u32> {
@@ -487,7 +488,7 @@ impl DerivationIndex {
 
     pub const fn from_index(value: u32) -> Self {
         match value {
-            0..=0x0FFFFFFF => DerivationIndex::Normal(NormalIndex(value)),
+            0..=0x7FFFFFFF => DerivationIndex::Normal(NormalIndex(value)),
             _ => DerivationIndex::Hardened(HardenedIndex(value - HARDENED_INDEX_BOUNDARY)),
         }
     }
@@ -558,9 +559,49 @@ impl FromStr for DerivationIndex {
     type Err = IndexParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.strip_suffix(['h', 'H', '*']) {
+        match s.strip_suffix(['h', 'H', '\'']) {
             Some(_) => HardenedIndex::from_str(s).map(Self::Hardened),
             None => NormalIndex::from_str(s).map(Self::Normal),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normal_arithmetics() {
+        let last = NormalIndex::MAX;
+        assert_eq!(last.index(), HARDENED_INDEX_BOUNDARY - 1);
+        assert_eq!(NormalIndex::ZERO.checked_add(5u8), Some(NormalIndex::from(5u8)));
+        assert_eq!(last.checked_inc(), None);
+        assert_eq!(last.checked_add(u32::MAX), None);
+        assert_eq!(last.saturating_inc(), last);
+        assert_eq!(last.wrapping_inc(), NormalIndex::ZERO);
+        assert_eq!(NormalIndex::ZERO.checked_dec(), None);
+
+        let mut index = last.checked_sub(1u8).unwrap();
+        assert_eq!(index.checked_add_assign(2u8), None);
+        assert_eq!(index, last.checked_sub(1u8).unwrap());
+        assert_eq!(index.checked_inc_assign(), Some(last));
+        assert!(!index.saturating_inc_assign());
+        assert_eq!(index, last);
+
+        let mut index = NormalIndex::ZERO;
+        index.wrapping_dec_assign();
+        assert_eq!(index, last);
+    }
+
+    #[test]
+    fn derivation_index() {
+        assert_eq!(
+            DerivationIndex::from_index(HARDENED_INDEX_BOUNDARY - 1),
+            DerivationIndex::Normal(NormalIndex::MAX)
+        );
+        assert_eq!(
+            DerivationIndex::from_index(HARDENED_INDEX_BOUNDARY),
+            DerivationIndex::hardened(0)
+        );
+    }
+}
@@ -29,25 +29,48 @@ extern crate serde_crate as serde;
 mod index;
 mod path;
 mod xpub;
+mod xpriv;
+mod wif;
 mod derive;
+mod hashes;
+mod musig;
+mod template;
+mod timelocks;
+#[cfg(feature = "mnemonic")]
+mod mnemonic;
 pub mod taptree;
 
 pub use bc::*;
 pub use derive::{
-    Derive, DeriveCompr, DeriveKey, DeriveScripts, DeriveSet, DeriveXOnly, DerivedAddr,
-    DerivedAddrParseError, DerivedScript, Keychain, Terminal, TerminalParseError,
+    Derive, DeriveCompr, DeriveError, DeriveKey, DeriveScripts, DeriveSecret, DeriveSet,
+    DeriveXOnly, DerivedAddr, DerivedAddrParseError, DerivedScript, DerivedSpk, Keychain,
+    Keychains, Terminal, TerminalParseError,
 };
+pub use hashes::tagged_hash;
 pub use index::{
     DerivationIndex, HardenedIndex, Idx, IdxBase, IndexError, IndexParseError, NormalIndex,
     HARDENED_INDEX_BOUNDARY,
 };
 pub use invoice::*;
+#[cfg(feature = "mnemonic")]
+pub use mnemonic::MnemonicError;
+pub use musig::{bip67_sort, key_agg, MusigKey, MusigKeyError};
 pub use path::{DerivationParseError, DerivationPath, DerivationSeg, SegParseError};
 pub use taptree::{
     ControlBlockFactory, FinalizedTree, InvalidTree, LeafInfo, TapDerivation, TapTree,
     TapTreeBuilder, UnfinalizedTree,
 };
+pub use template::{TemplateKey, TemplateKeyParseError, UnresolvedKey};
+#[cfg(feature = "serde")]
+pub use timelocks::serde_lock;
+pub use timelocks::{
+    lock_height, lock_timestamp, AbsLockTime, LockHeightExt, LockParseError, LockTimestampExt,
+    SeqNoExt, LOCK_HEIGHT_ANYTIME, LOCK_HEIGHT_MAX, LOCK_TIMESTAMP_MIN, SEQ_NO_MAX_VALUE,
+    SEQ_NO_SUBMAX_VALUE,
+};
+pub use wif::{WifDecodeError, WifKey, WifParseError, WIF_MAINNET_PREFIX, WIF_TESTNET_PREFIX};
+pub use xpriv::{Xpriv, XprivDecodeError, XprivDerivable, XprivParseError};
 pub use xpub::{
-    KeyOrigin, OriginParseError, Xpub, XpubDecodeError, XpubDerivable, XpubFp, XpubId, XpubMeta,
-    XpubOrigin, XpubParseError, XpubSpec,
+    KeyOrigin, KeychainSpec, KeychainSpecError, OriginParseError, Slip132Type, Xpub,
+    XpubDecodeError, XpubDerivable, XpubFp, XpubId, XpubMeta, XpubOrigin, XpubParseError, XpubSpec,
 };
@@ -0,0 +1,143 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! BIP39 mnemonic seed phrases.
+
+use bip39::{Language, Mnemonic};
+
+use crate::{HardenedIndex, Network, Xpriv, XprivDerivable, XpubDerivable};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum MnemonicError {
+    /// mnemonic has {0} words, while it must have 12, 15, 18, 21 or 24 words.
+    WordCount(usize),
+
+    /// mnemonic word #{0} is not a part of the BIP39 English word list.
+    UnknownWord(usize),
+
+    /// mnemonic checksum is invalid, meaning that some of the words are mistyped or go in a wrong
+    /// order.
+    InvalidChecksum,
+
+    /// invalid mnemonic - {0}.
+    Other(bip39::Error),
+}
+
+impl From<bip39::Error> for MnemonicError {
+    fn from(err: bip39::Error) -> Self {
+        match err {
+            bip39::Error::BadWordCount(count) => MnemonicError::WordCount(count),
+            // Word positions are reported starting from one, as seen by the user
+            bip39::Error::UnknownWord(pos) => MnemonicError::UnknownWord(pos + 1),
+            bip39::Error::InvalidChecksum => MnemonicError::InvalidChecksum,
+            err => MnemonicError::Other(err),
+        }
+    }
+}
+
+impl XprivDerivable {
+    /// Constructs master extended private key from a BIP39 English mnemonic `phrase` and an
+    /// optional `passphrase` (use an empty string if none).
+    ///
+    /// The key uses the standard receive and change keychains.
+    pub fn from_mnemonic(
+        phrase: &str,
+        passphrase: &str,
+        network: Network,
+    ) -> Result<Self, MnemonicError> {
+        let mnemonic = Mnemonic::parse_in(Language::English, phrase)?;
+        let seed = mnemonic.to_seed(passphrase);
+        let master = Xpriv::new_master(network.is_testnet(), &seed);
+        Ok(XprivDerivable::master(master))
+    }
+
+    /// Derives watch-only account-level key at `m/purpose'/coin'/account'`.
+    pub fn account_xpub(
+        &self,
+        purpose: HardenedIndex,
+        coin: HardenedIndex,
+        account: HardenedIndex,
+    ) -> XpubDerivable {
+        self.derive_hardened([purpose, coin, account]).to_xpub_derivable()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::DerivationIndex;
+
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                          abandon abandon about";
+
+    #[test]
+    fn bip39_vector() {
+        let xpriv = XprivDerivable::from_mnemonic(PHRASE, "TREZOR", Network::Mainnet).unwrap();
+        assert_eq!(
+            xpriv.xpriv().to_string(),
+            "xprv9s21ZrQH143K3h3fDYiay8mocZ3afhfULfb5GX8kCBdno77K4HiA15Tg23wpbeF1pLfs1c5SPmYHrEpTuuRhxMwvKDwqdKiGJS9XFKzUsAF"
+        );
+        assert!(xpriv.origin().derivation().is_empty());
+    }
+
+    #[test]
+    fn account() {
+        let master = XprivDerivable::from_mnemonic(PHRASE, "", Network::Testnet3).unwrap();
+        let xpub = master.account_xpub(
+            HardenedIndex::hardened(84),
+            HardenedIndex::hardened(1),
+            HardenedIndex::hardened(0),
+        );
+        let path = [84u8, 1, 0].map(HardenedIndex::from);
+        assert_eq!(xpub.xpub(), master.xpriv().derive_priv(path).to_xpub());
+        assert_eq!(xpub.origin().master_fp(), master.xpriv().fingerprint());
+        assert_eq!(
+            xpub.origin()
+                .derivation()
+                .iter()
+                .copied()
+                .map(DerivationIndex::from)
+                .collect::<Vec<_>>(),
+            path.map(DerivationIndex::from)
+        );
+        assert!(xpub.xpub().is_testnet());
+    }
+
+    #[test]
+    fn invalid() {
+        let unchecked = "abandon ".repeat(11) + "abandon";
+        assert_eq!(
+            XprivDerivable::from_mnemonic(&unchecked, "", Network::Mainnet),
+            Err(MnemonicError::InvalidChecksum)
+        );
+        let unknown = "abandon ".repeat(11) + "bitcoins";
+        assert_eq!(
+            XprivDerivable::from_mnemonic(&unknown, "", Network::Mainnet),
+            Err(MnemonicError::UnknownWord(12))
+        );
+        assert_eq!(
+            XprivDerivable::from_mnemonic("abandon about", "", Network::Mainnet),
+            Err(MnemonicError::WordCount(2))
+        );
+    }
+}
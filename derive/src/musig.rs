@@ -0,0 +1,280 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! MuSig2 (BIP-327) aggregated keys.
+
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use bc::secp256k1::{PublicKey, Scalar, SECP256K1};
+use bc::{CompressedPk, XOnlyPk};
+
+use crate::{
+    tagged_hash, Derive, DeriveKey, KeyOrigin, Keychain, NormalIndex, Terminal, XpubDerivable,
+    XpubParseError, XpubSpec,
+};
+
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum MusigKeyError {
+    /// MuSig2 aggregated key requires at least one participant key.
+    NoKeys,
+
+    /// participant keys of the MuSig2 aggregated key have no keychains in common.
+    NoKeychains,
+
+    /// invalid MuSig2 key expression '{0}'.
+    InvalidFormat(String),
+
+    /// invalid participant key in MuSig2 key expression - {0}
+    #[from]
+    Key(XpubParseError),
+}
+
+/// Sorts public keys according to BIP-67, i.e. lexicographically by their 33-byte compressed
+/// serialization. This is the order used by `sortedmulti` descriptors, and it matches BIP-327
+/// `KeySort` algorithm.
+pub fn bip67_sort(keys: &mut [CompressedPk]) { keys.sort_by_key(CompressedPk::to_byte_array); }
+
+/// Aggregates public keys using BIP-327 `KeyAgg` algorithm, returning x-only aggregated key.
+///
+/// The keys are aggregated in the provided order; use [`MusigKey`] for the descriptor keys, which
+/// are sorted before the aggregation. Returns `None` if the list of keys is empty.
+pub fn key_agg(keys: &[CompressedPk]) -> Option<XOnlyPk> {
+    let first = keys.first()?;
+    let ser = keys.iter().map(CompressedPk::to_byte_array).collect::<Vec<_>>();
+    let list = tagged_hash(b"KeyAgg list", ser.iter().map(|key| &key[..]));
+    let second = keys.iter().find(|key| *key != first);
+
+    let points = keys
+        .iter()
+        .zip(&ser)
+        .map(|(key, bytes)| {
+            let pk: PublicKey = **key;
+            if Some(key) == second {
+                return pk;
+            }
+            let coeff = tagged_hash(b"KeyAgg coefficient", [&list[..], &bytes[..]]);
+            let coeff = Scalar::from_be_bytes(coeff).expect("negligible probability");
+            pk.mul_tweak(SECP256K1, &coeff).expect("negligible probability")
+        })
+        .collect::<Vec<_>>();
+    let agg = PublicKey::combine_keys(&points.iter().collect::<Vec<_>>())
+        .expect("negligible probability");
+    Some(XOnlyPk::from(agg))
+}
+
+/// Taproot internal key which is a MuSig2 aggregation of the keys derived from multiple extended
+/// public keys.
+///
+/// For each terminal the participant keys are derived, sorted (BIP-327 `KeySort`) and aggregated
+/// into a single x-only key, such that the n-of-n multisig looks like a single key on-chain.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct MusigKey(Vec<XpubDerivable>);
+
+impl MusigKey {
+    pub fn new(keys: impl IntoIterator<Item = XpubDerivable>) -> Result<Self, MusigKeyError> {
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        if keys.is_empty() {
+            return Err(MusigKeyError::NoKeys);
+        }
+        let key = MusigKey(keys);
+        if key.keychains().is_empty() {
+            return Err(MusigKeyError::NoKeychains);
+        }
+        Ok(key)
+    }
+
+    /// Participant keys, in the order they were provided.
+    #[inline]
+    pub fn keys(&self) -> &[XpubDerivable] { &self.0 }
+
+    fn derive_participants(&self, terminal: Terminal) -> Vec<CompressedPk> {
+        let mut keys = self
+            .0
+            .iter()
+            .map(|xpub| Derive::<CompressedPk>::derive(xpub, terminal.keychain, terminal.index))
+            .collect::<Vec<_>>();
+        bip67_sort(&mut keys);
+        keys
+    }
+}
+
+impl Derive<XOnlyPk> for MusigKey {
+    #[inline]
+    fn default_keychain(&self) -> Keychain { Derive::<XOnlyPk>::default_keychain(&self.0[0]) }
+
+    fn keychains(&self) -> BTreeSet<Keychain> {
+        let mut keys = self.0.iter().map(Derive::<XOnlyPk>::keychains);
+        let first = keys.next().unwrap_or_default();
+        keys.fold(first, |acc, keychains| &acc & &keychains)
+    }
+
+    fn derive(&self, keychain: impl Into<Keychain>, index: impl Into<NormalIndex>) -> XOnlyPk {
+        let terminal = Terminal::new(keychain, index.into());
+        key_agg(&self.derive_participants(terminal)).expect("MuSig2 key always has participants")
+    }
+}
+
+impl DeriveKey<XOnlyPk> for MusigKey {
+    #[inline]
+    fn try_xpub_spec(&self) -> Option<&XpubSpec> { None }
+
+    fn participants(&self, terminal: Terminal) -> Vec<(XOnlyPk, KeyOrigin)> {
+        self.0
+            .iter()
+            .map(|xpub| {
+                let key = Derive::<XOnlyPk>::derive(xpub, terminal.keychain, terminal.index);
+                (key, KeyOrigin::with(xpub.spec().origin(), terminal))
+            })
+            .collect()
+    }
+}
+
+impl Display for MusigKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("musig(")?;
+        for (no, key) in self.0.iter().enumerate() {
+            if no > 0 {
+                f.write_str(",")?;
+            }
+            Display::fmt(key, f)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl FromStr for MusigKey {
+    type Err = MusigKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = s
+            .strip_prefix("musig(")
+            .and_then(|s| s.strip_suffix(')'))
+            .ok_or_else(|| MusigKeyError::InvalidFormat(s.to_owned()))?;
+        MusigKey::new(keys.split(',').map(XpubDerivable::from_str).collect::<Result<Vec<_>, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::XpubFp;
+
+    #[test]
+    fn bip67_vectors() {
+        let vectors = [
+            vec![
+                "02fe6f0a5a297eb38c391581c4413e084773ea23954d93f7753db7dc0adc188b2f",
+                "02ff12471208c14bd580709cb2358d98975247d8765f92bc25eab3b2763ed605f8",
+            ],
+            vec![
+                "02632b12f4ac5b1d1b72b2a3b508c19172de44f6f46bcee50ba33f3f9291e47ed0",
+                "027735a29bae7780a9755fae7a1c4374c656ac6a69ea9f3697fda61bb99a4f3e77",
+                "02e2cc6bd5f45edd43bebe7cb9b675f0ce9ed3efe613b177588290ad188d11b404",
+            ],
+            vec![
+                "020000000000000000000000000000000000004141414141414141414141414140",
+                "020000000000000000000000000000000000004141414141414141414141414141",
+                "030000000000000000000000000000000000004141414141414141414141414140",
+                "030000000000000000000000000000000000004141414141414141414141414141",
+            ],
+            vec![
+                "021f2f6e1e50cb6a953935c3601284925decd3fd21bc445712576873fb8c6ebc18",
+                "022df8750480ad5b26950b25c7ba79d3e37d75f640f8e5d9bcd5b150a0f85014da",
+                "03e3818b65bcc73a7d64064106a859cc1a5a728c4345ff0b641209fba0d90de6e9",
+            ],
+        ];
+        for sorted in vectors {
+            let sorted = sorted
+                .into_iter()
+                .map(|pk| CompressedPk::from_str(pk).unwrap())
+                .collect::<Vec<_>>();
+            let mut keys = sorted.clone();
+            keys.reverse();
+            bip67_sort(&mut keys);
+            assert_eq!(keys, sorted);
+        }
+    }
+
+    #[test]
+    fn key_agg_vectors() {
+        let x1 = CompressedPk::from_str(
+            "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+        )
+        .unwrap();
+        let x2 = CompressedPk::from_str(
+            "03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+        )
+        .unwrap();
+        let x3 = CompressedPk::from_str(
+            "023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66",
+        )
+        .unwrap();
+
+        let vectors = [
+            (vec![x1, x2, x3], "90539eede565f5d054f32cc0c220126889ed1e5d193baf15aef344fe59d4610c"),
+            (vec![x3, x2, x1], "6204de8b083426dc6eaf9502d27024d53fc826bf7d2012148a0575435df54b2b"),
+            (vec![x1, x1, x1], "b436e3bad62b8cd409969a224731c193d051162d8c5ae8b109306127da3aa935"),
+            (
+                vec![x1, x1, x2, x2],
+                "69bc22bfa5d106306e48a20679de1d7389386124d07571d0d872686028c26a3e",
+            ),
+        ];
+        for (keys, agg) in vectors {
+            assert_eq!(key_agg(&keys), Some(XOnlyPk::from_str(agg).unwrap()));
+        }
+        assert_eq!(key_agg(&[]), None);
+    }
+
+    #[test]
+    fn musig_key() {
+        let xpub1 = "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*";
+        let xpub2 = "[deadbeef/0h]xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw/0/*";
+        let s = format!("musig({xpub1},{xpub2})");
+        let key = MusigKey::from_str(&s).unwrap();
+        assert_eq!(key.to_string(), s);
+        assert_eq!(key.keychains(), bset![Keychain::OUTER]);
+
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(3u8));
+        let participants = key.participants(terminal);
+        assert_eq!(participants.len(), 2);
+        assert_eq!(participants[1].1.master_fp(), XpubFp::from_str("deadbeef").unwrap());
+        assert_eq!(
+            Some(key.derive(Keychain::OUTER, 3u8)),
+            key_agg(&key.derive_participants(terminal))
+        );
+
+        let reversed = MusigKey::from_str(&format!("musig({xpub2},{xpub1})")).unwrap();
+        assert_eq!(key.derive(0u8, 3u8), reversed.derive(0u8, 3u8));
+
+        assert_eq!(
+            MusigKey::from_str("musig()").unwrap_err(),
+            MusigKeyError::Key(XpubParseError::NoOrigin)
+        );
+        assert_eq!(
+            MusigKey::from_str(&format!("musig({xpub1},{})", xpub2.replace("/0/*", "/2/*"))),
+            Err(MusigKeyError::NoKeychains)
+        );
+    }
+}
@@ -20,13 +20,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::borrow::Borrow;
 use std::ops::Deref;
 use std::{slice, vec};
 
 use amplify::num::u7;
+use bc::opcodes::{OP_PUSHBYTES_1, OP_PUSHBYTES_75, OP_PUSHDATA1, OP_PUSHDATA2, OP_PUSHDATA4};
 use bc::{
     ControlBlock, InternalPk, LeafScript, OutputPk, Parity, TapLeafHash, TapMerklePath,
-    TapNodeHash, TapScript,
+    TapNodeHash, TapScript, XOnlyPk,
 };
 use commit_verify::merkle::MerkleBuoy;
 
@@ -148,6 +150,12 @@ impl TapTree {
     }
 
     pub fn into_vec(self) -> Vec<LeafInfo> { self.0 }
+
+    /// Returns hashes of the tree leaves which involve the key, in the form required for
+    /// [`TapDerivation::leaf_hashes`].
+    pub fn leaf_hashes_for(&self, key: XOnlyPk) -> Vec<TapLeafHash> {
+        self.0.iter().filter(|leaf| leaf.contains_key(key)).map(LeafInfo::leaf_hash).collect()
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
@@ -168,6 +176,47 @@ impl LeafInfo {
             script: LeafScript::from_tap_script(script),
         }
     }
+
+    #[inline]
+    pub fn leaf_hash(&self) -> TapLeafHash { TapLeafHash::with_leaf_script(&self.script) }
+
+    /// Detects whether the leaf script pushes the key onto the stack, i.e. the key participates
+    /// in the script.
+    ///
+    /// The script is parsed into operations, such that the key bytes found inside a larger data
+    /// push are not matched. Parsing stops at a truncated push.
+    pub fn contains_key(&self, key: XOnlyPk) -> bool {
+        let key = key.to_byte_array();
+        let mut script: &[u8] = self.script.script.as_ref();
+        while let Some((&op, rest)) = script.split_first() {
+            let (len, rest) = match op {
+                OP_PUSHBYTES_1..=OP_PUSHBYTES_75 => (op as usize, rest),
+                OP_PUSHDATA1 => match rest.split_first() {
+                    Some((&len, rest)) => (len as usize, rest),
+                    None => return false,
+                },
+                OP_PUSHDATA2 if rest.len() >= 2 => {
+                    (u16::from_le_bytes([rest[0], rest[1]]) as usize, &rest[2..])
+                }
+                OP_PUSHDATA4 if rest.len() >= 4 => {
+                    (u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize, &rest[4..])
+                }
+                OP_PUSHDATA2 | OP_PUSHDATA4 => return false,
+                _ => {
+                    script = rest;
+                    continue;
+                }
+            };
+            let Some(data) = rest.get(..len) else {
+                return false;
+            };
+            if data == key {
+                return true;
+            }
+            script = &rest[len..];
+        }
+        false
+    }
 }
 
 #[derive(Getters, Clone, Eq, PartialEq, Debug)]
@@ -226,7 +275,7 @@ impl Iterator for ControlBlockFactory {
 /// to spend this output. The leaf hashes are of the leaves which involve this public key. The
 /// internal key does not have leaf hashes, so can be indicated with a hashes len of 0.
 /// Finalizers should remove this field after `PSBT_IN_FINAL_SCRIPTWITNESS` is constructed.
-#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Clone, Eq, PartialEq, Hash, Default, Debug)]
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
@@ -238,11 +287,79 @@ pub struct TapDerivation {
 }
 
 impl TapDerivation {
-    pub fn with_internal_pk(xpub_origin: XpubOrigin, terminal: Terminal) -> Self {
+    /// Constructs derivation information for a taproot internal key, which doesn't participate in
+    /// any of the script leaves.
+    pub fn with_internal_pk(xpub_origin: impl Borrow<XpubOrigin>, terminal: Terminal) -> Self {
         let origin = KeyOrigin::with(xpub_origin, terminal);
         TapDerivation {
             leaf_hashes: empty!(),
             origin,
         }
     }
+
+    /// Constructs derivation information for a key used in script path spendings, taking the
+    /// hashes of the leaves in which the key participates.
+    ///
+    /// See [`TapTree::leaf_hashes_for`].
+    pub fn with_leaf_hashes(
+        xpub_origin: impl Borrow<XpubOrigin>,
+        terminal: Terminal,
+        leaf_hashes: impl IntoIterator<Item = TapLeafHash>,
+    ) -> Self {
+        TapDerivation {
+            leaf_hashes: leaf_hashes.into_iter().collect(),
+            origin: KeyOrigin::with(xpub_origin, terminal),
+        }
+    }
+
+    /// Detects whether the key is used only for the key path spending.
+    #[inline]
+    pub fn is_key_path_only(&self) -> bool { self.leaf_hashes.is_empty() }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bc::opcodes::OP_CHECKSIG;
+    use bc::{LeafVer, ScriptBytes};
+
+    use super::*;
+
+    #[test]
+    fn leaf_hashes() {
+        let key =
+            XOnlyPk::from_str("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+                .unwrap();
+        let mut script = ScriptBytes::default();
+        script.push_slice(&key.to_byte_array());
+        let mut script = script.to_vec();
+        script.push(OP_CHECKSIG);
+        let leaf = LeafScript::new(LeafVer::TapScript, ScriptBytes::from_unsafe(script));
+        let tree = TapTree::with_single_leaf(leaf.clone());
+        assert_eq!(tree.leaf_hashes_for(key), vec![TapLeafHash::with_leaf_script(&leaf)]);
+
+        let other =
+            XOnlyPk::from_str("c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5")
+                .unwrap();
+        assert!(tree.leaf_hashes_for(other).is_empty());
+
+        // Key bytes inside a larger data push are not a key push
+        let mut data = vec![0u8; 7];
+        data.push(32);
+        data.extend(key.to_byte_array());
+        let mut script = ScriptBytes::default();
+        script.push_slice(&data);
+        let mut script = script.to_vec();
+        script.push(OP_CHECKSIG);
+        let leaf = LeafScript::new(LeafVer::TapScript, ScriptBytes::from_unsafe(script));
+        assert!(TapTree::with_single_leaf(leaf).leaf_hashes_for(key).is_empty());
+
+        let derivation = TapDerivation::with_leaf_hashes(
+            XpubOrigin::from_str("643a7adc/86h/1h/0h").unwrap(),
+            Terminal::new(0, 5u8.into()),
+            tree.leaf_hashes_for(key),
+        );
+        assert!(!derivation.is_key_path_only());
+    }
 }
@@ -0,0 +1,188 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Descriptor templates with key slots for the co-signer keys which are not known yet.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// Key slot of a descriptor template which was not resolved into a key.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display("descriptor template key slot '@{0}' is not resolved")]
+pub struct UnresolvedKey(pub String);
+
+/// Errors parsing [`TemplateKey`], parameterized with the error type of the key parser.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum TemplateKeyParseError<E: Error> {
+    InvalidSlot(String),
+
+    Key(E),
+}
+
+impl<E: Error> Display for TemplateKeyParseError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateKeyParseError::InvalidSlot(slot) => write!(
+                f,
+                "invalid key slot '{slot}': slot names must be non-empty and contain only ASCII \
+                 alphanumeric characters and underscores."
+            ),
+            TemplateKeyParseError::Key(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for TemplateKeyParseError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TemplateKeyParseError::InvalidSlot(_) => None,
+            TemplateKeyParseError::Key(err) => Some(err),
+        }
+    }
+}
+
+/// Key of a descriptor template: either a known key or a named slot for a co-signer key, which
+/// is not provided yet.
+///
+/// Slots are written as `@NAME` in the descriptor string. Templates allow to author (multisig)
+/// descriptors before all co-signers have provided their keys; once the keys arrive the slots are
+/// replaced with them by [`TemplateKey::resolve`].
+///
+/// Template keys do not support derivation: the key has to be resolved first.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TemplateKey<K>(TemplateKeyInner<K>);
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+enum TemplateKeyInner<K> {
+    Key(K),
+    Slot(String),
+}
+
+impl<K> From<K> for TemplateKey<K> {
+    fn from(key: K) -> Self { TemplateKey(TemplateKeyInner::Key(key)) }
+}
+
+impl<K> TemplateKey<K> {
+    /// Constructs key slot. Returns `None` if the slot name is empty or contains characters other
+    /// than ASCII alphanumerics and underscores.
+    pub fn slot(name: impl Into<String>) -> Option<Self> {
+        let name = name.into();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return None;
+        }
+        Some(TemplateKey(TemplateKeyInner::Slot(name)))
+    }
+
+    #[inline]
+    pub fn is_resolved(&self) -> bool { matches!(self.0, TemplateKeyInner::Key(_)) }
+
+    /// Returns the name of the slot, or `None` if the key is known.
+    pub fn slot_name(&self) -> Option<&str> {
+        match &self.0 {
+            TemplateKeyInner::Key(_) => None,
+            TemplateKeyInner::Slot(name) => Some(name),
+        }
+    }
+
+    pub fn as_key(&self) -> Option<&K> {
+        match &self.0 {
+            TemplateKeyInner::Key(key) => Some(key),
+            TemplateKeyInner::Slot(_) => None,
+        }
+    }
+
+    /// Resolves the key, using the `resolver` to look up the keys for slots. Known keys are
+    /// cloned.
+    pub fn resolve(&self, resolver: impl FnOnce(&str) -> Option<K>) -> Result<K, UnresolvedKey>
+    where K: Clone {
+        match &self.0 {
+            TemplateKeyInner::Key(key) => Ok(key.clone()),
+            TemplateKeyInner::Slot(name) => {
+                resolver(name).ok_or_else(|| UnresolvedKey(name.clone()))
+            }
+        }
+    }
+}
+
+impl<K: Display> Display for TemplateKey<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            TemplateKeyInner::Key(key) => Display::fmt(key, f),
+            TemplateKeyInner::Slot(name) => write!(f, "@{name}"),
+        }
+    }
+}
+
+impl<K: FromStr> FromStr for TemplateKey<K>
+where K::Err: Error
+{
+    type Err = TemplateKeyParseError<K::Err>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('@') {
+            Some(name) => TemplateKey::slot(name)
+                .ok_or_else(|| TemplateKeyParseError::InvalidSlot(s.to_owned())),
+            None => K::from_str(s).map(TemplateKey::from).map_err(TemplateKeyParseError::Key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::XpubDerivable;
+
+    const XPUB: &str = "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*";
+
+    #[test]
+    fn parse_display() {
+        let key = TemplateKey::<XpubDerivable>::from_str(XPUB).unwrap();
+        assert!(key.is_resolved());
+        assert_eq!(key.to_string(), XPUB);
+
+        let slot = TemplateKey::<XpubDerivable>::from_str("@co_signer1").unwrap();
+        assert_eq!(slot.slot_name(), Some("co_signer1"));
+        assert_eq!(slot.to_string(), "@co_signer1");
+
+        for s in ["@", "@co-signer", "@a/0/*"] {
+            assert_eq!(
+                TemplateKey::<XpubDerivable>::from_str(s),
+                Err(TemplateKeyParseError::InvalidSlot(s.to_owned()))
+            );
+            assert_eq!(TemplateKey::<XpubDerivable>::slot(&s[1..]), None);
+        }
+        assert!(matches!(
+            TemplateKey::<XpubDerivable>::from_str("xpub"),
+            Err(TemplateKeyParseError::Key(_))
+        ));
+    }
+
+    #[test]
+    fn resolve() {
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let slot = TemplateKey::<XpubDerivable>::slot("alice").unwrap();
+        assert_eq!(slot.resolve(|_| None), Err(UnresolvedKey("alice".to_owned())));
+        assert_eq!(slot.resolve(|name| (name == "alice").then(|| xpub.clone())), Ok(xpub.clone()));
+        assert_eq!(TemplateKey::from(xpub.clone()).resolve(|_| None), Ok(xpub));
+    }
+}
@@ -0,0 +1,620 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers for the lock time types ([`bc::LockTimestamp`], [`bc::LockHeight`],
+//! [`bc::SeqNo`]) defined in the consensus library.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bc::{InvalidTimelock, LockHeight, LockTime, LockTimestamp, SeqNo, LOCKTIME_THRESHOLD};
+
+/// Maximal `nSeq` value, which disables both replace-by-fee and lock time of
+/// the transaction.
+pub const SEQ_NO_MAX_VALUE: u32 = 0xFFFFFFFF;
+
+/// `nSeq` value which enables transaction lock time but doesn't opt-in for
+/// replace-by-fee.
+pub const SEQ_NO_SUBMAX_VALUE: u32 = 0xFFFFFFFE;
+
+/// Lock height of zero, which doesn't lock the transaction.
+pub const LOCK_HEIGHT_ANYTIME: LockHeight = lock_height(0);
+
+/// Maximal lock height, which is one block below [`LOCKTIME_THRESHOLD`]. Larger lock time values
+/// are interpreted as UNIX timestamps.
+///
+/// ```
+/// use derive::{LockTimestamp, LOCKTIME_THRESHOLD, LOCK_HEIGHT_MAX, LOCK_TIMESTAMP_MIN};
+///
+/// assert_eq!(LOCKTIME_THRESHOLD, 500_000_000);
+/// assert_eq!(LOCK_HEIGHT_MAX.to_consensus_u32(), LOCKTIME_THRESHOLD - 1);
+/// assert_eq!(LOCK_TIMESTAMP_MIN.to_consensus_u32(), LOCKTIME_THRESHOLD);
+///
+/// let user_input = 499_999_999;
+/// assert!(user_input <= LOCK_HEIGHT_MAX.to_consensus_u32());
+/// assert!(LockTimestamp::try_from_consensus_u32(user_input).is_err());
+/// ```
+pub const LOCK_HEIGHT_MAX: LockHeight = lock_height(LOCKTIME_THRESHOLD - 1);
+
+/// Minimal non-zero lock timestamp, equal to [`LOCKTIME_THRESHOLD`] (November 5th, 1985). See
+/// [`LOCK_HEIGHT_MAX`] for an example of validating user input against these boundaries.
+pub const LOCK_TIMESTAMP_MIN: LockTimestamp = lock_timestamp(LOCKTIME_THRESHOLD);
+
+/// Constructs lock height in a const context.
+///
+/// # Panics
+///
+/// If the height is not less than [`LOCKTIME_THRESHOLD`]. When used to initialize a constant, this
+/// results in a compilation error.
+pub const fn lock_height(height: u32) -> LockHeight {
+    match LockHeight::try_from_consensus_u32(height) {
+        Ok(lock) => lock,
+        Err(_) => panic!("block height must be less than 500000000"),
+    }
+}
+
+/// Constructs lock timestamp from UNIX timestamp in a const context.
+///
+/// Zero lock timestamp ([`LockTimestamp::anytime`]) can't be constructed with this function.
+///
+/// # Panics
+///
+/// If the timestamp is less than [`LOCKTIME_THRESHOLD`]. When used to initialize a constant, this
+/// results in a compilation error.
+pub const fn lock_timestamp(timestamp: u32) -> LockTimestamp {
+    match LockTimestamp::try_from_consensus_u32(timestamp) {
+        Ok(lock) => lock,
+        Err(_) => panic!("timestamp must be not less than 500000000"),
+    }
+}
+
+/// Extension methods for [`SeqNo`] related to replace-by-fee (BIP-125).
+pub trait SeqNoExt {
+    /// Creates `nSeq` value `0xFFFFFFFD`, which opts-in for replace-by-fee and
+    /// enables transaction lock time without applying a relative time lock.
+    fn rbf() -> Self;
+
+    /// Checks if `nSeq` value opts-in for replace-by-fee (also always true for
+    /// relative time locks).
+    fn is_rbf(&self) -> bool;
+}
+
+impl SeqNoExt for SeqNo {
+    #[inline]
+    fn rbf() -> Self { SeqNo::from_consensus_u32(SEQ_NO_SUBMAX_VALUE - 1) }
+
+    #[inline]
+    fn is_rbf(&self) -> bool { self.to_consensus_u32() < SEQ_NO_SUBMAX_VALUE }
+}
+
+/// Errors parsing lock time string representation with [`LockHeightExt::parse_strict`] and
+/// [`LockTimestampExt::parse_strict`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum LockParseError {
+    /// time lock `{0}` has a wrong prefix; expected `{1}(...)`.
+    WrongPrefix(String, &'static str),
+
+    /// time lock `{0}` misses the closing parenthesis.
+    Unclosed(String),
+
+    /// time lock `{0}` must contain exactly one opening and one closing parenthesis.
+    ExtraParens(String),
+
+    /// time lock `{0}` has no value inside the parentheses.
+    Empty(String),
+
+    /// time lock `{0}` has unexpected characters after the closing parenthesis.
+    TrailingChars(String),
+
+    /// `{0}` is not a valid time lock number.
+    NotANumber(String),
+
+    /// block height {0} is out of range; heights must be less than 500000000.
+    HeightOutOfRange(String),
+
+    /// timestamp {0} is out of range; timestamps must be zero or not less than 500000000 and fit
+    /// into 32 bits.
+    TimestampOutOfRange(String),
+}
+
+/// Parses `{prefix}(number)` lock time representation, returning the number string, which is
+/// guaranteed to be a non-empty sequence of decimal digits. Returns `None` for `0` and `none`
+/// strings, which represent absence of the lock.
+///
+/// Unlike the `FromStr` implementations of the consensus library, which strip any number of
+/// closing parentheses, requires exactly one pair of them.
+fn parse_lock<'s>(s: &'s str, prefix: &'static str) -> Result<Option<&'s str>, LockParseError> {
+    if s == "0" || s.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    let inner = s
+        .get(..prefix.len())
+        .filter(|p| p.eq_ignore_ascii_case(prefix))
+        .and_then(|_| s[prefix.len()..].strip_prefix('('))
+        .ok_or_else(|| LockParseError::WrongPrefix(s.to_owned(), prefix))?;
+    let (no, rest) = inner.split_once(')').ok_or_else(|| LockParseError::Unclosed(s.to_owned()))?;
+    if no.contains('(') || rest.contains(['(', ')']) {
+        return Err(LockParseError::ExtraParens(s.to_owned()));
+    }
+    if !rest.is_empty() {
+        return Err(LockParseError::TrailingChars(s.to_owned()));
+    }
+    if no.is_empty() {
+        return Err(LockParseError::Empty(s.to_owned()));
+    }
+    if !no.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(LockParseError::NotANumber(no.to_owned()));
+    }
+    Ok(Some(no))
+}
+
+/// Extension methods for [`LockHeight`].
+pub trait LockHeightExt: Sized {
+    /// Parses `height(...)` string representation of the lock height (the prefix is
+    /// case-insensitive); `0` and `none` strings are parsed as [`LockHeight::anytime`].
+    ///
+    /// Unlike the `FromStr` implementation, rejects characters following the closing parenthesis,
+    /// repeated parentheses, empty values and signed numbers, and reports distinct errors for the
+    /// wrong prefix, non-numeric values and heights not less than [`LOCKTIME_THRESHOLD`].
+    fn parse_strict(s: &str) -> Result<Self, LockParseError>;
+}
+
+impl LockHeightExt for LockHeight {
+    fn parse_strict(s: &str) -> Result<Self, LockParseError> {
+        let Some(no) = parse_lock(s, "height")? else {
+            return Ok(LockHeight::anytime());
+        };
+        no.parse()
+            .ok()
+            .and_then(LockHeight::from_height)
+            .ok_or_else(|| LockParseError::HeightOutOfRange(no.to_owned()))
+    }
+}
+
+/// Extension methods for [`LockTimestamp`] constructing time locks from the system clock without
+/// panicking and parsing them from strings.
+pub trait LockTimestampExt: Sized {
+    /// Parses `time(...)` string representation of the lock timestamp (the prefix is
+    /// case-insensitive); `0`, `none` and `time(0)` strings are parsed as
+    /// [`LockTimestamp::anytime`].
+    ///
+    /// Unlike the `FromStr` implementation, rejects characters following the closing parenthesis,
+    /// repeated parentheses, empty values and signed numbers, and reports distinct errors for the
+    /// wrong prefix, non-numeric values and timestamps below [`LOCKTIME_THRESHOLD`] or
+    /// exceeding 32 bits.
+    fn parse_strict(s: &str) -> Result<Self, LockParseError>;
+
+    /// Creates absolute time lock valid since the current system time.
+    ///
+    /// Unlike `LockTimestamp::since_now`, fails instead of panicking if the system clock is
+    /// outside of the range of the lock timestamps: before 1985 (below [`LOCKTIME_THRESHOLD`]) or
+    /// after the rollover of 32-bit UNIX timestamps in February 2106. In the latter case the
+    /// error contains `u32::MAX`.
+    fn try_since_now() -> Result<Self, InvalidTimelock>;
+
+    /// Creates absolute time lock valid since the current system time, clamping the clock value
+    /// to the range of the lock timestamps.
+    ///
+    /// After the 2106 rollover the method keeps returning the maximal lock timestamp.
+    fn since_now_clamped() -> Self;
+}
+
+fn unix_timestamp_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|dur| dur.as_secs()).unwrap_or_default()
+}
+
+fn lock_timestamp_from_clock(secs: u64) -> Result<LockTimestamp, InvalidTimelock> {
+    let timestamp = u32::try_from(secs).map_err(|_| InvalidTimelock(u32::MAX))?;
+    LockTimestamp::from_unix_timestamp(timestamp).ok_or(InvalidTimelock(timestamp))
+}
+
+impl LockTimestampExt for LockTimestamp {
+    fn parse_strict(s: &str) -> Result<Self, LockParseError> {
+        let Some(no) = parse_lock(s, "time")? else {
+            return Ok(LockTimestamp::anytime());
+        };
+        match no.parse() {
+            Ok(0) => Some(LockTimestamp::anytime()),
+            Ok(timestamp) => LockTimestamp::from_unix_timestamp(timestamp),
+            Err(_) => None,
+        }
+        .ok_or_else(|| LockParseError::TimestampOutOfRange(no.to_owned()))
+    }
+
+    fn try_since_now() -> Result<Self, InvalidTimelock> {
+        lock_timestamp_from_clock(unix_timestamp_now())
+    }
+
+    fn since_now_clamped() -> Self {
+        let timestamp = unix_timestamp_now().clamp(LOCKTIME_THRESHOLD as u64, u32::MAX as u64);
+        LockTimestamp::from_unix_timestamp(timestamp as u32)
+            .expect("timestamp is clamped to the valid range")
+    }
+}
+
+/// Absolute lock time, which is either a block height or a UNIX timestamp.
+///
+/// Unlike [`LockTime`], the interpretation of the value is explicit, preventing heights from
+/// being treated as timestamps and vice versa. The string representation is the one of the
+/// wrapped type (`height(...)` or `time(...)`).
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, From)]
+pub enum AbsLockTime {
+    #[from]
+    Height(LockHeight),
+    #[from]
+    Time(LockTimestamp),
+}
+
+impl Default for AbsLockTime {
+    fn default() -> Self { AbsLockTime::Height(LockHeight::anytime()) }
+}
+
+impl From<LockTime> for AbsLockTime {
+    fn from(lock_time: LockTime) -> Self {
+        match LockHeight::try_from_lock_time(lock_time) {
+            Ok(height) => AbsLockTime::Height(height),
+            Err(_) => AbsLockTime::Time(
+                LockTimestamp::try_from_lock_time(lock_time)
+                    .expect("lock time which is not a height is always a timestamp"),
+            ),
+        }
+    }
+}
+
+impl From<AbsLockTime> for LockTime {
+    fn from(lock_time: AbsLockTime) -> Self {
+        match lock_time {
+            AbsLockTime::Height(height) => height.into(),
+            AbsLockTime::Time(time) => time.into(),
+        }
+    }
+}
+
+impl AbsLockTime {
+    #[inline]
+    pub fn is_height_based(self) -> bool { matches!(self, AbsLockTime::Height(_)) }
+
+    #[inline]
+    pub fn is_time_based(self) -> bool { matches!(self, AbsLockTime::Time(_)) }
+
+    /// Converts into [`LockTime`] representation.
+    #[inline]
+    pub fn to_lock_time(self) -> LockTime { self.into() }
+
+    /// Converts into full u32 representation of `nLockTime` value as it is serialized in bitcoin
+    /// transaction.
+    #[inline]
+    pub fn to_consensus_u32(self) -> u32 { self.to_lock_time().into_consensus_u32() }
+}
+
+impl Display for AbsLockTime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AbsLockTime::Height(height) => Display::fmt(height, f),
+            AbsLockTime::Time(time) => Display::fmt(time, f),
+        }
+    }
+}
+
+impl FromStr for AbsLockTime {
+    type Err = LockParseError;
+
+    /// Parses `height(...)` or `time(...)` strings with [`LockHeightExt::parse_strict`] and
+    /// [`LockTimestampExt::parse_strict`]; `0` and `none` are parsed as zero lock height.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.get(..5) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("time(") => {
+                LockTimestamp::parse_strict(s).map(AbsLockTime::Time)
+            }
+            _ => LockHeight::parse_strict(s).map(AbsLockTime::Height),
+        }
+    }
+}
+
+/// Serde helpers for [`bc::LockTimestamp`] and [`bc::LockHeight`] which are
+/// aware of the format being human-readable.
+///
+/// Human-readable formats (like JSON or YAML) receive `time(...)` and
+/// `height(...)` strings, which are parsed back with the `FromStr`
+/// implementation of the type. This ensures that a height can't be
+/// deserialized as a timestamp and vice versa. Binary formats keep the compact
+/// consensus `u32` representation.
+///
+/// Use as `#[serde(with = "derive::serde_lock")]`, or
+/// `#[serde(with = "derive::serde_lock::option")]` for optional fields.
+#[cfg(feature = "serde")]
+pub mod serde_lock {
+    use std::fmt::Display;
+    use std::str::FromStr;
+
+    use serde_crate::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T, S>(lock: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Copy + Display + Into<u32>,
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.collect_str(lock)
+        } else {
+            serializer.serialize_u32((*lock).into())
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr + TryFrom<u32>,
+        <T as FromStr>::Err: Display,
+        <T as TryFrom<u32>>::Error: Display,
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            T::from_str(&s).map_err(|err| {
+                de::Error::custom(format!("invalid lock time string representation; {err}"))
+            })
+        } else {
+            let val = u32::deserialize(deserializer)?;
+            T::try_from(val)
+                .map_err(|err| de::Error::custom(format!("invalid lock time value; {err}")))
+        }
+    }
+
+    struct Lock<T>(T);
+
+    impl<T: Copy + Display + Into<u32>> Serialize for Lock<T> {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer {
+            serialize(&self.0, serializer)
+        }
+    }
+
+    impl<'de, T> Deserialize<'de> for Lock<T>
+    where
+        T: FromStr + TryFrom<u32>,
+        <T as FromStr>::Err: Display,
+        <T as TryFrom<u32>>::Error: Display,
+    {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de> {
+            deserialize(deserializer).map(Lock)
+        }
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<T, S>(lock: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            T: Copy + Display + Into<u32>,
+            S: Serializer,
+        {
+            lock.map(Lock).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+        where
+            T: FromStr + TryFrom<u32>,
+            <T as FromStr>::Err: Display,
+            <T as TryFrom<u32>>::Error: Display,
+            D: Deserializer<'de>,
+        {
+            Option::<Lock<T>>::deserialize(deserializer).map(|lock| lock.map(|lock| lock.0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rbf() {
+        assert!(SeqNo::rbf().is_rbf());
+        assert_eq!(SeqNo::rbf().time_lock_interval(), None);
+        assert!(SeqNo::from_height(10).is_rbf());
+        assert!(SeqNo::from_intervals(10).is_rbf());
+        assert!(!SeqNo::from_consensus_u32(SEQ_NO_SUBMAX_VALUE).is_rbf());
+        assert!(!SeqNo::from_consensus_u32(SEQ_NO_MAX_VALUE).is_rbf());
+    }
+
+    #[test]
+    fn parse_strict() {
+        assert_eq!(
+            LockHeight::parse_strict("height(100)"),
+            Ok(LockHeight::from_height(100).unwrap())
+        );
+        assert_eq!(
+            LockHeight::parse_strict("HEIGHT(100)"),
+            LockHeight::parse_strict("height(100)")
+        );
+        assert_eq!(LockHeight::parse_strict("none"), Ok(LockHeight::anytime()));
+        assert_eq!(
+            LockHeight::parse_strict("height(500000000)"),
+            Err(LockParseError::HeightOutOfRange("500000000".to_owned()))
+        );
+        assert_eq!(
+            LockHeight::parse_strict("height(99999999999)"),
+            Err(LockParseError::HeightOutOfRange("99999999999".to_owned()))
+        );
+        assert_eq!(
+            LockHeight::parse_strict("time(100)"),
+            Err(LockParseError::WrongPrefix("time(100)".to_owned(), "height"))
+        );
+        assert_eq!(
+            LockHeight::parse_strict("height(100"),
+            Err(LockParseError::Unclosed("height(100".to_owned()))
+        );
+        assert_eq!(
+            LockHeight::parse_strict("height(100)x"),
+            Err(LockParseError::TrailingChars("height(100)x".to_owned()))
+        );
+        assert_eq!(
+            LockHeight::parse_strict("height(+5)"),
+            Err(LockParseError::NotANumber("+5".to_owned()))
+        );
+
+        let timestamp = LockTimestamp::from_unix_timestamp(1_700_000_000).unwrap();
+        assert_eq!(LockTimestamp::parse_strict("time(1700000000)"), Ok(timestamp));
+        assert_eq!(LockTimestamp::parse_strict(&timestamp.to_string()), Ok(timestamp));
+        assert_eq!(LockTimestamp::parse_strict("time(0)"), Ok(LockTimestamp::anytime()));
+        assert_eq!(
+            LockTimestamp::parse_strict("time(100)"),
+            Err(LockParseError::TimestampOutOfRange("100".to_owned()))
+        );
+        assert_eq!(
+            LockTimestamp::parse_strict("time(4294967296)"),
+            Err(LockParseError::TimestampOutOfRange("4294967296".to_owned()))
+        );
+        assert_eq!(
+            LockTimestamp::parse_strict("time(1700000000))"),
+            Err(LockParseError::ExtraParens("time(1700000000))".to_owned()))
+        );
+        assert_eq!(
+            LockTimestamp::parse_strict("time(abc)"),
+            Err(LockParseError::NotANumber("abc".to_owned()))
+        );
+    }
+
+    #[test]
+    fn parse_strict_malformed() {
+        let err = |s: &str| LockHeight::parse_strict(s).unwrap_err();
+        assert_eq!(err("height()"), LockParseError::Empty("height()".to_owned()));
+        assert_eq!(err("heigh(5)"), LockParseError::WrongPrefix("heigh(5)".to_owned(), "height"));
+        assert_eq!(
+            err("heights(5)"),
+            LockParseError::WrongPrefix("heights(5)".to_owned(), "height")
+        );
+        assert_eq!(err("height 5"), LockParseError::WrongPrefix("height 5".to_owned(), "height"));
+        assert_eq!(err("height"), LockParseError::WrongPrefix("height".to_owned(), "height"));
+        assert_eq!(err(""), LockParseError::WrongPrefix("".to_owned(), "height"));
+        assert_eq!(err("hé(5)"), LockParseError::WrongPrefix("hé(5)".to_owned(), "height"));
+        assert_eq!(err("height(5))"), LockParseError::ExtraParens("height(5))".to_owned()));
+        assert_eq!(err("height((5))"), LockParseError::ExtraParens("height((5))".to_owned()));
+        assert_eq!(err("height(5)(6)"), LockParseError::ExtraParens("height(5)(6)".to_owned()));
+        assert_eq!(err("height( 5)"), LockParseError::NotANumber(" 5".to_owned()));
+        assert_eq!(err("height(-5)"), LockParseError::NotANumber("-5".to_owned()));
+
+        let err = |s: &str| LockTimestamp::parse_strict(s).unwrap_err();
+        assert_eq!(err("time()"), LockParseError::Empty("time()".to_owned()));
+        assert_eq!(
+            err("tim(500000000)"),
+            LockParseError::WrongPrefix("tim(500000000)".to_owned(), "time")
+        );
+        assert_eq!(
+            err("time((500000000))"),
+            LockParseError::ExtraParens("time((500000000))".to_owned())
+        );
+        assert_eq!(err("time(500000000"), LockParseError::Unclosed("time(500000000".to_owned()));
+    }
+
+    #[test]
+    fn parse_strict_roundtrip() {
+        let heights = (0..LOCKTIME_THRESHOLD).step_by(4_999_999).chain([1, LOCKTIME_THRESHOLD - 1]);
+        for height in heights {
+            let lock = lock_height(height);
+            assert_eq!(LockHeight::parse_strict(&lock.to_string()), Ok(lock));
+            assert_eq!(LockHeight::parse_strict(&lock.to_string().to_uppercase()), Ok(lock));
+            assert_eq!(AbsLockTime::from_str(&lock.to_string()), Ok(AbsLockTime::Height(lock)));
+        }
+
+        let timestamps = (LOCKTIME_THRESHOLD..=u32::MAX).step_by(37_999_999).chain([u32::MAX]);
+        for timestamp in timestamps {
+            let lock = lock_timestamp(timestamp);
+            assert_eq!(LockTimestamp::parse_strict(&lock.to_string()), Ok(lock));
+            assert_eq!(LockTimestamp::parse_strict(&lock.to_string().to_uppercase()), Ok(lock));
+            assert_eq!(AbsLockTime::from_str(&lock.to_string()), Ok(AbsLockTime::Time(lock)));
+        }
+        let anytime = LockTimestamp::anytime();
+        assert_eq!(LockTimestamp::parse_strict(&anytime.to_string()), Ok(anytime));
+    }
+
+    #[test]
+    fn const_constructors() {
+        const HEIGHT: LockHeight = lock_height(840_000);
+        const TIMESTAMP: LockTimestamp = lock_timestamp(1_700_000_000);
+        assert_eq!(Some(HEIGHT), LockHeight::from_height(840_000));
+        assert_eq!(Some(TIMESTAMP), LockTimestamp::from_unix_timestamp(1_700_000_000));
+        assert_eq!(LOCK_HEIGHT_ANYTIME, LockHeight::anytime());
+        assert_eq!(Some(LOCK_HEIGHT_MAX), LockHeight::from_height(LOCKTIME_THRESHOLD - 1));
+        assert_eq!(LockHeight::from_height(LOCK_HEIGHT_MAX.to_consensus_u32() + 1), None);
+        assert_eq!(
+            Some(LOCK_TIMESTAMP_MIN),
+            LockTimestamp::from_unix_timestamp(LOCK_HEIGHT_MAX.to_consensus_u32() + 1)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "block height must be less than 500000000")]
+    fn lock_height_out_of_range() { lock_height(LOCKTIME_THRESHOLD); }
+
+    #[test]
+    #[should_panic(expected = "timestamp must be not less than 500000000")]
+    fn lock_timestamp_out_of_range() { lock_timestamp(LOCKTIME_THRESHOLD - 1); }
+
+    #[test]
+    fn abs_lock_time() {
+        let height = AbsLockTime::from(LockTime::from_consensus_u32(840_000));
+        assert_eq!(height, AbsLockTime::Height(lock_height(840_000)));
+        assert!(height.is_height_based());
+        assert_eq!(height.to_string(), "height(840000)");
+        assert_eq!(AbsLockTime::from_str("height(840000)"), Ok(height));
+        assert_eq!(height.to_lock_time(), LockTime::from_consensus_u32(840_000));
+
+        let time = AbsLockTime::from(LockTime::from_consensus_u32(LOCKTIME_THRESHOLD));
+        assert_eq!(time, AbsLockTime::Time(lock_timestamp(LOCKTIME_THRESHOLD)));
+        assert!(time.is_time_based());
+        assert_eq!(time.to_string(), "time(500000000)");
+        assert_eq!(AbsLockTime::from_str("TIME(500000000)"), Ok(time));
+        assert_eq!(time.to_consensus_u32(), LOCKTIME_THRESHOLD);
+
+        assert_eq!(AbsLockTime::from_str("none"), Ok(AbsLockTime::default()));
+        assert_eq!(
+            AbsLockTime::from_str("time(100)"),
+            Err(LockParseError::TimestampOutOfRange("100".to_owned()))
+        );
+        assert_eq!(
+            AbsLockTime::from_str("block(100)"),
+            Err(LockParseError::WrongPrefix("block(100)".to_owned(), "height"))
+        );
+    }
+
+    #[test]
+    fn since_now() {
+        let now = LockTimestamp::try_since_now().unwrap();
+        assert!(now.to_consensus_u32() >= LOCKTIME_THRESHOLD);
+        assert!(LockTimestamp::since_now_clamped() >= now);
+    }
+
+    #[test]
+    fn clock_out_of_range() {
+        assert_eq!(lock_timestamp_from_clock(100), Err(InvalidTimelock(100)));
+        assert_eq!(
+            lock_timestamp_from_clock(u32::MAX as u64),
+            Ok(LockTimestamp::from_unix_timestamp(u32::MAX).unwrap())
+        );
+        // after the 2106 rollover
+        assert_eq!(lock_timestamp_from_clock(u32::MAX as u64 + 1), Err(InvalidTimelock(u32::MAX)));
+    }
+}
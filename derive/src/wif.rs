@@ -0,0 +1,196 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wallet import format (WIF) for single private keys.
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use bc::secp256k1::{self, Keypair, PublicKey, SecretKey, SECP256K1};
+use bc::{CompressedPk, LegacyPk};
+
+use crate::base58;
+
+pub const WIF_MAINNET_PREFIX: u8 = 0x80;
+pub const WIF_TESTNET_PREFIX: u8 = 0xEF;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum WifDecodeError {
+    /// wrong length of WIF private key data ({0}).
+    WrongLength(usize),
+
+    /// unknown WIF private key version byte {0:#04x}.
+    UnknownVersion(u8),
+
+    /// invalid public key compression flag {0:#04x} in WIF private key.
+    InvalidCompressionFlag(u8),
+
+    /// WIF private key contains invalid secret key value.
+    #[from(secp256k1::Error)]
+    InvalidSecretKey,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+pub enum WifParseError {
+    /// wrong Base58 encoding of WIF private key data - {0}
+    #[display(doc_comments)]
+    #[from]
+    Base58(base58::Error),
+
+    #[display(inner)]
+    #[from]
+    Decode(WifDecodeError),
+}
+
+/// Single private key in the wallet import format (WIF).
+///
+/// Used for importing non-derivable keys, like ones from paper wallets.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct WifKey {
+    testnet: bool,
+    compressed: bool,
+    secret_key: SecretKey,
+}
+
+impl WifKey {
+    pub fn new(secret_key: SecretKey, testnet: bool, compressed: bool) -> Self {
+        WifKey {
+            testnet,
+            compressed,
+            secret_key,
+        }
+    }
+
+    pub fn decode(data: &[u8]) -> Result<WifKey, WifDecodeError> {
+        let compressed = match data.len() {
+            33 => false,
+            34 if data[33] == 0x01 => true,
+            34 => return Err(WifDecodeError::InvalidCompressionFlag(data[33])),
+            len => return Err(WifDecodeError::WrongLength(len)),
+        };
+        let testnet = match data[0] {
+            WIF_MAINNET_PREFIX => false,
+            WIF_TESTNET_PREFIX => true,
+            unknown => return Err(WifDecodeError::UnknownVersion(unknown)),
+        };
+        let secret_key = SecretKey::from_slice(&data[1..33])?;
+        Ok(WifKey {
+            testnet,
+            compressed,
+            secret_key,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(34);
+        data.push(match self.testnet {
+            false => WIF_MAINNET_PREFIX,
+            true => WIF_TESTNET_PREFIX,
+        });
+        data.extend_from_slice(&self.secret_key.secret_bytes());
+        if self.compressed {
+            data.push(0x01);
+        }
+        data
+    }
+
+    #[inline]
+    pub fn is_testnet(&self) -> bool { self.testnet }
+
+    /// Detects whether the public key for this private key must be serialized in compressed form.
+    #[inline]
+    pub fn is_compressed(&self) -> bool { self.compressed }
+
+    /// Returns secret key for ECDSA signing.
+    #[inline]
+    pub fn to_private_ecdsa(&self) -> SecretKey { self.secret_key }
+
+    /// Returns key pair for BIP340 signing.
+    pub fn to_keypair_bip340(&self) -> Keypair {
+        Keypair::from_secret_key(SECP256K1, &self.secret_key)
+    }
+
+    /// Returns compressed public key matching the private key, or `None` if the key was exported
+    /// for the use with uncompressed public keys.
+    pub fn to_compr_pub(&self) -> Option<CompressedPk> {
+        if !self.compressed {
+            return None;
+        }
+        Some(PublicKey::from_secret_key(SECP256K1, &self.secret_key).into())
+    }
+
+    /// Returns public key matching the private key, serialized according to the compression flag.
+    pub fn to_legacy_pub(&self) -> LegacyPk {
+        let pk = PublicKey::from_secret_key(SECP256K1, &self.secret_key);
+        match self.compressed {
+            true => LegacyPk::compressed(pk),
+            false => LegacyPk::uncompressed(pk),
+        }
+    }
+}
+
+impl Display for WifKey {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        base58::encode_check_to_fmt(f, &self.encode())
+    }
+}
+
+impl FromStr for WifKey {
+    type Err = WifParseError;
+
+    fn from_str(inp: &str) -> Result<WifKey, WifParseError> {
+        let data = base58::decode_check(inp)?;
+        Ok(WifKey::decode(&data)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wif_roundtrip() {
+        let uncompressed = "5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ";
+        let compressed = "KwdMAjGmerYanjeui5SHS7JkmpZvVipYvB2LJGU1ZxJwYvP98617";
+
+        let wif = WifKey::from_str(uncompressed).unwrap();
+        assert!(!wif.is_testnet());
+        assert!(!wif.is_compressed());
+        assert_eq!(wif.to_compr_pub(), None);
+        assert_eq!(wif.to_string(), uncompressed);
+
+        let wif2 = WifKey::from_str(compressed).unwrap();
+        assert!(!wif2.is_testnet());
+        assert!(wif2.is_compressed());
+        assert_eq!(wif2.to_private_ecdsa(), wif.to_private_ecdsa());
+        assert_eq!(wif2.to_string(), compressed);
+        assert_eq!(wif2.to_compr_pub().map(LegacyPk::from), Some(wif2.to_legacy_pub()));
+    }
+
+    #[test]
+    fn wif_invalid() {
+        let data = [0x81; 33];
+        assert_eq!(WifKey::decode(&data), Err(WifDecodeError::UnknownVersion(0x81)));
+        assert_eq!(WifKey::decode(&data[..32]), Err(WifDecodeError::WrongLength(32)));
+    }
+}
@@ -0,0 +1,415 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Borrow;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use amplify::ByteArray;
+use bc::secp256k1::{self, Keypair, PublicKey, SecretKey, SECP256K1};
+use bitcoin_hashes::{sha512, Hash, HashEngine, Hmac, HmacEngine};
+
+use crate::xpub::{ChainCode, XpubCore};
+use crate::{
+    base58, DerivationIndex, DerivationPath, DerivationSeg, HardenedIndex, IdxBase, Keychain, Xpub,
+    XpubDerivable, XpubFp, XpubId, XpubMeta, XpubOrigin, XpubParseError, XpubSpec,
+};
+
+pub const XPRIV_MAINNET_MAGIC: [u8; 4] = [0x04u8, 0x88, 0xAD, 0xE4];
+pub const XPRIV_TESTNET_MAGIC: [u8; 4] = [0x04u8, 0x35, 0x83, 0x94];
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum XprivDecodeError {
+    /// wrong length of extended private key data ({0}).
+    WrongExtendedKeyLength(usize),
+
+    /// provided key is not a standard BIP-32 extended private key
+    UnknownKeyType([u8; 4]),
+
+    /// extended private key contains invalid secret key value.
+    #[from(secp256k1::Error)]
+    InvalidSecretKey,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+pub enum XprivParseError {
+    /// wrong Base58 encoding of extended private key data - {0}
+    #[display(doc_comments)]
+    #[from]
+    Base58(base58::Error),
+
+    #[display(inner)]
+    #[from]
+    Decode(XprivDecodeError),
+
+    #[display(inner)]
+    #[from]
+    Spec(XpubParseError),
+}
+
+/// Deterministic part of the extended private key.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct XprivCore {
+    /// Secret key
+    pub private_key: SecretKey,
+    /// BIP32 chain code used for hierarchical derivation
+    pub chain_code: ChainCode,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Xpriv {
+    testnet: bool,
+    meta: XpubMeta,
+    core: XprivCore,
+}
+
+impl Xpriv {
+    /// Constructs master extended private key from a seed value.
+    pub fn new_master(testnet: bool, seed: &[u8]) -> Xpriv {
+        let mut hmac_engine: HmacEngine<sha512::Hash> = HmacEngine::new(b"Bitcoin seed");
+        hmac_engine.input(seed);
+        let hmac_result: Hmac<sha512::Hash> = Hmac::from_engine(hmac_engine);
+
+        let private_key =
+            SecretKey::from_slice(&hmac_result[..32]).expect("negligible probability");
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&hmac_result[32..]);
+
+        Xpriv {
+            testnet,
+            meta: XpubMeta {
+                depth: 0,
+                parent_fp: XpubFp::default(),
+                child_number: DerivationIndex::normal(0),
+            },
+            core: XprivCore {
+                private_key,
+                chain_code: chain_code.into(),
+            },
+        }
+    }
+
+    pub fn decode(data: impl Borrow<[u8]>) -> Result<Xpriv, XprivDecodeError> {
+        let data = data.borrow();
+
+        if data.len() != 78 {
+            return Err(XprivDecodeError::WrongExtendedKeyLength(data.len()));
+        }
+
+        let testnet = match &data[0..4] {
+            magic if magic == XPRIV_MAINNET_MAGIC => false,
+            magic if magic == XPRIV_TESTNET_MAGIC => true,
+            unknown => {
+                let mut magic = [0u8; 4];
+                magic.copy_from_slice(unknown);
+                return Err(XprivDecodeError::UnknownKeyType(magic));
+            }
+        };
+        let depth = data[4];
+
+        let mut parent_fp = [0u8; 4];
+        parent_fp.copy_from_slice(&data[5..9]);
+
+        let mut child_number = [0u8; 4];
+        child_number.copy_from_slice(&data[9..13]);
+        let child_number = u32::from_be_bytes(child_number);
+
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&data[13..45]);
+
+        if data[45] != 0 {
+            return Err(XprivDecodeError::InvalidSecretKey);
+        }
+        let private_key = SecretKey::from_slice(&data[46..78])?;
+
+        Ok(Xpriv {
+            testnet,
+            meta: XpubMeta {
+                depth,
+                parent_fp: parent_fp.into(),
+                child_number: child_number.into(),
+            },
+            core: XprivCore {
+                private_key,
+                chain_code: chain_code.into(),
+            },
+        })
+    }
+
+    pub fn encode(&self) -> [u8; 78] {
+        let mut ret = [0; 78];
+        ret[0..4].copy_from_slice(&match self.testnet {
+            false => XPRIV_MAINNET_MAGIC,
+            true => XPRIV_TESTNET_MAGIC,
+        });
+        ret[4] = self.meta.depth;
+        ret[5..9].copy_from_slice(self.meta.parent_fp.as_ref());
+        ret[9..13].copy_from_slice(&self.meta.child_number.index().to_be_bytes());
+        ret[13..45].copy_from_slice(self.core.chain_code.as_ref());
+        ret[46..78].copy_from_slice(&self.core.private_key.secret_bytes());
+        ret
+    }
+
+    #[inline]
+    pub fn is_testnet(&self) -> bool { self.testnet }
+
+    #[inline]
+    pub fn meta(&self) -> XpubMeta { self.meta }
+
+    /// Constructs extended public key matching this extended private key.
+    pub fn to_xpub(&self) -> Xpub {
+        Xpub {
+            testnet: self.testnet,
+            meta: self.meta,
+            core: XpubCore {
+                public_key: PublicKey::from_secret_key(SECP256K1, &self.core.private_key).into(),
+                chain_code: self.core.chain_code,
+            },
+        }
+    }
+
+    /// Returns the HASH160 of the public key corresponding to this private key.
+    pub fn identifier(&self) -> XpubId { self.to_xpub().identifier() }
+
+    pub fn fingerprint(&self) -> XpubFp { self.to_xpub().fingerprint() }
+
+    /// Returns secret key for ECDSA signing.
+    pub fn to_private_ecdsa(&self) -> SecretKey { self.core.private_key }
+
+    /// Returns key pair for BIP340 signing.
+    pub fn to_keypair_bip340(&self) -> Keypair {
+        Keypair::from_secret_key(SECP256K1, &self.core.private_key)
+    }
+
+    /// Derives extended private key using a given derivation path.
+    pub fn derive_priv<I: Into<DerivationIndex>>(&self, path: impl IntoIterator<Item = I>) -> Self {
+        let mut xpriv = *self;
+        for cnum in path {
+            xpriv = xpriv.ckd_priv(cnum)
+        }
+        xpriv
+    }
+
+    /// Private->Private child key derivation
+    ///
+    /// Uses the global secp256k1 context, thus no context is created for each derivation.
+    pub fn ckd_priv(&self, child_no: impl Into<DerivationIndex>) -> Xpriv {
+        let child_no = child_no.into();
+        let mut hmac_engine: HmacEngine<sha512::Hash> =
+            HmacEngine::new(self.core.chain_code.as_ref());
+        if child_no.is_hardened() {
+            hmac_engine.input(&[0u8]);
+            hmac_engine.input(&self.core.private_key.secret_bytes());
+        } else {
+            hmac_engine
+                .input(&PublicKey::from_secret_key(SECP256K1, &self.core.private_key).serialize());
+        }
+        hmac_engine.input(&child_no.index().to_be_bytes());
+        let hmac_result: Hmac<sha512::Hash> = Hmac::from_engine(hmac_engine);
+
+        let tweak = SecretKey::from_slice(&hmac_result[..32]).expect("negligible probability");
+        let private_key =
+            self.core.private_key.add_tweak(&tweak.into()).expect("negligible probability");
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&hmac_result[32..]);
+
+        Xpriv {
+            testnet: self.testnet,
+            meta: XpubMeta {
+                depth: self.meta.depth + 1,
+                parent_fp: self.fingerprint(),
+                child_number: child_no,
+            },
+            core: XprivCore {
+                private_key,
+                chain_code: ChainCode::from_byte_array(chain_code),
+            },
+        }
+    }
+}
+
+impl Display for Xpriv {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        base58::encode_check_to_fmt(f, &self.encode())
+    }
+}
+
+impl FromStr for Xpriv {
+    type Err = XprivParseError;
+
+    fn from_str(inp: &str) -> Result<Xpriv, XprivParseError> {
+        let data = base58::decode_check(inp)?;
+        Ok(Xpriv::decode(data)?)
+    }
+}
+
+/// Extended private key with its origin and the derivation terminal specification; a secret
+/// counterpart of [`XpubDerivable`].
+///
+/// The string representation is the same as for [`XpubDerivable`], with the extended public key
+/// replaced by the extended private key.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct XprivDerivable {
+    xpriv: Xpriv,
+    xpub: XpubDerivable,
+}
+
+impl XprivDerivable {
+    pub fn with(xpriv: Xpriv, origin: XpubOrigin, keychains: DerivationSeg<Keychain>) -> Self {
+        let spec = XpubSpec::new(xpriv.to_xpub(), origin);
+        XprivDerivable {
+            xpriv,
+            xpub: XpubDerivable::with(spec, keychains),
+        }
+    }
+
+    /// Constructs master key derivable with the standard receive and change keychains.
+    pub fn master(xpriv: Xpriv) -> Self {
+        let origin = XpubOrigin::new(xpriv.fingerprint(), DerivationPath::new());
+        let keychains = DerivationSeg::with([Keychain::OUTER, Keychain::INNER])
+            .expect("two keychains are within the segment size limit");
+        XprivDerivable::with(xpriv, origin, keychains)
+    }
+
+    /// Derives child key over hardened `path`, extending the key origin and keeping the keychains.
+    pub fn derive_hardened(&self, path: impl IntoIterator<Item = HardenedIndex>) -> Self {
+        let mut derivation = self.origin().derivation().clone();
+        let mut xpriv = self.xpriv;
+        for index in path {
+            xpriv = xpriv.ckd_priv(index);
+            derivation.push(index);
+        }
+        let origin = XpubOrigin::new(self.origin().master_fp(), derivation);
+        XprivDerivable::with(xpriv, origin, self.xpub.keychains.clone())
+    }
+
+    #[inline]
+    pub fn xpriv(&self) -> Xpriv { self.xpriv }
+
+    #[inline]
+    pub fn origin(&self) -> &XpubOrigin { self.xpub.origin() }
+
+    /// Returns watch-only version of the key, keeping the origin and terminal derivation
+    /// information.
+    #[inline]
+    pub fn to_xpub_derivable(&self) -> XpubDerivable { self.xpub.clone() }
+
+    #[inline]
+    pub fn as_xpub_derivable(&self) -> &XpubDerivable { &self.xpub }
+}
+
+impl Display for XprivDerivable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("[")?;
+        Display::fmt(self.xpub.origin(), f)?;
+        write!(f, "]{}/", self.xpriv)?;
+        if let Some(variant) = self.xpub.variant() {
+            write!(f, "{variant}/")?;
+        }
+        Display::fmt(&self.xpub.keychains, f)?;
+        f.write_str("/*")
+    }
+}
+
+impl FromStr for XprivDerivable {
+    type Err = XprivParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (origin, remains) = s.split_once(']').ok_or(XpubParseError::NoOrigin)?;
+        let (xpriv, terminal) = remains.split_once('/').unwrap_or((remains, ""));
+        let xpriv = Xpriv::from_str(xpriv)?;
+        let xpub = XpubDerivable::from_str(&format!("{origin}]{}/{terminal}", xpriv.to_xpub()))?;
+        Ok(XprivDerivable { xpriv, xpub })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DerivationPath, HardenedIndex};
+
+    // BIP-32 test vector 1
+    #[test]
+    fn bip32_vector1() {
+        let seed = [
+            0x00u8, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+            0x0e, 0x0f,
+        ];
+        let master = Xpriv::new_master(false, &seed);
+        assert_eq!(
+            master.to_string(),
+            "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi"
+        );
+        assert_eq!(
+            master.to_xpub().to_string(),
+            "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8"
+        );
+
+        let path = DerivationPath::<DerivationIndex>::from_str("0h/1/2h/2/1000000000").unwrap();
+        let derived = master.derive_priv(&path);
+        assert_eq!(
+            derived.to_string(),
+            "xprvA41z7zogVVwxVSgdKUHDy1SKmdb533PjDz7J6N6mV6uS3ze1ai8FHa8kmHScGpWmj4WggLyQjgPie1rFSruoUihUZREPSL39UNdE3BBDu76"
+        );
+        assert_eq!(
+            derived.to_xpub().to_string(),
+            "xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy"
+        );
+
+        let hardened = DerivationPath::<HardenedIndex>::from_str("0h").unwrap();
+        assert_eq!(
+            master.derive_priv(&hardened).to_string(),
+            "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7"
+        );
+    }
+
+    #[test]
+    fn decode_encode() {
+        let s = "tprv8ZgxMBicQKsPd9TeAdPADNnSyH9SSUUbTVeFszDE23Ki6TBB5nCefAdHkK8Fm3qMQR6sHwA56zqRmKmxnHk37JkiFzvncDqoKmPWubu7hDF";
+        let xpriv = Xpriv::from_str(s).unwrap();
+        assert!(xpriv.is_testnet());
+        assert_eq!(xpriv.to_string(), s);
+    }
+
+    #[test]
+    fn xpriv_derivable() {
+        let xpriv = "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7";
+        let s = format!("[3442193e/0h]{xpriv}/<0;1>/*");
+        let derivable = XprivDerivable::from_str(&s).unwrap();
+        assert_eq!(derivable.to_string(), s);
+        assert_eq!(derivable.xpriv(), Xpriv::from_str(xpriv).unwrap());
+
+        let xpub = derivable.to_xpub_derivable();
+        assert_eq!(xpub.xpub(), derivable.xpriv().to_xpub());
+        assert_eq!(
+            xpub.to_string(),
+            format!("[3442193e/0h]{}/<0;1>/*", derivable.xpriv().to_xpub())
+        );
+        assert_eq!(xpub.origin(), derivable.origin());
+
+        assert_eq!(
+            XprivDerivable::from_str(&format!("{xpriv}/<0;1>/*")),
+            Err(XprivParseError::Spec(XpubParseError::NoOrigin))
+        );
+    }
+}
@@ -21,6 +21,7 @@
 // limitations under the License.
 
 use std::borrow::Borrow;
+use std::collections::BTreeMap;
 use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 
@@ -37,6 +38,70 @@ use crate::{
 pub const XPUB_MAINNET_MAGIC: [u8; 4] = [0x04u8, 0x88, 0xB2, 0x1E];
 pub const XPUB_TESTNET_MAGIC: [u8; 4] = [0x04u8, 0x35, 0x87, 0xCF];
 
+/// Extended public key version defined by SLIP-132, which indicates the type of the scripts the
+/// key is used with.
+///
+/// The keys are always stored using the canonical BIP-32 version ([`Slip132Type::Bip32`]); other
+/// versions are only recognized during parsing and may be restored when the key is emitted.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default)]
+pub enum Slip132Type {
+    /// `xpub`/`tpub`: P2PKH or scripts defined by the descriptor.
+    #[default]
+    Bip32,
+
+    /// `ypub`/`upub`: P2WPKH nested in P2SH.
+    NestedWpkh,
+
+    /// `zpub`/`vpub`: P2WPKH.
+    Wpkh,
+
+    /// `Ypub`/`Upub`: multi-signature P2WSH nested in P2SH.
+    NestedWshMulti,
+
+    /// `Zpub`/`Vpub`: multi-signature P2WSH.
+    WshMulti,
+}
+
+impl Slip132Type {
+    pub const ALL: [Slip132Type; 5] = [
+        Slip132Type::Bip32,
+        Slip132Type::NestedWpkh,
+        Slip132Type::Wpkh,
+        Slip132Type::NestedWshMulti,
+        Slip132Type::WshMulti,
+    ];
+
+    /// Returns version bytes for the extended public key of this type.
+    pub const fn magic(self, testnet: bool) -> [u8; 4] {
+        match (self, testnet) {
+            (Slip132Type::Bip32, false) => XPUB_MAINNET_MAGIC,
+            (Slip132Type::Bip32, true) => XPUB_TESTNET_MAGIC,
+            (Slip132Type::NestedWpkh, false) => [0x04, 0x9D, 0x7C, 0xB2],
+            (Slip132Type::NestedWpkh, true) => [0x04, 0x4A, 0x52, 0x62],
+            (Slip132Type::Wpkh, false) => [0x04, 0xB2, 0x47, 0x46],
+            (Slip132Type::Wpkh, true) => [0x04, 0x5F, 0x1C, 0xF6],
+            (Slip132Type::NestedWshMulti, false) => [0x02, 0x95, 0xB4, 0x3F],
+            (Slip132Type::NestedWshMulti, true) => [0x02, 0x42, 0x89, 0xEF],
+            (Slip132Type::WshMulti, false) => [0x02, 0xAA, 0x7E, 0xD3],
+            (Slip132Type::WshMulti, true) => [0x02, 0x57, 0x54, 0x83],
+        }
+    }
+
+    /// Detects key type and network (`true` for testnet) from the version bytes.
+    pub fn with_magic(magic: [u8; 4]) -> Option<(Slip132Type, bool)> {
+        Self::ALL.into_iter().find_map(|ty| {
+            [false, true].into_iter().find(|testnet| ty.magic(*testnet) == magic).map(|tn| (ty, tn))
+        })
+    }
+
+    /// Detects the type of Base58-encoded extended public key.
+    pub fn detect(xpub: &str) -> Result<Slip132Type, XpubParseError> {
+        let data = base58::decode_check(xpub)?;
+        let (_, ty) = Xpub::decode_slip132(data)?;
+        Ok(ty)
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
 #[display(doc_comments)]
 pub enum XpubDecodeError {
@@ -52,7 +117,7 @@ pub enum XpubDecodeError {
     InvalidPubkey(InvalidPubkey<33>),
 }
 
-#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[derive(Clone, Eq, PartialEq, Debug, Display, From)]
 pub enum XpubParseError {
     /// wrong Base58 encoding of extended pubkey data - {0}
     #[display(doc_comments)]
@@ -71,8 +136,9 @@ pub enum XpubParseError {
     #[from]
     InvalidMasterFp(hex::Error),
 
-    /// invalid terminal derivation format.
-    InvalidTerminal,
+    #[display(inner)]
+    #[from]
+    KeychainSpec(KeychainSpecError),
 
     /// invalid keychain segment - {0}
     #[from]
@@ -98,6 +164,25 @@ pub enum XpubParseError {
     ParentMismatch,
 }
 
+impl std::error::Error for XpubParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            XpubParseError::Base58(err) => Some(err),
+            XpubParseError::Decode(err) => Some(err),
+            XpubParseError::DerivationPath(err) => Some(err),
+            XpubParseError::InvalidMasterFp(err) => Some(err),
+            XpubParseError::InvalidKeychain(err) => Some(err),
+            XpubParseError::InvalidIndex(err) => Some(err),
+            XpubParseError::KeychainSpec(err) => Some(err),
+            XpubParseError::NoOrigin
+            | XpubParseError::NoXpub
+            | XpubParseError::NetworkMismatch
+            | XpubParseError::DepthMismatch
+            | XpubParseError::ParentMismatch => None,
+        }
+    }
+}
+
 impl From<OriginParseError> for XpubParseError {
     fn from(err: OriginParseError) -> Self {
         match err {
@@ -186,28 +271,31 @@ pub struct XpubMeta {
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct Xpub {
-    testnet: bool,
-    meta: XpubMeta,
-    core: XpubCore,
+    pub(crate) testnet: bool,
+    pub(crate) meta: XpubMeta,
+    pub(crate) core: XpubCore,
 }
 
 impl Xpub {
+    /// Decodes the key from its binary representation, accepting any of SLIP-132 versions; see
+    /// [`Self::decode_slip132`] to get the detected version.
     pub fn decode(data: impl Borrow<[u8]>) -> Result<Xpub, XpubDecodeError> {
+        Self::decode_slip132(data).map(|(xpub, _)| xpub)
+    }
+
+    /// Decodes the key from its binary representation, returning it together with SLIP-132 type
+    /// detected from the version bytes.
+    pub fn decode_slip132(data: impl Borrow<[u8]>) -> Result<(Xpub, Slip132Type), XpubDecodeError> {
         let data = data.borrow();
 
         if data.len() != 78 {
             return Err(XpubDecodeError::WrongExtendedKeyLength(data.len()));
         }
 
-        let testnet = match &data[0..4] {
-            magic if magic == XPUB_MAINNET_MAGIC => false,
-            magic if magic == XPUB_TESTNET_MAGIC => true,
-            unknown => {
-                let mut magic = [0u8; 4];
-                magic.copy_from_slice(unknown);
-                return Err(XpubDecodeError::UnknownKeyType(magic));
-            }
-        };
+        let mut magic = [0u8; 4];
+        magic.copy_from_slice(&data[0..4]);
+        let (ty, testnet) =
+            Slip132Type::with_magic(magic).ok_or(XpubDecodeError::UnknownKeyType(magic))?;
         let depth = data[4];
 
         let mut parent_fp = [0u8; 4];
@@ -222,7 +310,7 @@ impl Xpub {
 
         let public_key = CompressedPk::from_bytes(&data[45..78])?;
 
-        Ok(Xpub {
+        let xpub = Xpub {
             testnet,
             meta: XpubMeta {
                 depth,
@@ -233,15 +321,19 @@ impl Xpub {
                 public_key,
                 chain_code: chain_code.into(),
             },
-        })
+        };
+        Ok((xpub, ty))
     }
 
-    pub fn encode(&self) -> [u8; 78] {
+    pub fn encode(&self) -> [u8; 78] { self.encode_slip132(Slip132Type::Bip32) }
+
+    /// Detects whether the key version bytes are for one of test networks.
+    pub fn is_testnet(&self) -> bool { self.testnet }
+
+    /// Encodes the key using SLIP-132 version bytes of the given type.
+    pub fn encode_slip132(&self, ty: Slip132Type) -> [u8; 78] {
         let mut ret = [0; 78];
-        ret[0..4].copy_from_slice(&match self.testnet {
-            false => XPUB_MAINNET_MAGIC,
-            true => XPUB_TESTNET_MAGIC,
-        });
+        ret[0..4].copy_from_slice(&ty.magic(self.testnet));
         ret[4] = self.meta.depth;
         ret[5..9].copy_from_slice(self.meta.parent_fp.as_ref());
         ret[9..13].copy_from_slice(&self.meta.child_number.index().to_be_bytes());
@@ -302,6 +394,8 @@ impl Xpub {
     }
 
     /// Public->Public child key derivation
+    ///
+    /// Uses the global secp256k1 context, thus no context is created for each derivation.
     pub fn ckd_pub(&self, child_no: NormalIndex) -> Xpub {
         let (scalar, chain_code) = self.ckd_pub_tweak(child_no);
         let tweaked =
@@ -330,6 +424,13 @@ impl Display for Xpub {
     }
 }
 
+impl Xpub {
+    /// Returns Base58 representation of the key using SLIP-132 version bytes of the given type.
+    pub fn to_slip132_string(&self, ty: Slip132Type) -> String {
+        base58::encode_check(&self.encode_slip132(ty))
+    }
+}
+
 impl FromStr for Xpub {
     type Err = XpubParseError;
 
@@ -389,8 +490,12 @@ pub enum OriginParseError {
     InvalidMasterFp(hex::Error),
 }
 
-#[derive(Getters, Clone, Eq, PartialEq, Hash, Debug, Display)]
-#[display("{master_fp}{derivation}", alt = "{master_fp}{derivation:#}")]
+/// Origin of a key: fingerprint of the master key and the full derivation path from it.
+///
+/// The string representation uses the bracketed form from BIP-380 (`[d34db33f/84h/0h/0h]`);
+/// the alternative formatting (`{:#}`) uses `'` for the hardened derivation steps. Parsing accepts
+/// both markers, as well as the origin without the brackets.
+#[derive(Getters, Clone, Eq, PartialEq, Hash, Debug)]
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
@@ -402,22 +507,50 @@ pub struct KeyOrigin {
     derivation: DerivationPath,
 }
 
+impl Display for KeyOrigin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}", self.master_fp)?;
+        Display::fmt(&self.derivation, f)?;
+        f.write_str("]")
+    }
+}
+
 impl FromStr for KeyOrigin {
     type Err = XpubParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = match s.strip_prefix('[') {
+            Some(s) => s.strip_suffix(']').ok_or(XpubParseError::NoOrigin)?,
+            None => s,
+        };
         let (master_fp, path) = match s.split_once('/') {
-            None => (XpubFp::default(), ""),
-            Some(("00000000", p)) | Some(("m", p)) => (XpubFp::default(), p),
-            Some((fp, p)) => (XpubFp::from_str(fp)?, p),
+            None => (s, ""),
+            Some((fp, path)) => (fp, path),
+        };
+        let master_fp = match master_fp {
+            "m" => XpubFp::default(),
+            fp => XpubFp::from_str(fp)?,
         };
+        let derivation =
+            if path.is_empty() { DerivationPath::new() } else { DerivationPath::from_str(path)? };
         Ok(KeyOrigin {
             master_fp,
-            derivation: DerivationPath::from_str(path)?,
+            derivation,
         })
     }
 }
 
+/// Empty origin with zero master key fingerprint and no derivation, used for the single keys
+/// which are not derived from an extended key.
+impl Default for KeyOrigin {
+    fn default() -> Self {
+        KeyOrigin {
+            master_fp: XpubFp::default(),
+            derivation: DerivationPath::new(),
+        }
+    }
+}
+
 impl KeyOrigin {
     pub fn new(master_fp: XpubFp, derivation: DerivationPath) -> Self {
         KeyOrigin {
@@ -426,11 +559,18 @@ impl KeyOrigin {
         }
     }
 
-    pub fn with(xpub_origin: XpubOrigin, terminal: Terminal) -> Self {
-        let mut derivation = DerivationPath::new();
+    /// Constructs origin of a key derived at the terminal from an extended key with the given
+    /// origin.
+    ///
+    /// The extended key origin may be passed by reference, which avoids cloning it when keysets
+    /// are built for many terminals.
+    pub fn with(xpub_origin: impl Borrow<XpubOrigin>, terminal: Terminal) -> Self {
+        let xpub_origin = xpub_origin.borrow();
+        let mut derivation = Vec::with_capacity(xpub_origin.derivation().len() + 2);
         derivation.extend(xpub_origin.derivation().iter().copied().map(DerivationIndex::from));
         derivation.push(terminal.keychain.into());
         derivation.push(DerivationIndex::Normal(terminal.index));
+        let derivation = DerivationPath::from(derivation);
         KeyOrigin {
             master_fp: xpub_origin.master_fp(),
             derivation,
@@ -438,6 +578,16 @@ impl KeyOrigin {
     }
 }
 
+impl From<XpubOrigin> for KeyOrigin {
+    fn from(xpub_origin: XpubOrigin) -> Self {
+        let derivation = xpub_origin.derivation().iter().copied().map(DerivationIndex::from);
+        KeyOrigin {
+            master_fp: xpub_origin.master_fp(),
+            derivation: derivation.collect(),
+        }
+    }
+}
+
 #[derive(Getters, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct XpubSpec {
     origin: XpubOrigin,
@@ -446,6 +596,31 @@ pub struct XpubSpec {
 
 impl XpubSpec {
     pub fn new(xpub: Xpub, origin: XpubOrigin) -> Self { XpubSpec { xpub, origin } }
+
+    /// Returns BIP-43 purpose, i.e. the first step of the origin derivation path, if present.
+    #[inline]
+    pub fn purpose(&self) -> Option<HardenedIndex> { self.origin.derivation.first().copied() }
+
+    /// Returns SLIP-44 coin type, i.e. the second step of the origin derivation path, if present.
+    #[inline]
+    pub fn coin_type(&self) -> Option<HardenedIndex> { self.origin.derivation.get(1).copied() }
+
+    /// Returns account number, i.e. the third step of the origin derivation path, if present.
+    #[inline]
+    pub fn account(&self) -> Option<HardenedIndex> { self.origin.derivation.get(2).copied() }
+
+    /// Checks whether the key is an account-level key of BIP-44, BIP-49, BIP-84 or BIP-86
+    /// standards: its origin is `purpose'/coin_type'/account'` with one of these purposes and
+    /// the coin type matching the network of the extended public key.
+    pub fn is_standard_account(&self) -> bool {
+        let Some(purpose) = self.purpose() else {
+            return false;
+        };
+        let coin_type = HardenedIndex::from(self.xpub.testnet as u8);
+        self.origin.derivation.len() == 3
+            && [44u8, 49, 84, 86].iter().any(|no| purpose == *no)
+            && self.coin_type() == Some(coin_type)
+    }
 }
 
 impl Display for XpubSpec {
@@ -484,20 +659,82 @@ impl FromStr for XpubSpec {
             }
         }
 
-        Ok(XpubSpec { origin, xpub })
+        Ok(XpubSpec::new(xpub, origin))
     }
 }
 
+/// Extended public key with the keychain and index derivation steps.
+///
+/// Extended keys for each of the keychains are derived once, when the key is constructed, so the
+/// derivation of a key for a terminal requires just a single non-hardened derivation step.
 #[derive(Getters, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct XpubDerivable {
     spec: XpubSpec,
     variant: Option<NormalIndex>,
     pub(crate) keychains: DerivationSeg<Keychain>,
+    /// Cache of the extended keys derived for each of the keychains from the `spec` key.
+    #[getter(skip)]
+    keychain_xpubs: BTreeMap<Keychain, Xpub>,
 }
 
 impl XpubDerivable {
+    fn new(
+        spec: XpubSpec,
+        variant: Option<NormalIndex>,
+        keychains: DerivationSeg<Keychain>,
+    ) -> Self {
+        let keychain_xpubs = keychains
+            .to_set()
+            .into_iter()
+            .map(|keychain| (keychain, spec.xpub.ckd_pub(keychain.into())))
+            .collect();
+        XpubDerivable {
+            spec,
+            variant,
+            keychains,
+            keychain_xpubs,
+        }
+    }
+
+    /// Constructs derivable extended public key without a variant derivation step.
+    pub fn with(spec: XpubSpec, keychains: DerivationSeg<Keychain>) -> Self {
+        XpubDerivable::new(spec, None, keychains)
+    }
+
+    /// Constructs derivable extended public key with a variant derivation step preceding the
+    /// keychain segment.
+    pub fn with_variant(
+        spec: XpubSpec,
+        variant: NormalIndex,
+        keychains: DerivationSeg<Keychain>,
+    ) -> Self {
+        XpubDerivable::new(spec, Some(variant), keychains)
+    }
+
+    /// Constructs derivable extended public key from the derivation steps following the key.
+    pub fn with_keychain_spec(spec: XpubSpec, keychain_spec: KeychainSpec) -> Self {
+        XpubDerivable::new(spec, keychain_spec.variant, keychain_spec.keychains)
+    }
+
+    /// Returns derivation steps following the extended key.
+    pub fn keychain_spec(&self) -> KeychainSpec {
+        KeychainSpec {
+            variant: self.variant,
+            keychains: self.keychains.clone(),
+        }
+    }
+
     pub fn xpub(&self) -> Xpub { self.spec.xpub }
 
+    /// Returns extended key for the keychain, which is taken from the cache for the keychains of
+    /// the key and derived otherwise.
+    pub(crate) fn keychain_xpub(&self, keychain: Keychain) -> Xpub {
+        match self.keychain_xpubs.get(&keychain) {
+            Some(xpub) => *xpub,
+            None => self.spec.xpub.ckd_pub(keychain.into()),
+        }
+    }
+
     pub fn origin(&self) -> &XpubOrigin { &self.spec.origin }
 }
 
@@ -512,6 +749,19 @@ impl Display for XpubDerivable {
     }
 }
 
+impl XpubDerivable {
+    /// Returns string representation of the key, where the extended public key uses SLIP-132
+    /// version bytes of the given type.
+    pub fn to_slip132_string(&self, ty: Slip132Type) -> String {
+        let mut s = format!("[{}]{}/", self.spec.origin, self.spec.xpub.to_slip132_string(ty));
+        if let Some(variant) = self.variant {
+            s.push_str(&format!("{variant}/"));
+        }
+        s.push_str(&format!("{}/*", self.keychains));
+        s
+    }
+}
+
 impl FromStr for XpubDerivable {
     type Err = XpubParseError;
 
@@ -523,28 +773,123 @@ impl FromStr for XpubDerivable {
             s.trim_start_matches('[').split_once(']').ok_or(XpubParseError::NoOrigin)?;
 
         let origin = XpubOrigin::from_str(origin)?;
-        let mut segs = remains.split('/');
-        let Some(xpub) = segs.next() else {
-            return Err(XpubParseError::NoXpub);
+        let (xpub, keychain_spec) = match remains.find('/') {
+            Some(pos) => remains.split_at(pos),
+            None => (remains, ""),
         };
+        if xpub.is_empty() {
+            return Err(XpubParseError::NoXpub);
+        }
         let xpub = Xpub::from_str(xpub)?;
+        // Extended key exported at a different level than the origin claims can't be used for
+        // signing, since the signers will derive different keys
+        if origin.derivation.len() != xpub.meta.depth as usize {
+            return Err(XpubParseError::DepthMismatch);
+        }
+        let keychain_spec = KeychainSpec::from_str(keychain_spec)?;
 
-        let (variant, keychains) = match (segs.next(), segs.next(), segs.next(), segs.next()) {
-            (Some(var), Some(keychains), Some("*"), None) => {
-                (Some(var.parse()?), keychains.parse()?)
+        Ok(XpubDerivable::with_keychain_spec(XpubSpec::new(xpub, origin), keychain_spec))
+    }
+}
+
+/// Errors parsing [`KeychainSpec`].
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum KeychainSpecError {
+    /// keychain spec '{0}' must be in form of `/KEYCHAINS/*` or `/VARIANT/KEYCHAINS/*`.
+    InvalidFormat(String),
+
+    /// keychain spec '{0}' doesn't end with the index wildcard `*`.
+    NoWildcard(String),
+
+    /// hardened wildcard '{0}' can't be used for derivation from an extended public key.
+    HardenedWildcard(String),
+
+    /// hardened keychain '{0}' can't be used for derivation from an extended public key.
+    HardenedKeychain(String),
+
+    /// malformed keychain set '{0}': keychains must be given either as a single number or as
+    /// `<A;B;...>` list in ascending order, without duplicates and leading zeros.
+    InvalidKeychains(String),
+
+    /// invalid variant derivation step '{0}'.
+    InvalidVariant(String),
+}
+
+/// Derivation steps of [`XpubDerivable`] following the extended key: an optional variant step,
+/// the keychain segment and the index wildcard, like `/<0;1>/*`, `/0/*` or `/5/<0;1>/*`.
+///
+/// Accepts only the canonical representation, such that parsing and displaying the spec always
+/// gives the original string. The [`XpubDerivable`] parser uses it for the part following the
+/// extended key.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct KeychainSpec {
+    pub variant: Option<NormalIndex>,
+    pub keychains: DerivationSeg<Keychain>,
+}
+
+impl Display for KeychainSpec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(variant) = self.variant {
+            write!(f, "/{variant}")?;
+        }
+        write!(f, "/{}/*", self.keychains)
+    }
+}
+
+impl FromStr for KeychainSpec {
+    type Err = KeychainSpecError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn is_hardened(seg: &str) -> bool { seg.ends_with(['h', 'H', '\'']) }
+
+        let segs = s
+            .strip_prefix('/')
+            .ok_or_else(|| KeychainSpecError::InvalidFormat(s.to_owned()))?
+            .split('/')
+            .collect::<Vec<_>>();
+        match segs.last().copied() {
+            Some("*") => {}
+            Some(wildcard) if wildcard.starts_with('*') && is_hardened(wildcard) => {
+                return Err(KeychainSpecError::HardenedWildcard(wildcard.to_owned()))
             }
-            (Some(keychains), Some("*"), None, None) => (None, keychains.parse()?),
-            _ => return Err(XpubParseError::InvalidTerminal),
+            _ => return Err(KeychainSpecError::NoWildcard(s.to_owned())),
+        }
+        let (variant, keychains) = match segs[..] {
+            [keychains, _] => (None, keychains),
+            [variant, keychains, _] => (Some(variant), keychains),
+            _ => return Err(KeychainSpecError::InvalidFormat(s.to_owned())),
         };
 
-        Ok(XpubDerivable {
-            spec: XpubSpec::new(xpub, origin),
-            variant,
-            keychains,
-        })
+        if keychains.trim_start_matches('<').trim_end_matches('>').split(';').any(is_hardened) {
+            return Err(KeychainSpecError::HardenedKeychain(keychains.to_owned()));
+        }
+        let keychains = keychains
+            .parse::<DerivationSeg<Keychain>>()
+            .ok()
+            .filter(|seg| seg.to_string() == keychains)
+            .ok_or_else(|| KeychainSpecError::InvalidKeychains(keychains.to_owned()))?;
+
+        let variant = variant
+            .map(|variant| {
+                variant
+                    .parse::<NormalIndex>()
+                    .ok()
+                    .filter(|index| index.to_string() == variant)
+                    .ok_or_else(|| KeychainSpecError::InvalidVariant(variant.to_owned()))
+            })
+            .transpose()?;
+
+        Ok(KeychainSpec { variant, keychains })
     }
 }
 
+impl TryFrom<&str> for KeychainSpec {
+    type Error = KeychainSpecError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> { KeychainSpec::from_str(s) }
+}
+
 #[cfg(feature = "serde")]
 mod _serde {
     use serde_crate::{de, Deserialize, Deserializer, Serialize, Serializer};
@@ -618,6 +963,7 @@ mod _serde {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::Derive;
 
     #[test]
     fn test_xpub_derivable_from_str_with_hardened_index() {
@@ -633,10 +979,191 @@ mod test {
         assert_eq!(s, format!("{xpub:#}"));
     }
 
+    #[test]
+    fn hardened_terminal() {
+        let s = "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2";
+        assert_eq!(
+            XpubDerivable::from_str(&format!("{s}/0/*h")),
+            Err(KeychainSpecError::HardenedWildcard("*h".to_owned()).into())
+        );
+        assert_eq!(
+            XpubDerivable::from_str(&format!("{s}/<0;1>/*'")),
+            Err(KeychainSpecError::HardenedWildcard("*'".to_owned()).into())
+        );
+        assert_eq!(
+            XpubDerivable::from_str(&format!("{s}/<0;1h>/*")),
+            Err(KeychainSpecError::HardenedKeychain("<0;1h>".to_owned()).into())
+        );
+        assert_eq!(
+            XpubDerivable::from_str(&format!("{s}/0h/*")),
+            Err(KeychainSpecError::HardenedKeychain("0h".to_owned()).into())
+        );
+        assert_eq!(
+            XpubDerivable::from_str(&format!("{s}/0")),
+            Err(KeychainSpecError::NoWildcard("/0".to_owned()).into())
+        );
+        assert_eq!(
+            XpubDerivable::from_str(s),
+            Err(KeychainSpecError::InvalidFormat(String::new()).into())
+        );
+        assert_eq!(
+            XpubDerivable::from_str(&format!("{s}/<1;0>/*")),
+            Err(KeychainSpecError::InvalidKeychains("<1;0>".to_owned()).into())
+        );
+        assert!(XpubDerivable::from_str(&format!("{s}/0/*")).is_ok());
+    }
+
+    #[test]
+    fn keychain_spec() {
+        for s in ["/<0;1>/*", "/0/*", "/5/<0;1;9>/*", "/10/*"] {
+            let spec = KeychainSpec::try_from(s).unwrap();
+            assert_eq!(spec.to_string(), s);
+        }
+
+        let s = "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2";
+        let keychain_spec = KeychainSpec::from_str("/5/<0;1>/*").unwrap();
+        let xpub = XpubDerivable::with_keychain_spec(
+            XpubSpec::from_str(s).unwrap(),
+            keychain_spec.clone(),
+        );
+        assert_eq!(xpub.to_string(), format!("{s}/5/<0;1>/*"));
+        assert_eq!(xpub.keychain_spec(), keychain_spec);
+
+        for (s, err) in [
+            ("<0;1>/*", KeychainSpecError::InvalidFormat("<0;1>/*".to_owned())),
+            ("/*", KeychainSpecError::InvalidFormat("/*".to_owned())),
+            ("/1/2/0/*", KeychainSpecError::InvalidFormat("/1/2/0/*".to_owned())),
+            ("/0", KeychainSpecError::NoWildcard("/0".to_owned())),
+            ("/<0;1>/5", KeychainSpecError::NoWildcard("/<0;1>/5".to_owned())),
+            ("/<0;1>/*/", KeychainSpecError::NoWildcard("/<0;1>/*/".to_owned())),
+            ("/0/*h", KeychainSpecError::HardenedWildcard("*h".to_owned())),
+            ("/<0;1>/*'", KeychainSpecError::HardenedWildcard("*'".to_owned())),
+            ("/<0;1h>/*", KeychainSpecError::HardenedKeychain("<0;1h>".to_owned())),
+            ("/<1;0>/*", KeychainSpecError::InvalidKeychains("<1;0>".to_owned())),
+            ("/<0;0>/*", KeychainSpecError::InvalidKeychains("<0;0>".to_owned())),
+            ("/<0;1/*", KeychainSpecError::InvalidKeychains("<0;1".to_owned())),
+            ("/01/*", KeychainSpecError::InvalidKeychains("01".to_owned())),
+            ("/<>/*", KeychainSpecError::InvalidKeychains("<>".to_owned())),
+            ("/x/0/*", KeychainSpecError::InvalidVariant("x".to_owned())),
+            ("/1h/0/*", KeychainSpecError::InvalidVariant("1h".to_owned())),
+        ] {
+            assert_eq!(KeychainSpec::from_str(s), Err(err), "{s}");
+        }
+    }
+
+    #[test]
+    fn keychain_cache() {
+        let s = "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*";
+        let xpub = XpubDerivable::from_str(s).unwrap();
+        assert_eq!(xpub.keychain_xpubs.len(), 2);
+        for keychain in [Keychain::OUTER, Keychain::INNER, Keychain::from(2u8)] {
+            let expected = xpub.xpub().derive_pub([NormalIndex::from(keychain)]);
+            assert_eq!(xpub.keychain_xpub(keychain), expected);
+            let index = NormalIndex::from(7u8);
+            assert_eq!(
+                Derive::<CompressedPk>::derive(&xpub, keychain, index),
+                expected.ckd_pub(index).to_compr_pub()
+            );
+        }
+        assert_eq!(XpubDerivable::from_str(&xpub.to_string()).unwrap(), xpub);
+    }
+
+    #[test]
+    fn account_path() {
+        let tpub = "tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2";
+        let spec = XpubSpec::from_str(&format!("[643a7adc/86h/1h/0h]{tpub}")).unwrap();
+        assert_eq!(spec.purpose(), Some(HardenedIndex::hardened(86)));
+        assert_eq!(spec.coin_type(), Some(HardenedIndex::hardened(1)));
+        assert_eq!(spec.account(), Some(HardenedIndex::hardened(0)));
+        assert!(spec.is_standard_account());
+
+        let xpub = Xpub::from_str(tpub).unwrap();
+        let spec = XpubSpec::new(xpub, XpubOrigin::from_str("643a7adc/86h").unwrap());
+        assert_eq!(spec.purpose(), Some(HardenedIndex::hardened(86)));
+        assert_eq!(spec.coin_type(), None);
+        assert_eq!(spec.account(), None);
+        assert!(!spec.is_standard_account());
+
+        for origin in ["643a7adc/87h/1h/0h", "643a7adc/86h/0h/0h", "643a7adc/48h/1h/0h/2h"] {
+            let spec = XpubSpec::new(xpub, XpubOrigin::from_str(origin).unwrap());
+            assert!(!spec.is_standard_account());
+        }
+    }
+
+    #[test]
+    fn key_origin() {
+        let origin = KeyOrigin::from_str("[d34db33f/84'/0'/0'/1/5]").unwrap();
+        assert_eq!(origin.master_fp(), XpubFp::from_str("d34db33f").unwrap());
+        assert_eq!(origin.derivation().len(), 5);
+        assert_eq!(origin.to_string(), "[d34db33f/84h/0h/0h/1/5]");
+        assert_eq!(format!("{origin:#}"), "[d34db33f/84'/0'/0'/1/5]");
+        assert_eq!(KeyOrigin::from_str(&origin.to_string()), Ok(origin.clone()));
+        assert_eq!(KeyOrigin::from_str("d34db33f/84h/0h/0h/1/5"), Ok(origin));
+
+        let master = KeyOrigin::from_str("[d34db33f]").unwrap();
+        assert!(master.derivation().is_empty());
+        assert_eq!(master.to_string(), "[d34db33f]");
+
+        assert!(KeyOrigin::from_str("[d34db33f/84h").is_err());
+        assert!(KeyOrigin::from_str("[d34db3/84h]").is_err());
+
+        let xpub_origin = XpubOrigin::from_str("d34db33f/84h/0h/0h").unwrap();
+        let terminal = Terminal::new(1u8, NormalIndex::normal(5));
+        let derived = KeyOrigin::with(&xpub_origin, terminal);
+        assert_eq!(derived, KeyOrigin::from_str("[d34db33f/84h/0h/0h/1/5]").unwrap());
+        assert_eq!(derived, KeyOrigin::with(xpub_origin, terminal));
+    }
+
     #[test]
     fn test_xpub_derivable_from_str_with_normal_index_rgb_keychain() {
         let s = "[643a7adc/86'/1'/0']tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1;9;10>/*";
         let xpub = XpubDerivable::from_str(s).unwrap();
         assert_eq!(s, format!("{xpub:#}"));
     }
+
+    #[test]
+    fn xpub_derivable_depth() {
+        let tpub = "tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2";
+        assert!(XpubDerivable::from_str(&format!("[643a7adc/86h/1h/0h]{tpub}/<0;1>/*")).is_ok());
+        for origin in ["643a7adc/86h/1h", "643a7adc/86h/1h/0h/0h"] {
+            assert_eq!(
+                XpubDerivable::from_str(&format!("[{origin}]{tpub}/<0;1>/*")),
+                Err(XpubParseError::DepthMismatch)
+            );
+        }
+    }
+
+    #[test]
+    fn slip132() {
+        let zpub = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+        assert_eq!(Slip132Type::detect(zpub).unwrap(), Slip132Type::Wpkh);
+        let xpub = Xpub::from_str(zpub).unwrap();
+        assert!(!xpub.testnet);
+        assert!(xpub.to_string().starts_with("xpub"));
+        assert_eq!(xpub.to_slip132_string(Slip132Type::Wpkh), zpub);
+        assert_eq!(Xpub::from_str(&xpub.to_string()).unwrap(), xpub);
+        assert_eq!(
+            Xpub::decode_slip132(xpub.encode_slip132(Slip132Type::Wpkh)),
+            Ok((xpub, Slip132Type::Wpkh))
+        );
+        assert_eq!(Xpub::decode_slip132(xpub.encode()), Ok((xpub, Slip132Type::Bip32)));
+
+        let tpub = "tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2";
+        let testnet = Xpub::from_str(tpub).unwrap();
+        let vpub = testnet.to_slip132_string(Slip132Type::Wpkh);
+        assert!(vpub.starts_with("vpub"));
+        assert!(testnet.to_slip132_string(Slip132Type::NestedWpkh).starts_with("upub"));
+        assert!(testnet.to_slip132_string(Slip132Type::WshMulti).starts_with("Vpub"));
+
+        let s = format!("[643a7adc/84h/1h/0h]{vpub}/<0;1>/*");
+        let derivable = XpubDerivable::from_str(&s).unwrap();
+        assert_eq!(derivable.xpub(), testnet);
+        assert_eq!(derivable.to_string(), s.replace(&vpub, tpub));
+        assert_eq!(derivable.to_slip132_string(Slip132Type::Wpkh), s);
+        assert_eq!(derivable.to_slip132_string(Slip132Type::Bip32), derivable.to_string());
+
+        let s = format!("[643a7adc/84h/1h/0h]{vpub}/7/<0;1>/*");
+        let derivable = XpubDerivable::from_str(&s).unwrap();
+        assert_eq!(derivable.to_slip132_string(Slip132Type::Wpkh), s);
+    }
 }
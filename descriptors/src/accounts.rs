@@ -0,0 +1,226 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Wallet combining several BIP-44 accounts, each represented by its own descriptor.
+
+use std::collections::BTreeMap;
+
+use derive::{
+    Address, DeriveCompr, DeriveSet, DeriveXOnly, HardenedIndex, Keychain, Network, NormalIndex,
+    Outpoint, Sats, XpubDerivable,
+};
+
+use crate::{Descriptor, NetworkMismatch, StdDescr, WalletUtxo};
+
+/// wallet has no account with index {0}.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub struct UnknownAccount(pub HardenedIndex);
+
+/// Wallet made of multiple accounts bound to the same network.
+///
+/// Each account is identified by its (hardened) BIP-44 account index and is described by a
+/// standard descriptor. The wallet keeps track of the unspent outputs of each account, allowing
+/// to compute both per-account and aggregated balances.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(
+        crate = "serde_crate",
+        rename_all = "camelCase",
+        bound(serialize = "K: serde::Serialize", deserialize = "K: serde::Deserialize<'de>")
+    )
+)]
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct MultiAccountWallet<K = XpubDerivable>
+where K: DeriveSet<Compr = K, XOnly = K> + DeriveCompr + DeriveXOnly
+{
+    network: Network,
+    accounts: BTreeMap<HardenedIndex, StdDescr<K>>,
+    utxos: BTreeMap<HardenedIndex, BTreeMap<Outpoint, WalletUtxo>>,
+}
+
+impl<K> MultiAccountWallet<K>
+where K: DeriveSet<Compr = K, XOnly = K> + DeriveCompr + DeriveXOnly
+{
+    /// Constructs a wallet without any accounts.
+    pub fn new(network: Network) -> Self {
+        MultiAccountWallet {
+            network,
+            accounts: empty!(),
+            utxos: empty!(),
+        }
+    }
+
+    #[inline]
+    pub fn network(&self) -> Network { self.network }
+
+    /// Adds an account descriptor, failing if its extended keys do not belong to the wallet
+    /// network. Returns the descriptor previously used by the account, if any; the unspent
+    /// outputs of the account are kept.
+    pub fn add_account(
+        &mut self,
+        account: impl Into<HardenedIndex>,
+        descriptor: StdDescr<K>,
+    ) -> Result<Option<StdDescr<K>>, NetworkMismatch> {
+        descriptor.check_network(self.network)?;
+        Ok(self.accounts.insert(account.into(), descriptor))
+    }
+
+    /// Removes an account together with all its unspent outputs.
+    pub fn remove_account(&mut self, account: impl Into<HardenedIndex>) -> Option<StdDescr<K>> {
+        let account = account.into();
+        self.utxos.remove(&account);
+        self.accounts.remove(&account)
+    }
+
+    /// Returns the descriptor of an account, if the account is known.
+    pub fn account(&self, account: impl Into<HardenedIndex>) -> Option<&StdDescr<K>> {
+        self.accounts.get(&account.into())
+    }
+
+    /// Iterates over all wallet accounts in the order of their indexes.
+    pub fn accounts(&self) -> impl Iterator<Item = (HardenedIndex, &StdDescr<K>)> {
+        self.accounts.iter().map(|(account, descr)| (*account, descr))
+    }
+
+    /// Derives address of an account at the given keychain and index. Returns `None` if the
+    /// account is unknown or the derived script pubkey can't be represented as an address.
+    pub fn address_at(
+        &self,
+        account: impl Into<HardenedIndex>,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+    ) -> Option<Address> {
+        self.account(account)?.address_at(keychain, index, self.network)
+    }
+
+    /// Registers unspent output of an account, returning a previously known output with the
+    /// same outpoint, if any. Errors without adding the output if the account is unknown.
+    pub fn add_utxo(
+        &mut self,
+        account: impl Into<HardenedIndex>,
+        utxo: WalletUtxo,
+    ) -> Result<Option<WalletUtxo>, UnknownAccount> {
+        let account = account.into();
+        if !self.accounts.contains_key(&account) {
+            return Err(UnknownAccount(account));
+        }
+        Ok(self.utxos.entry(account).or_default().insert(utxo.outpoint, utxo))
+    }
+
+    /// Removes a spent output from whichever account it belongs to, returning it if it was known.
+    pub fn remove_utxo(&mut self, outpoint: Outpoint) -> Option<WalletUtxo> {
+        self.utxos.values_mut().find_map(|utxos| utxos.remove(&outpoint))
+    }
+
+    /// Iterates over the unspent outputs of an account.
+    pub fn utxos(&self, account: impl Into<HardenedIndex>) -> impl Iterator<Item = &WalletUtxo> {
+        self.utxos.get(&account.into()).into_iter().flat_map(BTreeMap::values)
+    }
+
+    /// Sum of the unspent outputs of an account.
+    pub fn account_balance(&self, account: impl Into<HardenedIndex>) -> Sats {
+        self.utxos(account).map(|utxo| utxo.value).sum()
+    }
+
+    /// Sum of the unspent outputs across all wallet accounts.
+    pub fn balance(&self) -> Sats {
+        self.utxos.values().flat_map(BTreeMap::values).map(|utxo| utxo.value).sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use derive::{Idx, Terminal, Txid, Vout};
+
+    use super::*;
+    use crate::{TrKey, Wpkh};
+
+    const XPUB: &str = "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*";
+
+    fn utxo(vout: u32, value: u64) -> WalletUtxo {
+        WalletUtxo {
+            outpoint: Outpoint::new(Txid::from([0xA5; 32]), Vout::from_u32(vout)),
+            terminal: Terminal::new(Keychain::OUTER, NormalIndex::ZERO),
+            value: Sats(value),
+        }
+    }
+
+    #[test]
+    fn accounts() {
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let tr = StdDescr::<XpubDerivable>::from(TrKey::from(xpub.clone()));
+        let wpkh = StdDescr::<XpubDerivable>::from(Wpkh::from(xpub));
+
+        let mut wallet = MultiAccountWallet::new(Network::Mainnet);
+        assert_eq!(
+            wallet.add_account(0u8, tr.clone()),
+            Err(NetworkMismatch::Network(Network::Mainnet))
+        );
+
+        let mut wallet = MultiAccountWallet::new(Network::Testnet3);
+        assert_eq!(wallet.add_account(0u8, tr.clone()), Ok(None));
+        assert_eq!(wallet.add_account(1u8, wpkh.clone()), Ok(None));
+        assert_eq!(wallet.accounts().count(), 2);
+        assert_eq!(wallet.account(1u8), Some(&wpkh));
+        assert_eq!(wallet.address_at(0u8, 1u8, 3u8), tr.address_at(1u8, 3u8, Network::Testnet3));
+        assert_eq!(wallet.address_at(1u8, 0u8, 0u8), wpkh.first_address(Network::Testnet3));
+        assert_eq!(wallet.address_at(2u8, 0u8, 0u8), None);
+
+        assert_eq!(wallet.add_utxo(0u8, utxo(0, 1000)), Ok(None));
+        assert_eq!(wallet.add_utxo(1u8, utxo(1, 500)), Ok(None));
+        assert_eq!(wallet.add_utxo(1u8, utxo(2, 250)), Ok(None));
+        assert_eq!(wallet.add_utxo(1u8, utxo(2, 250)), Ok(Some(utxo(2, 250))));
+        assert_eq!(
+            wallet.add_utxo(2u8, utxo(3, 100)),
+            Err(UnknownAccount(HardenedIndex::from(2u8)))
+        );
+        assert_eq!(wallet.account_balance(0u8), Sats(1000));
+        assert_eq!(wallet.account_balance(1u8), Sats(750));
+        assert_eq!(wallet.balance(), Sats(1750));
+
+        assert_eq!(wallet.remove_utxo(utxo(2, 250).outpoint), Some(utxo(2, 250)));
+        assert_eq!(wallet.balance(), Sats(1500));
+        assert_eq!(wallet.remove_account(0u8), Some(tr));
+        assert_eq!(wallet.balance(), Sats(500));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_persistence() {
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let mut wallet = MultiAccountWallet::new(Network::Testnet3);
+        wallet.add_account(0u8, StdDescr::from(TrKey::from(xpub.clone()))).unwrap();
+        wallet.add_account(1u8, StdDescr::from(Wpkh::from(xpub))).unwrap();
+        wallet.add_utxo(0u8, utxo(0, 1000)).unwrap();
+        wallet.add_utxo(1u8, utxo(1, 500)).unwrap();
+
+        let json = serde_json::to_string(&wallet).unwrap();
+        let restored = serde_json::from_str::<MultiAccountWallet>(&json).unwrap();
+        assert_eq!(restored, wallet);
+        assert_eq!(restored.balance(), Sats(1500));
+        assert_eq!(restored.address_at(1u8, 0u8, 0u8), wallet.address_at(1u8, 0u8, 0u8));
+    }
+}
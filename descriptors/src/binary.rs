@@ -0,0 +1,408 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compact binary encoding of descriptors, used for storing them in binary wallet files.
+//!
+//! Each descriptor is encoded as a single-byte type tag followed by the encoding of its keys (and
+//! the merkle root for key-path-only taproot descriptors committing to a script tree).
+//! Extended keys are encoded as the master key fingerprint, the origin derivation path (a
+//! single-byte length followed by big-endian child numbers), the 78-byte BIP32 serialization of
+//! the key, an optional variant derivation index and the set of keychains. Keys with origins
+//! longer than 255 derivation steps can't be encoded.
+
+use std::borrow::Borrow;
+
+use amplify::Wrapper;
+use derive::{
+    CompressedPk, DerivationPath, DerivationSeg, Derive, DeriveCompr, DeriveSet, DeriveXOnly,
+    HardenedIndex, Idx, IdxBase, Keychain, NormalIndex, TapNodeHash, XOnlyPk, Xpub,
+    XpubDecodeError, XpubDerivable, XpubOrigin, XpubSpec,
+};
+
+use crate::{MultiError, StdDescr, TrKey, TrMultiA, Wpkh};
+
+pub(crate) const TAG_WPKH: u8 = 0x01;
+pub(crate) const TAG_TR_KEY: u8 = 0x02;
+pub(crate) const TAG_TR_MULTI_A: u8 = 0x03;
+pub(crate) const TAG_TR_KEY_ROOT: u8 = 0x04;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum BinaryEncodeError {
+    /// key origin derivation path has {0} steps, while at most 255 steps can be encoded.
+    OriginTooLong(usize),
+
+    /// extended key uses {0} keychains, while at most 255 keychains can be encoded.
+    TooManyKeychains(usize),
+
+    /// descriptor has {0} script keys, while at most 65535 keys can be encoded.
+    TooManyKeys(usize),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum BinaryDecodeError {
+    /// unexpected end of the descriptor binary data.
+    UnexpectedEnd,
+
+    /// descriptor binary data contains {0} extra bytes after the end of the descriptor.
+    DataNotConsumed(usize),
+
+    /// unknown descriptor type tag {0:#04x}.
+    UnknownTag(u8),
+
+    /// descriptor binary data contains invalid derivation index {0:#010x}.
+    InvalidIndex(u32),
+
+    /// descriptor binary data contains invalid optional value flag {0:#04x}.
+    InvalidFlag(u8),
+
+    /// descriptor binary data contains invalid keychain set.
+    InvalidKeychains,
+
+    /// descriptor binary data contains invalid public key.
+    InvalidPubkey,
+
+    /// invalid extended public key - {0}
+    #[from]
+    Xpub(XpubDecodeError),
+
+    /// invalid multisig descriptor - {0}
+    #[from]
+    Multi(MultiError),
+}
+
+/// Keys which can be used in the binary descriptor encoding.
+pub trait BinaryKey: Sized {
+    fn encode_key(&self, buf: &mut Vec<u8>) -> Result<(), BinaryEncodeError>;
+    fn decode_key(data: &mut &[u8]) -> Result<Self, BinaryDecodeError>;
+}
+
+fn read_bytes<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], BinaryDecodeError> {
+    if data.len() < len {
+        return Err(BinaryDecodeError::UnexpectedEnd);
+    }
+    let (bytes, remains) = data.split_at(len);
+    *data = remains;
+    Ok(bytes)
+}
+
+fn read_u8(data: &mut &[u8]) -> Result<u8, BinaryDecodeError> { Ok(read_bytes(data, 1)?[0]) }
+
+fn read_u16(data: &mut &[u8]) -> Result<u16, BinaryDecodeError> {
+    let mut buf = [0u8; 2];
+    buf.copy_from_slice(read_bytes(data, 2)?);
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32(data: &mut &[u8]) -> Result<u32, BinaryDecodeError> {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(read_bytes(data, 4)?);
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn decode_all<T>(
+    mut data: &[u8],
+    f: impl FnOnce(&mut &[u8]) -> Result<T, BinaryDecodeError>,
+) -> Result<T, BinaryDecodeError> {
+    let val = f(&mut data)?;
+    if !data.is_empty() {
+        return Err(BinaryDecodeError::DataNotConsumed(data.len()));
+    }
+    Ok(val)
+}
+
+impl BinaryKey for XpubDerivable {
+    fn encode_key(&self, buf: &mut Vec<u8>) -> Result<(), BinaryEncodeError> {
+        let origin = self.origin();
+        buf.extend(<[u8; 4]>::from(origin.master_fp()));
+        let path = origin.derivation();
+        let len =
+            u8::try_from(path.len()).map_err(|_| BinaryEncodeError::OriginTooLong(path.len()))?;
+        buf.push(len);
+        for index in path {
+            buf.extend(index.child_number().to_be_bytes());
+        }
+        buf.extend(self.xpub().encode());
+        match self.variant() {
+            None => buf.push(0),
+            Some(variant) => {
+                buf.push(1);
+                buf.extend(variant.index().to_be_bytes());
+            }
+        }
+        let keychains = Derive::<XOnlyPk>::keychains(self);
+        let count = u8::try_from(keychains.len())
+            .map_err(|_| BinaryEncodeError::TooManyKeychains(keychains.len()))?;
+        buf.push(count);
+        buf.extend(keychains.iter().map(Keychain::to_inner));
+        Ok(())
+    }
+
+    fn decode_key(data: &mut &[u8]) -> Result<Self, BinaryDecodeError> {
+        let mut master_fp = [0u8; 4];
+        master_fp.copy_from_slice(read_bytes(data, 4)?);
+        let len = read_u8(data)?;
+        let path = (0..len)
+            .map(|_| {
+                let index = read_u32(data)?;
+                HardenedIndex::try_from_child_number(index)
+                    .map_err(|_| BinaryDecodeError::InvalidIndex(index))
+            })
+            .collect::<Result<DerivationPath<_>, _>>()?;
+        let xpub = Xpub::decode(read_bytes(data, 78)?)?;
+        let spec = XpubSpec::new(xpub, XpubOrigin::new(master_fp.into(), path));
+        let variant = match read_u8(data)? {
+            0 => None,
+            1 => {
+                let index = read_u32(data)?;
+                Some(
+                    NormalIndex::try_from_index(index)
+                        .map_err(|_| BinaryDecodeError::InvalidIndex(index))?,
+                )
+            }
+            flag => return Err(BinaryDecodeError::InvalidFlag(flag)),
+        };
+        let count = read_u8(data)?;
+        let keychains = read_bytes(data, count as usize)?;
+        if keychains.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(BinaryDecodeError::InvalidKeychains);
+        }
+        let keychains = DerivationSeg::with(keychains.iter().copied().map(Keychain::with))
+            .map_err(|_| BinaryDecodeError::InvalidKeychains)?;
+        Ok(match variant {
+            None => XpubDerivable::with(spec, keychains),
+            Some(variant) => XpubDerivable::with_variant(spec, variant, keychains),
+        })
+    }
+}
+
+impl BinaryKey for CompressedPk {
+    fn encode_key(&self, buf: &mut Vec<u8>) -> Result<(), BinaryEncodeError> {
+        buf.extend(self.to_byte_array());
+        Ok(())
+    }
+
+    fn decode_key(data: &mut &[u8]) -> Result<Self, BinaryDecodeError> {
+        CompressedPk::from_bytes(read_bytes(data, 33)?)
+            .map_err(|_| BinaryDecodeError::InvalidPubkey)
+    }
+}
+
+impl BinaryKey for XOnlyPk {
+    fn encode_key(&self, buf: &mut Vec<u8>) -> Result<(), BinaryEncodeError> {
+        buf.extend(self.to_byte_array());
+        Ok(())
+    }
+
+    fn decode_key(data: &mut &[u8]) -> Result<Self, BinaryDecodeError> {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(read_bytes(data, 32)?);
+        XOnlyPk::from_byte_array(key).map_err(|_| BinaryDecodeError::InvalidPubkey)
+    }
+}
+
+impl<K: DeriveCompr + BinaryKey> Wpkh<K> {
+    /// Returns compact binary representation of the descriptor.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BinaryEncodeError> {
+        let mut buf = vec![TAG_WPKH];
+        self.as_key().encode_key(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Decodes descriptor from its binary representation produced by [`Self::to_bytes`].
+    pub fn from_bytes(data: impl AsRef<[u8]>) -> Result<Self, BinaryDecodeError> {
+        decode_all(data.as_ref(), |data| match read_u8(data)? {
+            TAG_WPKH => K::decode_key(data).map(Wpkh::from),
+            tag => Err(BinaryDecodeError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl<K: DeriveXOnly + BinaryKey> TrKey<K> {
+    /// Returns compact binary representation of the descriptor. Descriptors committing to a
+    /// script tree use a separate type tag, followed by the key and the 32-byte merkle root.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BinaryEncodeError> {
+        let Some(merkle_root) = self.merkle_root() else {
+            let mut buf = vec![TAG_TR_KEY];
+            self.as_internal_key().encode_key(&mut buf)?;
+            return Ok(buf);
+        };
+        let mut buf = vec![TAG_TR_KEY_ROOT];
+        self.as_internal_key().encode_key(&mut buf)?;
+        buf.extend_from_slice(merkle_root.borrow());
+        Ok(buf)
+    }
+
+    /// Decodes descriptor from its binary representation produced by [`Self::to_bytes`].
+    pub fn from_bytes(data: impl AsRef<[u8]>) -> Result<Self, BinaryDecodeError> {
+        decode_all(data.as_ref(), |data| match read_u8(data)? {
+            TAG_TR_KEY => K::decode_key(data).map(TrKey::from),
+            TAG_TR_KEY_ROOT => {
+                let key = K::decode_key(data)?;
+                let mut merkle_root = [0u8; 32];
+                merkle_root.copy_from_slice(read_bytes(data, 32)?);
+                Ok(TrKey::with_merkle_root(key, TapNodeHash::from(merkle_root)))
+            }
+            tag => Err(BinaryDecodeError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl<K: DeriveXOnly + BinaryKey> TrMultiA<K> {
+    /// Returns compact binary representation of the descriptor: optional internal key, threshold
+    /// and the list of the script keys in the descriptor order.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BinaryEncodeError> {
+        let mut buf = vec![TAG_TR_MULTI_A];
+        match self.internal_key() {
+            None => buf.push(0),
+            Some(key) => {
+                buf.push(1);
+                key.encode_key(&mut buf)?;
+            }
+        }
+        buf.extend(self.threshold().to_be_bytes());
+        let count = self.script_keys().len();
+        let count = u16::try_from(count).map_err(|_| BinaryEncodeError::TooManyKeys(count))?;
+        buf.extend(count.to_be_bytes());
+        for key in self.script_keys() {
+            key.encode_key(&mut buf)?;
+        }
+        Ok(buf)
+    }
+
+    /// Decodes descriptor from its binary representation produced by [`Self::to_bytes`].
+    pub fn from_bytes(data: impl AsRef<[u8]>) -> Result<Self, BinaryDecodeError> {
+        decode_all(data.as_ref(), |data| match read_u8(data)? {
+            TAG_TR_MULTI_A => {
+                let internal_key = match read_u8(data)? {
+                    0 => None,
+                    1 => Some(K::decode_key(data)?),
+                    flag => return Err(BinaryDecodeError::InvalidFlag(flag)),
+                };
+                let threshold = read_u16(data)?;
+                let count = read_u16(data)?;
+                let script_keys =
+                    (0..count).map(|_| K::decode_key(data)).collect::<Result<Vec<_>, _>>()?;
+                Ok(TrMultiA::new(internal_key, threshold, script_keys)?)
+            }
+            tag => Err(BinaryDecodeError::UnknownTag(tag)),
+        })
+    }
+}
+
+impl<K> StdDescr<K>
+where K: DeriveSet<Compr = K, XOnly = K> + DeriveCompr + DeriveXOnly + BinaryKey
+{
+    /// Returns compact binary representation of the descriptor, which matches the binary
+    /// representation of the underlying descriptor type.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BinaryEncodeError> {
+        match self {
+            StdDescr::Wpkh(d) => d.to_bytes(),
+            StdDescr::TrKey(d) => d.to_bytes(),
+            StdDescr::TrMultiA(d) => d.to_bytes(),
+        }
+    }
+
+    /// Decodes descriptor from its binary representation produced by [`Self::to_bytes`].
+    pub fn from_bytes(data: impl AsRef<[u8]>) -> Result<Self, BinaryDecodeError> {
+        let data = data.as_ref();
+        match data.first() {
+            Some(&TAG_WPKH) => Wpkh::from_bytes(data).map(StdDescr::Wpkh),
+            Some(&TAG_TR_KEY | &TAG_TR_KEY_ROOT) => TrKey::from_bytes(data).map(StdDescr::TrKey),
+            Some(&TAG_TR_MULTI_A) => TrMultiA::from_bytes(data).map(StdDescr::TrMultiA),
+            Some(tag) => Err(BinaryDecodeError::UnknownTag(*tag)),
+            None => Err(BinaryDecodeError::UnexpectedEnd),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        for s in [
+            "wpkh([643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*)",
+            "tr([643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/5/<0;1;9>/*)",
+            "tr([deadbeef/0h]xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw/1/*)",
+            "tr([deadbeef/0h]xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw/1/*,rawnode(5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21))",
+            "tr([643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*,sortedmulti_a(1,[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/2/<0;1>/*,[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/3/<0;1>/*))",
+        ] {
+            let descr = StdDescr::<XpubDerivable>::from_str(s).unwrap();
+            let data = descr.to_bytes().unwrap();
+            assert_eq!(StdDescr::<XpubDerivable>::from_bytes(&data).unwrap(), descr);
+            assert_eq!(descr.to_string(), s);
+        }
+
+        let wpkh = Wpkh::<CompressedPk>::from_str(
+            "wpkh(02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9)",
+        )
+        .unwrap();
+        let data = wpkh.to_bytes().unwrap();
+        assert_eq!(data.len(), 34);
+        assert_eq!(Wpkh::<CompressedPk>::from_bytes(&data).unwrap(), wpkh);
+        assert_eq!(
+            TrKey::<XOnlyPk>::from_bytes(&data),
+            Err(BinaryDecodeError::UnknownTag(TAG_WPKH))
+        );
+    }
+
+    #[test]
+    fn origin_too_long() {
+        let key = XpubDerivable::from_str("[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*").unwrap();
+        let path = vec![HardenedIndex::ZERO; 256];
+        let origin = XpubOrigin::new(key.origin().master_fp(), DerivationPath::from(path));
+        let key = XpubDerivable::with(
+            XpubSpec::new(key.xpub(), origin),
+            DerivationSeg::new(Keychain::OUTER),
+        );
+        assert_eq!(Wpkh::from(key).to_bytes(), Err(BinaryEncodeError::OriginTooLong(256)));
+    }
+
+    #[test]
+    fn invalid_data() {
+        let descr = StdDescr::<XpubDerivable>::from_str("wpkh([643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*)").unwrap();
+        let mut data = descr.to_bytes().unwrap();
+        assert_eq!(
+            StdDescr::<XpubDerivable>::from_bytes(&data[..data.len() - 1]),
+            Err(BinaryDecodeError::UnexpectedEnd)
+        );
+        data.push(0);
+        assert_eq!(
+            StdDescr::<XpubDerivable>::from_bytes(&data),
+            Err(BinaryDecodeError::DataNotConsumed(1))
+        );
+        data[0] = 0xFF;
+        assert_eq!(
+            StdDescr::<XpubDerivable>::from_bytes(&data),
+            Err(BinaryDecodeError::UnknownTag(0xFF))
+        );
+        assert_eq!(
+            StdDescr::<XpubDerivable>::from_bytes(Vec::<u8>::new()),
+            Err(BinaryDecodeError::UnexpectedEnd)
+        );
+    }
+}
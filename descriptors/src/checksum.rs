@@ -0,0 +1,132 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Descriptor checksums as defined in BIP-380 and used by Bitcoin Core.
+
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!\
+                             ^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum ChecksumError {
+    /// descriptor contains character '{0}' which is not allowed in descriptors.
+    InvalidChar(char),
+
+    /// descriptor checksum '{0}' has invalid length; checksums must be 8 characters long.
+    InvalidLength(String),
+
+    /// descriptor checksum mismatch: expected '{expected}', found '{found}'.
+    Mismatch { expected: String, found: String },
+}
+
+fn poly_mod(mut c: u64, val: u64) -> u64 {
+    let c0 = c >> 35;
+    c = ((c & 0x7ffffffff) << 5) ^ val;
+    if c0 & 1 != 0 {
+        c ^= 0xf5dee51989;
+    }
+    if c0 & 2 != 0 {
+        c ^= 0xa9fdca3312;
+    }
+    if c0 & 4 != 0 {
+        c ^= 0x1bab10e32d;
+    }
+    if c0 & 8 != 0 {
+        c ^= 0x3706b1677a;
+    }
+    if c0 & 16 != 0 {
+        c ^= 0x644d626ffd;
+    }
+    c
+}
+
+/// Computes checksum of a descriptor string (which must not contain the checksum itself).
+pub fn checksum(descriptor: &str) -> Result<String, ChecksumError> {
+    let mut c = 1u64;
+    let mut cls = 0u64;
+    let mut cls_count = 0u8;
+    for ch in descriptor.chars() {
+        let pos = INPUT_CHARSET.find(ch).ok_or(ChecksumError::InvalidChar(ch))? as u64;
+        c = poly_mod(c, pos & 31);
+        cls = cls * 3 + (pos >> 5);
+        cls_count += 1;
+        if cls_count == 3 {
+            c = poly_mod(c, cls);
+            cls = 0;
+            cls_count = 0;
+        }
+    }
+    if cls_count > 0 {
+        c = poly_mod(c, cls);
+    }
+    for _ in 0..8 {
+        c = poly_mod(c, 0);
+    }
+    c ^= 1;
+
+    Ok((0..8).map(|j| CHECKSUM_CHARSET[((c >> (5 * (7 - j))) & 31) as usize] as char).collect())
+}
+
+/// Verifies checksum of a descriptor string, if the checksum is present (i.e. the string has a
+/// `#checksum` suffix). Returns the descriptor string without the checksum.
+pub fn verify_checksum(s: &str) -> Result<&str, ChecksumError> {
+    let Some((descriptor, found)) = s.rsplit_once('#') else {
+        return Ok(s);
+    };
+    if found.len() != 8 {
+        return Err(ChecksumError::InvalidLength(found.to_owned()));
+    }
+    let expected = checksum(descriptor)?;
+    if expected != found {
+        return Err(ChecksumError::Mismatch {
+            expected,
+            found: found.to_owned(),
+        });
+    }
+    Ok(descriptor)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bip380_vectors() {
+        assert_eq!(checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert_eq!(checksum("raw(deadbeef)\u{e9}"), Err(ChecksumError::InvalidChar('\u{e9}')));
+
+        assert_eq!(verify_checksum("raw(deadbeef)#89f8spxm"), Ok("raw(deadbeef)"));
+        assert_eq!(verify_checksum("raw(deadbeef)"), Ok("raw(deadbeef)"));
+        assert_eq!(
+            verify_checksum("raw(deadbeef)#89f8spxn"),
+            Err(ChecksumError::Mismatch {
+                expected: "89f8spxm".to_owned(),
+                found: "89f8spxn".to_owned()
+            })
+        );
+        assert_eq!(
+            verify_checksum("raw(deadbeef)#89f8spx"),
+            Err(ChecksumError::InvalidLength("89f8spx".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,312 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export of descriptors in the format of Bitcoin Core `importdescriptors` RPC.
+
+use std::fmt::{self, Display, Formatter};
+use std::ops::RangeInclusive;
+
+use derive::{Derive, DerivedScript, IdxBase, Keychain, NormalIndex};
+
+use crate::checksum;
+
+/// Timestamp from which Bitcoin Core should rescan the blockchain for the transactions of an
+/// imported descriptor.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum ImportTimestamp {
+    /// Skip rescanning, since the descriptor has no history.
+    #[default]
+    Now,
+
+    /// UNIX timestamp of the earliest transaction of the descriptor.
+    Time(u64),
+}
+
+impl Display for ImportTimestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportTimestamp::Now => f.write_str("\"now\""),
+            ImportTimestamp::Time(time) => Display::fmt(time, f),
+        }
+    }
+}
+
+/// Errors expanding multipath key derivations (like `<0;1>`) of a descriptor into per-keychain
+/// descriptors.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum MultipathError {
+    /// multipath derivations of the descriptor have different number of paths ({0} and {1}).
+    LengthMismatch(usize, usize),
+
+    /// multipath derivations of the descriptor have {paths} paths, while the descriptor has
+    /// {keychains} keychains.
+    KeychainMismatch { paths: usize, keychains: usize },
+}
+
+/// Descriptor string for a single keychain of a (multipath) descriptor, for the wallets which
+/// don't support BIP-389 multipath derivations, like older versions of Bitcoin Core.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct KeychainDescriptor {
+    pub keychain: Keychain,
+    /// Whether the keychain is an internal (change) one, which is the case for all keychains
+    /// except [`Keychain::OUTER`].
+    pub internal: bool,
+    /// Descriptor string, including its checksum.
+    pub desc: String,
+}
+
+impl KeychainDescriptor {
+    /// Constructs descriptor strings for each of the descriptor keychains.
+    ///
+    /// Multipath key derivations (like `<0;1>`) are expanded into a separate descriptor for each
+    /// keychain according to BIP-389, and each of them gets its own checksum. Keychain
+    /// [`Keychain::OUTER`] is mapped to an external descriptor, all other keychains (including
+    /// [`Keychain::INNER`]) are internal.
+    ///
+    /// Errors if the multipath derivations of the descriptor have different number of paths, or if
+    /// the number of the paths doesn't match the number of the descriptor keychains.
+    pub fn with<D>(descriptor: &D) -> Result<Vec<KeychainDescriptor>, MultipathError>
+    where D: Derive<DerivedScript> + Display + ?Sized {
+        let descriptors = expand_multipath(&descriptor.to_string())?;
+        let keychains = descriptor.keychains();
+        if descriptors.len() != keychains.len() {
+            return Err(MultipathError::KeychainMismatch {
+                paths: descriptors.len(),
+                keychains: keychains.len(),
+            });
+        }
+        Ok(descriptors
+            .into_iter()
+            .zip(keychains)
+            .map(|(desc, keychain)| {
+                let checksum = checksum(&desc)
+                    .expect("descriptor string representation contains only valid characters");
+                KeychainDescriptor {
+                    keychain,
+                    internal: keychain != Keychain::OUTER,
+                    desc: format!("{desc}#{checksum}"),
+                }
+            })
+            .collect())
+    }
+}
+
+impl Display for KeychainDescriptor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { f.write_str(&self.desc) }
+}
+
+/// Single request object of Bitcoin Core `importdescriptors` RPC call.
+///
+/// The JSON representation of the object is produced by the [`Display`] implementation; the
+/// `active` and `range` fields are written only for ranged descriptors.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct CoreImport {
+    /// Descriptor string for a single keychain, including its checksum.
+    pub desc: String,
+    pub timestamp: ImportTimestamp,
+    /// Whether the descriptor is used to generate new addresses. Always `false` for unranged
+    /// descriptors.
+    pub active: bool,
+    pub internal: bool,
+    /// Range of derivation indexes to import, or `None` for unranged descriptors (which have no
+    /// wildcard).
+    pub range: Option<RangeInclusive<NormalIndex>>,
+}
+
+impl CoreImport {
+    /// Constructs `importdescriptors` requests for a descriptor, one per each of its keychains.
+    ///
+    /// Multipath key derivations (like `<0;1>`) are expanded into a separate descriptor for each
+    /// keychain according to BIP-389. Keychain [`Keychain::OUTER`] is imported as an external one
+    /// and [`Keychain::INNER`] as internal; both of them are marked as active. Other keychains are
+    /// imported as internal, but inactive, since Bitcoin Core doesn't support them.
+    ///
+    /// Descriptors without a wildcard (like the ones with raw keys) are unranged: Bitcoin Core
+    /// rejects a `range` for them and they can't be active, so the `range` argument is ignored.
+    pub fn with<D: Derive<DerivedScript> + Display>(
+        descriptor: &D,
+        timestamp: ImportTimestamp,
+        range: RangeInclusive<NormalIndex>,
+    ) -> Result<Vec<CoreImport>, MultipathError> {
+        Ok(KeychainDescriptor::with(descriptor)?
+            .into_iter()
+            .map(|keychain_descr| {
+                let ranged = keychain_descr.desc.contains('*');
+                CoreImport {
+                    timestamp,
+                    active: ranged
+                        && (keychain_descr.keychain == Keychain::OUTER
+                            || keychain_descr.keychain == Keychain::INNER),
+                    internal: keychain_descr.internal,
+                    desc: keychain_descr.desc,
+                    range: ranged.then(|| range.clone()),
+                }
+            })
+            .collect())
+    }
+}
+
+impl Display for CoreImport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("{\"desc\":\"")?;
+        for ch in self.desc.chars() {
+            if ch == '"' || ch == '\\' {
+                f.write_str("\\")?;
+            }
+            write!(f, "{ch}")?;
+        }
+        write!(f, "\",\"timestamp\":{}", self.timestamp)?;
+        if self.range.is_some() {
+            write!(f, ",\"active\":{}", self.active)?;
+        }
+        write!(f, ",\"internal\":{}", self.internal)?;
+        if let Some(range) = &self.range {
+            write!(f, ",\"range\":[{},{}]", range.start().index(), range.end().index())?;
+        }
+        f.write_str("}")
+    }
+}
+
+/// Expands multipath derivation segments (like `<0;1>`) in a descriptor string into a separate
+/// descriptor string for each of the segment indexes, following BIP-389. If the descriptor has
+/// multiple multipath segments, their n-th indexes are used for the n-th descriptor; all the
+/// segments must have the same number of indexes.
+fn expand_multipath(descriptor: &str) -> Result<Vec<String>, MultipathError> {
+    let mut parts = Vec::<(&str, Vec<&str>)>::new();
+    let mut rest = descriptor;
+    while let Some((prefix, tail)) = rest.split_once('<') {
+        let Some((multipath, tail)) = tail.split_once('>') else {
+            break;
+        };
+        parts.push((prefix, multipath.split(';').collect()));
+        rest = tail;
+    }
+    let count = parts.first().map(|(_, paths)| paths.len()).unwrap_or(1);
+    if let Some((_, paths)) = parts.iter().find(|(_, paths)| paths.len() != count) {
+        return Err(MultipathError::LengthMismatch(count, paths.len()));
+    }
+    Ok((0..count)
+        .map(|n| {
+            let mut desc = String::with_capacity(descriptor.len());
+            for (prefix, paths) in &parts {
+                desc.push_str(prefix);
+                desc.push_str(paths[n]);
+            }
+            desc.push_str(rest);
+            desc
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use derive::{CompressedPk, Idx, XpubDerivable};
+
+    use super::*;
+    use crate::{Descriptor, StdDescr, TrKey, Wpkh};
+
+    const XPUB: &str = "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*";
+    const PK: &str = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+
+    #[test]
+    fn multipath() {
+        assert_eq!(expand_multipath("wpkh(xpub/0/*)").unwrap(), vec!["wpkh(xpub/0/*)"]);
+        assert_eq!(expand_multipath("tr(xpub/<0;1>/*)").unwrap(), vec![
+            "tr(xpub/0/*)",
+            "tr(xpub/1/*)"
+        ]);
+        assert_eq!(expand_multipath("tr(k,sortedmulti_a(1,a/<0;1>/*,b/<2;3>/*))").unwrap(), vec![
+            "tr(k,sortedmulti_a(1,a/0/*,b/2/*))",
+            "tr(k,sortedmulti_a(1,a/1/*,b/3/*))"
+        ]);
+        assert_eq!(
+            expand_multipath("tr(k,sortedmulti_a(1,a/<0;1>/*,b/<0;1;2>/*))"),
+            Err(MultipathError::LengthMismatch(2, 3))
+        );
+    }
+
+    #[test]
+    fn keychain_descriptors() {
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let descr = StdDescr::<XpubDerivable>::from(TrKey::from(xpub));
+        let descriptors = descr.watch_descriptor_strings().unwrap();
+        assert_eq!(descriptors.len(), 2);
+        for (descriptor, (keychain, path)) in descriptors.iter().zip([(0u8, "0"), (1, "1")]) {
+            let desc = descr.to_string().replace("<0;1>", path);
+            assert_eq!(descriptor.keychain, Keychain::with(keychain));
+            assert_eq!(descriptor.internal, keychain != 0);
+            assert_eq!(descriptor.to_string(), format!("{desc}#{}", checksum(&desc).unwrap()));
+            assert!(StdDescr::<XpubDerivable>::from_str(&descriptor.desc).is_ok());
+        }
+        assert_ne!(descriptors[0].desc, descriptors[1].desc);
+
+        let single = descr.to_string().replace("<0;1>", "0");
+        let descr = StdDescr::<XpubDerivable>::from_str(&single).unwrap();
+        assert_eq!(descr.watch_descriptor_strings().unwrap(), vec![KeychainDescriptor {
+            keychain: Keychain::OUTER,
+            internal: false,
+            desc: format!("{single}#{}", checksum(&single).unwrap()),
+        }]);
+    }
+
+    #[test]
+    fn import() {
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let descr = StdDescr::<XpubDerivable>::from(TrKey::from(xpub));
+        let range = NormalIndex::ZERO..=NormalIndex::from(999u16);
+        let imports = CoreImport::with(&descr, ImportTimestamp::Now, range.clone()).unwrap();
+        assert_eq!(imports.len(), 2);
+
+        let external = descr.to_string().replace("<0;1>", "0");
+        assert_eq!(imports[0].desc, format!("{external}#{}", checksum(&external).unwrap()));
+        assert!(imports[0].active);
+        assert!(!imports[0].internal);
+        assert!(imports[1].desc.starts_with(&descr.to_string().replace("<0;1>", "1")));
+        assert!(imports[1].active);
+        assert!(imports[1].internal);
+        assert_eq!(
+            imports[0].to_string(),
+            format!(
+                "{{\"desc\":\"{}\",\"timestamp\":\"now\",\"active\":true,\"internal\":false,\"\
+                 range\":[0,999]}}",
+                imports[0].desc
+            )
+        );
+
+        let pk = CompressedPk::from_str(PK).unwrap();
+        let descr = Wpkh::from(pk);
+        let imports = CoreImport::with(&descr, ImportTimestamp::Time(1700000000), range).unwrap();
+        assert_eq!(imports.len(), 1);
+        assert!(!imports[0].active);
+        assert_eq!(imports[0].range, None);
+        assert_eq!(
+            imports[0].to_string(),
+            format!(
+                "{{\"desc\":\"{}\",\"timestamp\":1700000000,\"internal\":false}}",
+                imports[0].desc
+            )
+        );
+    }
+}
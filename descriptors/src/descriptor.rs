@@ -20,17 +20,38 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+//! Standard descriptors and the traits they implement.
+//!
+//! With the `serde` feature, descriptors serialize as self-describing objects with camelCase
+//! field names: `{"key": KEY}` for [`Wpkh`], `{"internalKey": KEY, "merkleRoot": ROOT}` for
+//! [`TrKey`] and `{"internalKey": KEY, "threshold": N, "scriptKeys": [KEY, ...]}` for
+//! [`TrMultiA`]. Earlier releases serialized `Wpkh` and `TrKey` as the bare key value; such
+//! data has to be wrapped into the objects above (or the descriptors re-created from their
+//! strings) before it can be deserialized.
+
 use std::collections::BTreeSet;
-use std::{iter, vec};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::iter;
+use std::str::FromStr;
 
+use derive::secp256k1::{Keypair, PublicKey, SecretKey, SECP256K1};
 use derive::{
-    CompressedPk, Derive, DeriveCompr, DeriveScripts, DeriveSet, DeriveXOnly, DerivedScript,
-    KeyOrigin, Keychain, NormalIndex, Sats, TapDerivation, Terminal, XOnlyPk, XpubDerivable,
-    XpubSpec,
+    tagged_hash, Address, AddressError, CompressedPk, Derive, DeriveCompr, DeriveScripts,
+    DeriveSecret, DeriveSet, DeriveXOnly, DerivedScript, Idx, KeyOrigin, Keychain, Network,
+    NormalIndex, Sats, ScriptPubkey, Slip132Type, TapDerivation, Terminal, WeightUnits, XOnlyPk,
+    XprivDerivable, Xpub, XpubDerivable, XpubFp, XpubParseError, XpubSpec,
+};
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    checksum, verify_checksum, Addresses, ChecksumError, KeychainDescriptor, MultiError,
+    MultipathError, ToPolicy, TrKey, TrMultiA, Wpkh,
 };
-use indexmap::IndexMap;
 
-use crate::{TrKey, Wpkh};
+/// Tag used for computing [`Descriptor::descriptor_id`].
+pub const DESCRIPTOR_ID_TAG: &str = "urn:lnp-bp:descriptor:id#2024-10";
 
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Display)]
 #[display(lowercase)]
@@ -53,6 +74,160 @@ impl SpkClass {
             SpkClass::P2wsh | SpkClass::P2tr => Sats(330),
         }
     }
+
+    /// Detects class of a script pubkey. Scripts not matching any of the standard templates are
+    /// reported as [`SpkClass::Bare`].
+    pub fn from_script_pubkey(script_pubkey: &ScriptPubkey) -> Self {
+        if script_pubkey.is_p2pkh() {
+            SpkClass::P2pkh
+        } else if script_pubkey.is_p2sh() {
+            SpkClass::P2sh
+        } else if script_pubkey.is_p2wpkh() {
+            SpkClass::P2wpkh
+        } else if script_pubkey.is_p2wsh() {
+            SpkClass::P2wsh
+        } else if script_pubkey.is_p2tr() {
+            SpkClass::P2tr
+        } else {
+            SpkClass::Bare
+        }
+    }
+
+    /// Returns the class of the scripts implied by SLIP-132 extended public key version, or
+    /// `None` for the canonical BIP-32 version, which doesn't imply any script type.
+    pub const fn from_slip132(ty: Slip132Type) -> Option<Self> {
+        match ty {
+            Slip132Type::Bip32 => None,
+            Slip132Type::NestedWpkh | Slip132Type::NestedWshMulti => Some(SpkClass::P2sh),
+            Slip132Type::Wpkh => Some(SpkClass::P2wpkh),
+            Slip132Type::WshMulti => Some(SpkClass::P2wsh),
+        }
+    }
+}
+
+/// Errors parsing descriptor string representation, parameterized with the error type of the key
+/// parser.
+///
+/// Key errors are reported together with the byte position of the key in the parsed string. For
+/// extended keys the underlying [`XpubParseError`] tells apart malformed key origins, invalid
+/// extended keys and invalid keychain or index derivation segments, and is available via
+/// [`Error::source`].
+#[derive(Clone, Eq, PartialEq, Debug, From)]
+pub enum DescriptorParseError<E: Error = XpubParseError> {
+    #[from]
+    Checksum(ChecksumError),
+
+    Unsupported(String),
+
+    InvalidFormat(String),
+
+    /// Invalid descriptor key.
+    Key {
+        /// Byte offset at which the invalid key starts in the parsed descriptor string, allowing
+        /// to point the user to the key which has failed to parse.
+        pos: usize,
+        /// Error returned by the key parser, which is also the [`Error::source`] of this error.
+        err: E,
+    },
+
+    #[from]
+    Multisig(MultiError),
+}
+
+impl<E: Error> Display for DescriptorParseError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DescriptorParseError::Checksum(err) => Display::fmt(err, f),
+            DescriptorParseError::Unsupported(name) => {
+                write!(f, "descriptor function '{name}' is not supported.")
+            }
+            DescriptorParseError::InvalidFormat(s) => {
+                write!(f, "invalid descriptor format '{s}'.")
+            }
+            DescriptorParseError::Key { pos, err } => {
+                write!(f, "invalid descriptor key at position {pos} - {err}")
+            }
+            DescriptorParseError::Multisig(err) => write!(f, "invalid multisig descriptor - {err}"),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for DescriptorParseError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DescriptorParseError::Checksum(err) => Some(err),
+            DescriptorParseError::Unsupported(_) | DescriptorParseError::InvalidFormat(_) => None,
+            DescriptorParseError::Key { err, .. } => Some(err),
+            DescriptorParseError::Multisig(err) => Some(err),
+        }
+    }
+}
+
+/// Mismatch between the networks of the descriptor extended keys.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum NetworkMismatch {
+    /// descriptor mixes extended keys for mainnet and test networks.
+    MixedKeys,
+
+    /// descriptor extended keys can't be used on {0} network.
+    Network(Network),
+}
+
+/// Errors deriving addresses from a descriptor for a specific network.
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(inner)]
+pub enum DescriptorAddressError {
+    #[from]
+    Network(NetworkMismatch),
+
+    #[from]
+    Address(AddressError),
+}
+
+/// Parses descriptor string in the form of `name(args)#checksum`, verifying the checksum (if
+/// present). Returns the descriptor function name, its arguments and the byte position of the
+/// arguments in `s`.
+pub(crate) fn parse_function<E: Error>(
+    s: &str,
+) -> Result<(&str, &str, usize), DescriptorParseError<E>> {
+    let trimmed = s.trim_start();
+    let descriptor = verify_checksum(trimmed.trim_end())?;
+    let (name, args) = descriptor
+        .strip_suffix(')')
+        .and_then(|s| s.split_once('('))
+        .filter(|(name, _)| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        })
+        .ok_or_else(|| DescriptorParseError::InvalidFormat(descriptor.to_owned()))?;
+    let pos = s.len() - trimmed.len() + name.len() + 1;
+    Ok((name, args, pos))
+}
+
+/// Parses descriptor key located at the byte position `pos` of the descriptor string, reporting
+/// the position on failure.
+pub(crate) fn parse_key<K: FromStr>(
+    key: &str,
+    pos: usize,
+) -> Result<K, DescriptorParseError<K::Err>>
+where
+    K::Err: Error,
+{
+    K::from_str(key).map_err(|err| DescriptorParseError::Key { pos, err })
+}
+
+/// Returns the first extended public key which appears more than once among the specifications.
+/// Key origins and derivation suffixes are not compared.
+pub(crate) fn duplicate_xpub<'a>(specs: impl IntoIterator<Item = &'a XpubSpec>) -> Option<Xpub> {
+    let mut seen = Vec::<Xpub>::new();
+    for spec in specs {
+        let xpub = *spec.xpub();
+        if seen.contains(&xpub) {
+            return Some(xpub);
+        }
+        seen.push(xpub);
+    }
+    None
 }
 
 pub trait Descriptor<K = XpubDerivable, V = ()>: DeriveScripts {
@@ -71,13 +246,294 @@ pub trait Descriptor<K = XpubDerivable, V = ()>: DeriveScripts {
 
     fn class(&self) -> SpkClass;
 
+    /// Detects whether the descriptor produces taproot outputs, requiring BIP-340 signatures and
+    /// taproot-specific PSBT fields.
+    fn supports_taproot(&self) -> bool { self.class() == SpkClass::P2tr }
+
+    /// Detects whether the descriptor produces segwit (of any version) outputs, which can be
+    /// spent with only the previous output (PSBT `witness_utxo`) known.
+    ///
+    /// Nested segwit descriptors, which use [`SpkClass::P2sh`] class, must override this method.
+    fn supports_segwit(&self) -> bool {
+        matches!(self.class(), SpkClass::P2wpkh | SpkClass::P2wsh | SpkClass::P2tr)
+    }
+
+    /// Detects whether spending the descriptor outputs requires the full previous transaction
+    /// (PSBT `non_witness_utxo`), which is the case for the legacy (non-segwit) outputs.
+    fn requires_prev_tx(&self) -> bool { !self.supports_segwit() }
+
+    /// Iterates over the descriptor keys in the order they are declared in the descriptor
+    /// string representation. For multisig descriptors the order of the keys is the one given by
+    /// the user, even if the keys are sorted inside the derived scripts.
     fn keys(&self) -> Self::KeyIter<'_>;
     fn vars(&self) -> Self::VarIter<'_>;
+    /// Iterates over the extended keys specifications of the descriptor keys, using the same
+    /// order as [`Self::keys`]. Non-derivable keys are skipped.
     fn xpubs(&self) -> Self::XpubIter<'_>;
 
     fn compr_keyset(&self, terminal: Terminal) -> IndexMap<CompressedPk, KeyOrigin>;
     fn xonly_keyset(&self, terminal: Terminal) -> IndexMap<XOnlyPk, TapDerivation>;
+
+    /// Derives [`Self::compr_keyset`] for `count` consecutive indexes of the keychain starting
+    /// from `start`, in the ascending order of the indexes. The range is truncated if it exceeds
+    /// the maximal normal index.
+    fn compr_keyset_range(
+        &self,
+        keychain: impl Into<Keychain>,
+        start: impl Into<NormalIndex>,
+        count: u32,
+    ) -> Vec<IndexMap<CompressedPk, KeyOrigin>>
+    where
+        Self: Sized,
+    {
+        Terminal::range(keychain, start.into(), count)
+            .map(|terminal| self.compr_keyset(terminal))
+            .collect()
+    }
+
+    /// Derives [`Self::xonly_keyset`] for `count` consecutive indexes of the keychain starting
+    /// from `start`, in the ascending order of the indexes. The range is truncated if it exceeds
+    /// the maximal normal index.
+    fn xonly_keyset_range(
+        &self,
+        keychain: impl Into<Keychain>,
+        start: impl Into<NormalIndex>,
+        count: u32,
+    ) -> Vec<IndexMap<XOnlyPk, TapDerivation>>
+    where
+        Self: Sized,
+    {
+        Terminal::range(keychain, start.into(), count)
+            .map(|terminal| self.xonly_keyset(terminal))
+            .collect()
+    }
+
+    /// Lists origins of all the keys which may sign for the `terminal`, such that a signer (like a
+    /// hardware wallet) can locate the keys it controls. For multisig descriptors this includes
+    /// the path of every participant.
+    ///
+    /// Origins are taken from both [`Self::compr_keyset`] and [`Self::xonly_keyset`], each listed
+    /// once in the order of the keysets. Keys which are not derived from an extended public key,
+    /// like raw keys with their empty origin or MuSig2 aggregated keys, are not listed.
+    fn signing_paths(&self, terminal: Terminal) -> Vec<KeyOrigin> {
+        let compr = self.compr_keyset(terminal).into_values();
+        let xonly = self.xonly_keyset(terminal).into_values().map(|derivation| derivation.origin);
+        compr
+            .chain(xonly)
+            .filter(|origin| *origin != KeyOrigin::default())
+            .collect::<IndexSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Checks whether any of the descriptor extended keys is derived from a master key with the
+    /// given fingerprint, or has the fingerprint itself.
+    fn contains_fingerprint(&self, fp: XpubFp) -> bool {
+        self.xpubs().any(|spec| spec.origin().master_fp() == fp || spec.xpub().fingerprint() == fp)
+    }
+
+    /// Checks whether the descriptor uses the extended key. Key origins are not compared.
+    fn contains_xpub(&self, spec: &XpubSpec) -> bool {
+        self.xpubs().any(|xpub_spec| xpub_spec.xpub() == spec.xpub())
+    }
+
+    /// Detects whether the same extended key is used more than once in the descriptor, for
+    /// instance by two multisig participants, which is usually a copy-paste mistake. Keys are
+    /// compared by their extended public keys, not by the keys derived for some terminal.
+    fn has_duplicate_keys(&self) -> bool { duplicate_xpub(self.xpubs()).is_some() }
+
+    /// Detects the network from the version bytes of the descriptor extended keys, ensuring they
+    /// all agree. Extended keys do not distinguish between test networks, thus
+    /// [`Network::Testnet3`] is reported for all of them. Returns `None` for descriptors without
+    /// extended keys.
+    fn network(&self) -> Result<Option<Network>, NetworkMismatch> {
+        let mut testnet = None;
+        for spec in self.xpubs() {
+            match testnet {
+                None => testnet = Some(spec.xpub().is_testnet()),
+                Some(tn) if tn != spec.xpub().is_testnet() => {
+                    return Err(NetworkMismatch::MixedKeys);
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(testnet.map(|tn| if tn { Network::Testnet3 } else { Network::Mainnet }))
+    }
+
+    /// Checks that the descriptor extended keys can be used on the given network.
+    fn check_network(&self, network: Network) -> Result<(), NetworkMismatch> {
+        match self.network()? {
+            Some(detected) if detected.is_testnet() != network.is_testnet() => {
+                Err(NetworkMismatch::Network(network))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Derives address for the given network, failing if the descriptor extended keys belong to a
+    /// different network.
+    fn derive_address_checked(
+        &self,
+        network: Network,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+    ) -> Result<Address, DescriptorAddressError> {
+        self.check_network(network)?;
+        Ok(self.derive_address(network.into(), keychain, index)?)
+    }
+
+    /// Derives address at the given keychain and index. Returns `None` if the derived script
+    /// pubkey can't be represented as an address.
+    fn address_at(
+        &self,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+        network: Network,
+    ) -> Option<Address> {
+        self.derive_address(network.into(), keychain, index).ok()
+    }
+
+    /// Lazily derives addresses of the keychain starting from the `start` index, yielding each
+    /// of them with its derivation index. Indexes for which the derived script pubkey can't be
+    /// represented as an address are skipped.
+    fn addresses(
+        &self,
+        keychain: impl Into<Keychain>,
+        start: impl Into<NormalIndex>,
+        network: Network,
+    ) -> Addresses<'_, Self>
+    where
+        Self: Sized,
+    {
+        Addresses::new(self, network.into(), keychain.into(), start.into())
+    }
+
+    /// Finds the smallest index of the keychain which script pubkey is not used, according to the
+    /// `is_used` predicate, deriving scripts starting from the zero index.
+    ///
+    /// The search derives at most `gap_limit` scripts. If all of them are used, the index
+    /// following the last checked one is returned.
+    fn first_unused(
+        &self,
+        keychain: impl Into<Keychain>,
+        mut is_used: impl FnMut(&ScriptPubkey) -> bool,
+        gap_limit: u32,
+    ) -> NormalIndex
+    where
+        Self: Sized,
+    {
+        let keychain = keychain.into();
+        let mut next = NormalIndex::ZERO;
+        for terminal in Terminal::range(keychain, NormalIndex::ZERO, gap_limit) {
+            if !is_used(&self.derive(keychain, terminal.index).to_script_pubkey()) {
+                return terminal.index;
+            }
+            next = terminal.index.saturating_inc();
+        }
+        next
+    }
+
+    /// Derives the first address of the default keychain.
+    fn first_address(&self, network: Network) -> Option<Address> {
+        self.address_at(self.default_keychain(), NormalIndex::ZERO, network)
+    }
+
+    /// Canonical string representation of the descriptor, which is the same as the `Display`
+    /// output, except for the sorted multisig descriptors, which list their keys sorted by the
+    /// key string representation, since the key order doesn't affect the derived scripts.
+    fn to_canonical_string(&self) -> String
+    where
+        Self: Display,
+        K: Display,
+    {
+        self.to_string()
+    }
+
+    /// Unique identifier of the descriptor, which can be used as a wallet fingerprint.
+    ///
+    /// The identifier is computed as BIP-340 tagged hash with [`DESCRIPTOR_ID_TAG`] over the
+    /// [canonical string representation](Self::to_canonical_string) of the descriptor followed
+    /// by `#` and its BIP-380 checksum. Thus the same descriptor always produces the same
+    /// identifier, independently from the way it was constructed or parsed, including the order
+    /// of the keys in sorted multisigs.
+    fn descriptor_id(&self) -> [u8; 32]
+    where
+        Self: Display,
+        K: Display,
+    {
+        let descriptor = self.to_canonical_string();
+        let checksum = checksum(&descriptor).expect("descriptor string is always valid");
+        tagged_hash(DESCRIPTOR_ID_TAG.as_bytes(), [
+            descriptor.as_bytes(),
+            &b"#"[..],
+            checksum.as_bytes(),
+        ])
+    }
+
+    /// Returns a separate descriptor string, with its own checksum, for each of the descriptor
+    /// keychains, expanding multipath derivations like `<0;1>`. This is required by the wallets
+    /// which don't support BIP-389, like older versions of Bitcoin Core.
+    ///
+    /// See [`KeychainDescriptor::with`] for the details and errors.
+    fn watch_descriptor_strings(&self) -> Result<Vec<KeychainDescriptor>, MultipathError>
+    where Self: Display {
+        KeychainDescriptor::with(self)
+    }
+
+    /// Private keys for the descriptor keys from [`Self::compr_keyset`], available when the
+    /// descriptor uses secret keys.
+    fn compr_seckeyset(&self, terminal: Terminal) -> IndexMap<CompressedPk, SecretKey>
+    where K: DeriveSecret {
+        let keyset = self.compr_keyset(terminal);
+        self.keys()
+            .map(|key| key.derive_secret(terminal.keychain, terminal.index))
+            .map(|sk| (CompressedPk::from(PublicKey::from_secret_key(SECP256K1, &sk)), sk))
+            .filter(|(pk, _)| keyset.contains_key(pk))
+            .collect()
+    }
+
+    /// Private keys for the descriptor keys from [`Self::xonly_keyset`], available when the
+    /// descriptor uses secret keys.
+    fn xonly_seckeyset(&self, terminal: Terminal) -> IndexMap<XOnlyPk, Keypair>
+    where K: DeriveSecret {
+        let keyset = self.xonly_keyset(terminal);
+        self.keys()
+            .map(|key| key.derive_secret(terminal.keychain, terminal.index))
+            .map(|sk| Keypair::from_secret_key(SECP256K1, &sk))
+            .map(|pair| (XOnlyPk::from(pair.x_only_public_key().0), pair))
+            .filter(|(pk, _)| keyset.contains_key(pk))
+            .collect()
+    }
+}
+
+/// Descriptors which can estimate the size of the data required to spend their outputs.
+pub trait SatisfactionWeight {
+    /// Maximal weight of the script sig (including its length prefix) and witness required to
+    /// spend an output generated by the descriptor.
+    fn max_satisfaction_weight(&self) -> WeightUnits;
+}
+
+/// Descriptors which keys can be replaced with keys of a different type, keeping the rest of the
+/// descriptor (key origins, thresholds and the order of the keys) unchanged.
+pub trait KeyTranslate<K, K2> {
+    type Output;
+
+    fn translate_keys<E>(&self, f: impl FnMut(&K) -> Result<K2, E>) -> Result<Self::Output, E>;
+}
+
+/// Descriptors using extended private keys, which can be converted into a watch-only form.
+pub trait ToWatchOnly: KeyTranslate<XprivDerivable, XpubDerivable> {
+    /// Replaces each extended private key with the matching extended public key, preserving the
+    /// key origins and the descriptor structure. The resulting descriptor is the one used by the
+    /// watch-only wallets.
+    fn to_watch_only(&self) -> Self::Output {
+        match self.translate_keys(|key| Ok::<_, Infallible>(key.to_xpub_derivable())) {
+            Ok(descr) => descr,
+            Err(never) => match never {},
+        }
+    }
 }
+impl<T: KeyTranslate<XprivDerivable, XpubDerivable>> ToWatchOnly for T {}
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug, From)]
 #[cfg_attr(
@@ -131,13 +587,13 @@ pub enum StdDescr<S: DeriveSet = XpubDerivable> {
      */
     #[from]
     TrKey(TrKey<S::XOnly>),
+
+    #[from]
+    TrMultiA(TrMultiA<S::XOnly>),
     /*
     #[from]
     TrMusig(TrMusig<S::XOnly>),
 
-    #[from]
-    TrMulti(TrMulti<S::XOnly>),
-
     #[from]
     TrTlMulti(TrTlMulti<S::XOnly>),
 
@@ -165,11 +621,116 @@ pub enum StdDescr<S: DeriveSet = XpubDerivable> {
      */
 }
 
+/// Iterator over the keys or extended keys of a [`StdDescr`], wrapping the iterator of the
+/// descriptor variant.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum StdDescrIter<W, T, M> {
+    Wpkh(W),
+    TrKey(T),
+    TrMultiA(M),
+}
+
+impl<I, W, T, M> Iterator for StdDescrIter<W, T, M>
+where
+    W: Iterator<Item = I>,
+    T: Iterator<Item = I>,
+    M: Iterator<Item = I>,
+{
+    type Item = I;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            StdDescrIter::Wpkh(iter) => iter.next(),
+            StdDescrIter::TrKey(iter) => iter.next(),
+            StdDescrIter::TrMultiA(iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            StdDescrIter::Wpkh(iter) => iter.size_hint(),
+            StdDescrIter::TrKey(iter) => iter.size_hint(),
+            StdDescrIter::TrMultiA(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl<S: DeriveSet> Display for StdDescr<S>
+where
+    S::Compr: Display,
+    S::XOnly: Display,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StdDescr::Wpkh(d) => Display::fmt(d, f),
+            StdDescr::TrKey(d) => Display::fmt(d, f),
+            StdDescr::TrMultiA(d) => Display::fmt(d, f),
+        }
+    }
+}
+
+impl<K> FromStr for StdDescr<K>
+where
+    K: DeriveSet<Compr = K, XOnly = K> + DeriveCompr + DeriveXOnly + FromStr,
+    K::Err: Error,
+{
+    type Err = DescriptorParseError<K::Err>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, key, pos) = parse_function(s)?;
+        match name {
+            "wpkh" => parse_key::<K>(key, pos).map(Wpkh::from).map(StdDescr::Wpkh),
+            "tr" if key.contains(",rawnode(") => TrKey::from_str(s).map(StdDescr::TrKey),
+            "tr" if key.contains(',') => TrMultiA::from_str(s).map(StdDescr::TrMultiA),
+            "tr" => parse_key::<K>(key, pos).map(TrKey::from).map(StdDescr::TrKey),
+            _ => Err(DescriptorParseError::Unsupported(name.to_owned())),
+        }
+    }
+}
+
+impl<K, K2> KeyTranslate<K, K2> for StdDescr<K>
+where
+    K: DeriveSet<Compr = K, XOnly = K> + DeriveCompr + DeriveXOnly,
+    K2: DeriveSet<Compr = K2, XOnly = K2> + DeriveCompr + DeriveXOnly,
+{
+    type Output = StdDescr<K2>;
+
+    fn translate_keys<E>(&self, f: impl FnMut(&K) -> Result<K2, E>) -> Result<StdDescr<K2>, E> {
+        Ok(match self {
+            StdDescr::Wpkh(d) => StdDescr::Wpkh(d.translate_keys(f)?),
+            StdDescr::TrKey(d) => StdDescr::TrKey(d.translate_keys(f)?),
+            StdDescr::TrMultiA(d) => StdDescr::TrMultiA(d.translate_keys(f)?),
+        })
+    }
+}
+
+impl<K> StdDescr<K>
+where K: DeriveSet<Compr = K, XOnly = K> + DeriveCompr + DeriveXOnly
+{
+    /// Migrates single-key descriptor to a taproot `sortedmulti_a` multisig, combining the
+    /// existing key, with its origin, with the `extra_keys` of the new co-signers.
+    ///
+    /// The resulting descriptor has no internal key, so the former single key can't spend the
+    /// funds alone. Errors if the descriptor is already a multisig, if the threshold is invalid
+    /// for the combined number of keys or if the same extended key is used more than once.
+    pub fn into_multisig(self, threshold: u16, extra_keys: Vec<K>) -> Result<Self, MultiError> {
+        let key = match self {
+            StdDescr::Wpkh(d) => d.into_key(),
+            StdDescr::TrKey(d) => d.into_internal_key(),
+            StdDescr::TrMultiA(_) => return Err(MultiError::AlreadyMultisig),
+        };
+        let keys = iter::once(key).chain(extra_keys);
+        TrMultiA::new_unique(None, threshold, keys).map(StdDescr::TrMultiA)
+    }
+}
+
 impl<S: DeriveSet> Derive<DerivedScript> for StdDescr<S> {
     fn default_keychain(&self) -> Keychain {
         match self {
             StdDescr::Wpkh(d) => d.default_keychain(),
             StdDescr::TrKey(d) => d.default_keychain(),
+            StdDescr::TrMultiA(d) => d.default_keychain(),
         }
     }
 
@@ -177,6 +738,7 @@ impl<S: DeriveSet> Derive<DerivedScript> for StdDescr<S> {
         match self {
             StdDescr::Wpkh(d) => d.keychains(),
             StdDescr::TrKey(d) => d.keychains(),
+            StdDescr::TrMultiA(d) => d.keychains(),
         }
     }
 
@@ -188,6 +750,7 @@ impl<S: DeriveSet> Derive<DerivedScript> for StdDescr<S> {
         match self {
             StdDescr::Wpkh(d) => d.derive(keychain, index),
             StdDescr::TrKey(d) => d.derive(keychain, index),
+            StdDescr::TrMultiA(d) => d.derive(keychain, index),
         }
     }
 }
@@ -195,39 +758,61 @@ impl<S: DeriveSet> Derive<DerivedScript> for StdDescr<S> {
 impl<K: DeriveSet<Compr = K, XOnly = K> + DeriveCompr + DeriveXOnly> Descriptor<K> for StdDescr<K>
 where Self: Derive<DerivedScript>
 {
-    type KeyIter<'k> = vec::IntoIter<&'k K> where Self: 'k, K: 'k;
+    type KeyIter<'k> = StdDescrIter<
+        <Wpkh<K> as Descriptor<K>>::KeyIter<'k>,
+        <TrKey<K> as Descriptor<K>>::KeyIter<'k>,
+        <TrMultiA<K> as Descriptor<K>>::KeyIter<'k>,
+    > where Self: 'k, K: 'k;
     type VarIter<'v> = iter::Empty<&'v ()> where Self: 'v, (): 'v;
-    type XpubIter<'x> = vec::IntoIter<&'x XpubSpec> where Self: 'x;
+    type XpubIter<'x> = StdDescrIter<
+        <Wpkh<K> as Descriptor<K>>::XpubIter<'x>,
+        <TrKey<K> as Descriptor<K>>::XpubIter<'x>,
+        <TrMultiA<K> as Descriptor<K>>::XpubIter<'x>,
+    > where Self: 'x;
 
     fn class(&self) -> SpkClass {
         match self {
             StdDescr::Wpkh(d) => d.class(),
             StdDescr::TrKey(d) => d.class(),
+            StdDescr::TrMultiA(d) => d.class(),
         }
     }
 
     fn keys(&self) -> Self::KeyIter<'_> {
         match self {
-            StdDescr::Wpkh(d) => d.keys().collect::<Vec<_>>(),
-            StdDescr::TrKey(d) => d.keys().collect::<Vec<_>>(),
+            StdDescr::Wpkh(d) => StdDescrIter::Wpkh(d.keys()),
+            StdDescr::TrKey(d) => StdDescrIter::TrKey(d.keys()),
+            StdDescr::TrMultiA(d) => StdDescrIter::TrMultiA(d.keys()),
         }
-        .into_iter()
     }
 
     fn vars(&self) -> Self::VarIter<'_> { iter::empty() }
 
     fn xpubs(&self) -> Self::XpubIter<'_> {
         match self {
-            StdDescr::Wpkh(d) => d.xpubs().collect::<Vec<_>>(),
-            StdDescr::TrKey(d) => d.xpubs().collect::<Vec<_>>(),
+            StdDescr::Wpkh(d) => StdDescrIter::Wpkh(d.xpubs()),
+            StdDescr::TrKey(d) => StdDescrIter::TrKey(d.xpubs()),
+            StdDescr::TrMultiA(d) => StdDescrIter::TrMultiA(d.xpubs()),
+        }
+    }
+
+    fn to_canonical_string(&self) -> String
+    where
+        Self: Display,
+        K: Display,
+    {
+        match self {
+            StdDescr::Wpkh(d) => d.to_canonical_string(),
+            StdDescr::TrKey(d) => d.to_canonical_string(),
+            StdDescr::TrMultiA(d) => d.to_canonical_string(),
         }
-        .into_iter()
     }
 
     fn compr_keyset(&self, terminal: Terminal) -> IndexMap<CompressedPk, KeyOrigin> {
         match self {
             StdDescr::Wpkh(d) => d.compr_keyset(terminal),
             StdDescr::TrKey(d) => d.compr_keyset(terminal),
+            StdDescr::TrMultiA(d) => d.compr_keyset(terminal),
         }
     }
 
@@ -235,6 +820,592 @@ where Self: Derive<DerivedScript>
         match self {
             StdDescr::Wpkh(d) => d.xonly_keyset(terminal),
             StdDescr::TrKey(d) => d.xonly_keyset(terminal),
+            StdDescr::TrMultiA(d) => d.xonly_keyset(terminal),
         }
     }
 }
+
+impl<K: DeriveSet<Compr = K, XOnly = K> + DeriveCompr + DeriveXOnly + Display> ToPolicy
+    for StdDescr<K>
+{
+    fn to_policy(&self) -> String {
+        match self {
+            StdDescr::Wpkh(d) => d.to_policy(),
+            StdDescr::TrKey(d) => d.to_policy(),
+            StdDescr::TrMultiA(d) => d.to_policy(),
+        }
+    }
+}
+
+impl<K: DeriveSet<Compr = K, XOnly = K> + DeriveCompr + DeriveXOnly> SatisfactionWeight
+    for StdDescr<K>
+{
+    fn max_satisfaction_weight(&self) -> WeightUnits {
+        match self {
+            StdDescr::Wpkh(d) => d.max_satisfaction_weight(),
+            StdDescr::TrKey(d) => d.max_satisfaction_weight(),
+            StdDescr::TrMultiA(d) => d.max_satisfaction_weight(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::hex::FromHex;
+    use derive::{
+        AddressNetwork, AddressPayload, KeychainSpecError, MusigKey, TapNodeHash, WPubkeyHash,
+        WifKey,
+    };
+
+    use super::*;
+    use crate::{Timelock, WshTimelocked};
+
+    const XPUB: &str = "[643a7adc/86'/1'/0']tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/0/*";
+
+    #[test]
+    fn parse_core() {
+        let descr = format!("tr({XPUB})");
+        let core = format!("{descr}#{}", checksum(&descr).unwrap());
+        let parsed = StdDescr::<XpubDerivable>::from_str(&core).unwrap();
+        assert_eq!(parsed, StdDescr::TrKey(TrKey::from(XpubDerivable::from_str(XPUB).unwrap())));
+        assert_eq!(parsed.to_string(), descr.replace('\'', "h"));
+        assert_eq!(parsed.to_policy(), format!("pk({})", XPUB.replace('\'', "h")));
+        assert_eq!(
+            Wpkh::<XpubDerivable>::from_str(&core),
+            Err(DescriptorParseError::Unsupported("tr".to_owned()))
+        );
+
+        let multipath = format!("wpkh({})", XPUB.replace("/0/*", "/<0;1>/*"));
+        let parsed = StdDescr::<XpubDerivable>::from_str(&multipath).unwrap();
+        assert_eq!(parsed.keychains().len(), 2);
+        assert_eq!(parsed.derive_multi(3u8), vec![
+            (Keychain::OUTER, parsed.derive(0u8, 3u8)),
+            (Keychain::INNER, parsed.derive(1u8, 3u8))
+        ]);
+        assert_eq!(parsed.to_string(), multipath.replace('\'', "h"));
+        assert_eq!(
+            parsed.to_policy(),
+            format!("pk({})", &multipath[5..multipath.len() - 1]).replace('\'', "h")
+        );
+
+        assert!(matches!(
+            StdDescr::<XpubDerivable>::from_str(&format!("{descr}#00000000")),
+            Err(DescriptorParseError::Checksum(ChecksumError::Mismatch { .. }))
+        ));
+        assert_eq!(
+            StdDescr::<XpubDerivable>::from_str(&format!("sh(wpkh({XPUB}))")),
+            Err(DescriptorParseError::Unsupported("sh".to_owned()))
+        );
+        assert!(matches!(
+            StdDescr::<XpubDerivable>::from_str("wpkh(xpub)"),
+            Err(DescriptorParseError::Key { pos: 5, .. })
+        ));
+
+        let bad_origin = format!("tr({})", XPUB.replace("643a7adc", "643a7adX"));
+        let err = StdDescr::<XpubDerivable>::from_str(&bad_origin).unwrap_err();
+        assert!(matches!(err, DescriptorParseError::Key {
+            pos: 3,
+            err: XpubParseError::InvalidMasterFp(_)
+        }));
+        assert!(err.source().and_then(|err| err.source()).is_some());
+        let multi = format!("tr({XPUB},sortedmulti_a(1,{XPUB},{}))", XPUB.replace("/0/*", "/0h/*"));
+        let Err(DescriptorParseError::Key { pos, err }) =
+            StdDescr::<XpubDerivable>::from_str(&multi)
+        else {
+            panic!("invalid multisig key must be reported");
+        };
+        assert_eq!(pos, multi.rfind('[').unwrap());
+        assert!(matches!(
+            err,
+            XpubParseError::KeychainSpec(KeychainSpecError::HardenedKeychain(_))
+        ));
+    }
+
+    #[test]
+    fn key_position() {
+        let pos = |s: &str| match StdDescr::<XpubDerivable>::from_str(s) {
+            Err(DescriptorParseError::Key { pos, .. }) => pos,
+            res => panic!("unexpected parse result {res:?}"),
+        };
+        assert_eq!(pos("  wpkh(xpub)"), 7);
+        let descr = "wpkh(xpub)";
+        assert_eq!(pos(&format!(" {descr}#{} ", checksum(descr).unwrap())), 6);
+        let multi = format!("tr({XPUB},sortedmulti_a(1,{XPUB},xpub,{XPUB}))");
+        assert_eq!(pos(&multi), multi.find(",xpub,").unwrap() + 1);
+
+        assert!(matches!(
+            WshTimelocked::<XpubDerivable>::from_str("wsh(and_v(v:pk(xpub),older(144)))"),
+            Err(DescriptorParseError::Key { pos: 15, .. })
+        ));
+    }
+
+    #[test]
+    fn tr_multi_a() {
+        let xpub = XPUB.replace("/0/*", "/<0;1>/*");
+        let cosigner1 = "[deadbeef/0h]xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw/<0;1>/*";
+        let cosigner2 = "[beefcafe/1h/2h/3h/4h/5h]xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy/<0;1>/*";
+        let s = format!("tr({xpub},sortedmulti_a(1,{cosigner1},{cosigner2}))");
+        let descr = StdDescr::<XpubDerivable>::from_str(&s).unwrap();
+        assert!(matches!(descr, StdDescr::TrMultiA(_)));
+        assert_eq!(descr.class(), SpkClass::P2tr);
+        assert_eq!(descr.to_string(), s.replace('\'', "h"));
+        assert_eq!(descr.keys().count(), 3);
+        assert_eq!(descr.xpubs().count(), 3);
+        assert_eq!(descr.keychains().len(), 2);
+
+        let terminal = Terminal::new(Keychain::INNER, NormalIndex::from(4u8));
+        let keyset = descr.xonly_keyset(terminal);
+        assert_eq!(keyset.len(), 3);
+        assert_eq!(keyset.values().filter(|derivation| derivation.is_key_path_only()).count(), 1);
+        assert!(descr.compr_keyset(terminal).is_empty());
+
+        let paths = descr.signing_paths(terminal);
+        assert_eq!(paths.len(), 3);
+        for origin in ["643a7adc/86h/1h/0h/1/4", "deadbeef/0h/1/4", "beefcafe/1h/2h/3h/4h/5h/1/4"] {
+            assert!(paths.contains(&KeyOrigin::from_str(origin).unwrap()));
+        }
+    }
+
+    #[test]
+    fn signing_paths_without_origin() {
+        // xpub with an unknown master fingerprint: the fingerprint is zero, yet the key can sign
+        let xpub = XPUB.replace("643a7adc", "00000000");
+        let descr = StdDescr::<XpubDerivable>::from_str(&format!("tr({xpub})")).unwrap();
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(5u8));
+        assert_eq!(descr.signing_paths(terminal), vec![KeyOrigin::from_str(
+            "00000000/86h/1h/0h/0/5"
+        )
+        .unwrap()]);
+
+        let pk = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+        let wpkh = Wpkh::<CompressedPk>::from_str(&format!("wpkh({pk})")).unwrap();
+        assert!(wpkh.signing_paths(terminal).is_empty());
+    }
+
+    #[test]
+    fn into_multisig() {
+        let key = XpubDerivable::from_str(XPUB).unwrap();
+        let cosigner1 = XpubDerivable::from_str("[deadbeef/0h]xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw/0/*").unwrap();
+        let cosigner2 = XpubDerivable::from_str("[beefcafe/1h/2h/3h/4h/5h]xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy/0/*").unwrap();
+        let cosigners = vec![cosigner1.clone(), cosigner2];
+
+        let single = StdDescr::<XpubDerivable>::from(Wpkh::from(key.clone()));
+        let multi = single.into_multisig(2, cosigners.clone()).unwrap();
+        let StdDescr::TrMultiA(ref tr_multi) = multi else {
+            panic!("multisig descriptor expected");
+        };
+        assert_eq!(tr_multi.threshold(), 2);
+        assert_eq!(tr_multi.internal_key(), None);
+        assert_eq!(tr_multi.script_keys()[0], key);
+        assert_eq!(tr_multi.script_keys()[0].origin(), key.origin());
+        assert_eq!(multi.xpubs().count(), 3);
+
+        let single = StdDescr::<XpubDerivable>::from(TrKey::from(key.clone()));
+        assert_eq!(single.into_multisig(2, cosigners.clone()), Ok(multi.clone()));
+
+        assert_eq!(multi.into_multisig(2, cosigners.clone()), Err(MultiError::AlreadyMultisig));
+        let single = StdDescr::<XpubDerivable>::from(Wpkh::from(key.clone()));
+        assert_eq!(
+            single.clone().into_multisig(4, cosigners),
+            Err(MultiError::ThresholdExceedsKeys {
+                threshold: 4,
+                keys: 3
+            })
+        );
+        assert_eq!(
+            single.into_multisig(1, vec![key.clone()]),
+            Err(MultiError::DuplicateKey(key.xpub()))
+        );
+    }
+
+    #[test]
+    fn capabilities() {
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let wpkh = Wpkh::from(xpub.clone());
+        let tr = TrKey::from(xpub.clone());
+        let tr_multi = TrMultiA::new(None, 1, [xpub.clone()]).unwrap();
+        let wsh_tl = WshTimelocked::new(xpub, Timelock::from_str("older(144)").unwrap());
+
+        assert!(!wpkh.supports_taproot() && wpkh.supports_segwit() && !wpkh.requires_prev_tx());
+        assert!(tr.supports_taproot() && tr.supports_segwit() && !tr.requires_prev_tx());
+        assert!(
+            tr_multi.supports_taproot()
+                && tr_multi.supports_segwit()
+                && !tr_multi.requires_prev_tx()
+        );
+        assert!(
+            !wsh_tl.supports_taproot() && wsh_tl.supports_segwit() && !wsh_tl.requires_prev_tx()
+        );
+
+        let descr = StdDescr::<XpubDerivable>::from(wpkh);
+        assert!(!descr.supports_taproot() && descr.supports_segwit());
+        let descr = StdDescr::<XpubDerivable>::from(tr);
+        assert!(descr.supports_taproot() && !descr.requires_prev_tx());
+    }
+
+    #[test]
+    fn keyset_range() {
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let wpkh = Wpkh::from(xpub.clone());
+        let tr = TrKey::from(xpub);
+
+        let keysets = wpkh.compr_keyset_range(Keychain::INNER, 3u8, 4);
+        assert_eq!(keysets.len(), 4);
+        for (offset, keyset) in keysets.into_iter().enumerate() {
+            let terminal = Terminal::new(Keychain::INNER, NormalIndex::from(3 + offset as u8));
+            assert_eq!(keyset, wpkh.compr_keyset(terminal));
+        }
+        let keysets = tr.xonly_keyset_range(Keychain::OUTER, 0u8, 2);
+        assert_eq!(keysets, vec![
+            tr.xonly_keyset(Terminal::new(Keychain::OUTER, NormalIndex::ZERO)),
+            tr.xonly_keyset(Terminal::new(Keychain::OUTER, NormalIndex::ONE)),
+        ]);
+
+        assert_eq!(wpkh.compr_keyset_range(Keychain::OUTER, NormalIndex::MAX, 3).len(), 1);
+        assert!(tr.xonly_keyset_range(Keychain::OUTER, 0u8, 0).is_empty());
+    }
+
+    #[test]
+    fn contains_key() {
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let descr = StdDescr::<XpubDerivable>::from(Wpkh::from(xpub.clone()));
+        assert!(descr.contains_fingerprint(XpubFp::from_str("643a7adc").unwrap()));
+        assert!(descr.contains_fingerprint(xpub.xpub().fingerprint()));
+        assert!(!descr.contains_fingerprint(XpubFp::from_str("deadbeef").unwrap()));
+        assert!(descr.contains_xpub(xpub.spec()));
+
+        let other = XpubDerivable::from_str(&XPUB.replace("643a7adc", "deadbeef")).unwrap();
+        assert!(descr.contains_xpub(other.spec()));
+        assert!(!descr.contains_fingerprint(XpubFp::from_str("00000000").unwrap()));
+    }
+
+    #[test]
+    fn wif_single_key() {
+        let wif = WifKey::from_str("KwdMAjGmerYanjeui5SHS7JkmpZvVipYvB2LJGU1ZxJwYvP98617").unwrap();
+        let pk = wif.to_compr_pub().unwrap();
+        let descr = Wpkh::with_wif(&wif).unwrap();
+        assert_eq!(descr.keychains(), bset![Keychain::OUTER]);
+        assert_eq!(descr.xpubs().count(), 0);
+        assert_eq!(descr.to_string(), format!("wpkh({pk})"));
+
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(5u8));
+        assert_eq!(descr.compr_keyset(terminal).into_iter().collect::<Vec<_>>(), vec![(
+            pk,
+            KeyOrigin::default()
+        )]);
+        assert_eq!(descr.derive(1u8, 5u8), descr.derive(0u8, 0u8));
+
+        let uncompressed =
+            WifKey::from_str("5HueCGU8rMjxEXxiPuD5BDku4MkFqeZyd4dZ1jvhTVqvbTLvyTJ").unwrap();
+        assert_eq!(Wpkh::with_wif(&uncompressed), None);
+    }
+
+    #[test]
+    fn musig_keyset() {
+        let other = "[deadbeef/0']xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw/0/*";
+        let descr = format!("tr(musig({XPUB},{other}))");
+        let tr = TrKey::<MusigKey>::from_str(&descr).unwrap();
+        assert_eq!(tr.to_string(), descr.replace('\'', "h"));
+        assert_eq!(tr.xpubs().count(), 0);
+
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(7u8));
+        let keyset = tr.xonly_keyset(terminal);
+        // the aggregated key has no origin, so only the participants are listed
+        assert_eq!(keyset.len(), 2);
+        let agg = tr.as_internal_key().derive(terminal.keychain, terminal.index);
+        assert!(!keyset.contains_key(&agg));
+        let fps = keyset.values().map(|d| d.origin.master_fp()).collect::<Vec<_>>();
+        assert_eq!(fps, vec![
+            XpubFp::from_str("643a7adc").unwrap(),
+            XpubFp::from_str("deadbeef").unwrap()
+        ]);
+        // the aggregated key has no origin
+        let paths = tr.signing_paths(terminal);
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[1], KeyOrigin::from_str("deadbeef/0h/0/7").unwrap());
+    }
+
+    #[test]
+    fn raw_keys() {
+        let pk = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+        let wpkh = Wpkh::<CompressedPk>::from_str(&format!("wpkh({pk})")).unwrap();
+        assert_eq!(wpkh.to_string(), format!("wpkh({pk})"));
+        assert_eq!(wpkh.derive(0u8, 0u8), wpkh.derive(1u8, 1000u16));
+        let compr = CompressedPk::from_str(pk).unwrap();
+        assert_eq!(
+            wpkh.derive(0u8, 0u8).to_script_pubkey(),
+            ScriptPubkey::p2wpkh(WPubkeyHash::from(compr))
+        );
+        let terminal = Terminal::new(Keychain::INNER, NormalIndex::from(12u8));
+        assert_eq!(wpkh.compr_keyset(terminal).into_iter().collect::<Vec<_>>(), vec![(
+            compr,
+            KeyOrigin::default()
+        )]);
+
+        let xonly = &pk[2..];
+        let tr = TrKey::<XOnlyPk>::from_str(&format!("tr({xonly})")).unwrap();
+        assert_eq!(tr.to_string(), format!("tr({xonly})"));
+        assert_eq!(tr.derive(0u8, 0u8), tr.derive(1u8, 1000u16));
+        assert_eq!(tr.xonly_keyset(terminal).into_iter().collect::<Vec<_>>(), vec![(
+            XOnlyPk::from_str(xonly).unwrap(),
+            TapDerivation::default()
+        )]);
+        assert!(matches!(
+            TrKey::<XOnlyPk>::from_str(&format!("tr({pk})")),
+            Err(DescriptorParseError::Key { pos: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn watch_only() {
+        let xpriv = "xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7";
+        let secret =
+            StdDescr::<XprivDerivable>::from_str(&format!("wpkh([3442193e/0h]{xpriv}/<0;1>/*)"))
+                .unwrap();
+        let xpub = XprivDerivable::from_str(&format!("[3442193e/0h]{xpriv}/<0;1>/*"))
+            .unwrap()
+            .xpriv()
+            .to_xpub();
+        let public = format!("wpkh([3442193e/0h]{xpub}/<0;1>/*)");
+        let watch_only = secret.to_watch_only();
+        assert_eq!(watch_only.to_string(), public);
+        assert_eq!(watch_only, StdDescr::<XpubDerivable>::from_str(&public).unwrap());
+
+        let terminal = Terminal::new(Keychain::INNER, NormalIndex::from(9u8));
+        assert_eq!(secret.compr_keyset(terminal), watch_only.compr_keyset(terminal));
+        assert_eq!(secret.derive(1u8, 9u8), watch_only.derive(1u8, 9u8));
+    }
+
+    #[test]
+    fn seckeysets() {
+        let xpriv = "[3442193e/0h]xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7/<0;1>/*";
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(3u8));
+
+        let wpkh = StdDescr::<XprivDerivable>::from_str(&format!("wpkh({xpriv})")).unwrap();
+        let seckeys = wpkh.compr_seckeyset(terminal);
+        assert_eq!(seckeys.len(), 1);
+        assert_eq!(
+            seckeys.keys().collect::<Vec<_>>(),
+            wpkh.compr_keyset(terminal).keys().collect::<Vec<_>>()
+        );
+        assert!(wpkh.xonly_seckeyset(terminal).is_empty());
+
+        let tr = StdDescr::<XprivDerivable>::from_str(&format!("tr({xpriv})")).unwrap();
+        let keypairs = tr.xonly_seckeyset(terminal);
+        assert_eq!(keypairs.len(), 1);
+        assert_eq!(
+            keypairs.keys().collect::<Vec<_>>(),
+            tr.xonly_keyset(terminal).keys().collect::<Vec<_>>()
+        );
+        assert!(tr.compr_seckeyset(terminal).is_empty());
+    }
+
+    #[test]
+    fn network_consistency() {
+        let testnet = XpubDerivable::from_str(XPUB).unwrap();
+        let mainnet = XpubDerivable::from_str("[deadbeef/0']xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw/0/*").unwrap();
+
+        let tr = TrKey::from(testnet.clone());
+        assert_eq!(tr.network(), Ok(Some(Network::Testnet3)));
+        assert_eq!(tr.check_network(Network::Signet), Ok(()));
+        assert_eq!(
+            tr.check_network(Network::Mainnet),
+            Err(NetworkMismatch::Network(Network::Mainnet))
+        );
+        assert_eq!(
+            tr.derive_address_checked(Network::Mainnet, 0u8, 0u8),
+            Err(DescriptorAddressError::Network(NetworkMismatch::Network(Network::Mainnet)))
+        );
+        let addr = tr.derive_address_checked(Network::Regtest, 0u8, 0u8).unwrap();
+        assert!(addr.to_string().starts_with("bcrt1p"));
+
+        let wpkh = Wpkh::from(mainnet.clone());
+        assert_eq!(wpkh.network(), Ok(Some(Network::Mainnet)));
+        assert!(wpkh.derive_address_checked(Network::Mainnet, 0u8, 0u8).is_ok());
+
+        let mixed = TrMultiA::new(Some(testnet), 1, [mainnet]).unwrap();
+        assert_eq!(mixed.network(), Err(NetworkMismatch::MixedKeys));
+        assert_eq!(mixed.check_network(Network::Testnet3), Err(NetworkMismatch::MixedKeys));
+
+        let raw = Wpkh::<CompressedPk>::from_str(
+            "wpkh(02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9)",
+        )
+        .unwrap();
+        assert_eq!(raw.network(), Ok(None));
+        assert_eq!(raw.check_network(Network::Mainnet), Ok(()));
+    }
+
+    #[test]
+    fn taproot_output_key() {
+        // BIP-341 wallet test vector for a key-path only output
+        let internal_key = "d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d";
+        let tr = TrKey::<XOnlyPk>::from_str(&format!("tr({internal_key})")).unwrap();
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(0u8));
+        assert_eq!(
+            tr.output_key(terminal).to_string(),
+            "53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343"
+        );
+
+        // BIP-341 wallet test vector for an output committing to a single-leaf script tree
+        let internal_key = "187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27";
+        let merkle_root = TapNodeHash::from_str(
+            "5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21",
+        )
+        .unwrap();
+        let tr = TrKey::with_merkle_root(XOnlyPk::from_str(internal_key).unwrap(), merkle_root);
+        assert_eq!(tr.merkle_root(), Some(merkle_root));
+        assert_eq!(tr.to_string(), format!("tr({internal_key},rawnode({merkle_root}))"));
+        assert_eq!(TrKey::from_str(&tr.to_string()), Ok(tr.clone()));
+        assert_ne!(tr.descriptor_id(), TrKey::from(*tr.as_internal_key()).descriptor_id());
+        assert_eq!(
+            tr.output_key(terminal).to_string(),
+            "147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3"
+        );
+        let derived = tr.derive(terminal.keychain, terminal.index);
+        assert_eq!(derived.to_tap_root(), Some(merkle_root));
+        assert_eq!(derived.as_tap_tree(), None);
+        assert_eq!(
+            derived.to_script_pubkey(),
+            ScriptPubkey::from_hex(
+                "5120147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3"
+            )
+            .unwrap()
+        );
+
+        let tr = TrKey::from(XpubDerivable::from_str(XPUB).unwrap());
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(5u8));
+        let spk = tr.derive(terminal.keychain, terminal.index).to_script_pubkey();
+        let script: &[u8] = spk.as_ref();
+        assert_eq!(&script[2..], &tr.output_key(terminal).to_byte_array());
+    }
+
+    #[test]
+    fn taproot_address_roundtrip() {
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let merkle_root = TapNodeHash::from([7u8; 32]);
+        let descriptors: [StdDescr; 3] = [
+            StdDescr::from(TrKey::from(xpub.clone())),
+            StdDescr::from(TrKey::with_merkle_root(xpub.clone(), merkle_root)),
+            StdDescr::from(TrMultiA::new(None, 1, [xpub]).unwrap()),
+        ];
+        let mut script_pubkeys = BTreeSet::new();
+        for descr in descriptors {
+            let derived = descr.derive(Keychain::OUTER, 0u8);
+            let addr = descr.derive_address(AddressNetwork::Testnet, 0u8, 0u8).unwrap();
+            // Key-path and script-path outputs are indistinguishable at the address level
+            assert!(matches!(addr.payload, AddressPayload::Tr(_)));
+            let parsed = Address::from_str(&addr.to_string()).unwrap();
+            assert_eq!(parsed, addr);
+            assert_eq!(parsed.script_pubkey(), derived.to_script_pubkey());
+            script_pubkeys.insert(parsed.script_pubkey());
+        }
+        assert_eq!(script_pubkeys.len(), 3);
+    }
+
+    #[test]
+    fn address_at() {
+        let tr = StdDescr::<XpubDerivable>::from_str(&format!("tr({XPUB})")).unwrap();
+        let first = tr.first_address(Network::Testnet3).unwrap();
+        assert_eq!(Some(first), tr.address_at(0u8, 0u8, Network::Testnet3));
+        assert_eq!(first, tr.derive_address(AddressNetwork::Testnet, 0u8, 0u8).unwrap());
+        assert_ne!(tr.address_at(0u8, 1u8, Network::Testnet3), Some(first));
+        assert!(first.to_string().starts_with("tb1p"));
+
+        let change =
+            tr.addresses(Keychain::INNER, 5u8, Network::Testnet3).take(3).collect::<Vec<_>>();
+        assert_eq!(change.len(), 3);
+        for (offset, (index, addr)) in change.into_iter().enumerate() {
+            assert_eq!(index, NormalIndex::from(5 + offset as u8));
+            assert_eq!(tr.address_at(1u8, index, Network::Testnet3), Some(addr));
+        }
+        let last = NormalIndex::MAX.saturating_dec();
+        assert_eq!(tr.addresses(0u8, last, Network::Testnet3).count(), 2);
+    }
+
+    #[test]
+    fn first_unused() {
+        let tr = StdDescr::<XpubDerivable>::from_str(&format!("tr({XPUB})")).unwrap();
+        let spk = |index: u8| tr.derive(0u8, index).to_script_pubkey();
+        let used = [spk(0), spk(1), spk(3)];
+        assert_eq!(tr.first_unused(0u8, |spk| used.contains(spk), 20), NormalIndex::from(2u8));
+        assert_eq!(tr.first_unused(0u8, |_| false, 20), NormalIndex::ZERO);
+        assert_eq!(tr.first_unused(0u8, |_| true, 20), NormalIndex::from(20u8));
+        assert_eq!(tr.first_unused(0u8, |spk| used.contains(spk), 2), NormalIndex::from(2u8));
+    }
+
+    #[test]
+    fn descriptor_id() {
+        let descr = StdDescr::<XpubDerivable>::from_str(&format!("tr({XPUB})")).unwrap();
+        let id = descr.descriptor_id();
+        assert_eq!(
+            id.iter().map(|b| format!("{b:02x}")).collect::<String>(),
+            "271af78486d159712e3fc098c9af7ca580f1941be4a9c6c58505c1ca4b10f630"
+        );
+
+        let with_checksum = format!("{descr}#n0g9042x");
+        assert_eq!(
+            StdDescr::<XpubDerivable>::from_str(&with_checksum).unwrap().descriptor_id(),
+            id
+        );
+        assert_eq!(TrKey::from(XpubDerivable::from_str(XPUB).unwrap()).descriptor_id(), id);
+        assert_ne!(Wpkh::from(XpubDerivable::from_str(XPUB).unwrap()).descriptor_id(), id);
+    }
+
+    #[test]
+    fn descriptor_id_sorted_multi() {
+        let key = XpubDerivable::from_str(XPUB).unwrap();
+        let cosigner = XpubDerivable::from_str("[deadbeef/0h]xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw/0/*").unwrap();
+        let multi1 = TrMultiA::new(None, 1, [key.clone(), cosigner.clone()]).unwrap();
+        let multi2 = TrMultiA::new(None, 1, [cosigner, key]).unwrap();
+        assert_ne!(multi1.to_string(), multi2.to_string());
+        assert_eq!(multi1.to_canonical_string(), multi2.to_canonical_string());
+        assert_eq!(multi1.descriptor_id(), multi2.descriptor_id());
+        assert_eq!(
+            StdDescr::<XpubDerivable>::from(multi1.clone()).descriptor_id(),
+            StdDescr::<XpubDerivable>::from(multi2).descriptor_id()
+        );
+        assert_eq!(
+            StdDescr::<XpubDerivable>::from_str(&multi1.to_canonical_string()).unwrap(),
+            StdDescr::from(multi1)
+        );
+    }
+
+    #[test]
+    fn slip132_class() {
+        assert_eq!(SpkClass::from_slip132(Slip132Type::Bip32), None);
+        assert_eq!(SpkClass::from_slip132(Slip132Type::NestedWpkh), Some(SpkClass::P2sh));
+        assert_eq!(SpkClass::from_slip132(Slip132Type::Wpkh), Some(SpkClass::P2wpkh));
+        assert_eq!(SpkClass::from_slip132(Slip132Type::NestedWshMulti), Some(SpkClass::P2sh));
+        assert_eq!(SpkClass::from_slip132(Slip132Type::WshMulti), Some(SpkClass::P2wsh));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_json() {
+        fn check<T>(value: T, json: &str)
+        where T: serde::Serialize + for<'de> serde::Deserialize<'de> + Eq + fmt::Debug {
+            assert_eq!(serde_json::to_string(&value).unwrap(), json);
+            assert_eq!(serde_json::from_str::<T>(json).unwrap(), value);
+        }
+
+        let key = XpubDerivable::from_str(XPUB).unwrap();
+        let xpub = XPUB.replace('\'', "h");
+        let pk = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+        let root = TapNodeHash::from([1u8; 32]);
+
+        check(Wpkh::from(CompressedPk::from_str(pk).unwrap()), &format!(r#"{{"key":"{pk}"}}"#));
+        check(TrKey::from(key.clone()), &format!(r#"{{"internalKey":"{xpub}"}}"#));
+        check(
+            TrKey::with_merkle_root(key.clone(), root),
+            &format!(r#"{{"internalKey":"{xpub}","merkleRoot":"{root}"}}"#),
+        );
+        check(
+            TrMultiA::new(None, 1, [key.clone()]).unwrap(),
+            &format!(r#"{{"internalKey":null,"threshold":1,"scriptKeys":["{xpub}"]}}"#),
+        );
+        check(
+            StdDescr::<XpubDerivable>::from(Wpkh::from(key)),
+            &format!(r#"{{"wpkh":{{"key":"{xpub}"}}}}"#),
+        );
+    }
+}
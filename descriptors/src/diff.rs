@@ -0,0 +1,367 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Comparison of descriptors, used for auditing wallet configuration changes.
+
+use std::collections::BTreeSet;
+
+use derive::{
+    Derive, DeriveCompr, DeriveKey, DeriveSet, DeriveXOnly, DerivedScript, Keychain, TapNodeHash,
+    XOnlyPk, XpubDerivable, XpubSpec,
+};
+
+use crate::{Descriptor, SpkClass, StdDescr, TrKey, TrMultiA, Wpkh};
+
+/// Differences between two descriptors, as reported by [`diff`].
+///
+/// Keys are compared as a whole, including the origin, the keychains and the derivation tail of
+/// the extended keys. Two different extended keys built from the same extended public key are
+/// reported as a changed key; other keys, including the non-derivable ones, are reported as
+/// removed and added. The internal key of taproot descriptors is compared separately from the
+/// keys used in the scripts, so moving a key between the key path and the script path is
+/// reported. The order of the script keys is ignored, so descriptors which differ only in the
+/// order of multisig keys are considered equivalent.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct DescriptorDiff<K = XpubDerivable> {
+    /// Keys used in the scripts present only in the new descriptor.
+    pub added_keys: Vec<K>,
+
+    /// Keys used in the scripts present only in the old descriptor.
+    pub removed_keys: Vec<K>,
+
+    /// Keys used in the scripts of both descriptors which share the same extended public key, but
+    /// differ in the origin, keychains or derivation, as old and new keys.
+    pub changed_keys: Vec<(K, K)>,
+
+    /// Old and new taproot internal key, if it has changed. `None` is used for descriptors
+    /// without an internal key (non-taproot descriptors or taproot descriptors with the key path
+    /// disabled).
+    pub internal_key: Option<(Option<K>, Option<K>)>,
+
+    /// Old and new merkle root of the script tree committed to by key-path-only taproot
+    /// descriptors, if it has changed.
+    pub merkle_root: Option<(Option<TapNodeHash>, Option<TapNodeHash>)>,
+
+    /// Old and new script pubkey class, if it has changed.
+    pub class: Option<(SpkClass, SpkClass)>,
+
+    /// Old and new number of signatures required for spending, if it has changed. Single-key
+    /// descriptors require one signature.
+    pub threshold: Option<(u16, u16)>,
+
+    /// Old and new sets of keychains, if they have changed.
+    pub keychains: Option<(BTreeSet<Keychain>, BTreeSet<Keychain>)>,
+}
+
+impl<K> Default for DescriptorDiff<K> {
+    fn default() -> Self {
+        DescriptorDiff {
+            added_keys: vec![],
+            removed_keys: vec![],
+            changed_keys: vec![],
+            internal_key: None,
+            merkle_root: None,
+            class: None,
+            threshold: None,
+            keychains: None,
+        }
+    }
+}
+
+impl<K> DescriptorDiff<K> {
+    /// Checks whether the descriptors are equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.added_keys.is_empty()
+            && self.removed_keys.is_empty()
+            && self.changed_keys.is_empty()
+            && self.internal_key.is_none()
+            && self.merkle_root.is_none()
+            && self.class.is_none()
+            && self.threshold.is_none()
+            && self.keychains.is_none()
+    }
+}
+
+/// Descriptors which can be compared with [`diff`].
+pub trait Diffable<K>: Descriptor<K> {
+    /// Returns keys of the descriptor split into the taproot internal key and the keys used in
+    /// the scripts.
+    fn key_roles(&self) -> (Option<&K>, Vec<&K>);
+
+    /// Returns extended public key specification of a descriptor key, or `None` for
+    /// non-derivable keys.
+    fn key_spec(key: &K) -> Option<&XpubSpec>;
+
+    /// Returns merkle root of the script tree committed to by key-path-only taproot descriptors.
+    fn merkle_root(&self) -> Option<TapNodeHash> { None }
+
+    /// Returns number of signatures required for spending.
+    fn threshold(&self) -> u16 { 1 }
+}
+
+impl<K: DeriveCompr> Diffable<K> for Wpkh<K> {
+    fn key_roles(&self) -> (Option<&K>, Vec<&K>) { (None, vec![self.as_key()]) }
+
+    fn key_spec(key: &K) -> Option<&XpubSpec> { key.try_xpub_spec() }
+}
+
+impl<K: DeriveXOnly> Diffable<K> for TrKey<K> {
+    fn key_roles(&self) -> (Option<&K>, Vec<&K>) { (Some(self.as_internal_key()), vec![]) }
+
+    fn key_spec(key: &K) -> Option<&XpubSpec> { key.try_xpub_spec() }
+
+    fn merkle_root(&self) -> Option<TapNodeHash> { TrKey::merkle_root(self) }
+}
+
+impl<K: DeriveXOnly> Diffable<K> for TrMultiA<K> {
+    fn key_roles(&self) -> (Option<&K>, Vec<&K>) {
+        (self.internal_key(), self.script_keys().iter().collect())
+    }
+
+    fn key_spec(key: &K) -> Option<&XpubSpec> { key.try_xpub_spec() }
+
+    fn threshold(&self) -> u16 { TrMultiA::threshold(self) }
+}
+
+impl<K> Diffable<K> for StdDescr<K>
+where K: DeriveSet<Compr = K, XOnly = K> + DeriveCompr + DeriveXOnly
+{
+    fn key_roles(&self) -> (Option<&K>, Vec<&K>) {
+        match self {
+            StdDescr::Wpkh(d) => d.key_roles(),
+            StdDescr::TrKey(d) => d.key_roles(),
+            StdDescr::TrMultiA(d) => d.key_roles(),
+        }
+    }
+
+    fn key_spec(key: &K) -> Option<&XpubSpec> { <K as DeriveKey<XOnlyPk>>::try_xpub_spec(key) }
+
+    fn merkle_root(&self) -> Option<TapNodeHash> {
+        match self {
+            StdDescr::TrKey(d) => Diffable::merkle_root(d),
+            StdDescr::Wpkh(_) | StdDescr::TrMultiA(_) => None,
+        }
+    }
+
+    fn threshold(&self) -> u16 {
+        match self {
+            StdDescr::Wpkh(_) | StdDescr::TrKey(_) => 1,
+            StdDescr::TrMultiA(d) => Diffable::threshold(d),
+        }
+    }
+}
+
+/// Reports keys, threshold, script class and keychains which differ between the `old` and the
+/// `new` descriptors.
+pub fn diff<K, D>(old: &D, new: &D) -> DescriptorDiff<K>
+where
+    D: Diffable<K>,
+    K: Eq + Clone,
+{
+    let (old_internal, mut removed) = old.key_roles();
+    let (new_internal, mut added) = new.key_roles();
+
+    let mut diff = DescriptorDiff::default();
+    if old_internal != new_internal {
+        diff.internal_key = Some((old_internal.cloned(), new_internal.cloned()));
+    }
+    let (old_root, new_root) = (old.merkle_root(), new.merkle_root());
+    if old_root != new_root {
+        diff.merkle_root = Some((old_root, new_root));
+    }
+
+    removed.retain(|key| match added.iter().position(|other| other == key) {
+        Some(pos) => {
+            added.remove(pos);
+            false
+        }
+        None => true,
+    });
+    let same_xpub = |a: &K, b: &K| match (D::key_spec(a), D::key_spec(b)) {
+        (Some(a), Some(b)) => a.xpub() == b.xpub(),
+        _ => false,
+    };
+    removed.retain(|key| match added.iter().position(|other| same_xpub(other, key)) {
+        Some(pos) => {
+            diff.changed_keys.push(((*key).clone(), added.remove(pos).clone()));
+            false
+        }
+        None => true,
+    });
+    diff.removed_keys = removed.into_iter().cloned().collect();
+    diff.added_keys = added.into_iter().cloned().collect();
+
+    let (old_class, new_class) = (old.class(), new.class());
+    if old_class != new_class {
+        diff.class = Some((old_class, new_class));
+    }
+    let (old_threshold, new_threshold) = (old.threshold(), new.threshold());
+    if old_threshold != new_threshold {
+        diff.threshold = Some((old_threshold, new_threshold));
+    }
+    let old_keychains = Derive::<DerivedScript>::keychains(old);
+    let new_keychains = Derive::<DerivedScript>::keychains(new);
+    if old_keychains != new_keychains {
+        diff.keychains = Some((old_keychains, new_keychains));
+    }
+    diff
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use derive::{CompressedPk, XpubDerivable};
+
+    use super::*;
+
+    const KEYS: [&str; 3] = [
+        "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*",
+        "[deadbeef/0h]xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw/<0;1>/*",
+        "[beefcafe/1h/2h/3h/4h/5h]xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy/<0;1>/*",
+    ];
+
+    fn key(no: usize) -> XpubDerivable { XpubDerivable::from_str(KEYS[no]).unwrap() }
+
+    fn multi(threshold: u16, keys: &[usize]) -> StdDescr {
+        TrMultiA::new(None, threshold, keys.iter().map(|no| key(*no))).unwrap().into()
+    }
+
+    #[test]
+    fn equivalent() {
+        assert!(diff(&multi(2, &[0, 1, 2]), &multi(2, &[2, 0, 1])).is_empty());
+        let wpkh = StdDescr::<XpubDerivable>::from(Wpkh::from(key(0)));
+        assert!(diff(&wpkh, &wpkh.clone()).is_empty());
+    }
+
+    #[test]
+    fn rotation() {
+        let old = multi(2, &[0, 1]);
+        let new = multi(3, &[0, 2, 1]);
+        let diff = diff(&old, &new);
+        assert_eq!(diff.added_keys, vec![key(2)]);
+        assert!(diff.removed_keys.is_empty() && diff.changed_keys.is_empty());
+        assert_eq!(diff.threshold, Some((2, 3)));
+        assert_eq!(diff.class, None);
+        assert_eq!(diff.keychains, None);
+
+        let new = multi(2, &[0, 2]);
+        let diff = super::diff(&old, &new);
+        assert_eq!(diff.added_keys, vec![key(2)]);
+        assert_eq!(diff.removed_keys, vec![key(1)]);
+        assert_eq!(diff.threshold, None);
+    }
+
+    #[test]
+    fn key_changes() {
+        let old = StdDescr::<XpubDerivable>::from(Wpkh::from(key(0)));
+        let new = StdDescr::<XpubDerivable>::from(TrKey::from(key(0)));
+        let diff = diff(&old, &new);
+        assert_eq!(diff.class, Some((SpkClass::P2wpkh, SpkClass::P2tr)));
+        assert!(diff.added_keys.is_empty());
+        assert_eq!(diff.removed_keys, vec![key(0)]);
+        assert_eq!(diff.internal_key, Some((None, Some(key(0)))));
+
+        let moved = KEYS[0].replace("643a7adc", "00c0ffee").replace("<0;1>", "<0;1;2>");
+        let new =
+            StdDescr::<XpubDerivable>::from(Wpkh::from(XpubDerivable::from_str(&moved).unwrap()));
+        let diff = super::diff(&old, &new);
+        assert_eq!(diff.changed_keys.len(), 1);
+        assert_eq!(diff.changed_keys[0].1.spec().origin().master_fp().to_string(), "00c0ffee");
+        assert_eq!(
+            diff.keychains,
+            Some((
+                [Keychain::OUTER, Keychain::INNER].into(),
+                [Keychain::OUTER, Keychain::INNER, Keychain::with(2)].into()
+            ))
+        );
+    }
+
+    #[test]
+    fn raw_keys() {
+        let old = CompressedPk::from_str(
+            "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9",
+        )
+        .unwrap();
+        let new = CompressedPk::from_str(
+            "0339a36013301597daef41fbe593a02cc513d0b55527ec2df1050e2e8ff49c85c2",
+        )
+        .unwrap();
+        let old_descr = Wpkh::from(old);
+        let new_descr = Wpkh::from(new);
+        assert!(diff(&old_descr, &old_descr.clone()).is_empty());
+        let diff = diff(&old_descr, &new_descr);
+        assert!(!diff.is_empty());
+        assert_eq!(diff.removed_keys, vec![old]);
+        assert_eq!(diff.added_keys, vec![new]);
+        assert!(diff.changed_keys.is_empty());
+    }
+
+    #[test]
+    fn keychain_change() {
+        let changed = KEYS[0].replace("<0;1>", "<0;2>");
+        let changed = XpubDerivable::from_str(&changed).unwrap();
+        let old = StdDescr::<XpubDerivable>::from(Wpkh::from(key(0)));
+        let new = StdDescr::<XpubDerivable>::from(Wpkh::from(changed.clone()));
+        let diff = diff(&old, &new);
+        assert_eq!(diff.changed_keys, vec![(key(0), changed.clone())]);
+        assert!(diff.added_keys.is_empty() && diff.removed_keys.is_empty());
+        assert_eq!(
+            diff.keychains,
+            Some((
+                [Keychain::OUTER, Keychain::INNER].into(),
+                [Keychain::OUTER, Keychain::with(2)].into()
+            ))
+        );
+
+        let old = multi(2, &[0, 1, 2]);
+        let new = StdDescr::<XpubDerivable>::from(
+            TrMultiA::new(None, 2, [key(1), key(2), changed.clone()]).unwrap(),
+        );
+        let diff = super::diff(&old, &new);
+        assert_eq!(diff.changed_keys, vec![(key(0), changed)]);
+    }
+
+    #[test]
+    fn internal_key_swap() {
+        let old =
+            StdDescr::<XpubDerivable>::from(TrMultiA::new(Some(key(0)), 1, [key(1)]).unwrap());
+        let new =
+            StdDescr::<XpubDerivable>::from(TrMultiA::new(Some(key(1)), 1, [key(0)]).unwrap());
+        let diff = diff(&old, &new);
+        assert!(!diff.is_empty());
+        assert_eq!(diff.internal_key, Some((Some(key(0)), Some(key(1)))));
+        assert_eq!(diff.added_keys, vec![key(0)]);
+        assert_eq!(diff.removed_keys, vec![key(1)]);
+    }
+
+    #[test]
+    fn merkle_root() {
+        let root = TapNodeHash::from([7u8; 32]);
+        let old = StdDescr::<XpubDerivable>::from(TrKey::from(key(0)));
+        let new = StdDescr::<XpubDerivable>::from(TrKey::with_merkle_root(key(0), root));
+        let diff = diff(&old, &new);
+        assert_eq!(diff.merkle_root, Some((None, Some(root))));
+        assert_eq!(diff.internal_key, None);
+        assert!(diff.added_keys.is_empty() && diff.removed_keys.is_empty());
+    }
+}
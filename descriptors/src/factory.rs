@@ -22,6 +22,49 @@
 
 use derive::{Address, AddressError, AddressNetwork, DeriveScripts, Idx, Keychain, NormalIndex};
 
+/// Lazy iterator over the addresses of a descriptor keychain, returned by
+/// [`crate::Descriptor::addresses`].
+///
+/// Yields the derivation index together with each address, skipping indexes for which the derived
+/// script pubkey can't be represented as an address. Ends after the maximal normal index.
+#[derive(Debug)]
+pub struct Addresses<'descr, D: DeriveScripts> {
+    descriptor: &'descr D,
+    network: AddressNetwork,
+    keychain: Keychain,
+    next: Option<NormalIndex>,
+}
+
+impl<'descr, D: DeriveScripts> Addresses<'descr, D> {
+    pub(crate) fn new(
+        descriptor: &'descr D,
+        network: AddressNetwork,
+        keychain: Keychain,
+        start: NormalIndex,
+    ) -> Self {
+        Addresses {
+            descriptor,
+            network,
+            keychain,
+            next: Some(start),
+        }
+    }
+}
+
+impl<'descr, D: DeriveScripts> Iterator for Addresses<'descr, D> {
+    type Item = (NormalIndex, Address);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let index = self.next?;
+            self.next = index.checked_inc();
+            if let Ok(addr) = self.descriptor.derive_address(self.network, self.keychain, index) {
+                return Some((index, addr));
+            }
+        }
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub struct AddressFactory<D: DeriveScripts> {
     pub descriptor: D,
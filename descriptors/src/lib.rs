@@ -27,12 +27,40 @@ extern crate amplify;
 extern crate serde_crate as serde;
 
 mod factory;
+mod accounts;
+mod binary;
+mod checksum;
+mod core_rpc;
 mod descriptor;
+mod diff;
 mod multisig;
+mod network;
+mod policy;
+mod scan;
 mod segwit;
 mod taproot;
+mod template;
+mod timelock;
+mod wallet;
 
-pub use descriptor::{Descriptor, SpkClass, StdDescr};
-pub use factory::AddressFactory;
-pub use segwit::Wpkh;
+pub use accounts::{MultiAccountWallet, UnknownAccount};
+pub use binary::{BinaryDecodeError, BinaryEncodeError, BinaryKey};
+pub use checksum::{checksum, verify_checksum, ChecksumError};
+pub use core_rpc::{CoreImport, ImportTimestamp, KeychainDescriptor, MultipathError};
+pub use descriptor::{
+    Descriptor, DescriptorAddressError, DescriptorParseError, KeyTranslate, NetworkMismatch,
+    SatisfactionWeight, SpkClass, StdDescr, StdDescrIter, ToWatchOnly, DESCRIPTOR_ID_TAG,
+};
+pub use diff::{diff, DescriptorDiff, Diffable};
+pub use factory::{AddressFactory, Addresses};
+pub use multisig::{
+    multi_a_script, MultiError, TrMultiA, MULTI_A_MAX_KEYS, UNSPENDABLE_INTERNAL_KEY,
+};
+pub use network::DescriptorWallet;
+pub use policy::{PolicyError, ScriptContext, ToPolicy};
+pub use scan::{scan, ScanResult, ScriptResolver, ScriptTx, ScriptUtxo};
+pub use segwit::{p2sh_wpkh_redeem_script, Wpkh};
 pub use taproot::TrKey;
+pub use template::DescrTemplate;
+pub use timelock::{Timelock, TimelockError, WshTimelocked};
+pub use wallet::{scan_tx, scan_tx_spends, ScriptIndex, WalletUtxo, WalletUtxos};
@@ -19,3 +19,542 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::{iter, option, slice, vec};
+
+use derive::opcodes::{
+    OP_CHECKSIG, OP_CHECKSIGADD, OP_NUMEQUAL, OP_PUSHBYTES_0, OP_PUSHBYTES_32, OP_PUSHNUM_1,
+};
+use derive::{
+    CompressedPk, ControlBlock, Derive, DeriveXOnly, DerivedScript, InternalPk, KeyOrigin,
+    Keychain, LeafScript, NormalIndex, TapDerivation, TapLeafHash, TapScript, TapTree, Terminal,
+    WeightUnits, XOnlyPk, Xpub, XpubDerivable, XpubSpec,
+};
+use indexmap::IndexMap;
+
+use crate::descriptor::{duplicate_xpub, parse_function, parse_key};
+use crate::{
+    Descriptor, DescriptorParseError, KeyTranslate, SatisfactionWeight, SpkClass, ToPolicy,
+};
+
+/// Maximal number of keys in `multi_a` tapscript, as defined in BIP-387.
+pub const MULTI_A_MAX_KEYS: usize = 999;
+
+/// BIP-341 provably unspendable internal key `H`, used when the taproot output must not have a key
+/// path spending.
+pub const UNSPENDABLE_INTERNAL_KEY: &str =
+    "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum MultiError {
+    /// multisig threshold must be non-zero.
+    ZeroThreshold,
+
+    /// multisig threshold {threshold} exceeds the number of keys {keys}.
+    ThresholdExceedsKeys { threshold: u16, keys: usize },
+
+    /// multisig contains {0} keys, while no more than 999 keys are allowed.
+    TooManyKeys(usize),
+
+    /// extended key {0} is used by more than one multisig participant.
+    DuplicateKey(Xpub),
+
+    /// descriptor is already a multisig one.
+    AlreadyMultisig,
+}
+
+/// Checks that the multisig `threshold` is valid for the given number of script keys.
+pub(crate) fn check_threshold(threshold: u16, keys: usize) -> Result<(), MultiError> {
+    if threshold == 0 {
+        return Err(MultiError::ZeroThreshold);
+    }
+    if keys > MULTI_A_MAX_KEYS {
+        return Err(MultiError::TooManyKeys(keys));
+    }
+    if threshold as usize > keys {
+        return Err(MultiError::ThresholdExceedsKeys { threshold, keys });
+    }
+    Ok(())
+}
+
+/// Pushes a non-negative number onto the script stack using the minimal encoding.
+pub(crate) fn push_num(script: &mut Vec<u8>, num: u32) {
+    match num {
+        0 => script.push(OP_PUSHBYTES_0),
+        1..=16 => script.push(OP_PUSHNUM_1 + num as u8 - 1),
+        _ => {
+            let mut bytes = num.to_le_bytes().to_vec();
+            while bytes.last() == Some(&0) {
+                bytes.pop();
+            }
+            if bytes.last().copied().unwrap_or_default() & 0x80 != 0 {
+                bytes.push(0);
+            }
+            script.push(bytes.len() as u8);
+            script.extend(bytes);
+        }
+    }
+}
+
+/// Constructs `multi_a` tapscript with the provided threshold over the keys, in the order they
+/// are given: `<key_1> OP_CHECKSIG <key_2> OP_CHECKSIGADD ... <key_n> OP_CHECKSIGADD <k>
+/// OP_NUMEQUAL`.
+pub fn multi_a_script(threshold: u16, keys: &[XOnlyPk]) -> TapScript {
+    let mut script = Vec::with_capacity(keys.len() * 34 + 4);
+    for (no, key) in keys.iter().enumerate() {
+        script.push(OP_PUSHBYTES_32);
+        script.extend_from_slice(&key.to_byte_array());
+        script.push(if no == 0 { OP_CHECKSIG } else { OP_CHECKSIGADD });
+    }
+    push_num(&mut script, threshold as u32);
+    script.push(OP_NUMEQUAL);
+    TapScript::from_unsafe(script)
+}
+
+/// Taproot k-of-n multisig with a single `sortedmulti_a` script leaf (BIP-387).
+///
+/// For each terminal the script keys are derived and sorted lexicographically before constructing
+/// the leaf script. If the internal key is not provided, the key path spending is disabled by
+/// using BIP-341 unspendable key [`UNSPENDABLE_INTERNAL_KEY`].
+///
+/// Serialized with serde as an object with `internalKey`, `threshold` and `scriptKeys` fields.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TrMultiA<K: DeriveXOnly = XpubDerivable> {
+    internal_key: Option<K>,
+    threshold: u16,
+    script_keys: Vec<K>,
+}
+
+impl<K: DeriveXOnly> TrMultiA<K> {
+    pub fn new(
+        internal_key: Option<K>,
+        threshold: u16,
+        script_keys: impl IntoIterator<Item = K>,
+    ) -> Result<Self, MultiError> {
+        let script_keys = script_keys.into_iter().collect::<Vec<_>>();
+        check_threshold(threshold, script_keys.len())?;
+        Ok(TrMultiA {
+            internal_key,
+            threshold,
+            script_keys,
+        })
+    }
+
+    /// Constructs multisig like [`Self::new`], additionally rejecting the same extended key used
+    /// more than once, either among the script keys or as both an internal and a script key.
+    pub fn new_unique(
+        internal_key: Option<K>,
+        threshold: u16,
+        script_keys: impl IntoIterator<Item = K>,
+    ) -> Result<Self, MultiError> {
+        let multi = Self::new(internal_key, threshold, script_keys)?;
+        let specs = multi
+            .internal_key
+            .iter()
+            .chain(&multi.script_keys)
+            .filter_map(|key| key.try_xpub_spec());
+        match duplicate_xpub(specs) {
+            Some(xpub) => Err(MultiError::DuplicateKey(xpub)),
+            None => Ok(multi),
+        }
+    }
+
+    #[inline]
+    pub fn internal_key(&self) -> Option<&K> { self.internal_key.as_ref() }
+
+    #[inline]
+    pub fn threshold(&self) -> u16 { self.threshold }
+
+    #[inline]
+    pub fn script_keys(&self) -> &[K] { &self.script_keys }
+
+    fn derive_internal_pk(&self, terminal: Terminal) -> InternalPk {
+        let key = match &self.internal_key {
+            Some(key) => key.derive(terminal.keychain, terminal.index),
+            None => XOnlyPk::from_str(UNSPENDABLE_INTERNAL_KEY).expect("hardcoded valid key"),
+        };
+        InternalPk::from_unchecked(key)
+    }
+
+    fn derive_script_keys(&self, terminal: Terminal) -> Vec<XOnlyPk> {
+        let mut keys = self
+            .script_keys
+            .iter()
+            .map(|key| key.derive(terminal.keychain, terminal.index))
+            .collect::<Vec<_>>();
+        keys.sort_by_key(XOnlyPk::to_byte_array);
+        keys
+    }
+
+    /// Derives `sortedmulti_a` leaf script for a given terminal.
+    pub fn derive_leaf_script(&self, terminal: Terminal) -> LeafScript {
+        LeafScript::from_tap_script(multi_a_script(
+            self.threshold,
+            &self.derive_script_keys(terminal),
+        ))
+    }
+
+    /// Computes BIP-341 output key for the given terminal, tweaking the internal key with the
+    /// merkle root of the script tree.
+    pub fn output_key(&self, terminal: Terminal) -> XOnlyPk {
+        let leaf_hash = self.derive_leaf_script(terminal).tap_leaf_hash();
+        let (output_pk, _) = self.derive_internal_pk(terminal).to_output_pk(Some(leaf_hash));
+        *output_pk
+    }
+
+    /// Constructs BIP-341 control block for spending the leaf with the given hash. Returns `None`
+    /// if the leaf is not a part of the script tree derived for the terminal.
+    pub fn control_block(&self, terminal: Terminal, leaf: TapLeafHash) -> Option<ControlBlock> {
+        let leaf_script = self.derive_leaf_script(terminal);
+        if leaf_script.tap_leaf_hash() != leaf {
+            return None;
+        }
+        let internal_pk = self.derive_internal_pk(terminal);
+        // The tree has a single leaf, thus the leaf hash is the merkle root and the merkle path
+        // is empty
+        let (_, parity) = internal_pk.to_output_pk(Some(leaf));
+        Some(ControlBlock::with(leaf_script.version, internal_pk, parity, empty!()))
+    }
+}
+
+impl<K: DeriveXOnly + Display> Display for TrMultiA<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("tr(")?;
+        match &self.internal_key {
+            Some(key) => Display::fmt(key, f)?,
+            None => f.write_str(UNSPENDABLE_INTERNAL_KEY)?,
+        }
+        write!(f, ",sortedmulti_a({}", self.threshold)?;
+        for key in &self.script_keys {
+            f.write_str(",")?;
+            Display::fmt(key, f)?;
+        }
+        f.write_str("))")
+    }
+}
+
+impl<K: DeriveXOnly + FromStr> FromStr for TrMultiA<K>
+where K::Err: Error
+{
+    type Err = DescriptorParseError<K::Err>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (internal_key, threshold, script_keys) = parse_tr_multi_a(s)?;
+        Ok(TrMultiA::new(internal_key, threshold, script_keys)?)
+    }
+}
+
+/// Parses `tr(KEY,sortedmulti_a(K,KEY_1,...,KEY_N))` descriptor into the optional internal key,
+/// threshold and script keys, without validating the threshold.
+#[allow(clippy::type_complexity)]
+pub(crate) fn parse_tr_multi_a<K: FromStr>(
+    s: &str,
+) -> Result<(Option<K>, u16, Vec<K>), DescriptorParseError<K::Err>>
+where K::Err: Error {
+    let (args, pos) = match parse_function(s)? {
+        ("tr", args, pos) => (args, pos),
+        (name, ..) => return Err(DescriptorParseError::Unsupported(name.to_owned())),
+    };
+    let invalid = || DescriptorParseError::InvalidFormat(s.to_owned());
+    let (internal_key, script) = args.split_once(',').ok_or_else(invalid)?;
+    let mut offset = pos + internal_key.len() + 1 + "sortedmulti_a(".len();
+    let mut params = script
+        .strip_prefix("sortedmulti_a(")
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(invalid)?
+        .split(',')
+        .map(|param| {
+            let param_pos = offset;
+            offset += param.len() + 1;
+            (param, param_pos)
+        });
+    let threshold = params.next().and_then(|(k, _)| k.parse().ok()).ok_or_else(invalid)?;
+    let internal_key = match internal_key {
+        UNSPENDABLE_INTERNAL_KEY => None,
+        key => Some(parse_key::<K>(key, pos)?),
+    };
+    let script_keys =
+        params.map(|(key, pos)| parse_key::<K>(key, pos)).collect::<Result<Vec<_>, _>>()?;
+    Ok((internal_key, threshold, script_keys))
+}
+
+impl<K: DeriveXOnly, K2: DeriveXOnly> KeyTranslate<K, K2> for TrMultiA<K> {
+    type Output = TrMultiA<K2>;
+
+    fn translate_keys<E>(&self, mut f: impl FnMut(&K) -> Result<K2, E>) -> Result<TrMultiA<K2>, E> {
+        Ok(TrMultiA {
+            internal_key: self.internal_key.as_ref().map(&mut f).transpose()?,
+            threshold: self.threshold,
+            script_keys: self.script_keys.iter().map(f).collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl<K: DeriveXOnly> Derive<DerivedScript> for TrMultiA<K> {
+    fn default_keychain(&self) -> Keychain {
+        self.keys().next().expect("at least one key is always present").default_keychain()
+    }
+
+    fn keychains(&self) -> BTreeSet<Keychain> {
+        let mut keys = self.keys().map(K::keychains);
+        let first = keys.next().unwrap_or_default();
+        keys.fold(first, |acc, keychains| &acc & &keychains)
+    }
+
+    fn derive(
+        &self,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+    ) -> DerivedScript {
+        let terminal = Terminal::new(keychain, index.into());
+        let tap_tree = TapTree::with_single_leaf(self.derive_leaf_script(terminal));
+        DerivedScript::TaprootScript(self.derive_internal_pk(terminal), tap_tree)
+    }
+}
+
+impl<K: DeriveXOnly> Descriptor<K> for TrMultiA<K> {
+    type KeyIter<'k> = iter::Chain<option::Iter<'k, K>, slice::Iter<'k, K>> where Self: 'k, K: 'k;
+    type VarIter<'v> = iter::Empty<&'v ()> where Self: 'v, (): 'v;
+    type XpubIter<'x> = iter::FilterMap<Self::KeyIter<'x>, fn(&'x K) -> Option<&'x XpubSpec>> where Self: 'x;
+
+    fn class(&self) -> SpkClass { SpkClass::P2tr }
+
+    fn keys(&self) -> Self::KeyIter<'_> { self.internal_key.iter().chain(&self.script_keys) }
+    fn vars(&self) -> Self::VarIter<'_> { iter::empty() }
+    fn xpubs(&self) -> Self::XpubIter<'_> { self.keys().filter_map(K::try_xpub_spec) }
+
+    fn to_canonical_string(&self) -> String
+    where
+        Self: Display,
+        K: Display,
+    {
+        let internal_key = match &self.internal_key {
+            Some(key) => key.to_string(),
+            None => UNSPENDABLE_INTERNAL_KEY.to_owned(),
+        };
+        let mut script_keys = self.script_keys.iter().map(K::to_string).collect::<Vec<_>>();
+        script_keys.sort();
+        format!("tr({internal_key},sortedmulti_a({},{}))", self.threshold, script_keys.join(","))
+    }
+
+    fn compr_keyset(&self, _terminal: Terminal) -> IndexMap<CompressedPk, KeyOrigin> {
+        IndexMap::new()
+    }
+
+    fn xonly_keyset(&self, terminal: Terminal) -> IndexMap<XOnlyPk, TapDerivation> {
+        let mut map = IndexMap::with_capacity(self.script_keys.len() + 1);
+        if let Some(key) = &self.internal_key {
+            if let Some(origin) = key.key_origin(terminal) {
+                let derivation = TapDerivation {
+                    leaf_hashes: vec![],
+                    origin,
+                };
+                map.insert(key.derive(terminal.keychain, terminal.index), derivation);
+            }
+        }
+        let leaf_hash = self.derive_leaf_script(terminal).tap_leaf_hash();
+        for key in &self.script_keys {
+            let Some(origin) = key.key_origin(terminal) else {
+                continue;
+            };
+            let pk = key.derive(terminal.keychain, terminal.index);
+            map.entry(pk)
+                .or_insert_with(|| TapDerivation {
+                    leaf_hashes: vec![],
+                    origin,
+                })
+                .leaf_hashes
+                .push(leaf_hash);
+        }
+        map
+    }
+}
+
+impl<K: DeriveXOnly + Display> ToPolicy for TrMultiA<K> {
+    fn to_policy(&self) -> String {
+        let keys = self.script_keys.iter().map(|key| format!(",pk({key})")).collect::<String>();
+        let policy = format!("thresh({}{keys})", self.threshold);
+        match &self.internal_key {
+            Some(key) => format!("or(pk({key}),{policy})"),
+            None => policy,
+        }
+    }
+}
+
+impl<K: DeriveXOnly> SatisfactionWeight for TrMultiA<K> {
+    fn max_satisfaction_weight(&self) -> WeightUnits {
+        // Empty script sig; witness with a BIP340 signature with a non-default sighash type for
+        // each of the threshold keys and an empty item for the rest of the keys, followed by the
+        // leaf script and the control block for a single-leaf tree
+        let keys = self.script_keys.len();
+        let threshold = self.threshold as usize;
+        // Each key takes 32-byte push and a single opcode
+        let script_len = multi_a_script(self.threshold, &[]).len() + keys * 34;
+        let varint = |len: usize| if len < 0xFD { 1 } else { 3 };
+        WeightUnits::no_discount(1)
+            + WeightUnits::witness_discount(
+                varint(keys + 2)
+                    + threshold * (1 + 65)
+                    + (keys - threshold)
+                    + varint(script_len)
+                    + script_len
+                    + 1
+                    + 33,
+            )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use derive::TapNodeHash;
+
+    use super::*;
+
+    const KEYS: [&str; 3] = [
+        "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*",
+        "[deadbeef/0h]xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw/<0;1>/*",
+        "[beefcafe/1h/2h/3h/4h/5h]xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy/<0;1>/*",
+    ];
+
+    #[test]
+    fn threshold_encoding() {
+        let bytes = |threshold| multi_a_script(threshold, &[]).to_vec();
+        assert_eq!(bytes(2), vec![OP_PUSHNUM_1 + 1, OP_NUMEQUAL]);
+        assert_eq!(bytes(17), vec![0x01, 17, OP_NUMEQUAL]);
+        assert_eq!(bytes(200), vec![0x02, 200, 0x00, OP_NUMEQUAL]);
+        assert_eq!(bytes(999), vec![0x02, 0xE7, 0x03, OP_NUMEQUAL]);
+    }
+
+    #[test]
+    fn sorted_multi_a() {
+        let descr = format!("tr({UNSPENDABLE_INTERNAL_KEY},sortedmulti_a(2,{}))", KEYS.join(","));
+        let tr = TrMultiA::<XpubDerivable>::from_str(&descr).unwrap();
+        assert_eq!(tr.to_string(), descr);
+        assert_eq!(tr.internal_key(), None);
+        assert_eq!(tr.keychains(), bset![Keychain::OUTER, Keychain::INNER]);
+        assert_eq!(tr.xpubs().count(), 3);
+
+        let terminal = Terminal::new(Keychain::INNER, NormalIndex::from(4u8));
+        let leaf = tr.derive_leaf_script(terminal);
+        let script: &[u8] = leaf.script.as_ref();
+        assert_eq!(script.len(), 3 * 34 + 2);
+        assert_eq!(script[33], OP_CHECKSIG);
+        assert_eq!(script[67], OP_CHECKSIGADD);
+        assert_eq!(&script[102..], &[OP_PUSHNUM_1 + 1, OP_NUMEQUAL]);
+        assert!(script[1..33] < script[35..67] && script[35..67] < script[69..101]);
+
+        let keyset = tr.xonly_keyset(terminal);
+        assert_eq!(keyset.len(), 3);
+        assert!(keyset.values().all(|d| d.leaf_hashes == vec![leaf.tap_leaf_hash()]));
+
+        let DerivedScript::TaprootScript(internal_pk, tap_tree) =
+            tr.derive(terminal.keychain, terminal.index)
+        else {
+            panic!("taproot script descriptor must derive script tree");
+        };
+        assert_eq!(
+            internal_pk.to_byte_array(),
+            XOnlyPk::from_str(UNSPENDABLE_INTERNAL_KEY).unwrap().to_byte_array()
+        );
+        assert_eq!(tap_tree.merkle_root(), TapNodeHash::from(leaf.tap_leaf_hash()));
+
+        assert_eq!(
+            TrMultiA::<XpubDerivable>::new(None, 3, vec![]),
+            Err(MultiError::ThresholdExceedsKeys {
+                threshold: 3,
+                keys: 0
+            })
+        );
+        assert!(matches!(
+            TrMultiA::<XpubDerivable>::from_str(&format!(
+                "tr({},sortedmulti_a(0,{}))",
+                KEYS[0], KEYS[1]
+            )),
+            Err(DescriptorParseError::Multisig(MultiError::ZeroThreshold))
+        ));
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let key = |no: usize| XpubDerivable::from_str(KEYS[no]).unwrap();
+        let tr = TrMultiA::new(None, 2, [key(0), key(1)]).unwrap();
+        assert!(!tr.has_duplicate_keys());
+        assert_eq!(TrMultiA::new_unique(None, 2, [key(0), key(1)]), Ok(tr));
+
+        let tr = TrMultiA::new(None, 2, [key(0), key(0)]).unwrap();
+        assert!(tr.has_duplicate_keys());
+        let xpub = key(0).xpub();
+        assert_eq!(
+            TrMultiA::new_unique(None, 2, [key(0), key(0)]),
+            Err(MultiError::DuplicateKey(xpub))
+        );
+        assert_eq!(
+            TrMultiA::new_unique(Some(key(0)), 1, [key(1), key(0)]),
+            Err(MultiError::DuplicateKey(xpub))
+        );
+    }
+
+    #[test]
+    fn control_block() {
+        let descr = format!("tr({},sortedmulti_a(2,{},{}))", KEYS[0], KEYS[1], KEYS[2]);
+        let tr = TrMultiA::<XpubDerivable>::from_str(&descr).unwrap();
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(7u8));
+        let derived = tr.derive(terminal.keychain, terminal.index);
+
+        let spk = derived.to_script_pubkey();
+        let script: &[u8] = spk.as_ref();
+        assert_eq!(&script[2..], &tr.output_key(terminal).to_byte_array());
+
+        let leaf = tr.derive_leaf_script(terminal);
+        let control_block = tr.control_block(terminal, leaf.tap_leaf_hash()).unwrap();
+        let leaf_scripts = derived.to_leaf_scripts();
+        assert_eq!(leaf_scripts.len(), 1);
+        assert_eq!(leaf_scripts.get(&control_block), Some(&leaf));
+        assert_eq!(control_block.internal_pk, derived.to_internal_pk().unwrap());
+        assert!(control_block.merkle_branch.is_empty());
+
+        let other = Terminal::new(Keychain::OUTER, NormalIndex::from(8u8));
+        assert_eq!(tr.control_block(terminal, tr.derive_leaf_script(other).tap_leaf_hash()), None);
+    }
+
+    #[test]
+    fn key_order() {
+        let keys = KEYS.map(|key| XpubDerivable::from_str(key).unwrap());
+        for order in [[0, 1, 2], [2, 0, 1], [1, 2, 0]] {
+            let descr = format!(
+                "tr({UNSPENDABLE_INTERNAL_KEY},sortedmulti_a(2,{},{},{}))",
+                KEYS[order[0]], KEYS[order[1]], KEYS[order[2]]
+            );
+            let tr = TrMultiA::<XpubDerivable>::from_str(&descr).unwrap();
+            let expected = order.map(|i| &keys[i]);
+            assert_eq!(tr.keys().collect::<Vec<_>>(), expected);
+            assert_eq!(tr.xpubs().collect::<Vec<_>>(), expected.map(|key| key.spec()).to_vec());
+        }
+
+        let descr = format!("tr({},sortedmulti_a(1,{},{}))", KEYS[1], KEYS[2], KEYS[0]);
+        let tr = TrMultiA::<XpubDerivable>::from_str(&descr).unwrap();
+        assert_eq!(tr.keys().collect::<Vec<_>>(), vec![&keys[1], &keys[2], &keys[0]]);
+    }
+
+    #[test]
+    fn policy() {
+        let descr =
+            format!("tr({UNSPENDABLE_INTERNAL_KEY},sortedmulti_a(2,{},{}))", KEYS[2], KEYS[0]);
+        let tr = TrMultiA::<XpubDerivable>::from_str(&descr).unwrap();
+        assert_eq!(tr.to_policy(), format!("thresh(2,pk({}),pk({}))", KEYS[2], KEYS[0]));
+
+        let descr = format!("tr({},sortedmulti_a(1,{}))", KEYS[0], KEYS[1]);
+        let tr = TrMultiA::<XpubDerivable>::from_str(&descr).unwrap();
+        assert_eq!(tr.to_policy(), format!("or(pk({}),thresh(1,pk({})))", KEYS[0], KEYS[1]));
+    }
+}
@@ -0,0 +1,245 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Descriptors bound to a specific bitcoin network.
+
+use std::marker::PhantomData;
+
+use derive::{Address, Keychain, Network, NormalIndex, XpubDerivable};
+
+use crate::{Descriptor, DescriptorAddressError, NetworkMismatch, SpkClass};
+
+/// Descriptor bound to a network, which is validated against the descriptor extended keys on
+/// construction, unless it is explicitly overridden with
+/// [`DescriptorWallet::with_network_override`]. All addresses produced by the wallet descriptor
+/// belong to that network.
+///
+/// The network is validated on deserialization as well. Wallets with an overridden network are
+/// serialized with the `networkOverride` flag, which skips the validation when they are loaded.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(
+        crate = "serde_crate",
+        try_from = "WalletData<D>",
+        into = "WalletData<D>",
+        bound(
+            serialize = "D: serde::Serialize + Clone, K: Clone",
+            deserialize = "D: serde::Deserialize<'de>"
+        )
+    )
+)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct DescriptorWallet<D: Descriptor<K>, K = XpubDerivable> {
+    descriptor: D,
+    network: Network,
+    _phantom: PhantomData<K>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "serde_crate", rename_all = "camelCase")]
+struct WalletData<D> {
+    descriptor: D,
+    network: Network,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    network_override: bool,
+}
+
+#[cfg(feature = "serde")]
+impl<D: Descriptor<K>, K> From<DescriptorWallet<D, K>> for WalletData<D> {
+    fn from(wallet: DescriptorWallet<D, K>) -> Self {
+        WalletData {
+            network_override: wallet.is_network_mismatch(),
+            descriptor: wallet.descriptor,
+            network: wallet.network,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<D: Descriptor<K>, K> TryFrom<WalletData<D>> for DescriptorWallet<D, K> {
+    type Error = NetworkMismatch;
+
+    fn try_from(data: WalletData<D>) -> Result<Self, Self::Error> {
+        if data.network_override {
+            Ok(DescriptorWallet::with_network_override(data.descriptor, data.network))
+        } else {
+            DescriptorWallet::with(data.descriptor, data.network)
+        }
+    }
+}
+
+impl<D: Descriptor<K>, K> DescriptorWallet<D, K> {
+    /// Binds the descriptor to the network, failing if the descriptor extended keys do not belong
+    /// to it.
+    pub fn with(descriptor: D, network: Network) -> Result<Self, NetworkMismatch> {
+        descriptor.check_network(network)?;
+        Ok(DescriptorWallet {
+            descriptor,
+            network,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Binds the descriptor to the network without checking the version bytes of the descriptor
+    /// extended keys, which is required when they don't reflect the actual network (for
+    /// instance, if the keys were re-serialized without the canonical version bytes). The
+    /// disagreement between the network and the version bytes can be detected with
+    /// [`Self::is_network_mismatch`].
+    pub fn with_network_override(descriptor: D, network: Network) -> Self {
+        DescriptorWallet {
+            descriptor,
+            network,
+            _phantom: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn descriptor(&self) -> &D { &self.descriptor }
+
+    #[inline]
+    pub fn into_descriptor(self) -> D { self.descriptor }
+
+    #[inline]
+    pub fn network(&self) -> Network { self.network }
+
+    #[inline]
+    pub fn class(&self) -> SpkClass { self.descriptor.class() }
+
+    /// Detects whether the version bytes of the descriptor extended keys disagree with the network
+    /// the wallet is bound to, which is possible only for wallets constructed with
+    /// [`Self::with_network_override`].
+    pub fn is_network_mismatch(&self) -> bool {
+        self.descriptor.check_network(self.network).is_err()
+    }
+
+    /// Checks that the wallet is bound to the given network.
+    pub fn check_network(&self, network: Network) -> Result<(), NetworkMismatch> {
+        if network != self.network {
+            return Err(NetworkMismatch::Network(network));
+        }
+        Ok(())
+    }
+
+    /// Derives wallet address at the given keychain and index. Returns `None` if the derived
+    /// script pubkey can't be represented as an address.
+    pub fn address_at(
+        &self,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+    ) -> Option<Address> {
+        self.descriptor.address_at(keychain, index, self.network)
+    }
+
+    /// Derives the first address of the default wallet keychain.
+    pub fn first_address(&self) -> Option<Address> { self.descriptor.first_address(self.network) }
+
+    /// Derives wallet address for a network expected by the caller, failing if the wallet is
+    /// bound to a different network.
+    pub fn derive_address(
+        &self,
+        network: Network,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+    ) -> Result<Address, DescriptorAddressError> {
+        self.check_network(network)?;
+        Ok(self.descriptor.derive_address(network.into(), keychain, index)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{StdDescr, TrKey};
+
+    const XPUB: &str = "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*";
+
+    #[test]
+    fn bound_network() {
+        let descr =
+            StdDescr::<XpubDerivable>::from(TrKey::from(XpubDerivable::from_str(XPUB).unwrap()));
+        assert_eq!(
+            DescriptorWallet::<_>::with(descr.clone(), Network::Mainnet),
+            Err(NetworkMismatch::Network(Network::Mainnet))
+        );
+
+        let wallet = DescriptorWallet::<_>::with(descr.clone(), Network::Signet).unwrap();
+        assert_eq!(wallet.network(), Network::Signet);
+        assert_eq!(wallet.class(), SpkClass::P2tr);
+        assert_eq!(wallet.first_address(), descr.first_address(Network::Signet));
+        assert_eq!(wallet.address_at(1u8, 3u8), descr.address_at(1u8, 3u8, Network::Signet));
+        assert_eq!(
+            wallet.derive_address(Network::Signet, 1u8, 3u8).ok(),
+            wallet.address_at(1u8, 3u8)
+        );
+        assert_eq!(
+            wallet.derive_address(Network::Regtest, 1u8, 3u8),
+            Err(DescriptorAddressError::Network(NetworkMismatch::Network(Network::Regtest)))
+        );
+    }
+
+    #[test]
+    fn network_override() {
+        let descr =
+            StdDescr::<XpubDerivable>::from(TrKey::from(XpubDerivable::from_str(XPUB).unwrap()));
+        let wallet = DescriptorWallet::<_>::with_network_override(descr.clone(), Network::Mainnet);
+        assert!(wallet.is_network_mismatch());
+        assert_eq!(wallet.network(), Network::Mainnet);
+        let addr = wallet.first_address().unwrap();
+        assert!(addr.to_string().starts_with("bc1p"));
+        assert_eq!(Some(addr), descr.first_address(Network::Mainnet));
+        assert_eq!(wallet.derive_address(Network::Mainnet, 0u8, 0u8), Ok(addr));
+        assert_eq!(
+            wallet.derive_address(Network::Testnet3, 0u8, 0u8),
+            Err(DescriptorAddressError::Network(NetworkMismatch::Network(Network::Testnet3)))
+        );
+
+        let wallet = DescriptorWallet::<_>::with_network_override(descr.clone(), Network::Signet);
+        assert!(!wallet.is_network_mismatch());
+        assert_eq!(Ok(wallet), DescriptorWallet::<_>::with(descr, Network::Signet));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_network_check() {
+        let descr =
+            StdDescr::<XpubDerivable>::from(TrKey::from(XpubDerivable::from_str(XPUB).unwrap()));
+        let wallet = DescriptorWallet::<_>::with(descr.clone(), Network::Signet).unwrap();
+        let json = serde_json::to_string(&wallet).unwrap();
+        assert!(!json.contains("networkOverride"));
+        assert_eq!(serde_json::from_str::<DescriptorWallet<StdDescr>>(&json).unwrap(), wallet);
+
+        let mainnet = json.replace("\"signet\"", "\"mainnet\"");
+        assert_eq!(
+            serde_json::from_str::<DescriptorWallet<StdDescr>>(&mainnet).unwrap_err().to_string(),
+            NetworkMismatch::Network(Network::Mainnet).to_string()
+        );
+
+        let wallet = DescriptorWallet::<_>::with_network_override(descr, Network::Mainnet);
+        let json = serde_json::to_string(&wallet).unwrap();
+        assert!(json.contains("\"networkOverride\":true"));
+        assert_eq!(serde_json::from_str::<DescriptorWallet<StdDescr>>(&json).unwrap(), wallet);
+    }
+}
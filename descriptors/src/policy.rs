@@ -0,0 +1,361 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion of descriptors to and from miniscript spending policies.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use derive::{DeriveCompr, DeriveSet, DeriveXOnly, XpubParseError};
+
+use crate::{MultiError, StdDescr, Timelock, TimelockError, TrKey, TrMultiA, Wpkh, WshTimelocked};
+
+/// Descriptors which spending conditions can be rendered as a miniscript policy.
+pub trait ToPolicy {
+    /// Renders the spending conditions of the descriptor as a miniscript policy, like `pk(KEY)`
+    /// for single-key descriptors or `thresh(k,pk(KEY1),...,pk(KEYn))` for multisigs.
+    ///
+    /// The policy describes who can spend and when, not the script encoding: the order of the
+    /// keys in sorted multisigs, script types and unspendable taproot internal keys are not a
+    /// part of it.
+    fn to_policy(&self) -> String;
+}
+
+/// Script context into which a spending policy is materialized.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[display(lowercase)]
+pub enum ScriptContext {
+    /// Segwit v0 outputs: `wpkh` and `wsh` descriptors.
+    Segwit,
+
+    /// Taproot outputs: `tr` descriptors.
+    Taproot,
+}
+
+/// Errors constructing a descriptor from a spending policy, parameterized with the error type of
+/// the key parser.
+#[derive(Clone, Eq, PartialEq, Debug, From)]
+pub enum PolicyError<E: Error = XpubParseError> {
+    InvalidFormat(String),
+
+    Unsupported {
+        policy: String,
+        context: ScriptContext,
+    },
+
+    /// Policy other than a single key in the segwit context, which requires a `wsh` script.
+    /// Standard descriptors don't support `wsh` scripts yet, so only `pk(KEY)` policies can be
+    /// materialized in the segwit context.
+    SegwitScript(String),
+
+    #[from]
+    Timelock(TimelockError),
+
+    #[from]
+    Multisig(MultiError),
+
+    Key(E),
+}
+
+impl<E: Error> Display for PolicyError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyError::InvalidFormat(s) => write!(f, "invalid policy expression '{s}'."),
+            PolicyError::Unsupported { policy, context } => {
+                write!(f, "policy '{policy}' can't be represented by a {context} descriptor.")
+            }
+            PolicyError::SegwitScript(policy) => write!(
+                f,
+                "policy '{policy}' requires a wsh script, while only single-key pk() policies are \
+                 supported in the segwit context."
+            ),
+            PolicyError::Timelock(err) => write!(f, "invalid policy timelock - {err}"),
+            PolicyError::Multisig(err) => write!(f, "invalid policy threshold - {err}"),
+            PolicyError::Key(err) => write!(f, "invalid policy key - {err}"),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for PolicyError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PolicyError::InvalidFormat(_)
+            | PolicyError::Unsupported { .. }
+            | PolicyError::SegwitScript(_) => None,
+            PolicyError::Timelock(err) => Some(err),
+            PolicyError::Multisig(err) => Some(err),
+            PolicyError::Key(err) => Some(err),
+        }
+    }
+}
+
+/// Parsed policy expression. Probabilities of `or` branches are discarded, since they affect only
+/// the script layout and not the spending conditions.
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum Policy<K> {
+    Key(K),
+    Timelock(Timelock),
+    Thresh(u16, Vec<Policy<K>>),
+    And(Box<Policy<K>>, Box<Policy<K>>),
+    Or(Box<Policy<K>>, Box<Policy<K>>),
+}
+
+/// Splits `name(arg1,arg2,...)` expression into the name and the top-level arguments.
+fn split_fragment(s: &str) -> Option<(&str, Vec<&str>)> {
+    let (name, args) = s.strip_suffix(')')?.split_once('(')?;
+    let mut depth = 0usize;
+    let mut start = 0;
+    let mut list = vec![];
+    for (pos, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                list.push(args[start..pos].trim());
+                start = pos + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+    list.push(args[start..].trim());
+    Some((name.trim(), list))
+}
+
+impl<K: FromStr> Policy<K>
+where K::Err: Error
+{
+    fn parse(s: &str) -> Result<Self, PolicyError<K::Err>> {
+        let invalid = || PolicyError::InvalidFormat(s.to_owned());
+        let (name, args) = split_fragment(s.trim()).ok_or_else(invalid)?;
+        let threshold = |k: &str| u16::from_str(k).map_err(|_| invalid());
+        let key = |key: &str| K::from_str(key).map(Policy::Key).map_err(PolicyError::Key);
+        // Strips `n@` probability prefix from an `or` branch
+        let branch = |arg: &str| match arg.split_once('@') {
+            Some((odds, sub)) if !odds.is_empty() && odds.chars().all(|c| c.is_ascii_digit()) => {
+                Policy::parse(sub)
+            }
+            _ => Policy::parse(arg),
+        };
+        Ok(match (name, args.as_slice()) {
+            ("pk", [k]) => key(k)?,
+            ("after" | "older", [_]) => Policy::Timelock(Timelock::from_str(s.trim())?),
+            ("thresh", [k, subs @ ..]) if !subs.is_empty() => Policy::Thresh(
+                threshold(k)?,
+                subs.iter().map(|sub| Policy::parse(sub)).collect::<Result<_, _>>()?,
+            ),
+            ("multi", [k, keys @ ..]) if !keys.is_empty() => Policy::Thresh(
+                threshold(k)?,
+                keys.iter().map(|k| key(k)).collect::<Result<_, _>>()?,
+            ),
+            ("and", [a, b]) => {
+                Policy::And(Box::new(Policy::parse(a)?), Box::new(Policy::parse(b)?))
+            }
+            ("or", [a, b]) => Policy::Or(Box::new(branch(a)?), Box::new(branch(b)?)),
+            _ => return Err(invalid()),
+        })
+    }
+
+    fn into_key(self) -> Option<K> {
+        match self {
+            Policy::Key(key) => Some(key),
+            _ => None,
+        }
+    }
+
+    fn into_multisig(self) -> Option<(u16, Vec<K>)> {
+        match self {
+            Policy::Thresh(k, subs) => {
+                Some((k, subs.into_iter().map(Policy::into_key).collect::<Option<_>>()?))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<K> StdDescr<K>
+where
+    K: DeriveSet<Compr = K, XOnly = K> + DeriveCompr + DeriveXOnly + FromStr,
+    K::Err: Error,
+{
+    /// Constructs descriptor from a miniscript spending policy, like the one produced by
+    /// [`crate::Descriptor::to_policy`].
+    ///
+    /// A single key `pk(KEY)` becomes `wpkh` or `tr` descriptor, depending on the context. In
+    /// taproot context a threshold of keys `thresh(k,pk(KEY1),...)` (or `multi(k,KEY1,...)`)
+    /// becomes `sortedmulti_a` leaf with an unspendable internal key, and `or` of a key and such
+    /// threshold uses the key as the internal key. Policies which can't be represented without
+    /// dropping some of the conditions are rejected with [`PolicyError::Unsupported`].
+    ///
+    /// In the segwit context only `pk(KEY)` policies are supported, since the standard
+    /// descriptors have no `wsh` script variants; other policies are rejected with
+    /// [`PolicyError::SegwitScript`]. Timelocked keys can be materialized with
+    /// [`WshTimelocked::from_policy`].
+    pub fn from_policy(policy: &str, wrapper: ScriptContext) -> Result<Self, PolicyError<K::Err>> {
+        let unsupported = || PolicyError::Unsupported {
+            policy: policy.trim().to_owned(),
+            context: wrapper,
+        };
+        Ok(match (wrapper, Policy::<K>::parse(policy)?) {
+            (ScriptContext::Segwit, Policy::Key(key)) => Wpkh::from(key).into(),
+            (ScriptContext::Segwit, _) => {
+                return Err(PolicyError::SegwitScript(policy.trim().to_owned()));
+            }
+            (ScriptContext::Taproot, Policy::Key(key)) => TrKey::from(key).into(),
+            (ScriptContext::Taproot, thresh @ Policy::Thresh(..)) => {
+                let (k, keys) = thresh.into_multisig().ok_or_else(unsupported)?;
+                TrMultiA::new(None, k, keys)?.into()
+            }
+            (ScriptContext::Taproot, Policy::Or(a, b)) => {
+                let (internal_key, (k, keys)) = match (*a, *b) {
+                    (Policy::Key(key), other) | (other, Policy::Key(key)) => match other {
+                        Policy::Key(other) => (key, (1, vec![other])),
+                        other => (key, other.into_multisig().ok_or_else(unsupported)?),
+                    },
+                    _ => return Err(unsupported()),
+                };
+                TrMultiA::new(Some(internal_key), k, keys)?.into()
+            }
+            _ => return Err(unsupported()),
+        })
+    }
+}
+
+impl<K: DeriveCompr + FromStr> WshTimelocked<K>
+where K::Err: Error
+{
+    /// Constructs descriptor from a miniscript spending policy `and(pk(KEY),after(n))` or
+    /// `and(pk(KEY),older(n))`, with the conditions in any order.
+    pub fn from_policy(policy: &str) -> Result<Self, PolicyError<K::Err>> {
+        let unsupported = || PolicyError::Unsupported {
+            policy: policy.trim().to_owned(),
+            context: ScriptContext::Segwit,
+        };
+        let Policy::And(a, b) = Policy::<K>::parse(policy)? else {
+            return Err(unsupported());
+        };
+        match (*a, *b) {
+            (Policy::Key(key), Policy::Timelock(timelock))
+            | (Policy::Timelock(timelock), Policy::Key(key)) => {
+                Ok(WshTimelocked::new(key, timelock))
+            }
+            _ => Err(unsupported()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use derive::XpubDerivable;
+
+    use super::*;
+    use crate::UNSPENDABLE_INTERNAL_KEY;
+
+    const KEYS: [&str; 3] = [
+        "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*",
+        "[deadbeef/0h]xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw/<0;1>/*",
+        "[beefcafe/1h/2h/3h/4h/5h]xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy/<0;1>/*",
+    ];
+
+    fn from_policy(policy: &str, context: ScriptContext) -> Result<StdDescr, PolicyError> {
+        StdDescr::<XpubDerivable>::from_policy(policy, context)
+    }
+
+    #[test]
+    fn single_key() {
+        let policy = format!("pk({})", KEYS[0]);
+        let wpkh = from_policy(&policy, ScriptContext::Segwit).unwrap();
+        assert_eq!(wpkh.to_string(), format!("wpkh({})", KEYS[0]));
+        let tr = from_policy(&policy, ScriptContext::Taproot).unwrap();
+        assert_eq!(tr.to_string(), format!("tr({})", KEYS[0]));
+        assert_eq!(tr.to_policy(), policy);
+    }
+
+    #[test]
+    fn multisig() {
+        let policy = format!("thresh(2, pk({}), pk({}), pk({}))", KEYS[0], KEYS[1], KEYS[2]);
+        let tr = from_policy(&policy, ScriptContext::Taproot).unwrap();
+        assert_eq!(
+            tr.to_string(),
+            format!("tr({UNSPENDABLE_INTERNAL_KEY},sortedmulti_a(2,{}))", KEYS.join(","))
+        );
+        assert_eq!(tr.to_policy(), policy.replace(' ', ""));
+        let err = from_policy(&policy, ScriptContext::Segwit).unwrap_err();
+        assert_eq!(err, PolicyError::SegwitScript(policy.clone()));
+        assert!(err.to_string().contains("only single-key pk() policies are supported"));
+        let multi = format!("multi(2,{})", KEYS.join(","));
+        assert_eq!(from_policy(&multi, ScriptContext::Taproot), Ok(tr));
+
+        let policy = format!("or(9@thresh(1,pk({})),1@pk({}))", KEYS[1], KEYS[0]);
+        let tr = from_policy(&policy, ScriptContext::Taproot).unwrap();
+        assert_eq!(tr.to_string(), format!("tr({},sortedmulti_a(1,{}))", KEYS[0], KEYS[1]));
+        let policy = tr.to_policy();
+        assert_eq!(from_policy(&policy, ScriptContext::Taproot), Ok(tr));
+
+        assert_eq!(
+            from_policy(
+                &format!("thresh(3,pk({}),pk({}))", KEYS[0], KEYS[1]),
+                ScriptContext::Taproot
+            ),
+            Err(PolicyError::Multisig(MultiError::ThresholdExceedsKeys {
+                threshold: 3,
+                keys: 2
+            }))
+        );
+    }
+
+    #[test]
+    fn timelocks() {
+        let policy = format!("and(older(144),pk({}))", KEYS[0]);
+        let wsh = WshTimelocked::<XpubDerivable>::from_policy(&policy).unwrap();
+        assert_eq!(wsh.to_string(), format!("wsh(and_v(v:pk({}),older(144)))", KEYS[0]));
+        assert_eq!(WshTimelocked::from_policy(&wsh.to_policy()), Ok(wsh));
+
+        assert_eq!(
+            from_policy(&policy, ScriptContext::Segwit),
+            Err(PolicyError::SegwitScript(policy.clone()))
+        );
+        let policy = format!("thresh(1,pk({}),after(840000))", KEYS[0]);
+        assert!(matches!(
+            from_policy(&policy, ScriptContext::Taproot),
+            Err(PolicyError::Unsupported { .. })
+        ));
+        assert!(matches!(
+            WshTimelocked::<XpubDerivable>::from_policy(&format!("and(pk({}),older(0))", KEYS[0])),
+            Err(PolicyError::Timelock(TimelockError::NotRelative(0)))
+        ));
+    }
+
+    #[test]
+    fn malformed() {
+        for policy in ["", "pk", "pk()", "thresh(1)", "or(pk(A))", "and(pk(A),pk(B)", "sha256(00)"]
+        {
+            assert!(matches!(
+                from_policy(policy, ScriptContext::Taproot),
+                Err(PolicyError::InvalidFormat(_) | PolicyError::Key(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Discovery of descriptor transactions and UTXOs using an external blockchain indexer.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use derive::{
+    DeriveScripts, Idx, Keychain, NormalIndex, Outpoint, Sats, ScriptPubkey, Terminal, Txid,
+};
+
+/// Transaction output available for spending, as reported by a [`ScriptResolver`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ScriptUtxo {
+    pub outpoint: Outpoint,
+    pub value: Sats,
+    /// Height of the block mining the transaction, or `None` for unconfirmed transactions.
+    pub height: Option<u32>,
+}
+
+/// Transaction spending or paying to a script, as reported by a [`ScriptResolver`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ScriptTx {
+    pub txid: Txid,
+    /// Height of the block mining the transaction, or `None` for unconfirmed transactions.
+    pub height: Option<u32>,
+}
+
+/// Blockchain indexer (like Electrum or Esplora servers) providing information about the
+/// transactions and UTXOs of a script pubkey.
+pub trait ScriptResolver {
+    type Error: Error;
+
+    /// Lists unspent outputs paying to the script pubkey.
+    fn utxos(&self, spk: &ScriptPubkey) -> Result<Vec<ScriptUtxo>, Self::Error>;
+
+    /// Lists all transactions (both confirmed and unconfirmed) which spend from or pay to the
+    /// script pubkey.
+    fn history(&self, spk: &ScriptPubkey) -> Result<Vec<ScriptTx>, Self::Error>;
+}
+
+/// Result of the descriptor scanning with [`scan`].
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct ScanResult {
+    /// Unspent outputs of the descriptor with the terminal derivation of their scripts.
+    pub utxos: Vec<(Terminal, ScriptUtxo)>,
+
+    /// Transactions of the descriptor with the terminal derivation of the scripts they use.
+    pub history: Vec<(Terminal, ScriptTx)>,
+
+    /// Index of the last script with a non-empty history per each of the descriptor keychains.
+    /// Keychains which have no used scripts are absent.
+    pub last_used: BTreeMap<Keychain, NormalIndex>,
+}
+
+impl ScanResult {
+    /// Total value of the found unspent outputs.
+    pub fn balance(&self) -> Sats { self.utxos.iter().map(|(_, utxo)| utxo.value).sum() }
+
+    /// Index of the first script following the last used one in the keychain.
+    pub fn next_unused(&self, keychain: impl Into<Keychain>) -> NormalIndex {
+        self.last_used
+            .get(&keychain.into())
+            .map(NormalIndex::saturating_inc)
+            .unwrap_or(NormalIndex::ZERO)
+    }
+}
+
+/// Scans all keychains of a descriptor, querying the resolver for the history and UTXOs of each
+/// of the derived scripts.
+///
+/// Scanning of a keychain stops after `gap_limit` consecutive scripts without history (BIP-44
+/// recommends the gap limit of 20).
+pub fn scan<D: DeriveScripts, R: ScriptResolver>(
+    descriptor: &D,
+    resolver: &R,
+    gap_limit: u32,
+) -> Result<ScanResult, R::Error> {
+    let mut result = ScanResult::default();
+    for keychain in descriptor.keychains() {
+        let mut gap = 0u32;
+        for terminal in Terminal::range(keychain, NormalIndex::ZERO, u32::MAX) {
+            if gap >= gap_limit {
+                break;
+            }
+            let spk = descriptor.derive(keychain, terminal.index).to_script_pubkey();
+            let history = resolver.history(&spk)?;
+            if history.is_empty() {
+                gap += 1;
+                continue;
+            }
+            gap = 0;
+            result.last_used.insert(keychain, terminal.index);
+            result.history.extend(history.into_iter().map(|tx| (terminal, tx)));
+            result.utxos.extend(resolver.utxos(&spk)?.into_iter().map(|utxo| (terminal, utxo)));
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::convert::Infallible;
+    use std::str::FromStr;
+
+    use derive::{Derive, DerivedScript, Vout, XpubDerivable};
+
+    use super::*;
+    use crate::TrKey;
+
+    const XPUB: &str = "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*";
+
+    #[derive(Default)]
+    struct Resolver(HashMap<ScriptPubkey, Vec<ScriptUtxo>>);
+
+    impl ScriptResolver for Resolver {
+        type Error = Infallible;
+
+        fn utxos(&self, spk: &ScriptPubkey) -> Result<Vec<ScriptUtxo>, Self::Error> {
+            Ok(self.0.get(spk).cloned().unwrap_or_default())
+        }
+
+        fn history(&self, spk: &ScriptPubkey) -> Result<Vec<ScriptTx>, Self::Error> {
+            Ok(self
+                .utxos(spk)?
+                .into_iter()
+                .map(|utxo| ScriptTx {
+                    txid: utxo.outpoint.txid,
+                    height: utxo.height,
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn gap_limit() {
+        let descr = TrKey::from(XpubDerivable::from_str(XPUB).unwrap());
+        let mut resolver = Resolver::default();
+        for (keychain, index, value) in
+            [(0u8, 0u16, 1000u64), (0, 4, 2000), (0, 10, 4000), (1, 1, 500)]
+        {
+            let spk = Derive::<DerivedScript>::derive(&descr, keychain, NormalIndex::normal(index))
+                .to_script_pubkey();
+            resolver.0.insert(spk, vec![ScriptUtxo {
+                outpoint: Outpoint::new(Txid::coinbase(), Vout::from_u32(index as u32)),
+                value: Sats::from_sats(value),
+                height: Some(100),
+            }]);
+        }
+
+        let result = scan(&descr, &resolver, 5).unwrap();
+        // The output at index 10 is beyond the gap limit
+        assert_eq!(result.balance(), Sats::from_sats(3500u64));
+        assert_eq!(result.utxos.len(), 3);
+        assert_eq!(result.history.len(), 3);
+        assert_eq!(result.last_used[&Keychain::OUTER], NormalIndex::normal(4));
+        assert_eq!(result.next_unused(Keychain::INNER), NormalIndex::normal(2));
+
+        let result = scan(&descr, &resolver, 6).unwrap();
+        assert_eq!(result.balance(), Sats::from_sats(7500u64));
+        assert_eq!(result.next_unused(Keychain::OUTER), NormalIndex::normal(11));
+    }
+}
@@ -21,38 +21,98 @@
 // limitations under the License.
 
 use std::collections::BTreeSet;
-use std::iter;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::{iter, option};
 
 use derive::{
     CompressedPk, Derive, DeriveCompr, DerivedScript, KeyOrigin, Keychain, NormalIndex,
-    ScriptPubkey, TapDerivation, Terminal, WPubkeyHash, XOnlyPk, XpubDerivable, XpubSpec,
+    ScriptPubkey, TapDerivation, Terminal, WPubkeyHash, WeightUnits, WifKey, XOnlyPk,
+    XpubDerivable, XpubSpec,
 };
 use indexmap::IndexMap;
 
-use crate::{Descriptor, SpkClass};
+use crate::descriptor::{parse_function, parse_key};
+use crate::{
+    Descriptor, DescriptorParseError, KeyTranslate, SatisfactionWeight, SpkClass, ToPolicy,
+};
+
+/// Constructs the redeem script of a P2SH-wrapped P2WPKH output (`0x0014{keyhash}`), which is
+/// put into the script sig when spending the output. The script is byte-identical to the script
+/// pubkey of the native P2WPKH output produced by [`Wpkh`] for the same key.
+pub fn p2sh_wpkh_redeem_script(key: &CompressedPk) -> ScriptPubkey {
+    ScriptPubkey::p2wpkh(WPubkeyHash::from(*key))
+}
 
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate",))]
-#[derive(Clone, Eq, PartialEq, Hash, Debug, From)]
-pub struct Wpkh<K: DeriveCompr = XpubDerivable>(K);
+/// Segwit v0 single-key descriptor `wpkh(KEY)`.
+///
+/// Serialized with serde as an object with a single `key` field.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Wpkh<K: DeriveCompr = XpubDerivable> {
+    key: K,
+}
+
+impl<K: DeriveCompr> From<K> for Wpkh<K> {
+    fn from(key: K) -> Self { Wpkh { key } }
+}
 
 impl<K: DeriveCompr> Wpkh<K> {
-    pub fn as_key(&self) -> &K { &self.0 }
-    pub fn into_key(self) -> K { self.0 }
+    pub fn as_key(&self) -> &K { &self.key }
+    pub fn into_key(self) -> K { self.key }
+}
+
+impl Wpkh<CompressedPk> {
+    /// Constructs non-derivable single-key descriptor from a private key in WIF format.
+    ///
+    /// Returns `None` if the private key is exported for the use with an uncompressed public key,
+    /// which can't be used in segwit outputs.
+    pub fn with_wif(wif: &WifKey) -> Option<Self> { wif.to_compr_pub().map(Wpkh::from) }
+}
+
+impl<K: DeriveCompr + Display> Display for Wpkh<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result { write!(f, "wpkh({})", self.key) }
+}
+
+impl<K: DeriveCompr + FromStr> FromStr for Wpkh<K>
+where K::Err: Error
+{
+    type Err = DescriptorParseError<K::Err>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_function(s)? {
+            ("wpkh", key, pos) => parse_key::<K>(key, pos).map(Self::from),
+            (name, ..) => Err(DescriptorParseError::Unsupported(name.to_owned())),
+        }
+    }
+}
+
+impl<K: DeriveCompr, K2: DeriveCompr> KeyTranslate<K, K2> for Wpkh<K> {
+    type Output = Wpkh<K2>;
+
+    fn translate_keys<E>(&self, mut f: impl FnMut(&K) -> Result<K2, E>) -> Result<Wpkh<K2>, E> {
+        f(&self.key).map(Wpkh::from)
+    }
 }
 
 impl<K: DeriveCompr> Derive<DerivedScript> for Wpkh<K> {
     #[inline]
-    fn default_keychain(&self) -> Keychain { self.0.default_keychain() }
+    fn default_keychain(&self) -> Keychain { self.key.default_keychain() }
 
     #[inline]
-    fn keychains(&self) -> BTreeSet<Keychain> { self.0.keychains() }
+    fn keychains(&self) -> BTreeSet<Keychain> { self.key.keychains() }
 
     fn derive(
         &self,
         keychain: impl Into<Keychain>,
         index: impl Into<NormalIndex>,
     ) -> DerivedScript {
-        let key = self.0.derive(keychain, index);
+        let key = self.key.derive(keychain, index);
         DerivedScript::Bare(ScriptPubkey::p2wpkh(WPubkeyHash::from(key)))
     }
 }
@@ -60,18 +120,19 @@ impl<K: DeriveCompr> Derive<DerivedScript> for Wpkh<K> {
 impl<K: DeriveCompr> Descriptor<K> for Wpkh<K> {
     type KeyIter<'k> = iter::Once<&'k K> where Self: 'k, K: 'k;
     type VarIter<'v> = iter::Empty<&'v ()> where Self: 'v, (): 'v;
-    type XpubIter<'x> = iter::Once<&'x XpubSpec> where Self: 'x;
+    type XpubIter<'x> = option::IntoIter<&'x XpubSpec> where Self: 'x;
 
     fn class(&self) -> SpkClass { SpkClass::P2wpkh }
 
-    fn keys(&self) -> Self::KeyIter<'_> { iter::once(&self.0) }
+    fn keys(&self) -> Self::KeyIter<'_> { iter::once(&self.key) }
     fn vars(&self) -> Self::VarIter<'_> { iter::empty() }
-    fn xpubs(&self) -> Self::XpubIter<'_> { iter::once(self.0.xpub_spec()) }
+    fn xpubs(&self) -> Self::XpubIter<'_> { self.key.try_xpub_spec().into_iter() }
 
     fn compr_keyset(&self, terminal: Terminal) -> IndexMap<CompressedPk, KeyOrigin> {
         let mut map = IndexMap::with_capacity(1);
-        let key = self.0.derive(terminal.keychain, terminal.index);
-        map.insert(key, KeyOrigin::with(self.0.xpub_spec().origin().clone(), terminal));
+        if let Some(origin) = self.key.key_origin(terminal) {
+            map.insert(self.key.derive(terminal.keychain, terminal.index), origin);
+        }
         map
     }
 
@@ -79,3 +140,41 @@ impl<K: DeriveCompr> Descriptor<K> for Wpkh<K> {
         IndexMap::new()
     }
 }
+
+impl<K: DeriveCompr + Display> ToPolicy for Wpkh<K> {
+    fn to_policy(&self) -> String { format!("pk({})", self.key) }
+}
+
+impl<K: DeriveCompr> SatisfactionWeight for Wpkh<K> {
+    fn max_satisfaction_weight(&self) -> WeightUnits {
+        // Empty script sig; witness with two elements: ECDSA signature with sighash type of at
+        // most 73 bytes and a compressed public key
+        WeightUnits::no_discount(1) + WeightUnits::witness_discount(1 + 1 + 73 + 1 + 33)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::hex::FromHex;
+    use derive::RedeemScript;
+
+    use super::*;
+
+    #[test]
+    fn p2sh_wpkh() {
+        // BIP-143 P2SH-P2WPKH test vector
+        let key = CompressedPk::from_str(
+            "03ad1d8e89212f0b92c74d23bb710c00662ad1470198ac48c43f7d6f93a2a26873",
+        )
+        .unwrap();
+        let redeem_script = p2sh_wpkh_redeem_script(&key);
+        assert_eq!(
+            redeem_script,
+            ScriptPubkey::from_hex("001479091972186c449eb1ded22b78e40d009bdf0089").unwrap()
+        );
+        assert_eq!(
+            RedeemScript::from_unsafe(redeem_script.to_vec()).to_script_pubkey(),
+            ScriptPubkey::from_hex("a9144733f37cf4db86fbc2efed2500b4f4e49f31202387").unwrap()
+        );
+    }
+}
@@ -21,52 +21,162 @@
 // limitations under the License.
 
 use std::collections::BTreeSet;
-use std::iter;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::{iter, option};
 
 use derive::{
     CompressedPk, Derive, DeriveXOnly, DerivedScript, InternalPk, KeyOrigin, Keychain, NormalIndex,
-    TapDerivation, Terminal, XOnlyPk, XpubDerivable, XpubSpec,
+    TapDerivation, TapNodeHash, Terminal, WeightUnits, XOnlyPk, XpubDerivable, XpubSpec,
 };
 use indexmap::IndexMap;
 
-use crate::{Descriptor, SpkClass};
+use crate::descriptor::{parse_function, parse_key};
+use crate::{
+    Descriptor, DescriptorParseError, KeyTranslate, SatisfactionWeight, SpkClass, ToPolicy,
+};
+
+/// Taproot key-path only descriptor `tr(KEY)`.
+///
+/// The output may commit to a script tree known only by its merkle root (see
+/// [`TrKey::with_merkle_root`]), producing the same output key as `tr(KEY,{TREE})` while being
+/// spent with the key path only. Such descriptors are represented as `tr(KEY,rawnode(ROOT))`,
+/// where `ROOT` is the hex-encoded merkle root.
+///
+/// Serialized with serde as an object with an `internalKey` field and an optional `merkleRoot`.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TrKey<K: DeriveXOnly = XpubDerivable> {
+    internal_key: K,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    merkle_root: Option<TapNodeHash>,
+}
 
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(crate = "serde_crate",))]
-#[derive(Clone, Eq, PartialEq, Hash, Debug, From)]
-pub struct TrKey<K: DeriveXOnly = XpubDerivable>(K);
+impl<K: DeriveXOnly> From<K> for TrKey<K> {
+    fn from(internal_key: K) -> Self {
+        TrKey {
+            internal_key,
+            merkle_root: None,
+        }
+    }
+}
 
 impl<K: DeriveXOnly> TrKey<K> {
-    pub fn as_internal_key(&self) -> &K { &self.0 }
-    pub fn into_internal_key(self) -> K { self.0 }
+    /// Constructs descriptor whose outputs commit to a script tree with the given merkle root,
+    /// while being spendable with the key path only.
+    pub fn with_merkle_root(internal_key: K, merkle_root: TapNodeHash) -> Self {
+        TrKey {
+            internal_key,
+            merkle_root: Some(merkle_root),
+        }
+    }
+
+    pub fn as_internal_key(&self) -> &K { &self.internal_key }
+    pub fn into_internal_key(self) -> K { self.internal_key }
+
+    #[inline]
+    pub fn merkle_root(&self) -> Option<TapNodeHash> { self.merkle_root }
+
+    /// Computes BIP-341 output key for the given terminal, tweaking the derived internal key with
+    /// the merkle root, if any.
+    pub fn output_key(&self, terminal: Terminal) -> XOnlyPk {
+        let internal_pk =
+            InternalPk::from_unchecked(self.internal_key.derive(terminal.keychain, terminal.index));
+        let (output_pk, _) = internal_pk.to_output_pk(self.merkle_root);
+        *output_pk
+    }
+}
+
+impl<K: DeriveXOnly + Display> Display for TrKey<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.merkle_root {
+            None => write!(f, "tr({})", self.internal_key),
+            Some(merkle_root) => write!(f, "tr({},rawnode({merkle_root}))", self.internal_key),
+        }
+    }
+}
+
+impl<K: DeriveXOnly + FromStr> FromStr for TrKey<K>
+where K::Err: Error
+{
+    type Err = DescriptorParseError<K::Err>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (internal_key, merkle_root) = parse_tr_key(s)?;
+        Ok(TrKey {
+            internal_key,
+            merkle_root,
+        })
+    }
+}
+
+/// Parses `tr(KEY)` or `tr(KEY,rawnode(ROOT))` descriptor into the key and the optional merkle
+/// root.
+pub(crate) fn parse_tr_key<K: FromStr>(
+    s: &str,
+) -> Result<(K, Option<TapNodeHash>), DescriptorParseError<K::Err>>
+where K::Err: Error {
+    match parse_function(s)? {
+        ("tr", args, pos) => match args.rsplit_once(",rawnode(") {
+            None => parse_key::<K>(args, pos).map(|key| (key, None)),
+            Some((key, node)) => {
+                let merkle_root = node
+                    .strip_suffix(')')
+                    .and_then(|node| TapNodeHash::from_str(node).ok())
+                    .ok_or_else(|| DescriptorParseError::InvalidFormat(s.to_owned()))?;
+                parse_key::<K>(key, pos).map(|key| (key, Some(merkle_root)))
+            }
+        },
+        (name, ..) => Err(DescriptorParseError::Unsupported(name.to_owned())),
+    }
+}
+
+impl<K: DeriveXOnly, K2: DeriveXOnly> KeyTranslate<K, K2> for TrKey<K> {
+    type Output = TrKey<K2>;
+
+    fn translate_keys<E>(&self, mut f: impl FnMut(&K) -> Result<K2, E>) -> Result<TrKey<K2>, E> {
+        Ok(TrKey {
+            internal_key: f(&self.internal_key)?,
+            merkle_root: self.merkle_root,
+        })
+    }
 }
 
 impl<K: DeriveXOnly> Derive<DerivedScript> for TrKey<K> {
     #[inline]
-    fn default_keychain(&self) -> Keychain { self.0.default_keychain() }
+    fn default_keychain(&self) -> Keychain { self.internal_key.default_keychain() }
 
     #[inline]
-    fn keychains(&self) -> BTreeSet<Keychain> { self.0.keychains() }
+    fn keychains(&self) -> BTreeSet<Keychain> { self.internal_key.keychains() }
 
     fn derive(
         &self,
         keychain: impl Into<Keychain>,
         index: impl Into<NormalIndex>,
     ) -> DerivedScript {
-        let internal_key = self.0.derive(keychain, index);
-        DerivedScript::TaprootKeyOnly(InternalPk::from_unchecked(internal_key))
+        let internal_pk = InternalPk::from_unchecked(self.internal_key.derive(keychain, index));
+        match self.merkle_root {
+            None => DerivedScript::TaprootKeyOnly(internal_pk),
+            Some(merkle_root) => DerivedScript::TaprootKeyTweaked(internal_pk, merkle_root),
+        }
     }
 }
 
 impl<K: DeriveXOnly> Descriptor<K> for TrKey<K> {
     type KeyIter<'k> = iter::Once<&'k K> where Self: 'k, K: 'k;
     type VarIter<'v> = iter::Empty<&'v ()> where Self: 'v, (): 'v;
-    type XpubIter<'x> = iter::Once<&'x XpubSpec> where Self: 'x;
+    type XpubIter<'x> = option::IntoIter<&'x XpubSpec> where Self: 'x;
 
     fn class(&self) -> SpkClass { SpkClass::P2tr }
 
-    fn keys(&self) -> Self::KeyIter<'_> { iter::once(&self.0) }
+    fn keys(&self) -> Self::KeyIter<'_> { iter::once(&self.internal_key) }
     fn vars(&self) -> Self::VarIter<'_> { iter::empty() }
-    fn xpubs(&self) -> Self::XpubIter<'_> { iter::once(self.0.xpub_spec()) }
+    fn xpubs(&self) -> Self::XpubIter<'_> { self.internal_key.try_xpub_spec().into_iter() }
 
     fn compr_keyset(&self, _terminal: Terminal) -> IndexMap<CompressedPk, KeyOrigin> {
         IndexMap::new()
@@ -74,15 +184,34 @@ impl<K: DeriveXOnly> Descriptor<K> for TrKey<K> {
 
     fn xonly_keyset(&self, terminal: Terminal) -> IndexMap<XOnlyPk, TapDerivation> {
         let mut map = IndexMap::with_capacity(1);
-        let key = self.0.derive(terminal.keychain, terminal.index);
-        map.insert(
-            key,
-            TapDerivation::with_internal_pk(self.0.xpub_spec().origin().clone(), terminal),
-        );
+        if let Some(origin) = self.internal_key.key_origin(terminal) {
+            let key = self.internal_key.derive(terminal.keychain, terminal.index);
+            map.insert(key, TapDerivation {
+                leaf_hashes: vec![],
+                origin,
+            });
+        }
+        for (key, origin) in self.internal_key.participants(terminal) {
+            map.insert(key, TapDerivation {
+                leaf_hashes: vec![],
+                origin,
+            });
+        }
         map
     }
 }
 
+impl<K: DeriveXOnly + Display> ToPolicy for TrKey<K> {
+    fn to_policy(&self) -> String { format!("pk({})", self.internal_key) }
+}
+
+impl<K: DeriveXOnly> SatisfactionWeight for TrKey<K> {
+    fn max_satisfaction_weight(&self) -> WeightUnits {
+        // Empty script sig; witness with a single BIP340 signature with a non-default sighash type
+        WeightUnits::no_discount(1) + WeightUnits::witness_discount(1 + 1 + 65)
+    }
+}
+
 /*
 pub struct TrScript<K: DeriveXOnly> {
     internal_key: K,
@@ -0,0 +1,265 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use derive::{
+    DeriveCompr, DeriveSet, DeriveXOnly, TapNodeHash, TemplateKey, TemplateKeyParseError,
+    UnresolvedKey, XpubDerivable,
+};
+
+use crate::descriptor::{parse_function, parse_key};
+use crate::multisig::{check_threshold, parse_tr_multi_a};
+use crate::taproot::parse_tr_key;
+use crate::{
+    DescriptorParseError, MultiError, StdDescr, TrKey, TrMultiA, Wpkh, UNSPENDABLE_INTERNAL_KEY,
+};
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+enum TemplateInner<K> {
+    Wpkh(TemplateKey<K>),
+    TrKey(TemplateKey<K>, Option<TapNodeHash>),
+    TrMultiA(Option<TemplateKey<K>>, u16, Vec<TemplateKey<K>>),
+}
+
+/// Template of a [`StdDescr`], which may contain key slots (`@NAME`) for the co-signer keys which
+/// are not provided yet.
+///
+/// Templates can't be used for derivation: once all co-signers have provided their keys, the
+/// template is converted into a descriptor with [`DescrTemplate::resolve`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct DescrTemplate<K = XpubDerivable>(TemplateInner<K>);
+
+impl<K> DescrTemplate<K> {
+    /// Constructs `wpkh(KEY)` template.
+    pub fn wpkh(key: TemplateKey<K>) -> Self { DescrTemplate(TemplateInner::Wpkh(key)) }
+
+    /// Constructs `tr(KEY)` template, which may commit to a script tree known only by its merkle
+    /// root (`tr(KEY,rawnode(ROOT))`).
+    pub fn tr_key(internal_key: TemplateKey<K>, merkle_root: Option<TapNodeHash>) -> Self {
+        DescrTemplate(TemplateInner::TrKey(internal_key, merkle_root))
+    }
+
+    /// Constructs `tr(KEY,sortedmulti_a(k,KEY1,...))` template, using BIP-341 unspendable internal
+    /// key if the `internal_key` is not provided. Errors if the threshold is zero or exceeds the
+    /// number of the script keys, or if there are too many script keys.
+    pub fn tr_multi_a(
+        internal_key: Option<TemplateKey<K>>,
+        threshold: u16,
+        script_keys: impl IntoIterator<Item = TemplateKey<K>>,
+    ) -> Result<Self, MultiError> {
+        let script_keys = script_keys.into_iter().collect::<Vec<_>>();
+        check_threshold(threshold, script_keys.len())?;
+        Ok(DescrTemplate(TemplateInner::TrMultiA(internal_key, threshold, script_keys)))
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &TemplateKey<K>> {
+        let (internal_key, script_keys) = match &self.0 {
+            TemplateInner::Wpkh(key) | TemplateInner::TrKey(key, _) => (Some(key), &[][..]),
+            TemplateInner::TrMultiA(internal_key, _, script_keys) => {
+                (internal_key.as_ref(), script_keys.as_slice())
+            }
+        };
+        internal_key.into_iter().chain(script_keys)
+    }
+
+    /// Returns names of the key slots which are not resolved yet, in the order of the keys.
+    pub fn slots(&self) -> Vec<&str> { self.keys().filter_map(TemplateKey::slot_name).collect() }
+
+    /// Detects whether all key slots of the descriptor template are resolved.
+    pub fn is_resolved(&self) -> bool { self.keys().all(TemplateKey::is_resolved) }
+
+    /// Converts descriptor template into a descriptor, replacing key slots with the keys returned
+    /// by the `resolver` for each of the slot names. Errors with the first slot for which the
+    /// resolver has returned no key.
+    pub fn resolve(
+        &self,
+        mut resolver: impl FnMut(&str) -> Option<K>,
+    ) -> Result<StdDescr<K>, UnresolvedKey>
+    where
+        K: DeriveSet<Compr = K, XOnly = K> + DeriveCompr + DeriveXOnly + Clone,
+    {
+        let mut resolve = |key: &TemplateKey<K>| key.resolve(&mut resolver);
+        Ok(match &self.0 {
+            TemplateInner::Wpkh(key) => StdDescr::Wpkh(Wpkh::from(resolve(key)?)),
+            TemplateInner::TrKey(key, None) => StdDescr::TrKey(TrKey::from(resolve(key)?)),
+            TemplateInner::TrKey(key, Some(merkle_root)) => {
+                StdDescr::TrKey(TrKey::with_merkle_root(resolve(key)?, *merkle_root))
+            }
+            TemplateInner::TrMultiA(internal_key, threshold, script_keys) => {
+                let internal_key = internal_key.as_ref().map(&mut resolve).transpose()?;
+                let script_keys = script_keys.iter().map(resolve).collect::<Result<Vec<_>, _>>()?;
+                let multi = TrMultiA::new(internal_key, *threshold, script_keys)
+                    .expect("threshold is validated when the template is constructed");
+                StdDescr::TrMultiA(multi)
+            }
+        })
+    }
+}
+
+impl<K: Display> Display for DescrTemplate<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            TemplateInner::Wpkh(key) => write!(f, "wpkh({key})"),
+            TemplateInner::TrKey(key, None) => write!(f, "tr({key})"),
+            TemplateInner::TrKey(key, Some(merkle_root)) => {
+                write!(f, "tr({key},rawnode({merkle_root}))")
+            }
+            TemplateInner::TrMultiA(internal_key, threshold, script_keys) => {
+                f.write_str("tr(")?;
+                match internal_key {
+                    Some(key) => Display::fmt(key, f)?,
+                    None => f.write_str(UNSPENDABLE_INTERNAL_KEY)?,
+                }
+                write!(f, ",sortedmulti_a({threshold}")?;
+                for key in script_keys {
+                    f.write_str(",")?;
+                    Display::fmt(key, f)?;
+                }
+                f.write_str("))")
+            }
+        }
+    }
+}
+
+impl<K: FromStr> FromStr for DescrTemplate<K>
+where K::Err: Error + 'static
+{
+    type Err = DescriptorParseError<TemplateKeyParseError<K::Err>>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, key, pos) = parse_function(s)?;
+        Ok(match name {
+            "wpkh" => DescrTemplate::wpkh(parse_key(key, pos)?),
+            "tr" if key.contains(',') && !key.contains(",rawnode(") => {
+                let (internal_key, threshold, script_keys) = parse_tr_multi_a(s)?;
+                DescrTemplate::tr_multi_a(internal_key, threshold, script_keys)?
+            }
+            "tr" => {
+                let (internal_key, merkle_root) = parse_tr_key(s)?;
+                DescrTemplate::tr_key(internal_key, merkle_root)
+            }
+            _ => return Err(DescriptorParseError::Unsupported(name.to_owned())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use derive::{Derive, Keychain};
+
+    use super::*;
+
+    const XPUB: &str = "[643a7adc/86'/1'/0']tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/0/*";
+
+    #[test]
+    fn template() {
+        let key = XpubDerivable::from_str(XPUB).unwrap();
+        let cosigner1 = XpubDerivable::from_str("[deadbeef/0h]xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw/0/*").unwrap();
+        let cosigner2 = XpubDerivable::from_str("[beefcafe/1h/2h/3h/4h/5h]xpub6H1LXWLaKsWFhvm6RVpEL9P4KfRZSW7abD2ttkWP3SSQvnyA8FSVqNTEcYFgJS2UaFcxupHiYkro49S8yGasTvXEYBVPamhGW6cFJodrTHy/0/*").unwrap();
+
+        let s = format!("tr({UNSPENDABLE_INTERNAL_KEY},sortedmulti_a(2,{XPUB},@alice,@bob))");
+        let template = DescrTemplate::<XpubDerivable>::from_str(&s).unwrap();
+        assert_eq!(template.to_string(), s.replace('\'', "h"));
+        assert_eq!(template.slots(), vec!["alice", "bob"]);
+        assert!(!template.is_resolved());
+
+        let cosigners = [("alice", cosigner1.clone()), ("bob", cosigner2.clone())];
+        let lookup =
+            |name: &str| cosigners.iter().find(|(n, _)| *n == name).map(|(_, k)| k.clone());
+        assert_eq!(
+            template.resolve(|name| (name == "alice").then(|| cosigner1.clone())),
+            Err(UnresolvedKey("bob".to_owned()))
+        );
+        let descr = template.resolve(lookup).unwrap();
+        let expected =
+            StdDescr::TrMultiA(TrMultiA::new(None, 2, [key, cosigner1, cosigner2]).unwrap());
+        assert_eq!(descr, expected);
+        assert_eq!(
+            descr.try_derive(Keychain::OUTER, 0u8).unwrap(),
+            expected.derive(Keychain::OUTER, 0u8)
+        );
+
+        assert!(matches!(
+            DescrTemplate::<XpubDerivable>::from_str(&format!("tr(@,sortedmulti_a(1,{XPUB}))")),
+            Err(DescriptorParseError::Key {
+                err: TemplateKeyParseError::InvalidSlot(_),
+                ..
+            })
+        ));
+        assert!(matches!(
+            DescrTemplate::<XpubDerivable>::from_str(&format!(
+                "tr({UNSPENDABLE_INTERNAL_KEY},sortedmulti_a(3,{XPUB},@alice))"
+            )),
+            Err(DescriptorParseError::Multisig(_))
+        ));
+    }
+
+    #[test]
+    fn template_unresolved() {
+        let template = DescrTemplate::<XpubDerivable>::from_str("tr(@alice)").unwrap();
+        assert_eq!(template.to_string(), "tr(@alice)");
+        assert_eq!(template.slots(), vec!["alice"]);
+        let err = template.resolve(|_| None).unwrap_err();
+        assert_eq!(err, UnresolvedKey("alice".to_owned()));
+        assert_eq!(err.to_string(), "descriptor template key slot '@alice' is not resolved");
+
+        let key = XpubDerivable::from_str(XPUB).unwrap();
+        let template = DescrTemplate::<XpubDerivable>::from_str(&format!("wpkh({XPUB})")).unwrap();
+        assert!(template.is_resolved());
+        assert_eq!(template.resolve(|_| None), Ok(StdDescr::Wpkh(Wpkh::from(key.clone()))));
+        assert_eq!(template, DescrTemplate::wpkh(TemplateKey::from(key)));
+    }
+
+    #[test]
+    fn template_constructors() {
+        let key = TemplateKey::from(XpubDerivable::from_str(XPUB).unwrap());
+        let alice = TemplateKey::slot("alice").unwrap();
+        let template = DescrTemplate::tr_multi_a(None, 2, [key.clone(), alice.clone()]).unwrap();
+        assert_eq!(
+            template,
+            DescrTemplate::from_str(&format!(
+                "tr({UNSPENDABLE_INTERNAL_KEY},sortedmulti_a(2,{XPUB},@alice))"
+            ))
+            .unwrap()
+        );
+        assert_eq!(template.slots(), vec!["alice"]);
+        assert_eq!(
+            DescrTemplate::tr_multi_a(None, 3, [key.clone(), alice.clone()]),
+            Err(MultiError::ThresholdExceedsKeys {
+                threshold: 3,
+                keys: 2
+            })
+        );
+        assert_eq!(
+            DescrTemplate::tr_multi_a(Some(key), 0, [alice]),
+            Err(MultiError::ZeroThreshold)
+        );
+        assert_eq!(
+            DescrTemplate::<XpubDerivable>::tr_key(TemplateKey::slot("bob").unwrap(), None)
+                .to_string(),
+            "tr(@bob)"
+        );
+    }
+}
@@ -0,0 +1,357 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::{iter, option};
+
+use derive::opcodes::{OP_CHECKSIGVERIFY, OP_CLTV, OP_CSV, OP_PUSHBYTES_32, OP_PUSHBYTES_33};
+use derive::{
+    CompressedPk, Derive, DeriveCompr, DerivedScript, KeyOrigin, Keychain, LeafScript, LockHeight,
+    LockTime, LockTimestamp, NormalIndex, SeqNo, TapDerivation, TapScript, Terminal, WeightUnits,
+    WitnessScript, XOnlyPk, XpubDerivable, XpubSpec, LOCKTIME_THRESHOLD, SEQ_NO_CSV_DISABLE_MASK,
+};
+use indexmap::IndexMap;
+
+use crate::descriptor::{parse_function, parse_key};
+use crate::multisig::push_num;
+use crate::{
+    Descriptor, DescriptorParseError, KeyTranslate, SatisfactionWeight, SpkClass, ToPolicy,
+};
+
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum TimelockError {
+    /// absolute timelock must be non-zero.
+    ZeroLockTime,
+
+    /// sequence number {0:#010x} doesn't represent a non-zero relative timelock.
+    NotRelative(u32),
+
+    /// invalid timelock expression '{0}'.
+    InvalidFormat(String),
+}
+
+/// Timelock condition enforced by a script: either absolute (`OP_CHECKLOCKTIMEVERIFY`) or
+/// relative (`OP_CHECKSEQUENCEVERIFY`).
+///
+/// The string representation matches miniscript `after(n)` and `older(n)` fragments.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Timelock {
+    /// Absolute timelock, requiring transaction `nLockTime` to be at least the given value.
+    After(LockTime),
+
+    /// Relative timelock, requiring input `nSequence` to be at least the given value.
+    Older(SeqNo),
+}
+
+impl Timelock {
+    /// Constructs absolute timelock which expires at a given block height.
+    pub fn after_height(height: LockHeight) -> Result<Self, TimelockError> {
+        if height.to_consensus_u32() == 0 {
+            return Err(TimelockError::ZeroLockTime);
+        }
+        Ok(Timelock::After(height.into_lock_time()))
+    }
+
+    /// Constructs absolute timelock which expires at a given UNIX timestamp.
+    pub fn after_time(timestamp: LockTimestamp) -> Result<Self, TimelockError> {
+        if timestamp.to_consensus_u32() == 0 {
+            return Err(TimelockError::ZeroLockTime);
+        }
+        Ok(Timelock::After(timestamp.into_lock_time()))
+    }
+
+    /// Constructs relative timelock from a sequence number, which must have the relative timelock
+    /// enabled and a non-zero number of blocks or time intervals.
+    pub fn older(seq_no: SeqNo) -> Result<Self, TimelockError> {
+        let value = seq_no.to_consensus_u32();
+        if value & SEQ_NO_CSV_DISABLE_MASK != 0 || value & 0xFFFF == 0 {
+            return Err(TimelockError::NotRelative(value));
+        }
+        Ok(Timelock::Older(seq_no))
+    }
+
+    /// Returns number which is put into the script for the timelock check.
+    pub fn to_consensus_u32(&self) -> u32 {
+        match self {
+            Timelock::After(lock_time) => lock_time.to_consensus_u32(),
+            Timelock::Older(seq_no) => seq_no.to_consensus_u32(),
+        }
+    }
+
+    fn push_check(&self, script: &mut Vec<u8>) {
+        push_num(script, self.to_consensus_u32());
+        script.push(match self {
+            Timelock::After(_) => OP_CLTV,
+            Timelock::Older(_) => OP_CSV,
+        });
+    }
+
+    /// Constructs witness script `<key> OP_CHECKSIGVERIFY <n> OP_CHECKLOCKTIMEVERIFY` (or
+    /// `OP_CHECKSEQUENCEVERIFY` for the relative timelock), matching miniscript
+    /// `and_v(v:pk(key),after(n))`.
+    pub fn to_witness_script(&self, key: CompressedPk) -> WitnessScript {
+        let mut script = Vec::with_capacity(34 + 7);
+        script.push(OP_PUSHBYTES_33);
+        script.extend_from_slice(&key.to_byte_array());
+        script.push(OP_CHECKSIGVERIFY);
+        self.push_check(&mut script);
+        WitnessScript::from_unsafe(script)
+    }
+
+    /// Constructs tapscript leaf with the same structure as [`Self::to_witness_script`], using
+    /// BIP340 key.
+    pub fn to_leaf_script(&self, key: XOnlyPk) -> LeafScript {
+        let mut script = Vec::with_capacity(33 + 7);
+        script.push(OP_PUSHBYTES_32);
+        script.extend_from_slice(&key.to_byte_array());
+        script.push(OP_CHECKSIGVERIFY);
+        self.push_check(&mut script);
+        LeafScript::from_tap_script(TapScript::from_unsafe(script))
+    }
+}
+
+impl Display for Timelock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Timelock::After(lock_time) => write!(f, "after({})", lock_time.to_consensus_u32()),
+            Timelock::Older(seq_no) => write!(f, "older({})", seq_no.to_consensus_u32()),
+        }
+    }
+}
+
+impl FromStr for Timelock {
+    type Err = TimelockError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || TimelockError::InvalidFormat(s.to_owned());
+        let (name, value) =
+            s.strip_suffix(')').and_then(|s| s.split_once('(')).ok_or_else(invalid)?;
+        let value = u32::from_str(value).map_err(|_| invalid())?;
+        match name {
+            "after" if value < LOCKTIME_THRESHOLD => {
+                Timelock::after_height(LockHeight::try_from(value).map_err(|_| invalid())?)
+            }
+            "after" => Timelock::after_time(LockTimestamp::try_from(value).map_err(|_| invalid())?),
+            "older" => Timelock::older(SeqNo::from_consensus_u32(value)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Segwit v0 descriptor for a key which can spend only after the timelock expires:
+/// `wsh(and_v(v:pk(KEY),after(n)))` or `wsh(and_v(v:pk(KEY),older(n)))`.
+///
+/// Useful for the refund paths of HTLC-style contracts.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct WshTimelocked<K: DeriveCompr = XpubDerivable> {
+    key: K,
+    timelock: Timelock,
+}
+
+impl<K: DeriveCompr> WshTimelocked<K> {
+    pub fn new(key: K, timelock: Timelock) -> Self { WshTimelocked { key, timelock } }
+
+    #[inline]
+    pub fn as_key(&self) -> &K { &self.key }
+
+    #[inline]
+    pub fn timelock(&self) -> Timelock { self.timelock }
+}
+
+impl<K: DeriveCompr + Display> Display for WshTimelocked<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "wsh(and_v(v:pk({}),{}))", self.key, self.timelock)
+    }
+}
+
+impl<K: DeriveCompr + FromStr> FromStr for WshTimelocked<K>
+where K::Err: Error
+{
+    type Err = DescriptorParseError<K::Err>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (inner, pos) = match parse_function(s)? {
+            ("wsh", inner, pos) => (inner, pos),
+            (name, ..) => return Err(DescriptorParseError::Unsupported(name.to_owned())),
+        };
+        let invalid = || DescriptorParseError::InvalidFormat(s.to_owned());
+        let (key, timelock) = inner
+            .strip_prefix("and_v(v:pk(")
+            .and_then(|s| s.strip_suffix(')'))
+            .and_then(|s| s.rsplit_once("),"))
+            .ok_or_else(invalid)?;
+        let timelock = Timelock::from_str(timelock)
+            .map_err(|err| DescriptorParseError::InvalidFormat(err.to_string()))?;
+        let key = parse_key::<K>(key, pos + "and_v(v:pk(".len())?;
+        Ok(WshTimelocked::new(key, timelock))
+    }
+}
+
+impl<K: DeriveCompr, K2: DeriveCompr> KeyTranslate<K, K2> for WshTimelocked<K> {
+    type Output = WshTimelocked<K2>;
+
+    fn translate_keys<E>(
+        &self,
+        mut f: impl FnMut(&K) -> Result<K2, E>,
+    ) -> Result<WshTimelocked<K2>, E> {
+        Ok(WshTimelocked::new(f(&self.key)?, self.timelock))
+    }
+}
+
+impl<K: DeriveCompr> Derive<DerivedScript> for WshTimelocked<K> {
+    #[inline]
+    fn default_keychain(&self) -> Keychain { self.key.default_keychain() }
+
+    #[inline]
+    fn keychains(&self) -> BTreeSet<Keychain> { self.key.keychains() }
+
+    fn derive(
+        &self,
+        keychain: impl Into<Keychain>,
+        index: impl Into<NormalIndex>,
+    ) -> DerivedScript {
+        let key = self.key.derive(keychain, index);
+        DerivedScript::Segwit(self.timelock.to_witness_script(key))
+    }
+}
+
+impl<K: DeriveCompr> Descriptor<K> for WshTimelocked<K> {
+    type KeyIter<'k> = iter::Once<&'k K> where Self: 'k, K: 'k;
+    type VarIter<'v> = iter::Empty<&'v ()> where Self: 'v, (): 'v;
+    type XpubIter<'x> = option::IntoIter<&'x XpubSpec> where Self: 'x;
+
+    fn class(&self) -> SpkClass { SpkClass::P2wsh }
+
+    fn keys(&self) -> Self::KeyIter<'_> { iter::once(&self.key) }
+    fn vars(&self) -> Self::VarIter<'_> { iter::empty() }
+    fn xpubs(&self) -> Self::XpubIter<'_> { self.key.try_xpub_spec().into_iter() }
+
+    fn compr_keyset(&self, terminal: Terminal) -> IndexMap<CompressedPk, KeyOrigin> {
+        let mut map = IndexMap::with_capacity(1);
+        if let Some(origin) = self.key.key_origin(terminal) {
+            map.insert(self.key.derive(terminal.keychain, terminal.index), origin);
+        }
+        map
+    }
+
+    fn xonly_keyset(&self, _terminal: Terminal) -> IndexMap<XOnlyPk, TapDerivation> {
+        IndexMap::new()
+    }
+}
+
+impl<K: DeriveCompr + Display> ToPolicy for WshTimelocked<K> {
+    fn to_policy(&self) -> String { format!("and(pk({}),{})", self.key, self.timelock) }
+}
+
+impl<K: DeriveCompr> SatisfactionWeight for WshTimelocked<K> {
+    fn max_satisfaction_weight(&self) -> WeightUnits {
+        // Empty script sig; witness with two elements: ECDSA signature with sighash type of at
+        // most 73 bytes and the witness script
+        let mut script = vec![];
+        self.timelock.push_check(&mut script);
+        let script_len = 1 + 33 + 1 + script.len();
+        WeightUnits::no_discount(1) + WeightUnits::witness_discount(1 + 1 + 73 + 1 + script_len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use derive::opcodes::OP_PUSHNUM_1;
+
+    use super::*;
+
+    #[test]
+    fn timelock_validation() {
+        assert_eq!(Timelock::after_height(LockHeight::anytime()), Err(TimelockError::ZeroLockTime));
+        assert_eq!(
+            Timelock::older(SeqNo::from_consensus_u32(0xFFFFFFFD)),
+            Err(TimelockError::NotRelative(0xFFFFFFFD))
+        );
+        assert_eq!(Timelock::older(SeqNo::from_height(0)), Err(TimelockError::NotRelative(0)));
+
+        let after = Timelock::from_str("after(840000)").unwrap();
+        assert_eq!(
+            after,
+            Timelock::after_height(LockHeight::try_from(840000u32).unwrap()).unwrap()
+        );
+        assert_eq!(after.to_string(), "after(840000)");
+        let after = Timelock::from_str("after(1700000000)").unwrap();
+        assert_eq!(
+            after,
+            Timelock::after_time(LockTimestamp::try_from(1700000000u32).unwrap()).unwrap()
+        );
+        let older = Timelock::from_str("older(144)").unwrap();
+        assert_eq!(older, Timelock::older(SeqNo::from_height(144)).unwrap());
+        assert!(Timelock::from_str("after(0)").is_err());
+        assert!(Timelock::from_str("before(10)").is_err());
+    }
+
+    #[test]
+    fn timelocked_scripts() {
+        let key = CompressedPk::from_str(
+            "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+        )
+        .unwrap();
+
+        let older = Timelock::older(SeqNo::from_height(16)).unwrap();
+        let script = older.to_witness_script(key);
+        let mut expected = vec![OP_PUSHBYTES_33];
+        expected.extend(key.to_byte_array());
+        expected.extend([OP_CHECKSIGVERIFY, OP_PUSHNUM_1 + 15, OP_CSV]);
+        assert_eq!(script.to_vec(), expected);
+
+        let after = Timelock::after_height(LockHeight::try_from(840000u32).unwrap()).unwrap();
+        let leaf = after.to_leaf_script(XOnlyPk::from(key));
+        let script: &[u8] = leaf.script.as_ref();
+        assert_eq!(script[0], OP_PUSHBYTES_32);
+        assert_eq!(script[33], OP_CHECKSIGVERIFY);
+        // 840000 = 0x0CD140
+        assert_eq!(&script[34..], &[0x03, 0x40, 0xD1, 0x0C, OP_CLTV]);
+    }
+
+    #[test]
+    fn wsh_timelocked() {
+        let xpub = "[643a7adc/84h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*";
+        let s = format!("wsh(and_v(v:pk({xpub}),older(144)))");
+        let descr = WshTimelocked::<XpubDerivable>::from_str(&s).unwrap();
+        assert_eq!(descr.to_string(), s);
+        assert_eq!(descr.timelock(), Timelock::older(SeqNo::from_height(144)).unwrap());
+
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(2u8));
+        let key = Derive::<CompressedPk>::derive(descr.as_key(), terminal.keychain, terminal.index);
+        let derived = descr.derive(terminal.keychain, terminal.index);
+        let witness_script = descr.timelock().to_witness_script(key);
+        assert_eq!(derived.as_witness_script(), Some(&witness_script));
+        assert_eq!(derived.to_script_pubkey(), witness_script.to_script_pubkey());
+        assert_eq!(descr.compr_keyset(terminal).len(), 1);
+        assert_eq!(descr.to_policy(), format!("and(pk({xpub}),older(144))"));
+
+        assert!(matches!(
+            WshTimelocked::<XpubDerivable>::from_str(&format!("wsh(and_v(v:pk({xpub}),older(0)))")),
+            Err(DescriptorParseError::InvalidFormat(_))
+        ));
+    }
+}
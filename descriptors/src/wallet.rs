@@ -0,0 +1,326 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Minimal wallet state: set of descriptor UTXOs updated from the incoming transactions.
+
+use std::collections::{BTreeMap, HashMap};
+
+use derive::{
+    DeriveScripts, Idx, IdxBase, Keychain, NormalIndex, Outpoint, Sats, ScriptPubkey, Terminal, Tx,
+    Vout,
+};
+
+/// Reverse lookup index from the script pubkeys derived by a descriptor to their terminal
+/// derivation.
+///
+/// The index covers the scripts in each of the descriptor keychains starting from zero up to a
+/// certain index, which can be extended with [`ScriptIndex::extend`].
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct ScriptIndex {
+    scripts: HashMap<ScriptPubkey, Terminal>,
+    derived: BTreeMap<Keychain, u32>,
+}
+
+impl ScriptIndex {
+    /// Constructs index of the first `count` scripts of each of the descriptor keychains.
+    pub fn new<D: DeriveScripts>(descriptor: &D, count: u32) -> Self {
+        let mut index = ScriptIndex::default();
+        for keychain in descriptor.keychains() {
+            index.extend(descriptor, keychain, count);
+        }
+        index
+    }
+
+    /// Extends the index to cover the first `count` scripts of the keychain.
+    pub fn extend<D: DeriveScripts>(&mut self, descriptor: &D, keychain: Keychain, count: u32) {
+        let derived = self.derived.entry(keychain).or_default();
+        let Ok(start) = NormalIndex::try_from_index(*derived) else {
+            return;
+        };
+        for terminal in Terminal::range(keychain, start, count.saturating_sub(*derived)) {
+            let spk = descriptor.derive(keychain, terminal.index).to_script_pubkey();
+            self.scripts.insert(spk, terminal);
+            *derived = terminal.index.index() + 1;
+        }
+    }
+
+    /// Number of indexed scripts in the keychain.
+    pub fn count(&self, keychain: Keychain) -> u32 {
+        self.derived.get(&keychain).copied().unwrap_or_default()
+    }
+
+    /// Looks up the terminal derivation of a script pubkey.
+    pub fn terminal(&self, spk: &ScriptPubkey) -> Option<Terminal> {
+        self.scripts.get(spk).copied()
+    }
+}
+
+/// Detects transaction outputs paying to the scripts from the script index.
+///
+/// Returns output number, terminal derivation of the output script and the output amount for
+/// each of the matching outputs.
+pub fn scan_tx(tx: &Tx, index: &ScriptIndex) -> Vec<(u32, Terminal, Sats)> {
+    tx.outputs()
+        .enumerate()
+        .filter_map(|(vout, output)| {
+            let terminal = index.terminal(&output.script_pubkey)?;
+            Some((vout as u32, terminal, output.value))
+        })
+        .collect()
+}
+
+/// Detects transaction inputs spending outputs from the set of known descriptor UTXOs.
+///
+/// Returns input number together with the spent output for each of the matching inputs.
+pub fn scan_tx_spends<'u>(
+    tx: &Tx,
+    utxos: impl IntoIterator<Item = &'u WalletUtxo>,
+) -> Vec<(u32, WalletUtxo)> {
+    let utxos = utxos.into_iter().map(|utxo| (utxo.outpoint, *utxo)).collect::<BTreeMap<_, _>>();
+    tx.inputs()
+        .enumerate()
+        .filter_map(|(no, input)| Some((no as u32, *utxos.get(&input.prev_output)?)))
+        .collect()
+}
+
+/// Unspent output of a descriptor.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", rename_all = "camelCase")
+)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct WalletUtxo {
+    pub outpoint: Outpoint,
+    pub terminal: Terminal,
+    pub value: Sats,
+}
+
+/// Set of unspent outputs of a descriptor tagged with the terminal derivation of their scripts.
+///
+/// The set is updated with [`WalletUtxos::process_tx`] for each new transaction (for instance,
+/// from the new blocks), which detects descriptor outputs using the [`ScriptIndex`]. The index is
+/// kept `lookahead` scripts ahead of the last used script in each keychain.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct WalletUtxos<D: DeriveScripts> {
+    descriptor: D,
+    lookahead: u32,
+    index: ScriptIndex,
+    utxos: BTreeMap<Outpoint, WalletUtxo>,
+}
+
+impl<D: DeriveScripts> WalletUtxos<D> {
+    pub fn new(descriptor: D, lookahead: u32) -> Self {
+        let index = ScriptIndex::new(&descriptor, lookahead);
+        WalletUtxos {
+            descriptor,
+            lookahead,
+            index,
+            utxos: empty!(),
+        }
+    }
+
+    #[inline]
+    pub fn descriptor(&self) -> &D { &self.descriptor }
+
+    #[inline]
+    pub fn script_index(&self) -> &ScriptIndex { &self.index }
+
+    /// Adds an unspent output with a known terminal derivation, returning a previously known
+    /// output with the same outpoint, if any.
+    pub fn add(&mut self, utxo: WalletUtxo) -> Option<WalletUtxo> {
+        self.extend_index(utxo.terminal);
+        self.utxos.insert(utxo.outpoint, utxo)
+    }
+
+    /// Removes a spent output, returning it if it was known.
+    pub fn remove(&mut self, outpoint: Outpoint) -> Option<WalletUtxo> {
+        self.utxos.remove(&outpoint)
+    }
+
+    /// Updates the set with a transaction, removing the outputs spent by its inputs and adding
+    /// the outputs paying to the descriptor scripts. Returns the added outputs ordered by their
+    /// output number.
+    ///
+    /// Since each added output extends the script index, the outputs are re-scanned until the
+    /// index stops growing, so outputs paying to the scripts covered only after processing other
+    /// outputs of the same transaction are detected as well.
+    pub fn process_tx(&mut self, tx: &Tx) -> Vec<WalletUtxo> {
+        for input in tx.inputs() {
+            self.utxos.remove(&input.prev_output);
+        }
+        let txid = tx.txid();
+        let mut added = BTreeMap::new();
+        loop {
+            let found = scan_tx(tx, &self.index)
+                .into_iter()
+                .filter(|(vout, ..)| !added.contains_key(vout))
+                .collect::<Vec<_>>();
+            if found.is_empty() {
+                break;
+            }
+            for (vout, terminal, value) in found {
+                let utxo = WalletUtxo {
+                    outpoint: Outpoint::new(txid, Vout::from_u32(vout)),
+                    terminal,
+                    value,
+                };
+                self.add(utxo);
+                added.insert(vout, utxo);
+            }
+        }
+        added.into_values().collect()
+    }
+
+    /// Total value of the unspent outputs.
+    pub fn balance(&self) -> Sats { self.utxos.values().map(|utxo| utxo.value).sum() }
+
+    pub fn utxos(&self) -> impl Iterator<Item = &WalletUtxo> { self.utxos.values() }
+
+    pub fn utxos_for_keychain(
+        &self,
+        keychain: impl Into<Keychain>,
+    ) -> impl Iterator<Item = &WalletUtxo> {
+        let keychain = keychain.into();
+        self.utxos.values().filter(move |utxo| utxo.terminal.keychain == keychain)
+    }
+
+    fn extend_index(&mut self, terminal: Terminal) {
+        let count = terminal.index.index().saturating_add(1).saturating_add(self.lookahead);
+        if count > self.index.count(terminal.keychain) {
+            self.index.extend(&self.descriptor, terminal.keychain, count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use derive::{
+        Derive, DerivedScript, LockTime, SeqNo, SigScript, TxIn, TxOut, TxVer, Txid, VarIntArray,
+        Witness, XpubDerivable,
+    };
+
+    use super::*;
+    use crate::Wpkh;
+
+    const XPUB: &str = "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*";
+
+    fn tx(inputs: &[Outpoint], outputs: &[(ScriptPubkey, u64)]) -> Tx {
+        Tx {
+            version: TxVer::V2,
+            inputs: VarIntArray::from_collection_unsafe(
+                inputs
+                    .iter()
+                    .map(|prev_output| TxIn {
+                        prev_output: *prev_output,
+                        sig_script: SigScript::default(),
+                        sequence: SeqNo::from_consensus_u32(0),
+                        witness: Witness::default(),
+                    })
+                    .collect(),
+            ),
+            outputs: VarIntArray::from_collection_unsafe(
+                outputs.iter().map(|(spk, value)| TxOut::new(spk.clone(), *value)).collect(),
+            ),
+            lock_time: LockTime::ZERO,
+        }
+    }
+
+    #[test]
+    fn process_tx() {
+        let descr = Wpkh::from(XpubDerivable::from_str(XPUB).unwrap());
+        let spk = |keychain: u8, index: u16| {
+            Derive::<DerivedScript>::derive(&descr, keychain, NormalIndex::normal(index))
+                .to_script_pubkey()
+        };
+        let mut wallet = WalletUtxos::new(descr.clone(), 5);
+        assert_eq!(wallet.script_index().count(Keychain::OUTER), 5);
+
+        let tx1 = tx(&[Outpoint::new(Txid::coinbase(), Vout::from_u32(0))], &[
+            (spk(0, 9), 2000),
+            (spk(0, 4), 1000),
+            (ScriptPubkey::default(), 500),
+            (spk(1, 0), 300),
+        ]);
+        let added = wallet.process_tx(&tx1);
+        // The script at index 9 gets covered by the index only after the output at index 4 is
+        // processed
+        assert_eq!(added.len(), 3);
+        assert_eq!(added[0].terminal, Terminal::new(0, NormalIndex::normal(9)));
+        assert_eq!(added[1].terminal, Terminal::new(0, NormalIndex::normal(4)));
+        assert_eq!(added[2].terminal, Terminal::new(1, NormalIndex::ZERO));
+        assert_eq!(wallet.balance(), Sats::from_sats(3300u64));
+        assert_eq!(wallet.script_index().count(Keychain::OUTER), 15);
+        assert_eq!(wallet.utxos_for_keychain(Keychain::INNER).count(), 1);
+
+        let tx2 = tx(&[added[0].outpoint], &[(spk(0, 12), 700)]);
+        let added = wallet.process_tx(&tx2);
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].terminal, Terminal::new(0, NormalIndex::normal(12)));
+        assert_eq!(wallet.balance(), Sats::from_sats(2000u64));
+
+        assert!(wallet.remove(added[0].outpoint).is_some());
+        assert_eq!(wallet.balance(), Sats::from_sats(1300u64));
+    }
+
+    #[test]
+    fn scan() {
+        let descr = Wpkh::from(XpubDerivable::from_str(XPUB).unwrap());
+        let spk = |keychain: u8, index: u16| {
+            Derive::<DerivedScript>::derive(&descr, keychain, NormalIndex::normal(index))
+                .to_script_pubkey()
+        };
+        let prevout = Outpoint::new(Txid::coinbase(), Vout::from_u32(1));
+        let tx = tx(&[Outpoint::new(Txid::coinbase(), Vout::from_u32(0)), prevout], &[
+            (spk(0, 9), 2000),
+            (ScriptPubkey::default(), 500),
+            (spk(0, 4), 1000),
+            (spk(1, 0), 300),
+        ]);
+
+        let mut index = ScriptIndex::new(&descr, 5);
+        assert_eq!(scan_tx(&tx, &index), vec![
+            (2, Terminal::new(0, NormalIndex::normal(4)), Sats(1000)),
+            (3, Terminal::new(1, NormalIndex::normal(0)), Sats(300)),
+        ]);
+        index.extend(&descr, Keychain::OUTER, 10);
+        assert_eq!(scan_tx(&tx, &index), vec![
+            (0, Terminal::new(0, NormalIndex::normal(9)), Sats(2000)),
+            (2, Terminal::new(0, NormalIndex::normal(4)), Sats(1000)),
+            (3, Terminal::new(1, NormalIndex::normal(0)), Sats(300)),
+        ]);
+        assert_eq!(scan_tx(&tx, &ScriptIndex::default()), vec![]);
+
+        let utxo = WalletUtxo {
+            outpoint: prevout,
+            terminal: Terminal::new(0, NormalIndex::ZERO),
+            value: Sats(700),
+        };
+        let other = WalletUtxo {
+            outpoint: Outpoint::new(Txid::coinbase(), Vout::from_u32(2)),
+            ..utxo
+        };
+        assert_eq!(scan_tx_spends(&tx, [&utxo, &other]), vec![(1, utxo)]);
+    }
+}
@@ -295,7 +295,11 @@ pub enum AddressPayload {
     #[from]
     Wsh(WScriptHash),
 
-    /// P2TR payload.
+    /// P2TR payload, which is the tweaked output key of any 32-byte witness v1 program.
+    ///
+    /// The output key commits to the script tree (if any), thus taproot outputs spendable only
+    /// with the key path and the outputs having script spending paths produce the same kind of
+    /// payload and can't be distinguished at the address level.
     #[from]
     Tr(OutputPk),
 }
@@ -311,6 +315,9 @@ impl AddressPayload {
 
     /// Constructs payload from a given `scriptPubkey`. Fails on future
     /// (post-taproot) witness types with `None`.
+    ///
+    /// All witness v1 programs of 32 bytes are converted into [`AddressPayload::Tr`] in the same
+    /// way, independently of whether they were produced for a key-path or a script-path spending.
     pub fn from_script(script: &ScriptPubkey) -> Result<Self, AddressError> {
         Ok(if script.is_p2pkh() {
             let mut bytes = [0u8; 20];
@@ -470,4 +477,15 @@ mod test {
         let b32 = "tb1p5kgdjdf99vfa2xwufd2cx2qru468z79s2arn3jf5feg95d9m62gqzpnjjk";
         assert_eq!(Address::from_str(b32).unwrap().to_string(), b32);
     }
+
+    #[test]
+    fn taproot_roundtrip() {
+        let addr =
+            Address::from_str("bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr")
+                .unwrap();
+        let script = addr.script_pubkey();
+        assert!(script.is_p2tr());
+        assert_eq!(Address::with(&script, AddressNetwork::Mainnet).unwrap(), addr);
+        assert_eq!(AddressPayload::from_script(&script).unwrap(), addr.payload);
+    }
 }
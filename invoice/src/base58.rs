@@ -229,3 +229,5 @@ impl fmt::Display for Error {
         }
     }
 }
+
+impl std::error::Error for Error {}
@@ -0,0 +1,505 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! PSBT construction split into the roles defined by BIP-174.
+
+use std::marker::PhantomData;
+
+use derive::{
+    LockHeight, Sats, ScriptPubkey, SeqNo, Terminal, Tx, VBytes, WeightUnits, SEQ_NO_SUBMAX_VALUE,
+};
+use descriptors::{Descriptor, SatisfactionWeight};
+
+use crate::coinselect::{self, InsufficientFunds, SelectionError, Utxo};
+use crate::{
+    ExtractError, FeeError, FeeRate, OutputError, Prevout, Psbt, PsbtVer, SighashError, Signer,
+    Unmodifiable, UnsignedTx, ValueError,
+};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum BuilderError {
+    /// PSBT inputs or outputs can't be modified.
+    #[from(Unmodifiable)]
+    Unmodifiable,
+
+    /// invalid output: {0}
+    #[from]
+    Output(OutputError),
+
+    /// PSBT has no input #{0}.
+    UnknownInput(usize),
+
+    /// PSBT has no inputs.
+    NoInputs,
+
+    /// PSBT has no outputs.
+    NoOutputs,
+
+    /// unable to compute transaction fee: {0}
+    #[from]
+    Fee(FeeError),
+
+    /// PSBT construction is not completed, so it can't be signed.
+    Incomplete,
+
+    /// unable to sign PSBT: {0}
+    #[from]
+    Sighash(SighashError),
+
+    /// input #{0} can't be finalized since it misses some of the required signatures.
+    NotFinalized(usize),
+
+    /// unable to extract signed transaction: {0}
+    #[from]
+    Extract(ExtractError),
+
+    /// {0}
+    #[from]
+    Funds(InsufficientFunds),
+}
+
+impl From<ValueError> for BuilderError {
+    fn from(err: ValueError) -> Self { BuilderError::Fee(err.into()) }
+}
+
+impl From<SelectionError> for BuilderError {
+    fn from(err: SelectionError) -> Self {
+        match err {
+            SelectionError::Funds(err) => BuilderError::Funds(err),
+            SelectionError::Value(err) => err.into(),
+        }
+    }
+}
+
+/// Builder guiding PSBT through the creator, updater, signer and finalizer roles.
+///
+/// Inputs and outputs can be added only until [`PsbtBuilder::complete`] is called. Signing
+/// requires the construction to be completed, and finalization requires all inputs to have the
+/// signatures needed to produce their final witnesses.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PsbtBuilder {
+    psbt: Psbt,
+}
+
+impl Default for PsbtBuilder {
+    fn default() -> Self { PsbtBuilder::new() }
+}
+
+impl PsbtBuilder {
+    /// Creates builder for an empty v2 PSBT.
+    pub fn new() -> Self {
+        PsbtBuilder {
+            psbt: Psbt::create(PsbtVer::V2),
+        }
+    }
+
+    /// Creates builder from an unsigned transaction. Information about the spent outputs and key
+    /// derivations must be added with [`PsbtBuilder::update_input`].
+    pub fn from_tx(tx: impl Into<UnsignedTx>) -> Self {
+        PsbtBuilder {
+            psbt: Psbt::from_tx(tx),
+        }
+    }
+
+    /// Creates builder spending `utxos` controlled by the `descriptor` to the `recipients`.
+    ///
+    /// Each of the UTXOs is provided with its terminal derivation, which is used to fill in the
+    /// input scripts and key derivation information.
+    pub fn with_utxos<K, D: Descriptor<K>>(
+        descriptor: &D,
+        utxos: impl IntoIterator<Item = (Prevout, Terminal)>,
+        recipients: impl IntoIterator<Item = (ScriptPubkey, Sats)>,
+    ) -> Result<Self, BuilderError> {
+        let mut builder = PsbtBuilder::new();
+        for (prevout, terminal) in utxos {
+            builder.add_input(prevout, descriptor, terminal)?;
+        }
+        for (script_pubkey, amount) in recipients {
+            builder.add_recipient(script_pubkey, amount)?;
+        }
+        Ok(builder)
+    }
+
+    /// Returns the PSBT under construction.
+    #[inline]
+    pub fn psbt(&self) -> &Psbt { &self.psbt }
+
+    /// Releases the PSBT, regardless of the stage it is at.
+    #[inline]
+    pub fn into_psbt(self) -> Psbt { self.psbt }
+
+    /// Adds an input spending `prevout` controlled by the `descriptor`. The input doesn't signal
+    /// RBF; use [`Psbt::enable_rbf`] after the construction is completed to opt in.
+    pub fn add_input<K, D: Descriptor<K>>(
+        &mut self,
+        prevout: Prevout,
+        descriptor: &D,
+        terminal: Terminal,
+    ) -> Result<&mut Self, BuilderError> {
+        let sequence = SeqNo::from_consensus_u32(SEQ_NO_SUBMAX_VALUE);
+        self.psbt.construct_input(prevout, descriptor, terminal, sequence)?;
+        Ok(self)
+    }
+
+    /// Adds a payment output; see [`Psbt::add_recipient`].
+    pub fn add_recipient(
+        &mut self,
+        script_pubkey: ScriptPubkey,
+        amount: Sats,
+    ) -> Result<&mut Self, BuilderError> {
+        self.psbt.add_recipient(script_pubkey, amount)?;
+        Ok(self)
+    }
+
+    /// Adds a change output controlled by the `descriptor`; see [`Psbt::add_descriptor_output`].
+    pub fn add_change<K, D: Descriptor<K>>(
+        &mut self,
+        descriptor: &D,
+        terminal: Terminal,
+        amount: Sats,
+    ) -> Result<&mut Self, BuilderError> {
+        self.psbt.add_descriptor_output(descriptor, terminal, amount)?;
+        Ok(self)
+    }
+
+    /// Updater role: attaches key derivation information for the input `index`, which is
+    /// controlled by the `descriptor`; see [`crate::Input::fill_derivation`].
+    pub fn update_input<K, D: Descriptor<K>>(
+        &mut self,
+        index: usize,
+        descriptor: &D,
+        terminal: Terminal,
+    ) -> Result<&mut Self, BuilderError> {
+        let input = self.psbt.input_mut(index).ok_or(BuilderError::UnknownInput(index))?;
+        input.fill_derivation(descriptor, terminal);
+        Ok(self)
+    }
+
+    /// Completes construction of the transaction, after which inputs and outputs can't be added.
+    ///
+    /// Errors if the PSBT has no inputs or outputs, if the amount of some of the inputs is
+    /// unknown or if the outputs spend more than the inputs provide.
+    pub fn complete(&mut self) -> Result<&mut Self, BuilderError> {
+        if self.psbt.inputs().next().is_none() {
+            return Err(BuilderError::NoInputs);
+        }
+        if self.psbt.outputs().next().is_none() {
+            return Err(BuilderError::NoOutputs);
+        }
+        self.psbt.fee()?;
+        self.psbt.complete_construction();
+        Ok(self)
+    }
+
+    /// Signer role: signs the inputs with the keys controlled by the `signer`; see
+    /// [`Psbt::sign_with`].
+    ///
+    /// Returns the number of signatures added. Errors if the construction is not completed.
+    pub fn sign(&mut self, signer: &impl Signer) -> Result<usize, BuilderError> {
+        if self.psbt.is_modifiable() {
+            return Err(BuilderError::Incomplete);
+        }
+        Ok(self.psbt.sign_with(signer)?)
+    }
+
+    /// Finalizer role: finalizes all inputs of the PSBT.
+    ///
+    /// Errors with the index of the first input which can't be finalized, for instance since it
+    /// lacks signatures. Inputs which can be finalized are finalized even in this case.
+    pub fn finalize(&mut self) -> Result<&mut Self, BuilderError> {
+        if self.psbt.is_modifiable() {
+            return Err(BuilderError::Incomplete);
+        }
+        self.psbt.finalize();
+        if let Some(input) = self.psbt.inputs().find(|input| !input.is_finalized()) {
+            return Err(BuilderError::NotFinalized(input.index()));
+        }
+        Ok(self)
+    }
+
+    /// Extractor role: extracts the signed transaction from the fully finalized PSBT.
+    pub fn extract(&self) -> Result<Tx, BuilderError> { Ok(self.psbt.extract_tx()?) }
+}
+
+/// Transaction builder paying to a set of recipients from the UTXOs controlled by a single
+/// descriptor at a target fee rate.
+///
+/// Inputs are either required to be spent ([`TxBuilder::spend`]) or provided as candidates for
+/// the coin selection ([`TxBuilder::candidate`]), which are used only if the required inputs don't
+/// cover the payments and the fee. The fee is computed from [`Psbt::expected_vsize`]; the
+/// remaining amount goes to the change output, unless it is below the dust limit for the
+/// descriptor, in which case it is left to miners.
+pub struct TxBuilder<'descr, K, D: Descriptor<K>> {
+    descriptor: &'descr D,
+    change: Terminal,
+    fee_rate: FeeRate,
+    required: Vec<(Prevout, Terminal)>,
+    candidates: Vec<(Prevout, Terminal)>,
+    recipients: Vec<(ScriptPubkey, Sats)>,
+    rbf: bool,
+    anti_fee_sniping: Option<(LockHeight, u32)>,
+    _phantom: PhantomData<K>,
+}
+
+impl<'descr, K, D: Descriptor<K>> TxBuilder<'descr, K, D> {
+    /// Creates builder spending UTXOs controlled by the `descriptor`, which is also used for the
+    /// change output at the `change` terminal.
+    pub fn new(descriptor: &'descr D, change: Terminal, fee_rate: FeeRate) -> Self {
+        TxBuilder {
+            descriptor,
+            change,
+            fee_rate,
+            required: vec![],
+            candidates: vec![],
+            recipients: vec![],
+            rbf: false,
+            anti_fee_sniping: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Adds an UTXO which must be spent by the transaction.
+    pub fn spend(&mut self, prevout: Prevout, terminal: Terminal) -> &mut Self {
+        self.required.push((prevout, terminal));
+        self
+    }
+
+    /// Adds an UTXO which may be spent by the transaction if the coin selection picks it.
+    pub fn candidate(&mut self, prevout: Prevout, terminal: Terminal) -> &mut Self {
+        self.candidates.push((prevout, terminal));
+        self
+    }
+
+    /// Adds a payment output.
+    pub fn pay(&mut self, script_pubkey: ScriptPubkey, amount: Sats) -> &mut Self {
+        self.recipients.push((script_pubkey, amount));
+        self
+    }
+
+    /// Sets whether the transaction signals replaceability (BIP-125); see [`Psbt::enable_rbf`].
+    pub fn rbf(&mut self, rbf: bool) -> &mut Self {
+        self.rbf = rbf;
+        self
+    }
+
+    /// Sets the locktime to discourage fee sniping; see [`Psbt::enable_anti_fee_sniping`].
+    pub fn anti_fee_sniping(&mut self, current_height: LockHeight, entropy: u32) -> &mut Self {
+        self.anti_fee_sniping = Some((current_height, entropy));
+        self
+    }
+
+    /// Constructs balanced unsigned PSBT, whose construction is completed.
+    ///
+    /// Errors if there are no recipients, some of the payments are below the dust limit, or the
+    /// available UTXOs can't cover the payments and the fee.
+    pub fn build(&self) -> Result<Psbt, BuilderError>
+    where D: SatisfactionWeight {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        for (script_pubkey, amount) in &self.recipients {
+            psbt.add_recipient(script_pubkey.clone(), *amount)?;
+        }
+        if psbt.outputs().next().is_none() {
+            return Err(BuilderError::NoOutputs);
+        }
+        for (prevout, terminal) in &self.required {
+            self.add_input(&mut psbt, *prevout, *terminal)?;
+        }
+
+        let required = self.required_amount(&psbt)?;
+        let input_sum = psbt.checked_input_sum()?;
+        if input_sum < required && !self.candidates.is_empty() {
+            // Until the transaction has segwit inputs its size doesn't include segwit marker and
+            // flag, so we pay for them in advance.
+            let marker_fee = self.fee_rate.fee_for(VBytes::from(WeightUnits::witness_discount(2)));
+            let target =
+                (required - input_sum).checked_add(marker_fee).ok_or(ValueError::Overflow)?;
+            let utxos = self
+                .candidates
+                .iter()
+                .map(|(prevout, _)| Utxo::with_descriptor(*prevout, self.descriptor))
+                .collect::<Vec<_>>();
+            let selection = coinselect::select(&utxos, target, self.fee_rate)?;
+            for utxo in selection.inputs {
+                let (prevout, terminal) = self
+                    .candidates
+                    .iter()
+                    .find(|(prevout, _)| prevout.outpoint() == utxo.outpoint)
+                    .expect("selected UTXO must be one of the candidates");
+                self.add_input(&mut psbt, *prevout, *terminal)?;
+            }
+        }
+        if psbt.inputs().next().is_none() {
+            return Err(BuilderError::NoInputs);
+        }
+
+        let required = self.required_amount(&psbt)?;
+        let input_sum = psbt.checked_input_sum()?;
+        if input_sum < required {
+            return Err(InsufficientFunds {
+                available: input_sum,
+                required,
+            }
+            .into());
+        }
+        let mut with_change = psbt.clone();
+        with_change.construct_change(self.descriptor, self.change, Sats::ZERO)?;
+        let required = self.required_amount(&with_change)?;
+        if let Some(change) = input_sum.checked_sub(required) {
+            if change >= self.descriptor.class().dust_limit() {
+                psbt.construct_change(self.descriptor, self.change, change)?;
+            }
+        }
+
+        if self.rbf {
+            psbt.enable_rbf();
+        }
+        if let Some((current_height, entropy)) = self.anti_fee_sniping {
+            psbt.enable_anti_fee_sniping(current_height, entropy);
+        }
+        psbt.complete_construction();
+        Ok(psbt)
+    }
+
+    fn add_input(
+        &self,
+        psbt: &mut Psbt,
+        prevout: Prevout,
+        terminal: Terminal,
+    ) -> Result<(), BuilderError> {
+        let sequence = SeqNo::from_consensus_u32(SEQ_NO_SUBMAX_VALUE);
+        psbt.construct_input(prevout, self.descriptor, terminal, sequence)?;
+        Ok(())
+    }
+
+    /// Sum of the output amounts and the fee required for the PSBT at the target fee rate.
+    fn required_amount(&self, psbt: &Psbt) -> Result<Sats, BuilderError> {
+        let fee = self.fee_rate.fee_for(psbt.expected_vsize()?);
+        Ok(psbt.checked_output_sum()?.checked_add(fee).ok_or(ValueError::Overflow)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use derive::{
+        HardenedIndex, Keychain, LockTime, NormalIndex, Outpoint, Txid, Vout, Xpriv, XpubDerivable,
+    };
+    use descriptors::Wpkh;
+
+    use super::*;
+
+    fn wallet() -> (Xpriv, Wpkh) {
+        let master = Xpriv::new_master(true, &[0xA5; 32]);
+        let path = [84u8, 1, 0].map(HardenedIndex::from);
+        let account = master.derive_priv(path);
+        let xpub = format!("[{}/84h/1h/0h]{}/<0;1>/*", master.fingerprint(), account.to_xpub());
+        (master, Wpkh::from(XpubDerivable::from_str(&xpub).unwrap()))
+    }
+
+    #[test]
+    fn roles() {
+        let (master, descriptor) = wallet();
+
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(0u8));
+        let change = Terminal::new(Keychain::INNER, NormalIndex::from(0u8));
+        let prevout = Prevout::new(Outpoint::coinbase(), Sats(10_000));
+        let recipient = (ScriptPubkey::p2wpkh([1u8; 20]), Sats(5_000));
+
+        let mut builder = PsbtBuilder::new();
+        assert_eq!(builder.complete().unwrap_err(), BuilderError::NoInputs);
+        assert_eq!(builder.sign(&master), Err(BuilderError::Incomplete));
+
+        let mut builder =
+            PsbtBuilder::with_utxos(&descriptor, [(prevout, terminal)], [recipient.clone()])
+                .unwrap();
+        builder.add_change(&descriptor, change, Sats(6_000)).unwrap();
+        assert!(matches!(
+            builder.complete(),
+            Err(BuilderError::Fee(FeeError::Value(ValueError::NegativeFee { .. })))
+        ));
+
+        let mut builder =
+            PsbtBuilder::with_utxos(&descriptor, [(prevout, terminal)], [recipient]).unwrap();
+        builder.add_change(&descriptor, change, Sats(4_000)).unwrap();
+        builder.complete().unwrap();
+        assert_eq!(
+            builder.add_recipient(ScriptPubkey::p2wpkh([2u8; 20]), Sats(1_000)).unwrap_err(),
+            BuilderError::Output(OutputError::Unmodifiable)
+        );
+        assert_eq!(builder.finalize().unwrap_err(), BuilderError::NotFinalized(0));
+        assert_eq!(builder.extract(), Err(BuilderError::Extract(ExtractError::NotFinalized(0))));
+
+        assert_eq!(builder.sign(&master), Ok(1));
+        builder.finalize().unwrap();
+        let tx = builder.extract().unwrap();
+        assert_eq!(tx.inputs.len(), 1);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(builder.psbt().fee(), Ok(Sats(1_000)));
+    }
+
+    #[test]
+    fn tx_builder() {
+        let (_, descriptor) = wallet();
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(0u8));
+        let change = Terminal::new(Keychain::INNER, NormalIndex::from(0u8));
+        let prevout = |no: u8, sats: u64| {
+            Prevout::new(Outpoint::new(Txid::from([no; 32]), Vout::from_u32(0)), Sats(sats))
+        };
+        let recipient = ScriptPubkey::p2wpkh([1u8; 20]);
+        let fee_rate = FeeRate::from_sat_per_vb(2);
+        let balanced = |psbt: &Psbt| {
+            let fee = psbt.fee().unwrap();
+            let expected = fee_rate.fee_for(psbt.expected_vsize().unwrap());
+            assert!(fee >= expected);
+            fee
+        };
+
+        let mut builder = TxBuilder::new(&descriptor, change, fee_rate);
+        assert_eq!(builder.build().unwrap_err(), BuilderError::NoOutputs);
+        builder.pay(recipient.clone(), Sats(5_000));
+        assert_eq!(builder.build().unwrap_err(), BuilderError::NoInputs);
+        builder.spend(prevout(1, 4_000), terminal);
+        assert!(matches!(builder.build(), Err(BuilderError::Funds(_))));
+
+        builder.candidate(prevout(2, 3_000), terminal).candidate(prevout(3, 20_000), terminal);
+        let psbt = builder.build().unwrap();
+        assert!(!psbt.is_modifiable());
+        assert_eq!(psbt.inputs().count(), 2);
+        assert_eq!(psbt.outputs().count(), 2);
+        let fee = balanced(&psbt);
+        assert_eq!(fee, fee_rate.fee_for(psbt.expected_vsize().unwrap()));
+        assert!(psbt.outputs().any(|output| output.terminal_derivation() == Some(change)));
+        assert!(!psbt.is_rbf());
+        assert_eq!(psbt.lock_time(), LockTime::ZERO);
+
+        let mut builder = TxBuilder::new(&descriptor, change, fee_rate);
+        builder.spend(prevout(1, 10_000), terminal).pay(recipient, Sats(9_500));
+        builder.rbf(true).anti_fee_sniping(LockHeight::from_height(800_000).unwrap(), 1);
+        let psbt = builder.build().unwrap();
+        assert_eq!(psbt.outputs().count(), 1);
+        assert_eq!(balanced(&psbt), Sats(500));
+        assert!(psbt.is_rbf());
+        assert_eq!(psbt.lock_time(), LockTime::from_consensus_u32(800_000));
+    }
+}
@@ -740,7 +740,22 @@ impl Decode for LockHeight {
 }
 
 psbt_code_using_consensus!(Witness);
-psbt_code_using_consensus!(ControlBlock);
+
+impl Encode for ControlBlock {
+    fn encode(&self, writer: &mut dyn Write) -> Result<usize, IoError> {
+        // We do not use consensus encoding from `bp-consensus` since it combines the leaf version
+        // with the output key parity using `&` instead of `|`, losing the parity bit.
+        let first_byte =
+            self.leaf_version.to_consensus_u8() | self.output_key_parity.to_consensus_u8();
+        let mut counter = first_byte.encode(writer)?;
+        counter += self.internal_pk.encode(writer)?;
+        for step in &self.merkle_branch {
+            counter += step.into_inner().encode(writer)?;
+        }
+        Ok(counter)
+    }
+}
+psbt_decode_from_consensus!(ControlBlock);
 
 impl Encode for ScriptBytes {
     fn encode(&self, writer: &mut dyn Write) -> Result<usize, IoError> {
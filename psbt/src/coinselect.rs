@@ -0,0 +1,330 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Coin selection: choosing UTXOs to fund a transaction.
+//!
+//! The main entry point is [`select`], which first tries to find a changeless input set with the
+//! branch-and-bound algorithm and falls back to the largest-first selection, which may require a
+//! change output.
+
+use derive::{Outpoint, Sats, VBytes, WeightUnits};
+use descriptors::SatisfactionWeight;
+
+use crate::{fee, FeeRate, Prevout, ValueError};
+
+/// Maximal number of branches explored by the branch-and-bound algorithm before giving up.
+pub const BNB_MAX_TRIES: usize = 100_000;
+
+/// Virtual size of a change output: 8 bytes of the amount and P2TR script pubkey with its length.
+const CHANGE_OUTPUT_VBYTES: u32 = 8 + 1 + 34;
+
+/// Weight of an input spending a P2TR change output with the key path.
+const CHANGE_SPEND_WEIGHT: u32 = (32 + 4 + 4 + 1) * 4 + 1 + 1 + 65;
+
+/// Unspent transaction output which can be used for a coin selection.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Utxo {
+    pub outpoint: Outpoint,
+    pub value: Sats,
+    /// Maximal weight of the script sig (including its length prefix) and witness spending the
+    /// output.
+    pub satisfaction_weight: WeightUnits,
+}
+
+impl Utxo {
+    pub fn new(outpoint: Outpoint, value: Sats, satisfaction_weight: WeightUnits) -> Self {
+        Utxo {
+            outpoint,
+            value,
+            satisfaction_weight,
+        }
+    }
+
+    /// Constructs UTXO for an output generated by a descriptor, taking the satisfaction weight
+    /// from [`SatisfactionWeight::max_satisfaction_weight`].
+    pub fn with_descriptor<D: SatisfactionWeight>(prevout: Prevout, descriptor: &D) -> Self {
+        Utxo::new(prevout.outpoint(), prevout.value, descriptor.max_satisfaction_weight())
+    }
+
+    /// Weight of the transaction input spending the UTXO.
+    pub fn input_weight(&self) -> WeightUnits {
+        WeightUnits::no_discount(32 + 4 + 4) + self.satisfaction_weight
+    }
+
+    /// Value of the UTXO remaining after paying the fee for its spending with the given fee rate.
+    /// Negative if the UTXO is uneconomic to spend. Values outside of the `i64` range are clamped.
+    pub fn effective_value(&self, fee_rate: FeeRate) -> i64 {
+        let fee = fee_rate.fee_for(VBytes::from(self.input_weight()));
+        let value = i128::from(self.value.sats()) - i128::from(fee.sats());
+        value.clamp(i64::MIN.into(), i64::MAX.into()) as i64
+    }
+}
+
+/// Result of a coin selection.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Selection {
+    /// Selected UTXOs.
+    pub inputs: Vec<Utxo>,
+
+    /// Total fee paid by the selected inputs. If no change is required, it also includes the
+    /// excess of the input value over the target, which is left to miners; otherwise it includes
+    /// the fee for the change output.
+    pub fee: Sats,
+
+    /// Amount which must be sent to a change output, if any.
+    pub change: Option<Sats>,
+}
+
+impl Selection {
+    /// Total value of the selected inputs.
+    pub fn input_value(&self) -> Sats { self.inputs.iter().map(|utxo| utxo.value).sum() }
+
+    #[inline]
+    pub fn needs_change(&self) -> bool { self.change.is_some() }
+
+    fn with(inputs: Vec<Utxo>, target: Sats, change: Option<Sats>) -> Result<Self, ValueError> {
+        let input_value = fee::checked_sum(inputs.iter().map(|utxo| utxo.value))?;
+        let output_value =
+            target.checked_add(change.unwrap_or_default()).ok_or(ValueError::Overflow)?;
+        let fee = fee::checked_fee(input_value, output_value)?;
+        Ok(Selection {
+            inputs,
+            fee,
+            change,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(
+    "insufficient funds: {available} of effective value are available, while {required} are \
+     required."
+)]
+pub struct InsufficientFunds {
+    /// Sum of effective values of all economically spendable UTXOs.
+    pub available: Sats,
+    /// Target amount.
+    pub required: Sats,
+}
+
+/// Errors of the coin selection.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(inner)]
+pub enum SelectionError {
+    #[from]
+    Funds(InsufficientFunds),
+
+    #[from]
+    Value(ValueError),
+}
+
+/// Selects UTXOs covering the `target` amount and the fees for spending the selected UTXOs at the
+/// given `fee_rate`.
+///
+/// The `target` must include the sum of the transaction outputs and the fee for the transaction
+/// data other than inputs (version, lock time, outputs). UTXOs which cost more to spend than their
+/// value are never selected. Errors with [`ValueError::Overflow`] if the sum of the UTXO values
+/// can't be represented.
+///
+/// First, a branch-and-bound search for a changeless selection is performed, which looks for the
+/// input set with an excess not exceeding the cost of creating and later spending a change output.
+/// If no such set is found in [`BNB_MAX_TRIES`] attempts, the UTXOs are selected largest-first.
+pub fn select(
+    utxos: &[Utxo],
+    target: Sats,
+    fee_rate: FeeRate,
+) -> Result<Selection, SelectionError> {
+    let mut pool = utxos
+        .iter()
+        .filter_map(|utxo| {
+            let value = utxo.effective_value(fee_rate);
+            (value > 0).then_some((*utxo, value as u64))
+        })
+        .collect::<Vec<_>>();
+    pool.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+    let available = fee::checked_sum(pool.iter().map(|(_, value)| Sats::from_sats(*value)))?;
+    if available < target {
+        return Err(InsufficientFunds {
+            available,
+            required: target,
+        }
+        .into());
+    }
+    // All the sums below are bounded by the available amount, so they can't overflow.
+    let available = available.sats();
+
+    let change_output_fee =
+        fee_rate.fee_for(VBytes::from(WeightUnits::no_discount(CHANGE_OUTPUT_VBYTES as usize)));
+    let change_spend_fee =
+        fee_rate.fee_for(VBytes::from(WeightUnits::witness_discount(CHANGE_SPEND_WEIGHT as usize)));
+    let cost_of_change =
+        change_output_fee.checked_add(change_spend_fee).ok_or(ValueError::Overflow)?.sats();
+
+    let values = pool.iter().map(|(_, value)| *value).collect::<Vec<_>>();
+    let mut bnb = BranchAndBound {
+        values: &values,
+        target: target.sats(),
+        upper_bound: target.sats().saturating_add(cost_of_change),
+        tries: BNB_MAX_TRIES,
+        current: vec![],
+        best: None,
+    };
+    bnb.search(0, 0, available);
+    if let Some((_, indexes)) = bnb.best {
+        let inputs = indexes.into_iter().map(|index| pool[index].0).collect::<Vec<_>>();
+        return Ok(Selection::with(inputs, target, None)?);
+    }
+
+    // Largest-first fallback
+    let mut sum = 0u64;
+    let mut inputs = vec![];
+    for (utxo, value) in &pool {
+        inputs.push(*utxo);
+        sum += value;
+        if sum >= target.sats() {
+            break;
+        }
+    }
+    let excess = sum - target.sats();
+    let change = if excess > cost_of_change {
+        Some(Sats::from_sats(excess - change_output_fee.sats()))
+    } else {
+        None
+    };
+    Ok(Selection::with(inputs, target, change)?)
+}
+
+struct BranchAndBound<'values> {
+    /// Effective values of UTXOs sorted in descending order.
+    values: &'values [u64],
+    target: u64,
+    upper_bound: u64,
+    tries: usize,
+    current: Vec<usize>,
+    /// The best found selection with its excess over the target.
+    best: Option<(u64, Vec<usize>)>,
+}
+
+impl BranchAndBound<'_> {
+    fn search(&mut self, index: usize, sum: u64, remaining: u64) {
+        if self.tries == 0 || sum > self.upper_bound {
+            return;
+        }
+        self.tries -= 1;
+
+        if sum >= self.target {
+            let excess = sum - self.target;
+            if self.best.as_ref().map(|(best, _)| excess < *best).unwrap_or(true) {
+                self.best = Some((excess, self.current.clone()));
+            }
+            if excess == 0 {
+                self.tries = 0;
+            }
+            return;
+        }
+        if index >= self.values.len() || sum + remaining < self.target {
+            return;
+        }
+
+        let value = self.values[index];
+        self.current.push(index);
+        self.search(index + 1, sum + value, remaining - value);
+        self.current.pop();
+        self.search(index + 1, sum, remaining - value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn utxos(values: &[u64]) -> Vec<Utxo> {
+        values
+            .iter()
+            .map(|value| {
+                Utxo::new(
+                    Outpoint::coinbase(),
+                    Sats::from_sats(*value),
+                    WeightUnits::no_discount(0),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn changeless() {
+        let utxos = utxos(&[1000, 5000, 2000, 7000]);
+        // with zero fee rate effective values equal to the values
+        let selection = select(&utxos, Sats::from_sats(8000u64), FeeRate::ZERO).unwrap();
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.input_value(), Sats::from_sats(8000u64));
+        assert_eq!(selection.fee, Sats::ZERO);
+    }
+
+    #[test]
+    fn largest_first() {
+        let utxos = utxos(&[100_000, 50_000, 20_000]);
+        let fee_rate = FeeRate::from_sat_per_vb(1);
+        let selection = select(&utxos, Sats::from_sats(110_000u64), fee_rate).unwrap();
+        assert_eq!(selection.inputs.len(), 2);
+        assert_eq!(selection.input_value(), Sats::from_sats(150_000u64));
+        let change = selection.change.unwrap();
+        // two inputs of 40 vbytes each and a change output of 43 vbytes
+        assert_eq!(selection.fee, Sats::from_sats(40 + 40 + 43u64));
+        assert_eq!(change + selection.fee + Sats::from_sats(110_000u64), selection.input_value());
+        assert!(selection.needs_change());
+    }
+
+    #[test]
+    fn insufficient() {
+        let utxos = utxos(&[1000, 30]);
+        let err =
+            select(&utxos, Sats::from_sats(2000u64), FeeRate::from_sat_per_vb(1)).unwrap_err();
+        // the second UTXO is uneconomic
+        assert_eq!(
+            err,
+            SelectionError::Funds(InsufficientFunds {
+                available: Sats::from_sats(960u64),
+                required: Sats::from_sats(2000u64),
+            })
+        );
+    }
+
+    #[test]
+    fn value_overflow() {
+        let utxos = utxos(&[u64::MAX / 2, u64::MAX / 2, u64::MAX / 2]);
+        let err = select(&utxos, Sats::from_sats(1000u64), FeeRate::ZERO).unwrap_err();
+        assert_eq!(err, SelectionError::Value(ValueError::Overflow));
+    }
+
+    #[test]
+    fn descriptor_weight() {
+        let utxo = Utxo::new(
+            Outpoint::coinbase(),
+            Sats::from_sats(1000u64),
+            WeightUnits::no_discount(1) + WeightUnits::witness_discount(109),
+        );
+        // 41 non-witness bytes and 109 witness bytes give 68.25 vbytes, rounded up
+        assert_eq!(utxo.effective_value(FeeRate::from_sat_per_vb(2)), 1000 - 138);
+    }
+}
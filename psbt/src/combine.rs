@@ -0,0 +1,309 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::hash::Hash;
+
+use derive::Txid;
+use indexmap::IndexMap;
+
+use crate::{GlobalKey, Input, InputKey, KeyData, Output, OutputKey, Psbt, ValueData};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum CombineError {
+    /// PSBTs can't be combined since they describe different unsigned transactions {0} and {1}.
+    DifferentTx(Txid, Txid),
+
+    /// PSBTs contain conflicting global {0} data.
+    GlobalConflict(GlobalKey),
+
+    /// PSBTs contain conflicting {key} data for the input #{index}.
+    InputConflict { index: usize, key: InputKey },
+
+    /// PSBTs contain conflicting {key} data for the output #{index}.
+    OutputConflict { index: usize, key: OutputKey },
+}
+
+fn merge_option<T: Eq, K>(dst: &mut Option<T>, src: Option<T>, key: K) -> Result<(), K> {
+    let Some(src) = src else {
+        return Ok(());
+    };
+    match dst {
+        None => *dst = Some(src),
+        Some(val) if *val == src => {}
+        Some(_) => return Err(key),
+    }
+    Ok(())
+}
+
+/// Merges maps, failing if the same map key has different values in the maps. This prevents,
+/// for instance, two different signatures made with the same public key from being silently
+/// accepted.
+fn merge_map<T: Hash + Eq, V: Eq, K>(
+    dst: &mut IndexMap<T, V>,
+    src: IndexMap<T, V>,
+    key: K,
+) -> Result<(), K> {
+    for (k, val) in src {
+        match dst.get(&k) {
+            None => {
+                dst.insert(k, val);
+            }
+            Some(existing) if *existing == val => {}
+            Some(_) => return Err(key),
+        }
+    }
+    Ok(())
+}
+
+fn merge_unknown<K>(
+    dst: &mut IndexMap<u8, IndexMap<KeyData, ValueData>>,
+    src: IndexMap<u8, IndexMap<KeyData, ValueData>>,
+    key: impl Fn(u8) -> K,
+) -> Result<(), K> {
+    for (key_type, submap) in src {
+        merge_map(dst.entry(key_type).or_default(), submap, key(key_type))?;
+    }
+    Ok(())
+}
+
+impl Psbt {
+    /// Combines PSBT with another one describing the same unsigned transaction, as defined by
+    /// the combiner role in BIP-174.
+    ///
+    /// Data which are present in both PSBTs must be equal, otherwise the method fails with an
+    /// error naming the conflicting field, and `self` is left unchanged. This includes different
+    /// signatures or key derivations provided for the same public key.
+    pub fn combine(&mut self, other: Psbt) -> Result<(), CombineError> {
+        let (txid, other_txid) = (self.txid(), other.txid());
+        if txid != other_txid {
+            return Err(CombineError::DifferentTx(txid, other_txid));
+        }
+
+        let mut combined = self.clone();
+
+        merge_option(
+            &mut combined.fallback_locktime,
+            other.fallback_locktime,
+            GlobalKey::FallbackLocktime,
+        )
+        .and_then(|_| merge_map(&mut combined.xpubs, other.xpubs, GlobalKey::Xpub))
+        .and_then(|_| {
+            merge_map(&mut combined.proprietary, other.proprietary, GlobalKey::Proprietary)
+        })
+        .and_then(|_| merge_unknown(&mut combined.unknown, other.unknown, GlobalKey::Unknown))
+        .map_err(CombineError::GlobalConflict)?;
+        if let Some(flags) = other.tx_modifiable {
+            let own = combined.tx_modifiable.get_or_insert_with(|| flags.clone());
+            own.inputs_modifiable &= flags.inputs_modifiable;
+            own.outputs_modifiable &= flags.outputs_modifiable;
+            own.sighash_single |= flags.sighash_single;
+        }
+
+        for (input, other) in combined.inputs.iter_mut().zip(other.inputs) {
+            input.combine(other).map_err(|key| CombineError::InputConflict {
+                index: input.index,
+                key,
+            })?;
+        }
+        for (output, other) in combined.outputs.iter_mut().zip(other.outputs) {
+            output.combine(other).map_err(|key| CombineError::OutputConflict {
+                index: output.index,
+                key,
+            })?;
+        }
+
+        *self = combined;
+        Ok(())
+    }
+}
+
+impl Input {
+    fn combine(&mut self, other: Input) -> Result<(), InputKey> {
+        merge_option(&mut self.sequence_number, other.sequence_number, InputKey::Sequence)?;
+        merge_option(
+            &mut self.required_time_lock,
+            other.required_time_lock,
+            InputKey::RequiredTimeLock,
+        )?;
+        merge_option(
+            &mut self.required_height_lock,
+            other.required_height_lock,
+            InputKey::RequiredHeighLock,
+        )?;
+        merge_option(&mut self.non_witness_tx, other.non_witness_tx, InputKey::NonWitnessUtxo)?;
+        merge_option(&mut self.witness_utxo, other.witness_utxo, InputKey::WitnessUtxo)?;
+        merge_map(&mut self.partial_sigs, other.partial_sigs, InputKey::PartialSig)?;
+        merge_option(&mut self.sighash_type, other.sighash_type, InputKey::SighashType)?;
+        merge_option(&mut self.redeem_script, other.redeem_script, InputKey::RedeemScript)?;
+        merge_option(&mut self.witness_script, other.witness_script, InputKey::WitnessScript)?;
+        merge_map(&mut self.bip32_derivation, other.bip32_derivation, InputKey::Bip32Derivation)?;
+        merge_option(&mut self.final_script_sig, other.final_script_sig, InputKey::FinalScriptSig)?;
+        merge_option(&mut self.final_witness, other.final_witness, InputKey::FinalWitness)?;
+        merge_option(
+            &mut self.proof_of_reserves,
+            other.proof_of_reserves,
+            InputKey::PorCommitment,
+        )?;
+        merge_map(&mut self.ripemd160, other.ripemd160, InputKey::Ripemd160)?;
+        merge_map(&mut self.sha256, other.sha256, InputKey::Sha256)?;
+        merge_map(&mut self.hash160, other.hash160, InputKey::Hash160)?;
+        merge_map(&mut self.hash256, other.hash256, InputKey::Hash256)?;
+        merge_option(&mut self.tap_key_sig, other.tap_key_sig, InputKey::TapKeySig)?;
+        merge_map(&mut self.tap_script_sig, other.tap_script_sig, InputKey::TapScriptSig)?;
+        merge_map(&mut self.tap_leaf_script, other.tap_leaf_script, InputKey::TapLeafScript)?;
+        merge_map(
+            &mut self.tap_bip32_derivation,
+            other.tap_bip32_derivation,
+            InputKey::TapBip32Derivation,
+        )?;
+        merge_option(&mut self.tap_internal_key, other.tap_internal_key, InputKey::TapInternalKey)?;
+        merge_option(&mut self.tap_merkle_root, other.tap_merkle_root, InputKey::TapMerkleRoot)?;
+        merge_map(&mut self.proprietary, other.proprietary, InputKey::Proprietary)?;
+        merge_unknown(&mut self.unknown, other.unknown, InputKey::Unknown)
+    }
+}
+
+impl Output {
+    fn combine(&mut self, other: Output) -> Result<(), OutputKey> {
+        merge_option(&mut self.redeem_script, other.redeem_script, OutputKey::RedeemScript)?;
+        merge_option(&mut self.witness_script, other.witness_script, OutputKey::WitnessScript)?;
+        merge_map(&mut self.bip32_derivation, other.bip32_derivation, OutputKey::Bip32Derivation)?;
+        merge_option(
+            &mut self.tap_internal_key,
+            other.tap_internal_key,
+            OutputKey::TapInternalKey,
+        )?;
+        merge_option(&mut self.tap_tree, other.tap_tree, OutputKey::TapTree)?;
+        merge_map(
+            &mut self.tap_bip32_derivation,
+            other.tap_bip32_derivation,
+            OutputKey::TapBip32Derivation,
+        )?;
+        merge_map(&mut self.proprietary, other.proprietary, OutputKey::Proprietary)?;
+        merge_unknown(&mut self.unknown, other.unknown, OutputKey::Unknown)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use derive::secp256k1::{ecdsa, schnorr, PublicKey, SecretKey, SECP256K1};
+    use derive::{Bip340Sig, LegacyPk, LegacySig, Sats, ScriptPubkey};
+
+    use super::*;
+    use crate::PsbtVer;
+
+    fn psbt(amount: u64) -> Psbt {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        psbt.construct_output_expect(ScriptPubkey::new(), Sats::from_sats(amount));
+        psbt
+    }
+
+    fn set_unknown(map: &mut IndexMap<u8, IndexMap<KeyData, ValueData>>, value: u8) {
+        map.entry(0xFA).or_default().insert(vec![1u8].into(), vec![value].into());
+    }
+
+    #[test]
+    fn idempotent() {
+        let mut combined = psbt(1000);
+        set_unknown(&mut combined.output_mut(0).unwrap().unknown, 2);
+        let orig = combined.clone();
+        combined.combine(orig.clone()).unwrap();
+        assert_eq!(combined, orig);
+    }
+
+    #[test]
+    fn merge() {
+        let mut combined = psbt(1000);
+        let mut other = combined.clone();
+        set_unknown(&mut other.unknown, 2);
+        combined.combine(other.clone()).unwrap();
+        assert_eq!(combined, other);
+    }
+
+    #[test]
+    fn conflict() {
+        let mut combined = psbt(1000);
+        let mut other = combined.clone();
+        set_unknown(&mut combined.output_mut(0).unwrap().unknown, 2);
+        set_unknown(&mut other.output_mut(0).unwrap().unknown, 3);
+        let orig = combined.clone();
+        let err = combined.combine(other).unwrap_err();
+        assert_eq!(err, CombineError::OutputConflict {
+            index: 0,
+            key: OutputKey::Unknown(0xFA)
+        });
+        assert_eq!(
+            err.to_string(),
+            "PSBTs contain conflicting unknown key type 0xfa data for the output #0."
+        );
+        assert_eq!(combined, orig);
+    }
+
+    #[test]
+    fn conflicting_sigs() {
+        let sk = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let pk = LegacyPk::compressed(PublicKey::from_secret_key(SECP256K1, &sk));
+        let sig =
+            |byte| LegacySig::sighash_all(ecdsa::Signature::from_compact(&[byte; 64]).unwrap());
+        let mut combined = psbt(1000);
+        combined.inputs.push(Input::new(0));
+        let mut other = combined.clone();
+        combined.input_mut(0).unwrap().partial_sigs.insert(pk, sig(1));
+
+        // The same signature merges idempotently
+        other.input_mut(0).unwrap().partial_sigs.insert(pk, sig(1));
+        combined.combine(other.clone()).unwrap();
+        assert_eq!(combined.input(0).unwrap().partial_sigs.len(), 1);
+
+        other.input_mut(0).unwrap().partial_sigs.insert(pk, sig(2));
+        let err = combined.combine(other.clone()).unwrap_err();
+        assert_eq!(err, CombineError::InputConflict {
+            index: 0,
+            key: InputKey::PartialSig
+        });
+        assert_eq!(
+            err.to_string(),
+            "PSBTs contain conflicting `PSBT_IN_PARTIAL_SIG` data for the input #0."
+        );
+
+        let mut other = combined.clone();
+        let tap_sig =
+            |byte| Bip340Sig::sighash_default(schnorr::Signature::from_slice(&[byte; 64]).unwrap());
+        combined.input_mut(0).unwrap().tap_key_sig = Some(tap_sig(1));
+        other.input_mut(0).unwrap().tap_key_sig = Some(tap_sig(2));
+        assert_eq!(
+            combined.combine(other),
+            Err(CombineError::InputConflict {
+                index: 0,
+                key: InputKey::TapKeySig
+            })
+        );
+    }
+
+    #[test]
+    fn different_tx() {
+        let mut combined = psbt(1000);
+        let other = psbt(2000);
+        assert!(matches!(combined.combine(other), Err(CombineError::DifferentTx(..))));
+    }
+}
@@ -23,23 +23,107 @@
 use std::collections::BTreeSet;
 
 use amplify::num::u5;
-use amplify::{Bytes20, Bytes32};
+use amplify::{ByteArray, Bytes20, Bytes32};
 use derive::{
     Bip340Sig, ByteStr, CompressedPk, ControlBlock, InternalPk, KeyOrigin, LeafScript, LegacyPk,
     LegacySig, LockHeight, LockTime, LockTimestamp, Outpoint, RedeemScript, Sats, ScriptPubkey,
-    SeqNo, SigScript, SighashType, TapDerivation, TapNodeHash, TapTree, Terminal, Tx, TxIn, TxOut,
-    TxVer, Txid, VarIntArray, Vout, Witness, WitnessScript, XOnlyPk, Xpub, XpubOrigin,
+    SeqNo, SeqNoExt, SigScript, SighashType, TapDerivation, TapLeafHash, TapNodeHash, TapTree,
+    Terminal, Tx, TxIn, TxOut, TxVer, Txid, VBytes, VarIntArray, Vout, Weight, WeightUnits,
+    Witness, WitnessScript, XOnlyPk, Xpub, XpubOrigin, XpubSpec, SEQ_NO_MAX_VALUE,
+    SEQ_NO_SUBMAX_VALUE,
 };
-use descriptors::Descriptor;
+use descriptors::{Descriptor, SpkClass};
 use indexmap::IndexMap;
 
 pub use self::display_from_str::PsbtParseError;
-use crate::{KeyData, PropKey, PsbtError, PsbtVer, ValueData};
+use crate::{fee, FeeRate, KeyData, PropKey, PsbtError, PsbtVer, ValueData, ValueError};
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
 #[display("PSBT can't be modified")]
 pub struct Unmodifiable;
 
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum OutputError {
+    /// PSBT outputs can't be modified.
+    #[from(Unmodifiable)]
+    Unmodifiable,
+
+    /// output amount {amount} is below the dust limit of {dust_limit} for {class} outputs.
+    Dust {
+        class: SpkClass,
+        amount: Sats,
+        dust_limit: Sats,
+    },
+
+    /// PSBT has no output #{0}.
+    UnknownOutput(Vout),
+
+    /// script pubkey of output #{0} doesn't match the one derived from the descriptor.
+    ScriptMismatch(Vout),
+}
+
+impl OutputError {
+    fn check_dust(class: SpkClass, amount: Sats) -> Result<(), OutputError> {
+        let dust_limit = class.dust_limit();
+        if amount < dust_limit {
+            return Err(OutputError::Dust {
+                class,
+                amount,
+                dust_limit,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum InputError {
+    /// PSBT inputs can't be modified.
+    #[from(Unmodifiable)]
+    Unmodifiable,
+
+    /// script pubkey of the spent output doesn't match the one derived from the descriptor.
+    ScriptMismatch,
+
+    /// the spent output is not a segwit one, so the full previous transaction is required to
+    /// sign the input.
+    NonWitnessTxRequired,
+
+    /// previous transaction {0} has no output #{1}.
+    UnknownOutput(Txid, Vout),
+
+    /// PSBT has no input #{0}.
+    UnknownInput(usize),
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum SortError {
+    /// PSBT inputs and outputs can't be modified.
+    #[from(Unmodifiable)]
+    Unmodifiable,
+
+    /// input #{0} is already signed, so the transaction can't be reordered.
+    Signed(usize),
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum FeeError {
+    /// input #{0} has neither witness UTXO nor non-witness transaction, so its amount is unknown.
+    MissingInputValue(usize),
+
+    /// unable to estimate size of the script sig and witness for input #{0}, since it spends an
+    /// output of unsupported type or misses the required scripts.
+    UnknownSatisfaction(usize),
+
+    /// {0}
+    #[from]
+    Value(ValueError),
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct Prevout {
     pub txid: Txid,
@@ -242,6 +326,21 @@ impl Psbt {
         psbt
     }
 
+    /// Returns the version of PSBT, which is used by default for its serialization.
+    #[inline]
+    pub fn version(&self) -> PsbtVer { self.version }
+
+    /// Converts PSBT into version 2 (BIP-370).
+    ///
+    /// PSBT data are kept in a version-independent form, thus the conversion is lossless and only
+    /// changes the version used for the serialization. The global unsigned transaction of version
+    /// 0 is represented in version 2 by the transaction version, fallback lock time and per-input
+    /// and per-output fields.
+    pub fn into_v2(mut self) -> Psbt {
+        self.version = PsbtVer::V2;
+        self
+    }
+
     pub(crate) fn reset_from_unsigned_tx(&mut self, unsigned_tx: UnsignedTx) {
         self.version = PsbtVer::V0;
         self.tx_version = unsigned_tx.version;
@@ -298,17 +397,165 @@ impl Psbt {
         self.fallback_locktime.unwrap_or(LockTime::ZERO)
     }
 
+    /// Checks whether the transaction signals replaceability (BIP-125), i.e. any of its inputs has
+    /// a sequence number below `0xFFFFFFFE`.
+    pub fn is_rbf(&self) -> bool {
+        self.inputs().any(|input| input.to_unsigned_txin().sequence.is_rbf())
+    }
+
+    /// Opts the transaction in for replace-by-fee (BIP-125) by setting the sequence number of each
+    /// input which doesn't signal replaceability yet to [`SeqNo::rbf`]. Sequence numbers which
+    /// already signal replaceability, including the ones encoding relative time locks, are
+    /// preserved.
+    ///
+    /// Since sequence numbers are committed to by signatures, the method must be called before
+    /// the PSBT gets signed.
+    pub fn enable_rbf(&mut self) {
+        for input in self.inputs_mut() {
+            if !matches!(input.sequence_number, Some(seq_no) if seq_no.is_rbf()) {
+                input.sequence_number = Some(SeqNo::rbf());
+            }
+        }
+    }
+
+    /// Sets the locktime to the current block height to discourage fee sniping, as it is done by
+    /// Bitcoin Core wallet. Does nothing if the PSBT already has a non-zero locktime.
+    ///
+    /// In one out of ten cases (when `entropy` is a multiple of ten) the locktime is moved up to
+    /// 99 blocks back, so the transactions which were delayed before being broadcasted can't be
+    /// distinguished. The `entropy` must be a random value provided by the caller.
+    ///
+    /// Inputs with final sequence numbers, which disable the locktime, are changed to
+    /// `0xFFFFFFFE`; the other sequence numbers are preserved. Thus, the method can be combined
+    /// with [`Self::enable_rbf`] in any order. Since locktime and sequence numbers are committed
+    /// to by signatures, the method must be called before the PSBT gets signed.
+    pub fn enable_anti_fee_sniping(&mut self, current_height: LockHeight, entropy: u32) {
+        if self.lock_time() != LockTime::ZERO {
+            return;
+        }
+        let mut height = current_height.to_consensus_u32();
+        if entropy % 10 == 0 {
+            height = height.saturating_sub((entropy / 10) % 100);
+        }
+        self.fallback_locktime = Some(LockTime::from_consensus_u32(height));
+        for input in self.inputs_mut() {
+            if input.to_unsigned_txin().sequence.to_consensus_u32() == SEQ_NO_MAX_VALUE {
+                input.sequence_number = Some(SeqNo::from_consensus_u32(SEQ_NO_SUBMAX_VALUE));
+            }
+        }
+    }
+
+    /// Constructs transaction using the final script sigs and witnesses of the inputs, where they
+    /// are present.
+    pub(crate) fn to_tx(&self) -> Tx {
+        Tx {
+            version: self.tx_version,
+            inputs: VarIntArray::from_collection_unsafe(
+                self.inputs().map(Input::to_txin).collect(),
+            ),
+            outputs: VarIntArray::from_collection_unsafe(
+                self.outputs().map(Output::to_txout).collect(),
+            ),
+            lock_time: self.lock_time(),
+        }
+    }
+
+    /// # Panics
+    ///
+    /// If any of the inputs misses information about the spent output. See
+    /// [`Psbt::checked_input_sum`] for a non-panicking version.
     #[inline]
     pub fn input_sum(&self) -> Sats { self.inputs().map(Input::value).sum() }
 
+    pub fn checked_input_sum(&self) -> Result<Sats, FeeError> {
+        self.inputs().try_fold(Sats::ZERO, |sum, input| {
+            let value = input.try_value().ok_or(FeeError::MissingInputValue(input.index))?;
+            Ok(sum.checked_add(value).ok_or(ValueError::Overflow)?)
+        })
+    }
+
+    /// Sums output amounts, saturating on overflow. See [`Psbt::checked_output_sum`] for a version
+    /// detecting the overflow.
     #[inline]
     pub fn output_sum(&self) -> Sats { self.outputs().map(Output::value).sum() }
 
     #[inline]
-    pub fn fee(&self) -> Option<Sats> { self.input_sum().checked_sub(self.output_sum()) }
+    pub fn checked_output_sum(&self) -> Result<Sats, ValueError> {
+        fee::checked_sum(self.outputs().map(Output::value))
+    }
+
+    /// Computes absolute fee paid by the transaction as a difference between the sum of amounts
+    /// spent by the inputs and the sum of output amounts.
+    pub fn fee(&self) -> Result<Sats, FeeError> {
+        let input_sum = self.checked_input_sum()?;
+        let output_sum = self.checked_output_sum()?;
+        Ok(fee::checked_fee(input_sum, output_sum)?)
+    }
+
+    /// Computes fee rate using virtual size of the transaction.
+    ///
+    /// For inputs which are not finalized yet the size of their signatures and witnesses is not
+    /// known and is not accounted for, thus the value represents an upper bound for the fee rate
+    /// until the PSBT is fully finalized.
+    pub fn fee_rate(&self) -> Result<FeeRate, FeeError> {
+        Ok(FeeRate::with_fee(self.fee()?, self.to_tx().vbytes()))
+    }
+
+    /// Estimates virtual size of the transaction once all of its inputs are signed and finalized,
+    /// using the maximal size of the satisfaction for each input which is not finalized yet.
+    ///
+    /// Errors with [`FeeError::UnknownSatisfaction`] if the satisfaction size of some of the inputs
+    /// can't be estimated; see [`Input::max_satisfaction_weight`] for the details.
+    pub fn expected_vsize(&self) -> Result<VBytes, FeeError> {
+        let inputs = self
+            .inputs()
+            .map(|input| {
+                let (sig_script, witness) =
+                    input.max_satisfaction().ok_or(FeeError::UnknownSatisfaction(input.index))?;
+                Ok(TxIn {
+                    sig_script,
+                    witness,
+                    ..input.to_txin()
+                })
+            })
+            .collect::<Result<Vec<_>, FeeError>>()?;
+        let tx = Tx {
+            inputs: VarIntArray::from_collection_unsafe(inputs),
+            ..self.to_tx()
+        };
+        Ok(tx.vbytes())
+    }
+
+    /// Estimates fee rate of the transaction once all of its inputs are signed and finalized. See
+    /// [`Psbt::expected_vsize`] for the details.
+    pub fn expected_fee_rate(&self) -> Result<FeeRate, FeeError> {
+        Ok(FeeRate::with_fee(self.fee()?, self.expected_vsize()?))
+    }
 
     pub fn xpubs(&self) -> impl Iterator<Item = (&Xpub, &XpubOrigin)> { self.xpubs.iter() }
 
+    /// Iterates over the global extended public keys (`PSBT_GLOBAL_XPUB`), returning them as
+    /// extended key specifications together with the key origin of each of the keys.
+    pub fn xpub_specs(&self) -> impl Iterator<Item = (XpubSpec, KeyOrigin)> + '_ {
+        self.xpubs
+            .iter()
+            .map(|(xpub, origin)| (XpubSpec::new(*xpub, origin.clone()), origin.clone().into()))
+    }
+
+    /// Adds extended public key to the global xpubs (`PSBT_GLOBAL_XPUB`), returning the origin
+    /// previously stored for the same key, if any.
+    pub fn set_xpub(&mut self, spec: &XpubSpec) -> Option<XpubOrigin> {
+        self.xpubs.insert(*spec.xpub(), spec.origin().clone())
+    }
+
+    /// Adds all extended public keys used by the `descriptor` to the global xpubs, allowing
+    /// signers to find their keys without knowing the descriptor.
+    pub fn set_descriptor_xpubs<K, D: Descriptor<K>>(&mut self, descriptor: &D) {
+        for spec in descriptor.xpubs() {
+            self.set_xpub(spec);
+        }
+    }
+
     pub fn is_modifiable(&self) -> bool {
         self.tx_modifiable.as_ref().map(ModifiableFlags::is_modifiable).unwrap_or_default()
     }
@@ -323,7 +570,7 @@ impl Psbt {
     pub fn are_outputs_modifiable(&self) -> bool {
         self.tx_modifiable
             .as_ref()
-            .map(|flags| flags.inputs_modifiable && !flags.sighash_single)
+            .map(|flags| flags.outputs_modifiable && !flags.sighash_single)
             .unwrap_or_default()
     }
 
@@ -384,6 +631,78 @@ impl Psbt {
             .expect("PSBT inputs are expected to be modifiable")
     }
 
+    /// Adds an input spending segwit or taproot output `txout` controlled by the `descriptor`.
+    ///
+    /// The input receives the witness UTXO, the redeem and witness scripts (if any) and the key
+    /// derivation information for the `terminal`. Errors if the `txout` script doesn't match the
+    /// one derived from the descriptor, or if the output is not a segwit one; use
+    /// [`Psbt::add_input_from_tx`] for spending legacy outputs.
+    pub fn add_input_from_utxo<K, D: Descriptor<K>>(
+        &mut self,
+        outpoint: Outpoint,
+        txout: TxOut,
+        descriptor: &D,
+        terminal: Terminal,
+    ) -> Result<&mut Input, InputError> {
+        if !Self::is_segwit_spend(&txout, descriptor, terminal)? {
+            return Err(InputError::NonWitnessTxRequired);
+        }
+        self.construct_input_from(outpoint, txout, descriptor, terminal)
+    }
+
+    /// Adds an input spending output `vout` of the previous transaction `prev_tx`, which is
+    /// controlled by the `descriptor`.
+    ///
+    /// The full previous transaction is stored in the input; for segwit and taproot outputs the
+    /// witness UTXO is also provided. Scripts and key derivation information are filled in the
+    /// same way as by [`Psbt::add_input_from_utxo`].
+    pub fn add_input_from_tx<K, D: Descriptor<K>>(
+        &mut self,
+        prev_tx: Tx,
+        vout: Vout,
+        descriptor: &D,
+        terminal: Terminal,
+    ) -> Result<&mut Input, InputError> {
+        let txid = prev_tx.txid();
+        let txout = prev_tx
+            .outputs
+            .get(vout.to_usize())
+            .cloned()
+            .ok_or(InputError::UnknownOutput(txid, vout))?;
+        let segwit = Self::is_segwit_spend(&txout, descriptor, terminal)?;
+        let input =
+            self.construct_input_from(Outpoint::new(txid, vout), txout, descriptor, terminal)?;
+        if !segwit {
+            input.witness_utxo = None;
+        }
+        input.non_witness_tx = Some(prev_tx);
+        Ok(input)
+    }
+
+    fn is_segwit_spend<K, D: Descriptor<K>>(
+        txout: &TxOut,
+        descriptor: &D,
+        terminal: Terminal,
+    ) -> Result<bool, InputError> {
+        let scripts = descriptor.derive(terminal.keychain, terminal.index);
+        if scripts.to_script_pubkey() != txout.script_pubkey {
+            return Err(InputError::ScriptMismatch);
+        }
+        Ok(txout.script_pubkey.is_witness_program() || scripts.as_witness_script().is_some())
+    }
+
+    fn construct_input_from<K, D: Descriptor<K>>(
+        &mut self,
+        outpoint: Outpoint,
+        txout: TxOut,
+        descriptor: &D,
+        terminal: Terminal,
+    ) -> Result<&mut Input, InputError> {
+        let prevout = Prevout::new(outpoint, txout.value);
+        let sequence = SeqNo::from_consensus_u32(SEQ_NO_SUBMAX_VALUE);
+        Ok(self.construct_input(prevout, descriptor, terminal, sequence)?)
+    }
+
     pub fn construct_output(
         &mut self,
         script_pubkey: ScriptPubkey,
@@ -449,6 +768,55 @@ impl Psbt {
             .expect("PSBT outputs are expected to be modifiable")
     }
 
+    /// Adds a payment output, checking that the amount is not below the dust limit for the output
+    /// type.
+    pub fn add_recipient(
+        &mut self,
+        script_pubkey: ScriptPubkey,
+        amount: Sats,
+    ) -> Result<&mut Output, OutputError> {
+        OutputError::check_dust(SpkClass::from_script_pubkey(&script_pubkey), amount)?;
+        Ok(self.construct_output(script_pubkey, amount)?)
+    }
+
+    /// Adds an output paying to the descriptor scripts derived for the `terminal`, checking that
+    /// the amount is not below the dust limit for the descriptor type.
+    ///
+    /// The output receives the key derivation information, which allows signers to recognize it
+    /// as a change.
+    pub fn add_descriptor_output<K, D: Descriptor<K>>(
+        &mut self,
+        descriptor: &D,
+        terminal: Terminal,
+        amount: Sats,
+    ) -> Result<&mut Output, OutputError> {
+        OutputError::check_dust(descriptor.class(), amount)?;
+        Ok(self.construct_change(descriptor, terminal, amount)?)
+    }
+
+    /// Fills key derivation information for an already existing output paying to the descriptor
+    /// scripts derived for the `terminal`, so signers can recognize it as a change; see
+    /// [`Output::fill_derivation`].
+    ///
+    /// Errors if there is no output `vout` or its script pubkey doesn't match the descriptor.
+    /// Since the derivation information is not committed to by the transaction, the method can
+    /// be used even when the outputs can't be modified anymore.
+    pub fn set_output_derivation<K, D: Descriptor<K>>(
+        &mut self,
+        vout: Vout,
+        descriptor: &D,
+        terminal: Terminal,
+    ) -> Result<&mut Output, OutputError> {
+        let output =
+            self.outputs.get_mut(vout.to_u32() as usize).ok_or(OutputError::UnknownOutput(vout))?;
+        let scripts = descriptor.derive(terminal.keychain, terminal.index);
+        if output.script != scripts.to_script_pubkey() {
+            return Err(OutputError::ScriptMismatch(vout));
+        }
+        output.fill_derivation(descriptor, terminal);
+        Ok(output)
+    }
+
     pub fn sort_outputs_by<K: Ord>(
         &mut self,
         f: impl FnMut(&Output) -> K,
@@ -458,11 +826,116 @@ impl Psbt {
         }
 
         self.outputs.sort_by_key(f);
+        self.reindex_outputs();
+
+        Ok(())
+    }
+
+    /// Removes input with the given index from the PSBT, shifting the subsequent inputs.
+    ///
+    /// Since the transaction changes, signatures of all remaining inputs are removed, including
+    /// the final script sigs and witnesses, and the inputs have to be signed again.
+    pub fn remove_input(&mut self, index: usize) -> Result<Input, InputError> {
+        if !self.are_inputs_modifiable() {
+            return Err(InputError::Unmodifiable);
+        }
+        if index >= self.inputs.len() {
+            return Err(InputError::UnknownInput(index));
+        }
+
+        let input = self.inputs.remove(index);
+        for (index, input) in self.inputs.iter_mut().enumerate() {
+            input.index = index;
+        }
+        self.remove_sigs();
+
+        Ok(input)
+    }
+
+    /// Removes output with the given index from the PSBT, shifting the subsequent outputs.
+    ///
+    /// Since the transaction changes, signatures of all inputs are removed, including the final
+    /// script sigs and witnesses, and the inputs have to be signed again.
+    pub fn remove_output(&mut self, index: usize) -> Result<Output, OutputError> {
+        if !self.are_outputs_modifiable() {
+            return Err(OutputError::Unmodifiable);
+        }
+        if index >= self.outputs.len() {
+            return Err(OutputError::UnknownOutput(Vout::from_u32(index as u32)));
+        }
+
+        let output = self.outputs.remove(index);
+        self.reindex_outputs();
+
+        Ok(output)
+    }
+
+    /// Randomly reorders the outputs using Fisher-Yates shuffle. The `rng` must return uniformly
+    /// distributed random values, for instance coming from `rand::random::<u64>`.
+    ///
+    /// Since the transaction changes, signatures of all inputs are removed, including the final
+    /// script sigs and witnesses, and the inputs have to be signed again.
+    pub fn shuffle_outputs(&mut self, mut rng: impl FnMut() -> u64) -> Result<(), Unmodifiable> {
+        if !self.are_outputs_modifiable() {
+            return Err(Unmodifiable);
+        }
+
+        for i in (1..self.outputs.len()).rev() {
+            let j = (rng() % (i as u64 + 1)) as usize;
+            self.outputs.swap(i, j);
+        }
+        self.reindex_outputs();
+
+        Ok(())
+    }
+
+    /// Sorts inputs and outputs according to BIP-69: inputs by the previous transaction id (in
+    /// its displayed, reversed byte order) and output number, outputs by amount and then by
+    /// script pubkey bytes.
+    ///
+    /// Since the transaction ordering is committed to by signatures, the method refuses to run
+    /// once any of the inputs has signatures. Sorting an already sorted PSBT doesn't change it.
+    pub fn sort_bip69(&mut self) -> Result<(), SortError> {
+        if !self.are_inputs_modifiable() || !self.are_outputs_modifiable() {
+            return Err(SortError::Unmodifiable);
+        }
+        if let Some(input) = self.inputs().find(|input| input.has_sigs()) {
+            return Err(SortError::Signed(input.index));
+        }
+
+        self.inputs.sort_by_key(|input| {
+            let mut txid = input.previous_outpoint.txid.to_byte_array();
+            txid.reverse();
+            (txid, input.previous_outpoint.vout)
+        });
+        for (index, input) in self.inputs.iter_mut().enumerate() {
+            input.index = index;
+        }
+        self.outputs.sort_by(|a, b| {
+            a.amount.cmp(&b.amount).then_with(|| a.script.as_slice().cmp(b.script.as_slice()))
+        });
+        self.reindex_outputs();
+
+        Ok(())
+    }
+
+    /// Updates output indexes after the outputs were reordered or removed, removing the
+    /// signatures which became invalid.
+    fn reindex_outputs(&mut self) {
         for (index, output) in self.outputs.iter_mut().enumerate() {
             output.index = index;
         }
+        self.remove_sigs();
+    }
 
-        Ok(())
+    fn remove_sigs(&mut self) {
+        for input in &mut self.inputs {
+            input.partial_sigs.clear();
+            input.tap_key_sig = None;
+            input.tap_script_sig.clear();
+            input.final_script_sig = None;
+            input.final_witness = None;
+        }
     }
 
     pub fn complete_construction(&mut self) {
@@ -496,12 +969,15 @@ mod display_from_str {
     }
 
     impl Psbt {
+        /// Parses Base64-encoded PSBT. Whitespaces and line breaks inside the string are ignored.
         pub fn from_base64(s: &str) -> Result<Psbt, PsbtParseError> {
-            Psbt::deserialize(BASE64_STANDARD.decode(s)?).map_err(PsbtParseError::from)
+            let data = BASE64_STANDARD.decode(strip_whitespace(s))?;
+            Psbt::deserialize(data).map_err(PsbtParseError::from)
         }
 
+        /// Parses hex-encoded PSBT. Whitespaces and line breaks inside the string are ignored.
         pub fn from_base16(s: &str) -> Result<Psbt, PsbtParseError> {
-            let data = Vec::<u8>::from_hex(s)?;
+            let data = Vec::<u8>::from_hex(&strip_whitespace(s))?;
             Psbt::deserialize(data).map_err(PsbtParseError::from)
         }
 
@@ -516,6 +992,8 @@ mod display_from_str {
         pub fn to_base16_ver(&self, version: PsbtVer) -> String { self.serialize(version).to_hex() }
     }
 
+    fn strip_whitespace(s: &str) -> String { s.split_whitespace().collect() }
+
     /// FromStr implementation parses both Base64 and Hex (Base16) encodings.
     impl FromStr for Psbt {
         type Err = PsbtParseError;
@@ -599,11 +1077,13 @@ pub struct Input {
     /// 32 bit unsigned little endian integer greater than or equal to 500000000
     /// representing the minimum Unix timestamp that this input requires to be
     /// set as the transaction's lock time.
+    #[cfg_attr(feature = "serde", serde(with = "derive::serde_lock::option"))]
     pub required_time_lock: Option<LockTimestamp>,
 
     /// 32 bit unsigned little endian integer less than 500000000 representing
     /// the minimum block height that this input requires to be set as the
     /// transaction's lock time.
+    #[cfg_attr(feature = "serde", serde(with = "derive::serde_lock::option"))]
     pub required_height_lock: Option<LockHeight>,
 
     /// The non-witness transaction this input spends from. Should only be
@@ -753,16 +1233,44 @@ impl Input {
         }
     }
 
+    pub(crate) fn to_txin(&self) -> TxIn {
+        let mut txin = TxIn::from(self.to_unsigned_txin());
+        if let Some(sig_script) = &self.final_script_sig {
+            txin.sig_script = sig_script.clone();
+        }
+        if let Some(witness) = &self.final_witness {
+            txin.witness = witness.clone();
+        }
+        txin
+    }
+
+    /// Detects whether the input has any signatures, either partial or final.
+    pub fn has_sigs(&self) -> bool {
+        !self.partial_sigs.is_empty()
+            || self.tap_key_sig.is_some()
+            || !self.tap_script_sig.is_empty()
+            || self.final_script_sig.is_some()
+            || self.final_witness.is_some()
+    }
+
+    /// Returns transaction output spent by this input, taking it either from the witness UTXO or
+    /// from the non-witness transaction. Returns `None` if the input contains neither of them.
+    pub fn try_prev_txout(&self) -> Option<&TxOut> {
+        match (&self.witness_utxo, &self.non_witness_tx) {
+            (Some(txout), _) => Some(txout),
+            (None, Some(tx)) => tx.outputs.get(self.previous_outpoint.vout_usize()),
+            (None, None) => None,
+        }
+    }
+
+    /// # Panics
+    ///
+    /// If the input contains neither witness UTXO nor a non-witness transaction, or the
+    /// transaction doesn't have the spent output.
     #[inline]
     pub fn prev_txout(&self) -> &TxOut {
-        // TODO: Add support for nonwitness_utxo
-        match (&self.witness_utxo, None::<&Tx>) {
-            (Some(txout), _) => txout,
-            (None, Some(tx)) => &tx.outputs[self.index],
-            (None, None) => unreachable!(
-                "PSBT input must contain either witness UTXO or a non-witness transaction"
-            ),
-        }
+        self.try_prev_txout()
+            .expect("PSBT input must contain either witness UTXO or a non-witness transaction")
     }
 
     #[inline]
@@ -777,8 +1285,94 @@ impl Input {
     #[inline]
     pub fn value(&self) -> Sats { self.prev_txout().value }
 
+    #[inline]
+    pub fn try_value(&self) -> Option<Sats> { self.try_prev_txout().map(|txout| txout.value) }
+
     #[inline]
     pub fn index(&self) -> usize { self.index }
+
+    /// Computes the maximal weight of the script sig and witness satisfying the spent output.
+    ///
+    /// For finalized inputs the weight of the final script sig and witness is returned. Otherwise,
+    /// the estimation is supported for P2PKH, P2WPKH, P2SH-nested P2WPKH and taproot key path
+    /// spendings; for other input types `None` is returned. Taproot inputs are assumed to be spent
+    /// with the key path unless they provide leaf scripts, in which case `None` is returned as
+    /// well.
+    pub fn max_satisfaction_weight(&self) -> Option<WeightUnits> {
+        let (sig_script, witness) = self.max_satisfaction()?;
+        Some(sig_script.weight_units() + witness.weight_units())
+    }
+
+    /// Constructs placeholders of the maximal size for the script sig and witness.
+    fn max_satisfaction(&self) -> Option<(SigScript, Witness)> {
+        // DER-encoded ECDSA signature of the maximal size followed by a sighash type byte
+        const ECDSA_SIG_LEN: usize = 73;
+        // BIP340 signature followed by a non-default sighash type byte
+        const BIP340_SIG_LEN: usize = 65;
+        const PUBKEY_LEN: usize = 33;
+
+        if self.is_finalized() {
+            let sig_script = self.final_script_sig.clone().unwrap_or_default();
+            let witness = self.final_witness.clone().unwrap_or_default();
+            return Some((sig_script, witness));
+        }
+
+        let script_pubkey = &self.try_prev_txout()?.script_pubkey;
+        let wpkh_witness =
+            || Witness::from_consensus_stack([vec![0u8; ECDSA_SIG_LEN], vec![0u8; PUBKEY_LEN]]);
+        if script_pubkey.is_p2pkh() {
+            let mut sig_script = vec![ECDSA_SIG_LEN as u8];
+            sig_script.extend([0u8; ECDSA_SIG_LEN]);
+            sig_script.push(PUBKEY_LEN as u8);
+            sig_script.extend([0u8; PUBKEY_LEN]);
+            Some((SigScript::from_unsafe(sig_script), empty!()))
+        } else if script_pubkey.is_p2wpkh() {
+            Some((SigScript::new(), wpkh_witness()))
+        } else if script_pubkey.is_p2sh() {
+            let redeem_script = self.redeem_script.as_ref()?;
+            let redeem_script = redeem_script.to_vec();
+            if !ScriptPubkey::from_unsafe(redeem_script.clone()).is_p2wpkh() {
+                return None;
+            }
+            let mut sig_script = vec![redeem_script.len() as u8];
+            sig_script.extend(redeem_script);
+            Some((SigScript::from_unsafe(sig_script), wpkh_witness()))
+        } else if script_pubkey.is_p2tr() && self.tap_leaf_script.is_empty() {
+            Some((SigScript::new(), Witness::from_consensus_stack([vec![0u8; BIP340_SIG_LEN]])))
+        } else {
+            None
+        }
+    }
+
+    /// Fills BIP32 derivation information which allows signers to find keys controlling the spent
+    /// output, deriving descriptor keys for the provided `terminal`.
+    ///
+    /// Key origins are composed of the [`derive::XpubSpec::origin`] and the terminal derivation.
+    /// For pre-taproot descriptors this fills `bip32_derivation`; for taproot descriptors - the
+    /// `tap_bip32_derivation` and `tap_internal_key` fields. If the taproot descriptor has script
+    /// leaves, their scripts and control blocks are added to `tap_leaf_script` and the
+    /// `tap_merkle_root` is set. Already existing entries are preserved.
+    pub fn fill_derivation<K, D: Descriptor<K>>(&mut self, descriptor: &D, terminal: Terminal) {
+        self.bip32_derivation.extend(descriptor.compr_keyset(terminal));
+        self.tap_bip32_derivation.extend(descriptor.xonly_keyset(terminal));
+        let scripts = descriptor.derive(terminal.keychain, terminal.index);
+        if let Some(internal_pk) = scripts.to_internal_pk() {
+            self.tap_internal_key = Some(internal_pk);
+        }
+        if let Some(merkle_root) = scripts.to_tap_root() {
+            self.tap_merkle_root = Some(merkle_root);
+        }
+        self.tap_leaf_script.extend(scripts.to_leaf_scripts());
+    }
+
+    /// Iterates over taproot script leaves present in the input `tap_leaf_script` field,
+    /// returning leaf hash together with the leaf script and the control block proving its
+    /// inclusion into the taproot output.
+    pub fn tap_leaves(&self) -> impl Iterator<Item = (TapLeafHash, &LeafScript, &ControlBlock)> {
+        self.tap_leaf_script.iter().map(|(control_block, leaf_script)| {
+            (leaf_script.tap_leaf_hash(), leaf_script, control_block)
+        })
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -878,6 +1472,31 @@ impl Output {
     #[inline]
     pub fn vout(&self) -> Vout { Vout::from_u32(self.index as u32) }
 
+    /// Fills key derivation information for the output, deriving descriptor keys for the
+    /// provided `terminal`. This allows signers to recognize the output as a change.
+    ///
+    /// For pre-taproot descriptors this fills `bip32_derivation` and the redeem and witness
+    /// scripts; for taproot descriptors - the `tap_bip32_derivation`, `tap_internal_key` and
+    /// `tap_tree` fields. Already existing derivation entries are preserved. The method doesn't
+    /// check that the output script pubkey matches the descriptor.
+    pub fn fill_derivation<K, D: Descriptor<K>>(&mut self, descriptor: &D, terminal: Terminal) {
+        self.bip32_derivation.extend(descriptor.compr_keyset(terminal));
+        self.tap_bip32_derivation.extend(descriptor.xonly_keyset(terminal));
+        let scripts = descriptor.derive(terminal.keychain, terminal.index);
+        if let Some(redeem_script) = scripts.to_redeem_script() {
+            self.redeem_script = Some(redeem_script);
+        }
+        if let Some(witness_script) = scripts.to_witness_script() {
+            self.witness_script = Some(witness_script);
+        }
+        if let Some(internal_pk) = scripts.to_internal_pk() {
+            self.tap_internal_key = Some(internal_pk);
+        }
+        if let Some(tap_tree) = scripts.to_tap_tree() {
+            self.tap_tree = Some(tap_tree);
+        }
+    }
+
     pub fn terminal_derivation(&self) -> Option<Terminal> {
         if self.bip32_derivation.is_empty() && self.tap_bip32_derivation.is_empty() {
             return None;
@@ -963,3 +1582,500 @@ impl ModifiableFlags {
         self.inputs_modifiable | self.outputs_modifiable | self.sighash_single
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use derive::secp256k1::schnorr;
+    use derive::{Derive, Keychain, NormalIndex, XpubDerivable};
+    use descriptors::{TrKey, Wpkh};
+
+    use super::*;
+    use crate::{KeyAlreadyPresent, KeyMap};
+
+    const XPUB: &str = "[643a7adc/86h/1h/0h]tpubDCNiWHaiSkgnQjuhsg9kjwaUzaxQjUcmhagvYzqQ3TYJTgFGJstVaqnu4yhtFktBhCVFmBNLQ5sN53qKzZbMksm3XEyGJsEhQPfVZdWmTE2/<0;1>/*";
+
+    #[test]
+    fn fill_derivation() {
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let terminal = Terminal::new(Keychain::INNER, NormalIndex::from(5u8));
+        let origin = KeyOrigin::from_str("643a7adc/86h/1h/0h/1/5").unwrap();
+
+        let mut input = Input::new(0);
+        input.fill_derivation(&Wpkh::from(xpub.clone()), terminal);
+        assert_eq!(input.bip32_derivation.len(), 1);
+        assert_eq!(input.bip32_derivation[0], origin);
+        assert!(input.tap_bip32_derivation.is_empty());
+        assert_eq!(input.tap_internal_key, None);
+
+        let mut input = Input::new(0);
+        input.fill_derivation(&TrKey::from(xpub), terminal);
+        assert!(input.bip32_derivation.is_empty());
+        assert_eq!(input.tap_bip32_derivation.len(), 1);
+        let (pk, derivation) = input.tap_bip32_derivation.first().unwrap();
+        assert_eq!(derivation.origin, origin);
+        assert!(derivation.leaf_hashes.is_empty());
+        assert_eq!(input.tap_internal_key, Some(InternalPk::from(*pk)));
+    }
+
+    #[test]
+    fn rbf() {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        for sequence in [
+            SeqNo::from_consensus_u32(SEQ_NO_MAX_VALUE),
+            SeqNo::from_consensus_u32(SEQ_NO_SUBMAX_VALUE),
+            SeqNo::from_height(144),
+        ] {
+            assert!(!psbt.is_rbf());
+            let mut input = Input::new(psbt.inputs.len());
+            input.sequence_number = Some(sequence);
+            psbt.inputs.push(input);
+        }
+        assert!(psbt.is_rbf());
+
+        psbt.enable_rbf();
+        assert!(psbt.is_rbf());
+        assert_eq!(psbt.inputs[0].sequence_number, Some(SeqNo::rbf()));
+        assert_eq!(psbt.inputs[1].sequence_number, Some(SeqNo::rbf()));
+        assert_eq!(psbt.inputs[2].sequence_number, Some(SeqNo::from_height(144)));
+    }
+
+    #[test]
+    fn remove_and_shuffle() {
+        let sig = schnorr::Signature::from_slice(&[1u8; 64]).unwrap();
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        for vout in 0..3u32 {
+            let mut input = Input::new(psbt.inputs.len());
+            input.previous_outpoint = Outpoint::new(Txid::from([1u8; 32]), Vout::from_u32(vout));
+            input.tap_key_sig = Some(Bip340Sig::sighash_default(sig));
+            psbt.inputs.push(input);
+        }
+        for value in 1..=5u64 {
+            psbt.add_recipient(ScriptPubkey::p2wpkh([1u8; 20]), Sats(1000 * value)).unwrap();
+        }
+
+        let input = psbt.remove_input(1).unwrap();
+        assert_eq!(input.previous_outpoint.vout, Vout::from_u32(1));
+        assert_eq!(psbt.remove_input(2), Err(InputError::UnknownInput(2)));
+        assert_eq!(psbt.inputs().map(Input::index).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(psbt.inputs[1].previous_outpoint.vout, Vout::from_u32(2));
+        assert!(psbt.inputs().all(|input| input.tap_key_sig.is_none()));
+
+        psbt.input_mut(0).unwrap().tap_key_sig = Some(Bip340Sig::sighash_default(sig));
+        let output = psbt.remove_output(0).unwrap();
+        assert_eq!(output.value(), Sats(1000));
+        assert_eq!(psbt.remove_output(4), Err(OutputError::UnknownOutput(Vout::from_u32(4))));
+        assert_eq!(psbt.input(0).unwrap().tap_key_sig, None);
+
+        let mut entropy = 0x5eed_u64;
+        psbt.shuffle_outputs(|| {
+            entropy = entropy.wrapping_mul(6364136223846793005).wrapping_add(1);
+            entropy >> 33
+        })
+        .unwrap();
+        assert_eq!(psbt.outputs().map(Output::index).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+        let mut values = psbt.outputs().map(Output::value).collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, vec![Sats(2000), Sats(3000), Sats(4000), Sats(5000)]);
+
+        psbt.complete_construction();
+        assert_eq!(psbt.remove_input(0), Err(InputError::Unmodifiable));
+        assert_eq!(psbt.remove_output(0), Err(OutputError::Unmodifiable));
+        assert_eq!(psbt.shuffle_outputs(|| 0), Err(Unmodifiable));
+    }
+
+    #[test]
+    fn bip69() {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        for (txid, vout) in [
+            ("0100000000000000000000000000000000000000000000000000000000000000", 0u32),
+            ("0000000000000000000000000000000000000000000000000000000000000001", 1),
+            ("0000000000000000000000000000000000000000000000000000000000000001", 0),
+        ] {
+            let mut input = Input::new(psbt.inputs.len());
+            input.previous_outpoint =
+                Outpoint::new(Txid::from_str(txid).unwrap(), Vout::from_u32(vout));
+            psbt.inputs.push(input);
+        }
+        let p2wpkh = ScriptPubkey::p2wpkh([2u8; 20]);
+        let p2wsh = ScriptPubkey::p2wsh([1u8; 32]);
+        psbt.add_recipient(p2wsh.clone(), Sats(2000)).unwrap();
+        psbt.add_recipient(p2wpkh.clone(), Sats(2000)).unwrap();
+        psbt.add_recipient(p2wpkh.clone(), Sats(1000)).unwrap();
+
+        psbt.sort_bip69().unwrap();
+        let tx = psbt.to_unsigned_tx();
+        assert_eq!(
+            tx.inputs.iter().map(|txin| txin.prev_output.to_string()).collect::<Vec<_>>(),
+            vec![
+                "0000000000000000000000000000000000000000000000000000000000000001:0",
+                "0000000000000000000000000000000000000000000000000000000000000001:1",
+                "0100000000000000000000000000000000000000000000000000000000000000:0",
+            ]
+        );
+        assert_eq!(psbt.inputs().map(Input::index).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(tx.outputs.iter().map(|txout| txout.value).collect::<Vec<_>>(), vec![
+            Sats(1000),
+            Sats(2000),
+            Sats(2000)
+        ]);
+        // P2WPKH script pushes 20 bytes after `OP_0`, so it goes before 32-byte push of P2WSH
+        assert_eq!(tx.outputs[1].script_pubkey, p2wpkh);
+        assert_eq!(tx.outputs[2].script_pubkey, p2wsh);
+
+        let sorted = psbt.clone();
+        psbt.sort_bip69().unwrap();
+        assert_eq!(psbt, sorted);
+
+        psbt.input_mut(2).unwrap().final_witness = Some(Witness::from_consensus_stack([vec![1]]));
+        assert_eq!(psbt.sort_bip69(), Err(SortError::Signed(2)));
+        psbt.complete_construction();
+        assert_eq!(psbt.sort_bip69(), Err(SortError::Unmodifiable));
+    }
+
+    #[test]
+    fn add_outputs() {
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let descriptor = TrKey::from(xpub);
+        let terminal = Terminal::new(Keychain::INNER, NormalIndex::from(5u8));
+        let mut psbt = Psbt::create(PsbtVer::V2);
+
+        let output = psbt.add_descriptor_output(&descriptor, terminal, Sats(330)).unwrap();
+        assert_eq!(output.tap_bip32_derivation.len(), 1);
+        assert!(output.tap_internal_key.is_some());
+        assert_eq!(
+            psbt.add_descriptor_output(&descriptor, terminal, Sats(329)),
+            Err(OutputError::Dust {
+                class: SpkClass::P2tr,
+                amount: Sats(329),
+                dust_limit: Sats(330)
+            })
+        );
+
+        let script_pubkey = ScriptPubkey::p2wpkh([1u8; 20]);
+        assert!(matches!(
+            psbt.add_recipient(script_pubkey.clone(), Sats(293)),
+            Err(OutputError::Dust { .. })
+        ));
+        let output = psbt.add_recipient(script_pubkey.clone(), Sats(294)).unwrap();
+        assert_eq!(output.script, script_pubkey);
+        assert!(output.bip32_derivation.is_empty());
+        psbt.add_recipient(ScriptPubkey::op_return(&[]), Sats::ZERO).unwrap();
+        assert_eq!(psbt.outputs().count(), 3);
+
+        psbt.complete_construction();
+        assert_eq!(psbt.add_recipient(script_pubkey, Sats(1000)), Err(OutputError::Unmodifiable));
+    }
+
+    #[test]
+    fn set_output_derivation() {
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let wpkh = Wpkh::from(xpub.clone());
+        let tr = TrKey::from(xpub);
+        let terminal = Terminal::new(Keychain::INNER, NormalIndex::from(7u8));
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        for script_pubkey in [
+            wpkh.derive(terminal.keychain, terminal.index).to_script_pubkey(),
+            tr.derive(terminal.keychain, terminal.index).to_script_pubkey(),
+        ] {
+            psbt.construct_output_expect(script_pubkey, Sats(1000));
+        }
+
+        assert_eq!(
+            psbt.set_output_derivation(Vout::from_u32(2), &wpkh, terminal),
+            Err(OutputError::UnknownOutput(Vout::from_u32(2)))
+        );
+        assert_eq!(
+            psbt.set_output_derivation(Vout::from_u32(1), &wpkh, terminal),
+            Err(OutputError::ScriptMismatch(Vout::from_u32(1)))
+        );
+        psbt.set_output_derivation(Vout::from_u32(0), &wpkh, terminal).unwrap();
+        psbt.set_output_derivation(Vout::from_u32(1), &tr, terminal).unwrap();
+
+        let origin = KeyOrigin::from_str("643a7adc/86h/1h/0h/1/7").unwrap();
+        for ver in [PsbtVer::V0, PsbtVer::V2] {
+            let psbt = Psbt::deserialize(psbt.serialize(ver)).unwrap();
+            let output = psbt.output(0).unwrap();
+            assert_eq!(output.bip32_derivation.len(), 1);
+            assert_eq!(output.bip32_derivation[0], origin);
+            assert_eq!(output.terminal_derivation(), Some(terminal));
+
+            let output = psbt.output(1).unwrap();
+            assert!(output.bip32_derivation.is_empty());
+            let (pk, derivation) = output.tap_bip32_derivation.first().unwrap();
+            assert_eq!(derivation.origin, origin);
+            assert_eq!(output.tap_internal_key, Some(InternalPk::from(*pk)));
+            assert_eq!(output.terminal_derivation(), Some(terminal));
+        }
+    }
+
+    #[test]
+    fn add_input_from_utxo() {
+        let descriptor = Wpkh::from(XpubDerivable::from_str(XPUB).unwrap());
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(3u8));
+        let script_pubkey = descriptor.derive(terminal.keychain, terminal.index).to_script_pubkey();
+        let txout = TxOut::new(script_pubkey, Sats(10_000));
+        let mut psbt = Psbt::create(PsbtVer::V2);
+
+        let foreign = TxOut::new(ScriptPubkey::p2wpkh([1u8; 20]), Sats(10_000));
+        assert_eq!(
+            psbt.add_input_from_utxo(Outpoint::coinbase(), foreign, &descriptor, terminal),
+            Err(InputError::ScriptMismatch)
+        );
+
+        let input = psbt
+            .add_input_from_utxo(Outpoint::coinbase(), txout.clone(), &descriptor, terminal)
+            .unwrap();
+        assert_eq!(input.witness_utxo, Some(txout.clone()));
+        assert_eq!(input.non_witness_tx, None);
+        assert_eq!(input.bip32_derivation.len(), 1);
+
+        let prev_tx = Tx {
+            version: TxVer::V2,
+            inputs: VarIntArray::from_collection_unsafe(vec![]),
+            outputs: VarIntArray::from_collection_unsafe(vec![txout.clone()]),
+            lock_time: LockTime::ZERO,
+        };
+        let txid = prev_tx.txid();
+        assert_eq!(
+            psbt.add_input_from_tx(prev_tx.clone(), Vout::from_u32(1), &descriptor, terminal),
+            Err(InputError::UnknownOutput(txid, Vout::from_u32(1)))
+        );
+        let input = psbt
+            .add_input_from_tx(prev_tx.clone(), Vout::from_u32(0), &descriptor, terminal)
+            .unwrap();
+        assert_eq!(input.previous_outpoint, Outpoint::new(txid, Vout::from_u32(0)));
+        assert_eq!(input.witness_utxo, Some(txout));
+        assert_eq!(input.non_witness_tx, Some(prev_tx));
+        assert_eq!(psbt.checked_input_sum(), Ok(Sats(20_000)));
+    }
+
+    #[test]
+    fn global_xpubs() {
+        let descriptor = TrKey::from(XpubDerivable::from_str(XPUB).unwrap());
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        psbt.set_descriptor_xpubs(&descriptor);
+
+        let psbt = Psbt::from_base64(&psbt.to_base64()).unwrap();
+        let specs = psbt.xpub_specs().collect::<Vec<_>>();
+        assert_eq!(specs.len(), 1);
+        let (spec, origin) = &specs[0];
+        assert_eq!(spec, descriptor.xpubs().next().unwrap());
+        assert_eq!(*origin, KeyOrigin::from_str("643a7adc/86h/1h/0h").unwrap());
+    }
+
+    #[test]
+    fn expected_vsize() {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        let prevout = Prevout::new(Outpoint::coinbase(), Sats(10_000));
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(0u8));
+        psbt.construct_input_expect(
+            prevout,
+            &Wpkh::from(xpub.clone()),
+            terminal,
+            SeqNo::from_consensus_u32(0),
+        );
+        psbt.construct_output_expect(ScriptPubkey::p2wpkh([1u8; 20]), Sats(9_000));
+
+        // 82 bytes of non-witness data, 2 bytes of segwit marker and flag and 109 witness bytes
+        assert_eq!(
+            psbt.expected_vsize(),
+            Ok(VBytes::from(WeightUnits::no_discount(82) + WeightUnits::witness_discount(111)))
+        );
+        assert_eq!(
+            psbt.input(0).unwrap().max_satisfaction_weight(),
+            Some(WeightUnits::no_discount(1) + WeightUnits::witness_discount(109))
+        );
+        assert_eq!(
+            psbt.expected_fee_rate(),
+            Ok(FeeRate::with_fee(Sats(1000), VBytes::from(WeightUnits::witness_discount(439))))
+        );
+
+        psbt.construct_input_expect(
+            prevout,
+            &TrKey::from(xpub),
+            terminal,
+            SeqNo::from_consensus_u32(0),
+        );
+        assert_eq!(
+            psbt.input(1).unwrap().max_satisfaction_weight(),
+            Some(WeightUnits::no_discount(1) + WeightUnits::witness_discount(67))
+        );
+        assert!(psbt.expected_vsize().is_ok());
+
+        let mut input = Input::new(2);
+        input.witness_utxo = Some(TxOut::new(ScriptPubkey::p2wsh([1u8; 32]), Sats(10_000)));
+        psbt.inputs.push(input);
+        assert_eq!(psbt.expected_vsize(), Err(FeeError::UnknownSatisfaction(2)));
+    }
+
+    #[test]
+    fn fee_overspend() {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        let prevout = Prevout::new(Outpoint::coinbase(), Sats(10_000));
+        let xpub = XpubDerivable::from_str(XPUB).unwrap();
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(0u8));
+        psbt.construct_input_expect(
+            prevout,
+            &Wpkh::from(xpub),
+            terminal,
+            SeqNo::from_consensus_u32(0),
+        );
+        psbt.construct_output_expect(ScriptPubkey::p2wpkh([1u8; 20]), Sats(9_000));
+        assert_eq!(psbt.fee(), Ok(Sats(1_000)));
+
+        psbt.construct_output_expect(ScriptPubkey::p2wpkh([2u8; 20]), Sats(2_000));
+        assert_eq!(
+            psbt.fee(),
+            Err(FeeError::Value(ValueError::NegativeFee {
+                input_sum: Sats(10_000),
+                output_sum: Sats(11_000)
+            }))
+        );
+        assert!(psbt.fee_rate().is_err());
+
+        psbt.construct_output_expect(ScriptPubkey::p2wpkh([3u8; 20]), Sats(u64::MAX));
+        assert_eq!(psbt.checked_output_sum(), Err(ValueError::Overflow));
+        assert_eq!(psbt.fee(), Err(FeeError::Value(ValueError::Overflow)));
+    }
+
+    #[test]
+    fn base64_whitespace() {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        psbt.construct_output_expect(ScriptPubkey::op_return(&[1, 2, 3]), Sats::ZERO);
+        let base64 = psbt.to_base64();
+        let (start, end) = base64.split_at(10);
+        let wrapped = format!("  {start}\n {end}\r\n");
+        assert_eq!(Psbt::from_base64(&wrapped).unwrap(), psbt);
+        assert_eq!(Psbt::from_str(&wrapped).unwrap(), psbt);
+
+        let base16 = psbt.to_base16();
+        let (start, end) = base16.split_at(10);
+        assert_eq!(Psbt::from_base16(&format!("{start}\t{end}\n")).unwrap(), psbt);
+    }
+
+    #[test]
+    fn unknown_roundtrip() {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        let prop_key = |identifier: &str, subtype| PropKey {
+            identifier: identifier.to_owned(),
+            subtype,
+            data: KeyData::from(vec![]),
+        };
+        psbt.proprietary.insert(prop_key("vendor", 2), ValueData::from(vec![2]));
+        psbt.proprietary.insert(prop_key("vendor", 1), ValueData::from(vec![1]));
+        psbt.unknown
+            .entry(0x20)
+            .or_default()
+            .insert(KeyData::from(vec![2]), ValueData::from(vec![4]));
+        psbt.unknown
+            .entry(0x20)
+            .or_default()
+            .insert(KeyData::from(vec![1]), ValueData::from(vec![3]));
+        psbt.unknown
+            .entry(0x10)
+            .or_default()
+            .insert(KeyData::from(vec![]), ValueData::from(vec![5]));
+
+        let data = psbt.serialize(PsbtVer::V2);
+        let parsed = Psbt::deserialize(&data).unwrap();
+        assert_eq!(parsed.serialize(PsbtVer::V2), data);
+        assert_eq!(parsed.unknown.keys().copied().collect::<Vec<_>>(), vec![0x20, 0x10]);
+        assert_eq!(parsed.unknown[&0x20].keys().cloned().collect::<Vec<_>>(), vec![
+            KeyData::from(vec![2]),
+            KeyData::from(vec![1])
+        ]);
+        assert_eq!(parsed.proprietary.keys().cloned().collect::<Vec<_>>(), vec![
+            prop_key("vendor", 2),
+            prop_key("vendor", 1)
+        ]);
+        assert_eq!(parsed.proprietary[&prop_key("vendor", 2)], ValueData::from(vec![2]));
+    }
+
+    #[test]
+    fn proprietary_keys() {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        let key = PropKey::new("vendor", 1, vec![0xAA]);
+        psbt.push_proprietary(key.clone(), vec![1]).unwrap();
+        assert_eq!(
+            psbt.push_proprietary(key.clone(), vec![2]),
+            Err(KeyAlreadyPresent(key.clone()))
+        );
+        assert_eq!(psbt.set_proprietary(key.clone(), vec![2]), Some(ValueData::from(vec![1])));
+        psbt.push_proprietary(PropKey::new("vendor", 1, vec![0xBB]), vec![3]).unwrap();
+        psbt.push_proprietary(PropKey::new("vendor", 2, vec![]), vec![4]).unwrap();
+        assert_eq!(psbt.proprietary_with("vendor", 1, vec![0xAA]), Some(&ValueData::from(vec![2])));
+        assert_eq!(psbt.proprietary_with("other", 1, vec![0xAA]), None);
+        assert_eq!(psbt.proprietary_subtype("vendor", 1), vec![
+            (&KeyData::from(vec![0xAA]), &ValueData::from(vec![2])),
+            (&KeyData::from(vec![0xBB]), &ValueData::from(vec![3])),
+        ]);
+
+        let parsed = Psbt::deserialize(psbt.serialize(PsbtVer::V2)).unwrap();
+        assert_eq!(parsed.proprietary_subtype("vendor", 1).len(), 2);
+        assert_eq!(parsed.proprietary_with("vendor", 2, vec![]), Some(&ValueData::from(vec![4])));
+    }
+
+    #[test]
+    fn anti_fee_sniping() {
+        let height = LockHeight::try_from(840_000u32).unwrap();
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        for sequence in [SeqNo::from_consensus_u32(SEQ_NO_MAX_VALUE), SeqNo::rbf()] {
+            let mut input = Input::new(psbt.inputs.len());
+            input.sequence_number = Some(sequence);
+            psbt.inputs.push(input);
+        }
+
+        psbt.enable_anti_fee_sniping(height, 1);
+        assert_eq!(psbt.lock_time(), LockTime::from_consensus_u32(840_000));
+        assert_eq!(
+            psbt.inputs[0].sequence_number,
+            Some(SeqNo::from_consensus_u32(SEQ_NO_SUBMAX_VALUE))
+        );
+        assert_eq!(psbt.inputs[1].sequence_number, Some(SeqNo::rbf()));
+        assert!(psbt.is_rbf());
+
+        psbt.enable_rbf();
+        assert_eq!(psbt.inputs[0].sequence_number, Some(SeqNo::rbf()));
+        psbt.enable_anti_fee_sniping(LockHeight::try_from(850_000u32).unwrap(), 1);
+        assert_eq!(psbt.lock_time(), LockTime::from_consensus_u32(840_000));
+
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        psbt.enable_anti_fee_sniping(height, 370);
+        assert_eq!(psbt.lock_time(), LockTime::from_consensus_u32(839_963));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_locks() {
+        #[derive(Eq, PartialEq, Debug, Serialize, Deserialize)]
+        #[serde(crate = "serde_crate", rename_all = "camelCase")]
+        struct Locks {
+            #[serde(with = "derive::serde_lock::option")]
+            required_time_lock: Option<LockTimestamp>,
+            #[serde(with = "derive::serde_lock::option")]
+            required_height_lock: Option<LockHeight>,
+        }
+
+        let mut input = Input::new(0);
+        let json = serde_json::to_value(&input).unwrap();
+        assert_eq!(json["requiredTimeLock"], serde_json::Value::Null);
+        assert_eq!(json["requiredHeightLock"], serde_json::Value::Null);
+        assert_eq!(serde_json::from_value::<Locks>(json).unwrap(), Locks {
+            required_time_lock: None,
+            required_height_lock: None,
+        });
+
+        input.required_time_lock = LockTimestamp::from_unix_timestamp(1_700_000_000);
+        input.required_height_lock = LockHeight::from_height(840_000);
+        let json = serde_json::to_value(&input).unwrap();
+        assert_eq!(json["requiredTimeLock"], "time(1700000000)");
+        assert_eq!(json["requiredHeightLock"], "height(840000)");
+        assert_eq!(serde_json::from_value::<Locks>(json).unwrap(), Locks {
+            required_time_lock: input.required_time_lock,
+            required_height_lock: input.required_height_lock,
+        });
+    }
+}
@@ -0,0 +1,165 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{self, Display, Formatter};
+
+use derive::{Sats, VBytes};
+
+/// Errors in arithmetic on transaction amounts.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum ValueError {
+    /// the sum of the amounts overflows.
+    Overflow,
+
+    /// the sum of the output amounts ({output_sum}) exceeds the sum of the input amounts
+    /// ({input_sum}), which would result in a negative fee.
+    NegativeFee { input_sum: Sats, output_sum: Sats },
+}
+
+/// Sums the amounts, erroring instead of saturating or wrapping on overflow.
+pub(crate) fn checked_sum(values: impl IntoIterator<Item = Sats>) -> Result<Sats, ValueError> {
+    values
+        .into_iter()
+        .try_fold(Sats::ZERO, |sum, value| sum.checked_add(value).ok_or(ValueError::Overflow))
+}
+
+/// Computes fee as a difference between the input and output sums.
+pub(crate) fn checked_fee(input_sum: Sats, output_sum: Sats) -> Result<Sats, ValueError> {
+    input_sum.checked_sub(output_sum).ok_or(ValueError::NegativeFee {
+        input_sum,
+        output_sum,
+    })
+}
+
+/// Transaction fee rate, measured in satoshis per 1000 virtual bytes (sat/kvB), allowing fractional
+/// sat/vB values without floating point arithmetic.
+#[derive(Wrapper, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Default, Debug, From)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", transparent)
+)]
+pub struct FeeRate(u64);
+
+impl FeeRate {
+    pub const ZERO: Self = FeeRate(0);
+
+    /// Minimal fee rate accepted for relay by nodes with the default policy (1 sat/vB).
+    pub const MIN_RELAY: Self = FeeRate(1000);
+
+    /// Maximal fee rate accepted by nodes with the default settings for the transactions submitted
+    /// with `sendrawtransaction` RPC (0.1 BTC/kvB).
+    pub const DEFAULT_MAX: Self = FeeRate(10_000_000);
+
+    #[inline]
+    pub const fn from_sat_per_kvb(sat_per_kvb: u64) -> Self { FeeRate(sat_per_kvb) }
+
+    #[inline]
+    pub const fn from_sat_per_vb(sat_per_vb: u64) -> Self {
+        FeeRate(sat_per_vb.saturating_mul(1000))
+    }
+
+    /// Computes fee rate of a transaction with the given fee and virtual size.
+    ///
+    /// Returns [`FeeRate::ZERO`] for a zero virtual size.
+    pub fn with_fee(fee: Sats, vsize: VBytes) -> Self {
+        match vsize.to_u32() {
+            0 => FeeRate::ZERO,
+            vsize => FeeRate(fee.sats().saturating_mul(1000) / vsize as u64),
+        }
+    }
+
+    #[inline]
+    pub const fn to_sat_per_kvb(&self) -> u64 { self.0 }
+
+    /// Adds two fee rates, returning `None` on overflow.
+    #[inline]
+    pub const fn checked_add(self, other: FeeRate) -> Option<FeeRate> {
+        match self.0.checked_add(other.0) {
+            Some(rate) => Some(FeeRate(rate)),
+            None => None,
+        }
+    }
+
+    /// Subtracts a fee rate, returning `None` if the result would be negative.
+    #[inline]
+    pub const fn checked_sub(self, other: FeeRate) -> Option<FeeRate> {
+        match self.0.checked_sub(other.0) {
+            Some(rate) => Some(FeeRate(rate)),
+            None => None,
+        }
+    }
+
+    #[inline]
+    pub fn to_sat_per_vb(&self) -> f64 { self.0 as f64 / 1000.0 }
+
+    /// Computes fee for a transaction of a given virtual size, rounding up to the next satoshi.
+    pub fn fee_for(&self, vsize: VBytes) -> Sats {
+        let msats = self.0.saturating_mul(vsize.to_u32() as u64);
+        Sats::from_sats(msats / 1000 + (msats % 1000 != 0) as u64)
+    }
+}
+
+impl Display for FeeRate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} sat/vB", self.to_sat_per_vb())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use derive::WeightUnits;
+
+    use super::*;
+
+    #[test]
+    fn fee_rate() {
+        let vsize = VBytes::from(WeightUnits::no_discount(250));
+        let rate = FeeRate::from_sat_per_vb(2);
+        assert_eq!(rate.fee_for(vsize), Sats::from_sats(500u64));
+        assert_eq!(FeeRate::with_fee(Sats::from_sats(500u64), vsize), rate);
+        assert_eq!(FeeRate::from_sat_per_kvb(1500).fee_for(vsize), Sats::from_sats(375u64));
+        assert_eq!(FeeRate::from_sat_per_kvb(1001).fee_for(vsize), Sats::from_sats(251u64));
+        assert_eq!(FeeRate::from_sat_per_kvb(2500).to_string(), "2.5 sat/vB");
+
+        assert_eq!(rate.checked_add(FeeRate::MIN_RELAY), Some(FeeRate::from_sat_per_vb(3)));
+        assert_eq!(FeeRate::from_sat_per_kvb(u64::MAX).checked_add(FeeRate::MIN_RELAY), None);
+        assert_eq!(rate.checked_sub(FeeRate::MIN_RELAY), Some(FeeRate::MIN_RELAY));
+        assert_eq!(FeeRate::MIN_RELAY.checked_sub(rate), None);
+    }
+
+    #[test]
+    fn checked_values() {
+        let sats = |value: u64| Sats::from_sats(value);
+        assert_eq!(checked_sum([sats(1), sats(2), sats(3)]), Ok(sats(6)));
+        assert_eq!(checked_sum([sats(u64::MAX), sats(1)]), Err(ValueError::Overflow));
+        assert_eq!(checked_fee(sats(1000), sats(900)), Ok(sats(100)));
+        assert_eq!(
+            checked_fee(sats(900), sats(1000)),
+            Err(ValueError::NegativeFee {
+                input_sum: sats(900),
+                output_sum: sats(1000)
+            })
+        );
+    }
+}
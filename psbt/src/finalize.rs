@@ -0,0 +1,535 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amplify::Wrapper;
+use derive::opcodes::{OP_NUMEQUAL, OP_PUSHBYTES_32, OP_PUSHNUM_1, OP_PUSHNUM_16};
+use derive::{
+    CompressedPk, ControlBlock, InternalPk, LeafVer, Sats, ScriptPubkey, SighashType,
+    TapBranchHash, TapLeafHash, TapNodeHash, Tx, WPubkeyHash, Weight, Witness, XOnlyPk,
+};
+use descriptors::multi_a_script;
+
+use crate::{Encode, FeeError, FeeRate, Input, Psbt};
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum ExtractError {
+    /// input #{0} is not finalized.
+    NotFinalized(usize),
+
+    /// unable to compute transaction fee: {0}
+    #[from]
+    Fee(FeeError),
+
+    /// transaction fee rate {fee_rate} exceeds the maximum allowed fee rate of {max_fee_rate}.
+    FeeRateTooHigh {
+        fee_rate: FeeRate,
+        max_fee_rate: FeeRate,
+    },
+
+    /// transaction fee {fee} exceeds the maximum allowed fee of {max_fee}.
+    FeeTooHigh { fee: Sats, max_fee: Sats },
+}
+
+impl Psbt {
+    /// Maximal absolute fee accepted by nodes with the default settings for the transactions
+    /// submitted with `sendrawtransaction` RPC (0.1 BTC).
+    pub const DEFAULT_MAX_FEE: Sats = Sats(10_000_000);
+
+    /// Finalizes all inputs which have enough information to be finalized.
+    ///
+    /// Returns indexes of the inputs which were finalized by this call. Inputs which are already
+    /// finalized or can't be finalized are left untouched.
+    pub fn finalize(&mut self) -> Vec<usize> {
+        self.inputs.iter_mut().filter_map(|input| input.finalize().then_some(input.index)).collect()
+    }
+
+    pub fn is_finalized(&self) -> bool { self.inputs().all(Input::is_finalized) }
+
+    /// Extracts signed transaction from a fully finalized PSBT.
+    ///
+    /// Errors if any of the inputs is not finalized. The fee paid by the transaction is not
+    /// checked; use [`Psbt::extract_tx_capped`] to protect from overpaying fees.
+    pub fn extract_tx(&self) -> Result<Tx, ExtractError> {
+        if let Some(input) = self.inputs().find(|input| !input.is_finalized()) {
+            return Err(ExtractError::NotFinalized(input.index));
+        }
+        Ok(self.to_tx())
+    }
+
+    /// Extracts signed transaction from a fully finalized PSBT, checking that its fee rate doesn't
+    /// exceed `max_fee_rate`. For instance, [`FeeRate::DEFAULT_MAX`] matches the limit which is
+    /// applied by nodes to the transactions submitted with `sendrawtransaction`.
+    pub fn extract_tx_capped(&self, max_fee_rate: FeeRate) -> Result<Tx, ExtractError> {
+        let tx = self.extract_tx()?;
+        let fee_rate = FeeRate::with_fee(self.fee()?, tx.vbytes());
+        if fee_rate > max_fee_rate {
+            return Err(ExtractError::FeeRateTooHigh {
+                fee_rate,
+                max_fee_rate,
+            });
+        }
+        Ok(tx)
+    }
+
+    /// Extracts signed transaction from a fully finalized PSBT, checking its fee against
+    /// [`Psbt::DEFAULT_MAX_FEE`] and [`FeeRate::DEFAULT_MAX`]. Use [`Psbt::check_fee`] followed
+    /// by [`Psbt::extract_tx`] to apply different limits.
+    pub fn extract_tx_checked(&self) -> Result<Tx, ExtractError> {
+        let tx = self.extract_tx()?;
+        self.check_fee(Self::DEFAULT_MAX_FEE, FeeRate::DEFAULT_MAX)?;
+        Ok(tx)
+    }
+
+    /// Checks that the fee paid by the transaction exceeds neither `max_fee` nor `max_fee_rate`,
+    /// returning the fee.
+    ///
+    /// For inputs which are not finalized yet the size of their witnesses is not accounted for,
+    /// so before the finalization the check is stricter than needed.
+    pub fn check_fee(&self, max_fee: Sats, max_fee_rate: FeeRate) -> Result<Sats, ExtractError> {
+        let fee = self.fee()?;
+        if fee > max_fee {
+            return Err(ExtractError::FeeTooHigh { fee, max_fee });
+        }
+        let fee_rate = FeeRate::with_fee(fee, self.to_tx().vbytes());
+        if fee_rate > max_fee_rate {
+            return Err(ExtractError::FeeRateTooHigh {
+                fee_rate,
+                max_fee_rate,
+            });
+        }
+        Ok(fee)
+    }
+}
+
+impl Input {
+    pub fn is_finalized(&self) -> bool {
+        self.final_script_sig.is_some() || self.final_witness.is_some()
+    }
+
+    /// Finalizes the input, constructing final witness from the signature data.
+    ///
+    /// Signatures are serialized together with their sighash type byte, which is omitted only
+    /// for taproot signatures using `SIGHASH_DEFAULT`.
+    ///
+    /// Taproot inputs are finalized using the key path if a key signature is present. Otherwise,
+    /// the first script leaf from `tap_leaf_script` which can be satisfied with the signatures
+    /// from `tap_script_sig` is used; at the present moment only `multi_a` leaf scripts are
+    /// supported.
+    ///
+    /// Returns `false` if the input is already finalized or it can't be finalized, i.e. it spends
+    /// an output of unsupported type, doesn't have all the required signatures or the signatures
+    /// use sighash type different from the one required by the input.
+    pub fn finalize(&mut self) -> bool {
+        if self.is_finalized() {
+            return false;
+        }
+        let Some(prev_txout) = self.try_prev_txout() else {
+            return false;
+        };
+        let script_pubkey = &prev_txout.script_pubkey;
+        let witness = if script_pubkey.is_p2wpkh() {
+            self.wpkh_witness(script_pubkey)
+        } else if script_pubkey.is_p2tr() {
+            self.tr_key_witness(script_pubkey).or_else(|| self.tr_script_witness(script_pubkey))
+        } else {
+            None
+        };
+        let Some(witness) = witness else {
+            return false;
+        };
+        self.final_witness = Some(witness);
+        self.clear_finalized();
+        true
+    }
+
+    fn wpkh_witness(&self, script_pubkey: &ScriptPubkey) -> Option<Witness> {
+        if self.partial_sigs.len() != 1 {
+            return None;
+        }
+        let (pk, sig) = self.partial_sigs.first()?;
+        if !pk.compressed || matches!(self.sighash_type, Some(ty) if ty != sig.sighash_type) {
+            return None;
+        }
+        let pkh = WPubkeyHash::from(CompressedPk::from(pk.pubkey));
+        if *script_pubkey != ScriptPubkey::p2wpkh(pkh) {
+            return None;
+        }
+        Some(Witness::from_consensus_stack([sig.to_vec(), pk.to_vec()]))
+    }
+
+    fn tr_key_witness(&self, script_pubkey: &ScriptPubkey) -> Option<Witness> {
+        let sig = self.tap_key_sig?;
+        if let Some(sighash_type) = self.sighash_type {
+            if sig.sighash_type.unwrap_or(SighashType::all()) != sighash_type {
+                return None;
+            }
+        }
+        // Key-path spend must not be mixed with script-path signatures. The output may still
+        // commit to a script tree by its merkle root, which requires the internal key to verify
+        // the output key; the leaf scripts are dropped once the input is finalized.
+        if !self.tap_script_sig.is_empty() {
+            return None;
+        }
+        match (self.tap_internal_key, self.tap_merkle_root) {
+            (Some(internal_pk), merkle_root) => {
+                if *script_pubkey != ScriptPubkey::p2tr(internal_pk, merkle_root) {
+                    return None;
+                }
+            }
+            (None, Some(_)) => return None,
+            (None, None) => {}
+        }
+        Some(Witness::from_consensus_stack([sig.to_vec()]))
+    }
+
+    fn tr_script_witness(&self, script_pubkey: &ScriptPubkey) -> Option<Witness> {
+        self.tap_leaves().find_map(|(leaf_hash, leaf_script, control_block)| {
+            if leaf_script.version != LeafVer::TapScript {
+                return None;
+            }
+            let merkle_root = merkle_root(leaf_hash, control_block);
+            if *script_pubkey != ScriptPubkey::p2tr(control_block.internal_pk, Some(merkle_root)) {
+                return None;
+            }
+            let (keys, threshold) = parse_multi_a(&leaf_script.script)?;
+
+            let mut sigs = Vec::with_capacity(keys.len());
+            let mut count = 0u16;
+            for pk in &keys {
+                let key = (InternalPk::from(*pk), leaf_hash.into_inner());
+                match self.tap_script_sig.get(&key) {
+                    Some(sig) if count < threshold => {
+                        if let Some(sighash_type) = self.sighash_type {
+                            if sig.sighash_type.unwrap_or(SighashType::all()) != sighash_type {
+                                return None;
+                            }
+                        }
+                        count += 1;
+                        sigs.push(sig.to_vec());
+                    }
+                    _ => sigs.push(vec![]),
+                }
+            }
+            if count < threshold {
+                return None;
+            }
+
+            let mut control_block_bytes = vec![];
+            control_block.encode(&mut control_block_bytes).expect("in-memory writer doesn't fail");
+            // Script checks signatures in the order of the keys, taking them from the top of the
+            // stack, so the signature for the first key must come last.
+            let stack =
+                sigs.into_iter().rev().chain([leaf_script.script.to_vec(), control_block_bytes]);
+            Some(Witness::from_consensus_stack(stack))
+        })
+    }
+
+    /// Removes all data which are not required anymore after the input finalization, as defined
+    /// by the finalizer role in BIP-174.
+    fn clear_finalized(&mut self) {
+        self.partial_sigs.clear();
+        self.sighash_type = None;
+        self.redeem_script = None;
+        self.witness_script = None;
+        self.bip32_derivation.clear();
+        self.ripemd160.clear();
+        self.sha256.clear();
+        self.hash160.clear();
+        self.hash256.clear();
+        self.tap_key_sig = None;
+        self.tap_script_sig.clear();
+        self.tap_leaf_script.clear();
+        self.tap_bip32_derivation.clear();
+        self.tap_internal_key = None;
+        self.tap_merkle_root = None;
+    }
+}
+
+/// Reconstructs taproot merkle root from the leaf hash and the merkle proof in the control
+/// block.
+fn merkle_root(leaf_hash: TapLeafHash, control_block: &ControlBlock) -> TapNodeHash {
+    let mut node = TapNodeHash::from(leaf_hash);
+    for step in &control_block.merkle_branch {
+        node = TapBranchHash::with_nodes(node, TapNodeHash::from(*step)).into();
+    }
+    node
+}
+
+/// Parses `multi_a` script, returning its keys in the script order and the signature threshold.
+/// Returns `None` if the script is not a `multi_a` script as constructed by [`multi_a_script`].
+fn parse_multi_a(script: &[u8]) -> Option<(Vec<XOnlyPk>, u16)> {
+    let mut keys = vec![];
+    let mut rest = script;
+    while let [OP_PUSHBYTES_32, tail @ ..] = rest {
+        if tail.len() < 33 {
+            return None;
+        }
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&tail[..32]);
+        keys.push(XOnlyPk::from_byte_array(key).ok()?);
+        rest = &tail[33..];
+    }
+    let threshold = match rest {
+        [op @ OP_PUSHNUM_1..=OP_PUSHNUM_16, OP_NUMEQUAL] => (op - OP_PUSHNUM_1 + 1) as u16,
+        [len @ 1..=2, num @ .., OP_NUMEQUAL] if num.len() == *len as usize => {
+            let mut bytes = [0u8; 2];
+            bytes[..num.len()].copy_from_slice(num);
+            u16::from_le_bytes(bytes)
+        }
+        _ => return None,
+    };
+    if threshold == 0 || threshold as usize > keys.len() {
+        return None;
+    }
+    // Checks opcodes following the keys and the minimal encoding of the threshold
+    if multi_a_script(threshold, &keys).as_slice() != script {
+        return None;
+    }
+    Some((keys, threshold))
+}
+
+#[cfg(test)]
+mod test {
+    use derive::secp256k1::{ecdsa, schnorr, PublicKey, SecretKey, SECP256K1};
+    use derive::{Bip340Sig, InternalPk, LegacyPk, LegacySig, TapNodeHash, TxOut};
+
+    use super::*;
+    use crate::{Output, PsbtVer, ValueError};
+
+    fn wpkh_psbt() -> (Psbt, LegacyPk, LegacySig) {
+        let sk = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let pk = LegacyPk::compressed(PublicKey::from_secret_key(SECP256K1, &sk));
+        let sig = LegacySig::sighash_all(ecdsa::Signature::from_compact(&[1u8; 64]).unwrap());
+        let spk = ScriptPubkey::p2wpkh(WPubkeyHash::from(CompressedPk::from(pk.pubkey)));
+
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        let mut input = Input::new(0);
+        input.witness_utxo = Some(TxOut::new(spk, Sats::from_sats(1000u64)));
+        psbt.inputs.push(input);
+        (psbt, pk, sig)
+    }
+
+    #[test]
+    fn wpkh() {
+        let (mut psbt, pk, sig) = wpkh_psbt();
+        assert_eq!(psbt.finalize(), Vec::<usize>::new());
+        assert!(!psbt.is_finalized());
+
+        psbt.input_mut(0).unwrap().partial_sigs.insert(pk, sig);
+        assert_eq!(psbt.finalize(), vec![0]);
+        assert!(psbt.is_finalized());
+        let input = psbt.input(0).unwrap();
+        assert!(input.partial_sigs.is_empty());
+        assert_eq!(
+            input.final_witness,
+            Some(Witness::from_consensus_stack([sig.to_vec(), pk.to_vec()]))
+        );
+        assert_eq!(psbt.finalize(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn wpkh_sighash_type() {
+        let (mut psbt, pk, sig) = wpkh_psbt();
+        let sig = LegacySig {
+            sig: sig.sig,
+            sighash_type: SighashType::single_anyone_can_pay(),
+        };
+        let input = psbt.input_mut(0).unwrap();
+        input.partial_sigs.insert(pk, sig);
+        input.sighash_type = Some(SighashType::none());
+        assert_eq!(psbt.finalize(), Vec::<usize>::new());
+
+        psbt.input_mut(0).unwrap().sighash_type = Some(SighashType::single_anyone_can_pay());
+        assert_eq!(psbt.finalize(), vec![0]);
+        let witness = psbt.input(0).unwrap().final_witness.as_ref().unwrap();
+        assert_eq!(witness.elements().next().unwrap().last(), Some(&0x83));
+    }
+
+    #[test]
+    fn wpkh_wrong_key() {
+        let (mut psbt, _, sig) = wpkh_psbt();
+        let sk = SecretKey::from_slice(&[2u8; 32]).unwrap();
+        let pk = LegacyPk::compressed(PublicKey::from_secret_key(SECP256K1, &sk));
+        psbt.input_mut(0).unwrap().partial_sigs.insert(pk, sig);
+        assert_eq!(psbt.finalize(), Vec::<usize>::new());
+        assert!(!psbt.input(0).unwrap().partial_sigs.is_empty());
+    }
+
+    #[test]
+    fn extract() {
+        let (mut psbt, pk, sig) = wpkh_psbt();
+        assert_eq!(psbt.extract_tx(), Err(ExtractError::NotFinalized(0)));
+
+        psbt.input_mut(0).unwrap().partial_sigs.insert(pk, sig);
+        psbt.finalize();
+        psbt.outputs.push(Output::with_txout(
+            TxOut::new(ScriptPubkey::op_return(&[]), Sats::from_sats(500u64)),
+            0,
+        ));
+        let tx = psbt.extract_tx().unwrap();
+        assert_eq!(tx.inputs.len(), 1);
+        assert_eq!(
+            tx.inputs[0].witness,
+            Witness::from_consensus_stack([sig.to_vec(), pk.to_vec()])
+        );
+        assert_eq!(psbt.extract_tx_capped(FeeRate::DEFAULT_MAX), Ok(tx));
+        assert!(matches!(
+            psbt.extract_tx_capped(FeeRate::MIN_RELAY),
+            Err(ExtractError::FeeRateTooHigh { .. })
+        ));
+
+        psbt.outputs[0].amount = Sats::from_sats(1100u64);
+        assert!(matches!(
+            psbt.extract_tx_capped(FeeRate::DEFAULT_MAX),
+            Err(ExtractError::Fee(FeeError::Value(ValueError::NegativeFee { .. })))
+        ));
+    }
+
+    #[test]
+    fn check_fee() {
+        let (mut psbt, pk, sig) = wpkh_psbt();
+        psbt.input_mut(0).unwrap().partial_sigs.insert(pk, sig);
+        psbt.finalize();
+        psbt.outputs.push(Output::with_txout(
+            TxOut::new(ScriptPubkey::op_return(&[]), Sats::from_sats(500u64)),
+            0,
+        ));
+
+        assert_eq!(psbt.check_fee(Sats(500), FeeRate::DEFAULT_MAX), Ok(Sats(500)));
+        assert_eq!(
+            psbt.check_fee(Sats(499), FeeRate::DEFAULT_MAX),
+            Err(ExtractError::FeeTooHigh {
+                fee: Sats(500),
+                max_fee: Sats(499)
+            })
+        );
+        assert!(matches!(
+            psbt.check_fee(Psbt::DEFAULT_MAX_FEE, FeeRate::MIN_RELAY),
+            Err(ExtractError::FeeRateTooHigh { .. })
+        ));
+        assert_eq!(psbt.extract_tx_checked(), psbt.extract_tx());
+
+        psbt.input_mut(0).unwrap().witness_utxo.as_mut().unwrap().value = Sats(20_000_000);
+        assert!(matches!(psbt.extract_tx_checked(), Err(ExtractError::FeeTooHigh { .. })));
+    }
+
+    fn tr_psbt() -> Psbt {
+        let sk = SecretKey::from_slice(&[1u8; 32]).unwrap();
+        let (xonly, _) = PublicKey::from_secret_key(SECP256K1, &sk).x_only_public_key();
+        let internal_pk = InternalPk::from(xonly);
+        let spk = ScriptPubkey::p2tr_key_only(internal_pk);
+
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        let mut input = Input::new(0);
+        input.witness_utxo = Some(TxOut::new(spk, Sats::from_sats(1000u64)));
+        input.tap_internal_key = Some(internal_pk);
+        psbt.inputs.push(input);
+        psbt
+    }
+
+    #[test]
+    fn tr_key() {
+        let sig = schnorr::Signature::from_slice(&[1u8; 64]).unwrap();
+        for sig in [Bip340Sig::sighash_default(sig), Bip340Sig {
+            sig,
+            sighash_type: Some(SighashType::all()),
+        }] {
+            let mut psbt = tr_psbt();
+            psbt.input_mut(0).unwrap().tap_key_sig = Some(sig);
+            assert_eq!(psbt.finalize(), vec![0]);
+            let input = psbt.input(0).unwrap();
+            assert_eq!(input.tap_key_sig, None);
+            assert_eq!(input.tap_internal_key, None);
+            assert_eq!(input.final_witness, Some(Witness::from_consensus_stack([sig.to_vec()])));
+        }
+    }
+
+    #[test]
+    fn tr_key_sighash_type() {
+        let sig = Bip340Sig {
+            sig: schnorr::Signature::from_slice(&[1u8; 64]).unwrap(),
+            sighash_type: Some(SighashType::none()),
+        };
+        let mut psbt = tr_psbt();
+        let input = psbt.input_mut(0).unwrap();
+        input.tap_key_sig = Some(sig);
+        input.sighash_type = Some(SighashType::all());
+        assert_eq!(psbt.finalize(), Vec::<usize>::new());
+
+        psbt.input_mut(0).unwrap().sighash_type = Some(SighashType::none());
+        assert_eq!(psbt.finalize(), vec![0]);
+        let witness = psbt.input(0).unwrap().final_witness.as_ref().unwrap();
+        assert_eq!(witness.elements().next().unwrap().len(), 65);
+        assert_eq!(witness.elements().next().unwrap().last(), Some(&0x02));
+    }
+
+    #[test]
+    fn tr_key_script_path() {
+        let sig = schnorr::Signature::from_slice(&[1u8; 64]).unwrap();
+        let mut psbt = tr_psbt();
+        let input = psbt.input_mut(0).unwrap();
+        input.tap_key_sig = Some(Bip340Sig::sighash_default(sig));
+        input.tap_merkle_root = Some(TapNodeHash::from([1u8; 32]));
+        assert_eq!(psbt.finalize(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn tr_key_merkle_root() {
+        let sig = schnorr::Signature::from_slice(&[1u8; 64]).unwrap();
+        let merkle_root = TapNodeHash::from([1u8; 32]);
+        let mut psbt = tr_psbt();
+        let input = psbt.input_mut(0).unwrap();
+        let internal_pk = input.tap_internal_key.unwrap();
+        input.witness_utxo.as_mut().unwrap().script_pubkey =
+            ScriptPubkey::p2tr(internal_pk, Some(merkle_root));
+        input.tap_key_sig = Some(Bip340Sig::sighash_default(sig));
+        assert_eq!(psbt.finalize(), Vec::<usize>::new());
+
+        let input = psbt.input_mut(0).unwrap();
+        input.tap_merkle_root = Some(merkle_root);
+        input.tap_internal_key = None;
+        assert_eq!(psbt.finalize(), Vec::<usize>::new());
+
+        psbt.input_mut(0).unwrap().tap_internal_key = Some(internal_pk);
+        assert_eq!(psbt.finalize(), vec![0]);
+    }
+
+    #[test]
+    fn multi_a_threshold() {
+        let keys = (1u8..=20)
+            .map(|byte| {
+                let sk = SecretKey::from_slice(&[byte; 32]).unwrap();
+                XOnlyPk::from(PublicKey::from_secret_key(SECP256K1, &sk))
+            })
+            .collect::<Vec<_>>();
+        for threshold in [1, 2, 16, 17, 20] {
+            let script = multi_a_script(threshold, &keys);
+            assert_eq!(parse_multi_a(script.as_slice()), Some((keys.clone(), threshold)));
+        }
+        let mut script = multi_a_script(2, &keys).to_vec();
+        *script.last_mut().unwrap() = OP_PUSHNUM_1;
+        assert_eq!(parse_multi_a(&script), None);
+        let script = multi_a_script(21, &keys);
+        assert_eq!(parse_multi_a(script.as_slice()), None);
+    }
+}
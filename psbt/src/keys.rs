@@ -57,7 +57,8 @@ const PSBT_GLOBAL_TX_MODIFIABLE: u8 = 0x06;
 const PSBT_GLOBAL_VERSION: u8 = 0xFB;
 const PSBT_GLOBAL_PROPRIETARY: u8 = 0xFC;
 
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display(doc_comments)]
 #[non_exhaustive]
 pub enum GlobalKey {
     /// `PSBT_GLOBAL_UNSIGNED_TX`
@@ -88,6 +89,7 @@ pub enum GlobalKey {
     Proprietary,
 
     /// All unknown keys
+    #[display("unknown key type {0:#04x}")]
     Unknown(u8),
 }
 
@@ -227,7 +229,8 @@ const PSBT_IN_TAP_INTERNAL_KEY: u8 = 0x17;
 const PSBT_IN_TAP_MERKLE_ROOT: u8 = 0x18;
 const PSBT_IN_PROPRIETARY: u8 = 0xFC;
 
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display(doc_comments)]
 #[non_exhaustive]
 pub enum InputKey {
     /// `PSBT_IN_NON_WITNESS_UTXO`
@@ -309,6 +312,7 @@ pub enum InputKey {
     Proprietary,
 
     /// All unknown keys
+    #[display("unknown key type {0:#04x}")]
     Unknown(u8),
 }
 
@@ -558,7 +562,8 @@ const PSBT_OUT_TAP_TREE: u8 = 0x06;
 const PSBT_OUT_TAP_BIP32_DERIVATION: u8 = 0x07;
 const PSBT_OUT_PROPRIETARY: u8 = 0xFC;
 
-#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display(doc_comments)]
 #[non_exhaustive]
 pub enum OutputKey {
     /// `PSBT_OUT_REDEEM_SCRIPT`
@@ -589,6 +594,7 @@ pub enum OutputKey {
     Proprietary,
 
     /// All unknown keys
+    #[display("unknown key type {0:#04x}")]
     Unknown(u8),
 }
 
@@ -739,3 +745,14 @@ pub struct PropKey {
     pub subtype: u64,
     pub data: KeyData,
 }
+
+impl PropKey {
+    /// Constructs proprietary key from its prefix (identifier), subtype and key data.
+    pub fn new(identifier: impl Into<String>, subtype: u64, data: impl Into<Vec<u8>>) -> Self {
+        PropKey {
+            identifier: identifier.into(),
+            subtype,
+            data: KeyData::from(data.into()),
+        }
+    }
+}
@@ -30,20 +30,34 @@ extern crate strict_encoding;
 extern crate serde_crate as serde;
 
 mod data;
+pub mod coinselect;
+mod fee;
 mod keys;
 mod maps;
 mod coders;
+mod combine;
+mod finalize;
+mod sighash;
+mod sign;
+mod builder;
 #[cfg(feature = "client-side-validation")]
 mod csval;
 
+pub use builder::{BuilderError, PsbtBuilder, TxBuilder};
 pub use coders::{Decode, DecodeError, Encode, PsbtError};
+pub use combine::CombineError;
 #[cfg(feature = "client-side-validation")]
 pub use csval::*;
 pub use data::{
-    Input, ModifiableFlags, Output, Prevout, Psbt, PsbtParseError, UnsignedTx, UnsignedTxIn,
+    FeeError, Input, InputError, ModifiableFlags, Output, OutputError, Prevout, Psbt,
+    PsbtParseError, SortError, Unmodifiable, UnsignedTx, UnsignedTxIn,
 };
+pub use fee::{FeeRate, ValueError};
+pub use finalize::ExtractError;
 pub use keys::{GlobalKey, InputKey, KeyPair, KeyType, OutputKey, PropKey};
 pub use maps::{KeyAlreadyPresent, KeyData, KeyMap, Map, MapName, ValueData};
+pub use sighash::{Sighash, SighashError};
+pub use sign::{Signer, TapSignPath};
 
 #[cfg(feature = "strict_encoding")]
 pub const LIB_NAME_PSBT: &str = "Psbt";
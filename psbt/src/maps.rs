@@ -174,6 +174,8 @@ pub trait KeyMap: Sized {
             }
         }
 
+        // Unknown and proprietary keys are serialized in the order they were added or parsed, so
+        // a parsed PSBT is serialized back byte-for-byte
         for (key_type, submap) in self._unknown_map() {
             for (key_data, value_data) in submap {
                 let pair = KeyPair::new(
@@ -243,6 +245,29 @@ pub trait KeyMap: Sized {
         self._proprietary_map_mut().shift_remove(key)
     }
 
+    /// Returns value of the proprietary key with the given prefix, subtype and key data.
+    fn proprietary_with(
+        &self,
+        identifier: &str,
+        subtype: u64,
+        data: impl Into<Vec<u8>>,
+    ) -> Option<&ValueData> {
+        self.proprietary(&PropKey::new(identifier, subtype, data))
+    }
+    /// Inserts proprietary key, replacing and returning a previously present value.
+    fn set_proprietary(&mut self, key: PropKey, value: impl Into<ValueData>) -> Option<ValueData> {
+        self._proprietary_map_mut().insert(key, value.into())
+    }
+    /// Lists key data and values of all proprietary keys with the given prefix and subtype, in
+    /// the order of their insertion.
+    fn proprietary_subtype(&self, identifier: &str, subtype: u64) -> Vec<(&KeyData, &ValueData)> {
+        self._proprietary_map()
+            .iter()
+            .filter(|(key, _)| key.identifier == identifier && key.subtype == subtype)
+            .map(|(key, value)| (&key.data, value))
+            .collect()
+    }
+
     #[doc(hidden)]
     fn _unknown_map(&self) -> &IndexMap<u8, IndexMap<KeyData, ValueData>>;
     #[doc(hidden)]
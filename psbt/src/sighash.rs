@@ -0,0 +1,519 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Borrow;
+
+use amplify::Bytes32;
+use commit_verify::{Digest, DigestExt, Sha256};
+use derive::{
+    ConsensusEncode, Sats, ScriptBytes, ScriptPubkey, SeqNo, SigScript, SighashFlag, SighashType,
+    TapLeafHash, Tx, TxIn, TxOut, VarIntArray, MIDSTATE_TAPSIGHASH,
+};
+
+use crate::{Output, Psbt};
+
+/// Signature hash: a message which is signed by a transaction input signature.
+#[derive(Wrapper, Copy, Clone, Eq, PartialEq, Hash, From)]
+#[wrapper(BorrowSlice, Index, RangeOps, Debug, Hex, Display, FromStr)]
+pub struct Sighash(
+    #[from]
+    #[from([u8; 32])]
+    Bytes32,
+);
+
+impl Sighash {
+    pub fn to_byte_array(&self) -> [u8; 32] { self.0.to_byte_array() }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum SighashError {
+    /// PSBT doesn't have input #{0}.
+    InvalidInputIndex(usize),
+
+    /// input #{0} has neither witness UTXO nor non-witness transaction, so the output spent by it
+    /// is unknown.
+    NoPrevout(usize),
+
+    /// input #{0} uses SIGHASH_SINGLE, but there is no transaction output with the same index.
+    NoSingleOutput(usize),
+}
+
+impl Psbt {
+    /// Computes the original pre-segwit signature hash for a legacy input.
+    ///
+    /// The `script_code` must be the script pubkey of the spent output or, for P2SH inputs, the
+    /// redeem script; `OP_CODESEPARATOR`s are not processed.
+    ///
+    /// For `SIGHASH_SINGLE` inputs without an output with the same index this reproduces the bug
+    /// of the original algorithm, which is a part of the consensus rules: the signature hash is
+    /// the number one. A signature for such a hash doesn't commit to the transaction at all and
+    /// can be reused in any other transaction spending the same output.
+    pub fn legacy_sighash(
+        &self,
+        input_index: usize,
+        script_code: &ScriptBytes,
+        sighash_type: SighashType,
+    ) -> Result<Sighash, SighashError> {
+        if input_index >= self.inputs.len() {
+            return Err(SighashError::InvalidInputIndex(input_index));
+        }
+        let SighashType {
+            flag,
+            anyone_can_pay,
+        } = sighash_type;
+        if flag == SighashFlag::Single && input_index >= self.outputs.len() {
+            let mut one = [0u8; 32];
+            one[0] = 1;
+            return Ok(Sighash::from(one));
+        }
+
+        let inputs = self
+            .inputs()
+            .filter(|input| !anyone_can_pay || input.index() == input_index)
+            .map(|input| {
+                let mut txin = TxIn::from(input.to_unsigned_txin());
+                if input.index() == input_index {
+                    txin.sig_script = SigScript::from_unsafe(script_code.to_vec());
+                } else if flag != SighashFlag::All {
+                    txin.sequence = SeqNo::from_consensus_u32(0);
+                }
+                txin
+            })
+            .collect::<Vec<_>>();
+        let outputs = match flag {
+            SighashFlag::All => self.outputs().map(Output::to_txout).collect(),
+            SighashFlag::None => vec![],
+            SighashFlag::Single => self
+                .outputs()
+                .take(input_index + 1)
+                .map(|output| match output.index() == input_index {
+                    true => output.to_txout(),
+                    false => TxOut::new(ScriptPubkey::new(), Sats::from_sats(u64::MAX)),
+                })
+                .collect(),
+        };
+        let tx = Tx {
+            version: self.tx_version,
+            inputs: VarIntArray::from_collection_unsafe(inputs),
+            outputs: VarIntArray::from_collection_unsafe(outputs),
+            lock_time: self.lock_time(),
+        };
+
+        let mut preimage = tx.consensus_serialize();
+        preimage.extend(sighash_type.to_consensus_u32().consensus_serialize());
+        Ok(Sighash::from(sha256d(&preimage)))
+    }
+
+    /// Computes BIP143 signature hash for a segwit v0 input.
+    ///
+    /// The `script_code` must be the script code as defined in BIP143: for P2WPKH inputs this is
+    /// a P2PKH script for the same public key hash; for P2WSH inputs this is the witness script.
+    ///
+    /// Unlike the legacy algorithm, for `SIGHASH_SINGLE` inputs without an output with the same
+    /// index the signature still commits to the transaction inputs, while the outputs are not
+    /// committed to.
+    pub fn segwit_v0_sighash(
+        &self,
+        input_index: usize,
+        script_code: &ScriptBytes,
+        sighash_type: SighashType,
+    ) -> Result<Sighash, SighashError> {
+        let input = self.input(input_index).ok_or(SighashError::InvalidInputIndex(input_index))?;
+        let value = input.try_value().ok_or(SighashError::NoPrevout(input_index))?;
+        let txin = input.to_unsigned_txin();
+        let SighashType {
+            flag,
+            anyone_can_pay,
+        } = sighash_type;
+
+        let mut hash_prevouts = [0u8; 32];
+        let mut hash_sequence = [0u8; 32];
+        let mut hash_outputs = [0u8; 32];
+        if !anyone_can_pay {
+            let prevouts = self
+                .inputs()
+                .flat_map(|input| input.previous_outpoint.consensus_serialize())
+                .collect::<Vec<_>>();
+            hash_prevouts = sha256d(&prevouts);
+        }
+        if !anyone_can_pay && flag == SighashFlag::All {
+            let sequences = self
+                .inputs()
+                .flat_map(|input| input.to_unsigned_txin().sequence.consensus_serialize())
+                .collect::<Vec<_>>();
+            hash_sequence = sha256d(&sequences);
+        }
+        match flag {
+            SighashFlag::All => {
+                let outputs = self
+                    .outputs()
+                    .flat_map(|output| output.to_txout().consensus_serialize())
+                    .collect::<Vec<_>>();
+                hash_outputs = sha256d(&outputs);
+            }
+            SighashFlag::Single => {
+                if let Some(output) = self.output(input_index) {
+                    hash_outputs = sha256d(&output.to_txout().consensus_serialize());
+                }
+            }
+            SighashFlag::None => {}
+        }
+
+        let mut preimage = self.tx_version.consensus_serialize();
+        preimage.extend(hash_prevouts);
+        preimage.extend(hash_sequence);
+        preimage.extend(txin.prev_output.consensus_serialize());
+        preimage.extend(script_code.consensus_serialize());
+        preimage.extend(value.consensus_serialize());
+        preimage.extend(txin.sequence.consensus_serialize());
+        preimage.extend(hash_outputs);
+        preimage.extend(self.lock_time().consensus_serialize());
+        preimage.extend(sighash_type.to_consensus_u32().consensus_serialize());
+
+        Ok(Sighash::from(sha256d(&preimage)))
+    }
+
+    /// Computes BIP341 signature hash for a taproot input.
+    ///
+    /// For key path spending `leaf_hash` must be `None`; for script path spending it must contain
+    /// hash of the leaf script which is executed. If `sighash_type` is `None`, the default sighash
+    /// type (`SIGHASH_DEFAULT`) is used. Annexes are not supported.
+    pub fn taproot_sighash(
+        &self,
+        input_index: usize,
+        leaf_hash: Option<TapLeafHash>,
+        sighash_type: Option<SighashType>,
+    ) -> Result<Sighash, SighashError> {
+        let input = self.input(input_index).ok_or(SighashError::InvalidInputIndex(input_index))?;
+        let (flag, anyone_can_pay) = match sighash_type {
+            Some(sighash_type) => (sighash_type.flag, sighash_type.anyone_can_pay),
+            None => (SighashFlag::All, false),
+        };
+
+        let mut engine = Sha256::from_tag(MIDSTATE_TAPSIGHASH);
+        // Epoch
+        engine.input_raw(&[0x00]);
+        engine.input_raw(&[sighash_type.map(SighashType::into_consensus_u8).unwrap_or_default()]);
+        engine.input_raw(&self.tx_version.consensus_serialize());
+        engine.input_raw(&self.lock_time().consensus_serialize());
+
+        if !anyone_can_pay {
+            let mut prevouts = vec![];
+            let mut amounts = vec![];
+            let mut script_pubkeys = vec![];
+            let mut sequences = vec![];
+            for input in self.inputs() {
+                let txout = input.try_prev_txout().ok_or(SighashError::NoPrevout(input.index()))?;
+                prevouts.extend(input.previous_outpoint.consensus_serialize());
+                amounts.extend(txout.value.consensus_serialize());
+                script_pubkeys.extend(txout.script_pubkey.consensus_serialize());
+                sequences.extend(input.to_unsigned_txin().sequence.consensus_serialize());
+            }
+            engine.input_raw(&sha256(&prevouts));
+            engine.input_raw(&sha256(&amounts));
+            engine.input_raw(&sha256(&script_pubkeys));
+            engine.input_raw(&sha256(&sequences));
+        }
+        if flag == SighashFlag::All {
+            let outputs = self
+                .outputs()
+                .flat_map(|output| output.to_txout().consensus_serialize())
+                .collect::<Vec<_>>();
+            engine.input_raw(&sha256(&outputs));
+        }
+
+        let spend_type = if leaf_hash.is_some() { 2u8 } else { 0u8 };
+        engine.input_raw(&[spend_type]);
+        if anyone_can_pay {
+            let txout = input.try_prev_txout().ok_or(SighashError::NoPrevout(input_index))?;
+            engine.input_raw(&input.previous_outpoint.consensus_serialize());
+            engine.input_raw(&txout.value.consensus_serialize());
+            engine.input_raw(&txout.script_pubkey.consensus_serialize());
+            engine.input_raw(&input.to_unsigned_txin().sequence.consensus_serialize());
+        } else {
+            engine.input_raw(&(input_index as u32).consensus_serialize());
+        }
+
+        if flag == SighashFlag::Single {
+            let output =
+                self.output(input_index).ok_or(SighashError::NoSingleOutput(input_index))?;
+            engine.input_raw(&sha256(&output.to_txout().consensus_serialize()));
+        }
+
+        if let Some(leaf_hash) = leaf_hash {
+            engine.input_raw(leaf_hash.borrow());
+            // Key version
+            engine.input_raw(&[0x00]);
+            // Code separator position: none
+            engine.input_raw(&u32::MAX.to_le_bytes());
+        }
+
+        Ok(Sighash::from(engine.finish()))
+    }
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] { Sha256::digest(data).into() }
+
+fn sha256d(data: &[u8]) -> [u8; 32] { sha256(&sha256(data)) }
+
+#[cfg(test)]
+mod test {
+    use amplify::hex::FromHex;
+    use derive::ConsensusDecode;
+
+    use super::*;
+    use crate::{Input, PsbtVer};
+
+    // Native P2WPKH example from BIP143
+    #[test]
+    fn bip143_p2wpkh() {
+        let tx = Tx::consensus_deserialize(
+            Vec::<u8>::from_hex(
+                "0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000\
+                 00eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a01000000\
+                 00ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac90\
+                 93510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut psbt = Psbt::from_tx(tx);
+        let script_pubkey = ScriptPubkey::from_unsafe(
+            Vec::from_hex("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap(),
+        );
+        psbt.input_mut(1).unwrap().witness_utxo =
+            Some(TxOut::new(script_pubkey, Sats::from_sats(600_000_000u64)));
+        let script_code = ScriptBytes::from_unsafe(
+            Vec::from_hex("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac").unwrap(),
+        );
+
+        let sighash = psbt.segwit_v0_sighash(1, &script_code, SighashType::all()).unwrap();
+        assert_eq!(
+            sighash.to_string(),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+        assert_eq!(
+            psbt.segwit_v0_sighash(0, &script_code, SighashType::all()),
+            Err(SighashError::NoPrevout(0))
+        );
+        assert_eq!(
+            psbt.segwit_v0_sighash(2, &script_code, SighashType::all()),
+            Err(SighashError::InvalidInputIndex(2))
+        );
+    }
+
+    // P2SH-P2WSH 6-of-6 multisig example from BIP143, covering all sighash types
+    #[test]
+    fn bip143_sighash_types() {
+        let tx = Tx::consensus_deserialize(
+            Vec::<u8>::from_hex(
+                "010000000136641869ca081e70f394c6948e8af409e18b619df2ed74aa106c1ca29787b96e0100000000\
+                 ffffffff0200e9a435000000001976a914389ffce9cd9ae88dcc0631e88a821ffdbe9bfe2688acc0832f\
+                 05000000001976a9147480a33f950689af511e6e84c138dbbd3c3ee41588ac00000000",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut psbt = Psbt::from_tx(tx);
+        psbt.input_mut(0).unwrap().witness_utxo = Some(TxOut::new(
+            ScriptPubkey::from_unsafe(
+                Vec::from_hex("a9149993a429037b5d912407a71c252019287b8d27a587").unwrap(),
+            ),
+            Sats::from_sats(987_654_321u64),
+        ));
+        let script_code = ScriptBytes::from_unsafe(
+            Vec::from_hex(
+                "56210307b8ae49ac90a048e9b53357a2354b3334e9c8bee813ecb98e99a7e07e8c3ba32103b28f0c28\
+                 bfab54554ae8c658ac5c3e0ce6e79ad336331f78c428dd43eea8449b21034b8113d703413d57761b8b\
+                 9781957b8c0ac1dfe69f492580ca4195f50376ba4a21033400f6afecb833092a9a21cfdf1ed1376e58\
+                 c5d1f47de74683123987e967a8f42103a6d48b1131e94ba04d9737d61acdaa1322008af9602b3b1486\
+                 2c07a1789aac162102d8b661b0b3302ee2f162b09e07a55ad5dfbe673a9f01d9f0c19617681024306b\
+                 56ae",
+            )
+            .unwrap(),
+        );
+
+        for (sighash_type, sighash) in [
+            (
+                SighashType::all(),
+                "185c0be5263dce5b4bb50a047973c1b6272bfbd0103a89444597dc40b248ee7c",
+            ),
+            (
+                SighashType::none(),
+                "e9733bc60ea13c95c6527066bb975a2ff29a925e80aa14c213f686cbae5d2f36",
+            ),
+            (
+                SighashType::single(),
+                "1e1f1c303dc025bd664acb72e583e933fae4cff9148bf78c157d1e8f78530aea",
+            ),
+            (
+                SighashType::all_anyone_can_pay(),
+                "2a67f03e63a6a422125878b40b82da593be8d4efaafe88ee528af6e5a9955c6e",
+            ),
+            (
+                SighashType::none_anyone_can_pay(),
+                "781ba15f3779d5542ce8ecb5c18716733a5ee42a6f51488ec96154934e2c890a",
+            ),
+            (
+                SighashType::single_anyone_can_pay(),
+                "511e8e52ed574121fc1b654970395502128263f62662e076dc6baf05c2e6a99b",
+            ),
+        ] {
+            assert_eq!(
+                psbt.segwit_v0_sighash(0, &script_code, sighash_type).unwrap().to_string(),
+                sighash
+            );
+        }
+    }
+
+    // Key path spending test vectors from BIP341
+    #[test]
+    fn bip341_key_path() {
+        let tx = Tx::consensus_deserialize(
+            Vec::<u8>::from_hex(
+                "02000000097de20cbff686da83a54981d2b9bab3586f4ca7e48f57f5b55963115f3b334e9c01000000\
+                 0000000000d7b7cab57b1393ace2d064f4d4a2cb8af6def61273e127517d44759b6dafdd9900000000\
+                 00fffffffff8e1f583384333689228c5d28eac13366be082dc57441760d957275419a4184200000000\
+                 00fffffffff0689180aa63b30cb162a73c6d2a38b7eeda2a83ece74310fda0843ad604853b01000000\
+                 00feffffffaa5202bdf6d8ccd2ee0f0202afbbb7461d9264a25e5bfd3c5a52ee1239e0ba6c00000000\
+                 00feffffff956149bdc66faa968eb2be2d2faa29718acbfe3941215893a2a3446d32acd05000000000\
+                 0000000000e664b9773b88c09c32cb70a2a3e4da0ced63b7ba3b22f848531bbb1d5d5f4c9401000000\
+                 0000000000e9aa6b8e6c9de67619e6a3924ae25696bb7b694bb677a632a74ef7eadfd4eabf00000000\
+                 00ffffffffa778eb6a263dc090464cd125c466b5a99667720b1c110468831d058aa1b82af101000000\
+                 00ffffffff0200ca9a3b000000001976a91406afd46bcdfd22ef94ac122aa11f241244a37ecc88ac80\
+                 7840cb0000000020ac9a87f5594be208f8532db38cff670c450ed2fea8fcdefcc9a663f78bab962b00\
+                 65cd1d",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut psbt = Psbt::from_tx(tx);
+        let utxos = [
+            ("512053a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343", 420000000u64),
+            ("5120147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3", 462000000),
+            ("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac", 294000000),
+            ("5120e4d810fd50586274face62b8a807eb9719cef49c04177cc6b76a9a4251d5450e", 504000000),
+            ("512091b64d5324723a985170e4dc5a0f84c041804f2cd12660fa5dec09fc21783605", 630000000),
+            ("00147dd65592d0ab2fe0d0257d571abf032cd9db93dc", 378000000),
+            ("512075169f4001aa68f15bbed28b218df1d0a62cbbcf1188c6665110c293c907b831", 672000000),
+            ("5120712447206d7a5238acc7ff53fbe94a3b64539ad291c7cdbc490b7577e4b17df5", 546000000),
+            ("512077e30a5522dd9f894c3f8b8bd4c4b2cf82ca7da8a3ea6a239655c39c050ab220", 588000000),
+        ];
+        for (input, (script_pubkey, value)) in psbt.inputs_mut().zip(utxos) {
+            input.witness_utxo = Some(TxOut::new(
+                ScriptPubkey::from_unsafe(Vec::from_hex(script_pubkey).unwrap()),
+                Sats::from_sats(value),
+            ));
+        }
+
+        for (index, sighash_type, sighash) in [
+            (0, Some(0x03), "2514a6272f85cfa0f45eb907fcb0d121b808ed37c6ea160a5a9046ed5526d555"),
+            (1, Some(0x83), "325a644af47e8a5a2591cda0ab0723978537318f10e6a63d4eed783b96a71a4d"),
+            (3, Some(0x01), "bf013ea93474aa67815b1b6cc441d23b64fa310911d991e713cd34c7f5d46669"),
+            (4, None, "4f900a0bae3f1446fd48490c2958b5a023228f01661cda3496a11da502a7f7ef"),
+            (6, Some(0x02), "15f25c298eb5cdc7eb1d638dd2d45c97c4c59dcaec6679cfc16ad84f30876b85"),
+            (7, Some(0x82), "cd292de50313804dabe4685e83f923d2969577191a3e1d2882220dca88cbeb10"),
+            (8, Some(0x81), "cccb739eca6c13a8a89e6e5cd317ffe55669bbda23f2fd37b0f18755e008edd2"),
+        ] {
+            let sighash_type = sighash_type.map(SighashType::from_consensus_u32);
+            assert_eq!(
+                psbt.taproot_sighash(index, None, sighash_type).unwrap().to_string(),
+                sighash,
+                "input #{index}"
+            );
+        }
+    }
+
+    #[test]
+    fn legacy_sighash_modes() {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        for index in 0..2 {
+            psbt.inputs.push(Input::new(index));
+        }
+        psbt.construct_output_expect(ScriptPubkey::p2wpkh([1u8; 20]), Sats(1000));
+        let script_code = ScriptPubkey::p2pkh([2u8; 20]).as_script_bytes().clone();
+        let sighash = |psbt: &Psbt, index, sighash_type| {
+            psbt.legacy_sighash(index, &script_code, sighash_type).unwrap()
+        };
+
+        // SIGHASH_SINGLE bug: there is no output #1 matching input #1
+        let mut one = [0u8; 32];
+        one[0] = 1;
+        assert_eq!(sighash(&psbt, 1, SighashType::single()), Sighash::from(one));
+        assert_eq!(sighash(&psbt, 1, SighashType::single_anyone_can_pay()), Sighash::from(one));
+        assert_ne!(sighash(&psbt, 0, SighashType::single()), Sighash::from(one));
+        assert_eq!(
+            psbt.legacy_sighash(2, &script_code, SighashType::all()),
+            Err(SighashError::InvalidInputIndex(2))
+        );
+
+        let all = sighash(&psbt, 0, SighashType::all());
+        let none = sighash(&psbt, 0, SighashType::none());
+        let single = sighash(&psbt, 0, SighashType::single());
+        let acp = sighash(&psbt, 0, SighashType::all_anyone_can_pay());
+        assert_ne!(all, none);
+        assert_ne!(all, single);
+
+        // Adding an output changes only signatures committing to all outputs
+        psbt.construct_output_expect(ScriptPubkey::p2wpkh([3u8; 20]), Sats(1000));
+        assert_ne!(sighash(&psbt, 0, SighashType::all()), all);
+        assert_eq!(sighash(&psbt, 0, SighashType::none()), none);
+        assert_eq!(sighash(&psbt, 0, SighashType::single()), single);
+        assert_ne!(sighash(&psbt, 1, SighashType::single()), Sighash::from(one));
+
+        // Adding an input doesn't change signatures with ANYONECANPAY
+        psbt.outputs.pop();
+        psbt.inputs.push(Input::new(2));
+        assert_eq!(sighash(&psbt, 0, SighashType::all_anyone_can_pay()), acp);
+        assert_ne!(sighash(&psbt, 0, SighashType::all()), all);
+    }
+
+    #[test]
+    fn segwit_v0_single_no_output() {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        let mut input = Input::new(0);
+        input.witness_utxo = Some(TxOut::new(ScriptPubkey::new(), Sats::from_sats(1000u64)));
+        psbt.inputs.push(input);
+        let script_code = ScriptPubkey::p2pkh([2u8; 20]).as_script_bytes().clone();
+        // No SIGHASH_SINGLE bug in BIP143: the signature hash is computed without the outputs
+        let single = psbt.segwit_v0_sighash(0, &script_code, SighashType::single()).unwrap();
+        let mut one = [0u8; 32];
+        one[0] = 1;
+        assert_ne!(single, Sighash::from(one));
+        psbt.construct_output_expect(ScriptPubkey::p2wpkh([1u8; 20]), Sats(1000));
+        assert_ne!(psbt.segwit_v0_sighash(0, &script_code, SighashType::single()).unwrap(), single);
+    }
+
+    #[test]
+    fn taproot_single_no_output() {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        let mut input = Input::new(0);
+        input.witness_utxo = Some(TxOut::new(ScriptPubkey::new(), Sats::from_sats(1000u64)));
+        psbt.inputs.push(input);
+        assert!(psbt.taproot_sighash(0, None, None).is_ok());
+        assert_eq!(
+            psbt.taproot_sighash(0, None, Some(SighashType::single())),
+            Err(SighashError::NoSingleOutput(0))
+        );
+    }
+}
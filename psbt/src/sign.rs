@@ -0,0 +1,776 @@
+// Modern, minimalistic & standard-compliant cold wallet library.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Written in 2020-2024 by
+//     Dr Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// Copyright (C) 2020-2024 LNP/BP Standards Association. All rights reserved.
+// Copyright (C) 2020-2024 Dr Maxim Orlovsky. All rights reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use amplify::{Bytes32, Wrapper};
+use commit_verify::{DigestExt, Sha256};
+use derive::secp256k1::{ecdsa, schnorr, Keypair, Message, Scalar, SECP256K1};
+use derive::{
+    Bip340Sig, CompressedPk, DerivationIndex, InternalPk, KeyOrigin, LegacyPk, LegacySig,
+    ScriptBytes, ScriptPubkey, SighashFlag, SighashType, TapLeafHash, TapNodeHash, WPubkeyHash,
+    XOnlyPk, Xpriv, XprivDerivable, XpubFp,
+};
+
+use crate::{Input, Psbt, Sighash, SighashError};
+
+/// Taproot spending path for which a BIP340 signature is requested from a [`Signer`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum TapSignPath {
+    /// Key path spending. The signing key must be tweaked with the script tree `merkle_root`
+    /// (if any) as defined in BIP341.
+    KeyPath { merkle_root: Option<TapNodeHash> },
+
+    /// Script path spending of the leaf with the given hash. The signing key is used untweaked.
+    ScriptPath(TapLeafHash),
+}
+
+/// Source of private keys used in PSBT signing: a software key, a hardware wallet or a remote
+/// signing service.
+///
+/// The signer is provided with the signature hash and the key derivation information taken from
+/// the PSBT input. Signers must check that the key they derive matches the provided public key
+/// and return `None` for the keys they don't control.
+pub trait Signer {
+    /// Fingerprint of the master key of the signer. Only keys with origins using this fingerprint
+    /// are passed to the signer.
+    fn master_fp(&self) -> XpubFp;
+
+    /// Creates ECDSA signature for a segwit v0 input.
+    fn sign_ecdsa(
+        &self,
+        sighash: Sighash,
+        pk: CompressedPk,
+        origin: &KeyOrigin,
+    ) -> Option<ecdsa::Signature>;
+
+    /// Creates BIP340 signature for a taproot input.
+    fn sign_bip340(
+        &self,
+        sighash: Sighash,
+        pk: XOnlyPk,
+        origin: &KeyOrigin,
+        path: TapSignPath,
+    ) -> Option<schnorr::Signature>;
+}
+
+impl Signer for Xpriv {
+    fn master_fp(&self) -> XpubFp { self.fingerprint() }
+
+    fn sign_ecdsa(
+        &self,
+        sighash: Sighash,
+        pk: CompressedPk,
+        origin: &KeyOrigin,
+    ) -> Option<ecdsa::Signature> {
+        let keypair = derive_keypair(self, origin.derivation());
+        sign_ecdsa(&keypair, sighash, pk)
+    }
+
+    fn sign_bip340(
+        &self,
+        sighash: Sighash,
+        pk: XOnlyPk,
+        origin: &KeyOrigin,
+        path: TapSignPath,
+    ) -> Option<schnorr::Signature> {
+        let keypair = derive_keypair(self, origin.derivation());
+        sign_bip340(keypair, sighash, pk, path, None)
+    }
+}
+
+impl Signer for XprivDerivable {
+    fn master_fp(&self) -> XpubFp { self.origin().master_fp() }
+
+    fn sign_ecdsa(
+        &self,
+        sighash: Sighash,
+        pk: CompressedPk,
+        origin: &KeyOrigin,
+    ) -> Option<ecdsa::Signature> {
+        let keypair = derive_keypair(&self.xpriv(), terminal_path(self, origin)?);
+        sign_ecdsa(&keypair, sighash, pk)
+    }
+
+    fn sign_bip340(
+        &self,
+        sighash: Sighash,
+        pk: XOnlyPk,
+        origin: &KeyOrigin,
+        path: TapSignPath,
+    ) -> Option<schnorr::Signature> {
+        let keypair = derive_keypair(&self.xpriv(), terminal_path(self, origin)?);
+        sign_bip340(keypair, sighash, pk, path, None)
+    }
+}
+
+/// Software signer using auxiliary randomness for BIP340 signatures.
+struct AuxRandSigner<'a> {
+    xpriv: &'a Xpriv,
+    aux_rand: [u8; 32],
+}
+
+impl Signer for AuxRandSigner<'_> {
+    fn master_fp(&self) -> XpubFp { self.xpriv.master_fp() }
+
+    fn sign_ecdsa(
+        &self,
+        sighash: Sighash,
+        pk: CompressedPk,
+        origin: &KeyOrigin,
+    ) -> Option<ecdsa::Signature> {
+        self.xpriv.sign_ecdsa(sighash, pk, origin)
+    }
+
+    fn sign_bip340(
+        &self,
+        sighash: Sighash,
+        pk: XOnlyPk,
+        origin: &KeyOrigin,
+        path: TapSignPath,
+    ) -> Option<schnorr::Signature> {
+        let keypair = derive_keypair(self.xpriv, origin.derivation());
+        sign_bip340(keypair, sighash, pk, path, Some(&self.aux_rand))
+    }
+}
+
+impl Psbt {
+    /// Signs all inputs which can be signed with keys derived from the master extended private
+    /// key `xpriv`; see [`Psbt::sign_with`] for the details.
+    ///
+    /// BIP340 signatures are created without auxiliary randomness, so signing is fully
+    /// deterministic; see [`Psbt::sign_with_aux_rand`] for the details.
+    ///
+    /// Returns the number of signatures added.
+    pub fn sign(&mut self, xpriv: &Xpriv) -> Result<usize, SighashError> { self.sign_with(xpriv) }
+
+    /// Signs the inputs in the same way as [`Psbt::sign`], using `aux_rand` as auxiliary
+    /// randomness for BIP340 nonce generation.
+    ///
+    /// BIP340 nonces are always derived from the secret key and the signed message, so the
+    /// security of the signatures doesn't depend on the quality of the randomness. Auxiliary
+    /// randomness, which must be freshly generated 32 bytes, is recommended by BIP340 as a
+    /// protection against side-channel and fault injection attacks. With `None` the nonces
+    /// depend only on the key and the message, which makes repeated signing produce identical
+    /// signatures (useful for testing and signature comparison) at the cost of losing this
+    /// protection.
+    ///
+    /// ECDSA signatures always use deterministic RFC6979 nonces and are not affected.
+    pub fn sign_with_aux_rand(
+        &mut self,
+        xpriv: &Xpriv,
+        aux_rand: Option<[u8; 32]>,
+    ) -> Result<usize, SighashError> {
+        match aux_rand {
+            Some(aux_rand) => self.sign_with(&AuxRandSigner { xpriv, aux_rand }),
+            None => self.sign_with(xpriv),
+        }
+    }
+
+    /// Signs all inputs which can be signed by the `signer`.
+    ///
+    /// The signer is requested to sign for each key from the `bip32_derivation` and
+    /// `tap_bip32_derivation` fields of the inputs which has the same master key fingerprint as
+    /// the signer. Segwit v0 inputs (P2WPKH, P2WSH and their P2SH-nested forms) receive ECDSA
+    /// partial signatures; taproot inputs receive BIP340 signatures for the key path, if the key
+    /// is the internal key, and for each of the script path leaves listed for the key.
+    ///
+    /// Inputs which can't be signed are skipped: these are finalized inputs, inputs without
+    /// information about the spent output, legacy pre-segwit inputs and inputs with scripts which
+    /// don't match the provided derivation information.
+    ///
+    /// Signatures use the sighash type from the input `sighash_type` field, defaulting to
+    /// `SIGHASH_ALL` (or `SIGHASH_DEFAULT` for taproot). Signing an input with `SIGHASH_SINGLE`
+    /// fails with [`SighashError::NoSingleOutput`] if there is no output with the same index.
+    ///
+    /// Returns the number of signatures added.
+    pub fn sign_with(&mut self, signer: &impl Signer) -> Result<usize, SighashError> {
+        let mut count = 0;
+        for index in 0..self.inputs.len() {
+            count += self.sign_input(index, signer)?;
+        }
+        Ok(count)
+    }
+
+    /// Sets sighash type which must be used by the signers of the input `input_index`.
+    ///
+    /// Signatures already present in the input which use a different sighash type are removed.
+    pub fn set_sighash_type(
+        &mut self,
+        input_index: usize,
+        sighash_type: SighashType,
+    ) -> Result<(), SighashError> {
+        let input =
+            self.inputs.get_mut(input_index).ok_or(SighashError::InvalidInputIndex(input_index))?;
+        input.sighash_type = Some(sighash_type);
+        input.partial_sigs.retain(|_, sig| sig.sighash_type == sighash_type);
+        // Taproot signatures with the default sighash type are equivalent to SIGHASH_ALL
+        let bip340_matches =
+            |sig: &Bip340Sig| sig.sighash_type.unwrap_or(SighashType::all()) == sighash_type;
+        if !input.tap_key_sig.as_ref().map(bip340_matches).unwrap_or(true) {
+            input.tap_key_sig = None;
+        }
+        input.tap_script_sig.retain(|_, sig| bip340_matches(sig));
+        Ok(())
+    }
+
+    fn sign_input(&mut self, index: usize, signer: &impl Signer) -> Result<usize, SighashError> {
+        let input = &self.inputs[index];
+        if input.is_finalized() {
+            return Ok(0);
+        }
+        let Some(prev_txout) = input.try_prev_txout() else {
+            return Ok(0);
+        };
+        let count = if prev_txout.script_pubkey.is_p2tr() {
+            self.sign_taproot(index, signer)?
+        } else {
+            self.sign_segwit_v0(index, signer)?
+        };
+        if count > 0 {
+            let sighash_type = self.inputs[index].sighash_type.unwrap_or(SighashType::all());
+            self.restrict_modifiable(sighash_type);
+        }
+        Ok(count)
+    }
+
+    /// Updates transaction modifiable flags after adding a signature, as required by BIP-370.
+    fn restrict_modifiable(&mut self, sighash_type: SighashType) {
+        let Some(flags) = &mut self.tx_modifiable else {
+            return;
+        };
+        if !sighash_type.anyone_can_pay {
+            flags.inputs_modifiable = false;
+        }
+        if sighash_type.flag != SighashFlag::None {
+            flags.outputs_modifiable = false;
+        }
+        if sighash_type.flag == SighashFlag::Single {
+            flags.sighash_single = true;
+        }
+    }
+
+    fn sign_segwit_v0(
+        &mut self,
+        index: usize,
+        signer: &impl Signer,
+    ) -> Result<usize, SighashError> {
+        let input = &self.inputs[index];
+        let sighash_type = input.sighash_type.unwrap_or(SighashType::all());
+        let mut sigs = vec![];
+        for (pk, origin) in &input.bip32_derivation {
+            let legacy_pk = LegacyPk::compressed(**pk);
+            if input.partial_sigs.contains_key(&legacy_pk)
+                || origin.master_fp() != signer.master_fp()
+            {
+                continue;
+            }
+            let Some(script_code) = input.script_code(*pk) else {
+                continue;
+            };
+            // BIP143 doesn't fail in this case, but the signature would commit to no outputs
+            if sighash_type.flag == SighashFlag::Single && self.output(index).is_none() {
+                return Err(SighashError::NoSingleOutput(index));
+            }
+            let sighash = self.segwit_v0_sighash(index, &script_code, sighash_type)?;
+            let Some(sig) = signer.sign_ecdsa(sighash, *pk, origin) else {
+                continue;
+            };
+            sigs.push((legacy_pk, LegacySig { sig, sighash_type }));
+        }
+
+        let count = sigs.len();
+        self.inputs[index].partial_sigs.extend(sigs);
+        Ok(count)
+    }
+
+    fn sign_taproot(&mut self, index: usize, signer: &impl Signer) -> Result<usize, SighashError> {
+        let input = &self.inputs[index];
+        let sighash_type = input.sighash_type;
+        let mut key_sig = None;
+        let mut script_sigs = vec![];
+        for (pk, derivation) in &input.tap_bip32_derivation {
+            let origin = &derivation.origin;
+            if origin.master_fp() != signer.master_fp() {
+                continue;
+            }
+
+            if derivation.leaf_hashes.is_empty()
+                && input.tap_key_sig.is_none()
+                && input.tap_internal_key == Some(InternalPk::from(*pk))
+            {
+                let internal_pk = InternalPk::from(*pk);
+                let merkle_root = input.tap_merkle_root;
+                if ScriptPubkey::p2tr(internal_pk, merkle_root) != input.prev_txout().script_pubkey
+                {
+                    continue;
+                }
+                let sighash = self.taproot_sighash(index, None, sighash_type)?;
+                let path = TapSignPath::KeyPath { merkle_root };
+                if let Some(sig) = signer.sign_bip340(sighash, *pk, origin, path) {
+                    key_sig = Some(Bip340Sig { sig, sighash_type });
+                }
+            }
+
+            for leaf_hash in &derivation.leaf_hashes {
+                let key = (InternalPk::from(*pk), leaf_hash.into_inner());
+                if input.tap_script_sig.contains_key(&key) {
+                    continue;
+                }
+                let sighash = self.taproot_sighash(index, Some(*leaf_hash), sighash_type)?;
+                let path = TapSignPath::ScriptPath(*leaf_hash);
+                if let Some(sig) = signer.sign_bip340(sighash, *pk, origin, path) {
+                    script_sigs.push((key, Bip340Sig { sig, sighash_type }));
+                }
+            }
+        }
+
+        let input = &mut self.inputs[index];
+        let count = key_sig.is_some() as usize + script_sigs.len();
+        if key_sig.is_some() {
+            input.tap_key_sig = key_sig;
+        }
+        input.tap_script_sig.extend(script_sigs);
+        Ok(count)
+    }
+}
+
+impl Input {
+    /// Detects BIP143 script code for signing the input with the given key. Returns `None` for
+    /// non-segwit inputs, or if the scripts provided in the input don't match the spent output.
+    fn script_code(&self, pk: CompressedPk) -> Option<ScriptBytes> {
+        let script_pubkey = &self.try_prev_txout()?.script_pubkey;
+        let pkh = WPubkeyHash::from(pk);
+        let witness_script_pubkey = if script_pubkey.is_p2sh() {
+            let redeem_script = self.redeem_script.as_ref()?;
+            if redeem_script.to_script_pubkey() != *script_pubkey {
+                return None;
+            }
+            ScriptPubkey::from_unsafe(redeem_script.as_script_bytes().to_vec())
+        } else {
+            script_pubkey.clone()
+        };
+
+        if witness_script_pubkey == ScriptPubkey::p2wpkh(pkh) {
+            Some(ScriptPubkey::p2pkh(pkh).as_script_bytes().clone())
+        } else if witness_script_pubkey.is_p2wsh() {
+            let witness_script = self.witness_script.as_ref()?;
+            if witness_script.to_script_pubkey() != witness_script_pubkey {
+                return None;
+            }
+            Some(witness_script.as_script_bytes().clone())
+        } else {
+            None
+        }
+    }
+}
+
+fn derive_keypair(xpriv: &Xpriv, path: &[DerivationIndex]) -> Keypair {
+    xpriv.derive_priv(path.iter().copied()).to_keypair_bip340()
+}
+
+/// Returns part of the key origin derivation path following the account-level derivation of the
+/// extended key, or `None` if the origin doesn't belong to the key.
+fn terminal_path<'o>(
+    xpriv: &XprivDerivable,
+    origin: &'o KeyOrigin,
+) -> Option<&'o [DerivationIndex]> {
+    let account = xpriv.origin().derivation();
+    let path = origin.derivation();
+    if path.len() < account.len()
+        || !account.iter().zip(path.iter()).all(|(a, b)| DerivationIndex::from(*a) == *b)
+    {
+        return None;
+    }
+    Some(&path[account.len()..])
+}
+
+fn sign_ecdsa(keypair: &Keypair, sighash: Sighash, pk: CompressedPk) -> Option<ecdsa::Signature> {
+    if keypair.public_key() != *pk {
+        return None;
+    }
+    let msg = Message::from_digest(sighash.to_byte_array());
+    Some(SECP256K1.sign_ecdsa(&msg, &keypair.secret_key()))
+}
+
+fn sign_bip340(
+    keypair: Keypair,
+    sighash: Sighash,
+    pk: XOnlyPk,
+    path: TapSignPath,
+    aux_rand: Option<&[u8; 32]>,
+) -> Option<schnorr::Signature> {
+    if XOnlyPk::from(keypair.public_key()) != pk {
+        return None;
+    }
+    let keypair = match path {
+        TapSignPath::KeyPath { merkle_root } => {
+            let tweak = tap_tweak(InternalPk::from(pk), merkle_root.map(Wrapper::into_inner));
+            keypair.add_xonly_tweak(SECP256K1, &tweak).expect("negligible probability")
+        }
+        TapSignPath::ScriptPath(_) => keypair,
+    };
+    let msg = Message::from_digest(sighash.to_byte_array());
+    Some(match aux_rand {
+        Some(aux_rand) => SECP256K1.sign_schnorr_with_aux_rand(&msg, &keypair, aux_rand),
+        None => SECP256K1.sign_schnorr_no_aux_rand(&msg, &keypair),
+    })
+}
+
+/// Computes BIP341 tweak for the internal key and an optional script tree merkle root.
+fn tap_tweak(internal_pk: InternalPk, merkle_root: Option<Bytes32>) -> Scalar {
+    let mut engine = Sha256::from_tag(b"TapTweak");
+    engine.input_raw(&internal_pk.to_byte_array());
+    if let Some(merkle_root) = merkle_root {
+        engine.input_raw(&merkle_root.to_byte_array());
+    }
+    Scalar::from_be_bytes(engine.finish()).expect("negligible probability")
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use derive::secp256k1::XOnlyPublicKey;
+    use derive::{
+        DerivationPath, Derive, HardenedIndex, Keychain, NormalIndex, Sats, TapDerivation,
+        Terminal, TxOut,
+    };
+    use descriptors::TrMultiA;
+
+    use super::*;
+    use crate::PsbtVer;
+
+    fn setup(path: &str) -> (Xpriv, KeyOrigin, Keypair) {
+        let master = Xpriv::new_master(true, &[0xA5; 32]);
+        let path = DerivationPath::from_str(path).unwrap();
+        let keypair = master.derive_priv(&path).to_keypair_bip340();
+        (master, KeyOrigin::new(master.fingerprint(), path), keypair)
+    }
+
+    fn psbt_with(input: Input) -> Psbt {
+        let mut psbt = Psbt::create(PsbtVer::V2);
+        psbt.inputs.push(input);
+        psbt
+    }
+
+    #[test]
+    fn wpkh() {
+        let (master, origin, keypair) = setup("84h/1h/0h/0/3");
+        let pk = CompressedPk::from(keypair.public_key());
+        let mut input = Input::new(0);
+        input.witness_utxo =
+            Some(TxOut::new(ScriptPubkey::p2wpkh(WPubkeyHash::from(pk)), Sats::from_sats(1000u64)));
+        input.bip32_derivation.insert(pk, origin);
+        let mut psbt = psbt_with(input);
+
+        assert_eq!(psbt.sign(&master), Ok(1));
+        let sig = psbt.input(0).unwrap().partial_sigs[&LegacyPk::compressed(*pk)];
+        assert_eq!(sig.sighash_type, SighashType::all());
+        let script_code = ScriptPubkey::p2pkh(WPubkeyHash::from(pk));
+        let sighash =
+            psbt.segwit_v0_sighash(0, script_code.as_script_bytes(), SighashType::all()).unwrap();
+        let msg = Message::from_digest(sighash.to_byte_array());
+        SECP256K1.verify_ecdsa(&msg, &sig.sig, &pk).unwrap();
+
+        assert_eq!(psbt.sign(&master), Ok(0));
+        assert_eq!(psbt.finalize(), vec![0]);
+    }
+
+    #[test]
+    fn signers() {
+        struct Offline(XpubFp);
+        impl Signer for Offline {
+            fn master_fp(&self) -> XpubFp { self.0 }
+            fn sign_ecdsa(
+                &self,
+                _: Sighash,
+                _: CompressedPk,
+                _: &KeyOrigin,
+            ) -> Option<ecdsa::Signature> {
+                None
+            }
+            fn sign_bip340(
+                &self,
+                _: Sighash,
+                _: XOnlyPk,
+                _: &KeyOrigin,
+                _: TapSignPath,
+            ) -> Option<schnorr::Signature> {
+                None
+            }
+        }
+
+        let (master, origin, keypair) = setup("84h/1h/0h/0/3");
+        let pk = CompressedPk::from(keypair.public_key());
+        let mut input = Input::new(0);
+        input.witness_utxo =
+            Some(TxOut::new(ScriptPubkey::p2wpkh(WPubkeyHash::from(pk)), Sats::from_sats(1000u64)));
+        input.bip32_derivation.insert(pk, origin.clone());
+        let psbt = psbt_with(input);
+
+        let mut signed = psbt.clone();
+        assert_eq!(signed.sign_with(&Offline(master.fingerprint())), Ok(0));
+        assert_eq!(signed.sign(&master), Ok(1));
+
+        let account = master.derive_priv([84u8, 1, 0].map(HardenedIndex::from));
+        let xpriv = XprivDerivable::from_str(&format!(
+            "[{}/84h/1h/0h]{account}/<0;1>/*",
+            master.fingerprint()
+        ))
+        .unwrap();
+        let mut psbt = psbt;
+        assert_eq!(psbt.sign_with(&xpriv), Ok(1));
+        assert_eq!(psbt, signed);
+
+        let other = XprivDerivable::from_str(&format!(
+            "[{}/84h/1h/1h]{account}/<0;1>/*",
+            master.fingerprint()
+        ))
+        .unwrap();
+        assert_eq!(psbt_with(Input::new(0)).sign_with(&other), Ok(0));
+        assert_eq!(other.sign_ecdsa(Sighash::from([0u8; 32]), pk, &origin), None);
+    }
+
+    #[test]
+    fn wpkh_single() {
+        let (master, origin, keypair) = setup("84h/1h/0h/0/3");
+        let pk = CompressedPk::from(keypair.public_key());
+        let mut input = Input::new(0);
+        input.witness_utxo =
+            Some(TxOut::new(ScriptPubkey::p2wpkh(WPubkeyHash::from(pk)), Sats::from_sats(1000u64)));
+        input.bip32_derivation.insert(pk, origin);
+        input.sighash_type = Some(SighashType::single());
+        let mut psbt = psbt_with(input);
+
+        assert_eq!(psbt.sign(&master), Err(SighashError::NoSingleOutput(0)));
+        psbt.construct_output_expect(ScriptPubkey::p2wpkh([1u8; 20]), Sats::from_sats(500u64));
+        assert_eq!(psbt.sign(&master), Ok(1));
+        let sig = psbt.input(0).unwrap().partial_sigs[&LegacyPk::compressed(*pk)];
+        assert_eq!(sig.sighash_type, SighashType::single());
+        assert_eq!(psbt.finalize(), vec![0]);
+    }
+
+    #[test]
+    fn tr_key() {
+        let (master, origin, keypair) = setup("86h/1h/0h/0/0");
+        let pk = XOnlyPk::from(keypair.public_key());
+        let internal_pk = InternalPk::from(pk);
+        let script_pubkey = ScriptPubkey::p2tr_key_only(internal_pk);
+        let mut input = Input::new(0);
+        input.witness_utxo = Some(TxOut::new(script_pubkey.clone(), Sats::from_sats(1000u64)));
+        input.tap_internal_key = Some(internal_pk);
+        input.tap_bip32_derivation.insert(pk, TapDerivation {
+            leaf_hashes: vec![],
+            origin,
+        });
+        let mut psbt = psbt_with(input);
+
+        assert_eq!(psbt.sign(&master), Ok(1));
+        let sig = psbt.input(0).unwrap().tap_key_sig.unwrap();
+        assert_eq!(sig.sighash_type, None);
+        let sighash = psbt.taproot_sighash(0, None, None).unwrap();
+        let msg = Message::from_digest(sighash.to_byte_array());
+        let output_pk = XOnlyPublicKey::from_slice(&script_pubkey[2..]).unwrap();
+        SECP256K1.verify_schnorr(&sig.sig, &msg, &output_pk).unwrap();
+
+        assert_eq!(psbt.sign(&master), Ok(0));
+        assert_eq!(psbt.finalize(), vec![0]);
+    }
+
+    #[test]
+    fn tr_key_deterministic() {
+        let (master, origin, keypair) = setup("86h/1h/0h/0/0");
+        let pk = XOnlyPk::from(keypair.public_key());
+        let internal_pk = InternalPk::from(pk);
+        let script_pubkey = ScriptPubkey::p2tr_key_only(internal_pk);
+        let mut input = Input::new(0);
+        input.witness_utxo = Some(TxOut::new(script_pubkey.clone(), Sats::from_sats(1000u64)));
+        input.tap_internal_key = Some(internal_pk);
+        input.tap_bip32_derivation.insert(pk, TapDerivation {
+            leaf_hashes: vec![],
+            origin,
+        });
+        let psbt = psbt_with(input);
+        let sign = |aux_rand| {
+            let mut psbt = psbt.clone();
+            assert_eq!(psbt.sign_with_aux_rand(&master, aux_rand), Ok(1));
+            psbt.input(0).unwrap().tap_key_sig.unwrap().sig
+        };
+
+        assert_eq!(sign(None), sign(None));
+        assert_eq!(sign(Some([7u8; 32])), sign(Some([7u8; 32])));
+        let randomized = sign(Some([8u8; 32]));
+        assert_ne!(randomized, sign(None));
+
+        let sighash = psbt.taproot_sighash(0, None, None).unwrap();
+        let msg = Message::from_digest(sighash.to_byte_array());
+        let output_pk = XOnlyPublicKey::from_slice(&script_pubkey[2..]).unwrap();
+        SECP256K1.verify_schnorr(&randomized, &msg, &output_pk).unwrap();
+    }
+
+    #[test]
+    fn tr_multi_a() {
+        let masters = [[0xA5; 32], [0x5A; 32]].map(|seed| Xpriv::new_master(true, &seed));
+        let keys = masters.iter().map(|master| {
+            let account = master.derive_priv([86u8, 1, 0].map(HardenedIndex::from));
+            XprivDerivable::from_str(&format!(
+                "[{}/86h/1h/0h]{account}/<0;1>/*",
+                master.fingerprint()
+            ))
+            .unwrap()
+            .to_xpub_derivable()
+        });
+        let descr = TrMultiA::new(None, 2, keys).unwrap();
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(2u8));
+        let script_pubkey = descr.derive(terminal.keychain, terminal.index).to_script_pubkey();
+        let mut input = Input::new(0);
+        input.witness_utxo = Some(TxOut::new(script_pubkey, Sats::from_sats(1000u64)));
+        input.fill_derivation(&descr, terminal);
+        let (leaf_hash, leaf_script, control_block) = input.tap_leaves().next().unwrap();
+        let (leaf_script, control_block) = (leaf_script.clone(), control_block.clone());
+        assert_eq!(input.tap_leaves().count(), 1);
+        let mut psbt = psbt_with(input);
+
+        assert_eq!(psbt.sign(&masters[0]), Ok(1));
+        assert_eq!(psbt.finalize(), Vec::<usize>::new());
+        assert_eq!(psbt.sign(&masters[1]), Ok(1));
+        let sighash = psbt.taproot_sighash(0, Some(leaf_hash), None).unwrap();
+        let msg = Message::from_digest(sighash.to_byte_array());
+        for ((pk, _), sig) in &psbt.input(0).unwrap().tap_script_sig {
+            SECP256K1.verify_schnorr(&sig.sig, &msg, pk).unwrap();
+        }
+
+        assert_eq!(psbt.finalize(), vec![0]);
+        let witness = psbt.input(0).unwrap().final_witness.as_ref().unwrap();
+        assert_eq!(witness.len(), 4);
+        assert_eq!(witness[0].len(), 64);
+        assert_eq!(witness[1].len(), 64);
+        assert_eq!(witness[2].to_vec(), leaf_script.script.to_vec());
+        assert_eq!(witness[3].len(), 33);
+        assert_eq!(
+            witness[3][0],
+            control_block.leaf_version.to_consensus_u8()
+                | control_block.output_key_parity.to_consensus_u8()
+        );
+        // Signature for the first script key goes last in the witness stack
+        let script_keys = leaf_script.script[..68]
+            .chunks(34)
+            .map(|chunk| XOnlyPublicKey::from_slice(&chunk[1..33]).unwrap());
+        for (pk, sig) in script_keys.zip([&witness[1], &witness[0]]) {
+            let sig = schnorr::Signature::from_slice(sig).unwrap();
+            SECP256K1.verify_schnorr(&sig, &msg, &pk).unwrap();
+        }
+    }
+
+    #[test]
+    fn tr_multi_a_key_path() {
+        let masters =
+            [[0xA5; 32], [0x5A; 32], [0x33; 32]].map(|seed| Xpriv::new_master(true, &seed));
+        let mut keys = masters.iter().map(|master| {
+            let account = master.derive_priv([86u8, 1, 0].map(HardenedIndex::from));
+            XprivDerivable::from_str(&format!(
+                "[{}/86h/1h/0h]{account}/<0;1>/*",
+                master.fingerprint()
+            ))
+            .unwrap()
+            .to_xpub_derivable()
+        });
+        let internal_key = keys.next();
+        let descr = TrMultiA::new(internal_key, 2, keys).unwrap();
+        let terminal = Terminal::new(Keychain::OUTER, NormalIndex::from(2u8));
+        let script_pubkey = descr.derive(terminal.keychain, terminal.index).to_script_pubkey();
+        let mut input = Input::new(0);
+        input.witness_utxo = Some(TxOut::new(script_pubkey.clone(), Sats::from_sats(1000u64)));
+        input.fill_derivation(&descr, terminal);
+        assert_eq!(input.tap_leaves().count(), 1);
+        let mut psbt = psbt_with(input);
+
+        assert_eq!(psbt.sign(&masters[0]), Ok(1));
+        let input = psbt.input(0).unwrap();
+        assert!(input.tap_key_sig.is_some());
+        assert!(input.tap_script_sig.is_empty());
+        let sighash = psbt.taproot_sighash(0, None, None).unwrap();
+        let msg = Message::from_digest(sighash.to_byte_array());
+        let output_pk = XOnlyPublicKey::from_slice(&script_pubkey[2..]).unwrap();
+        SECP256K1.verify_schnorr(&input.tap_key_sig.unwrap().sig, &msg, &output_pk).unwrap();
+
+        assert_eq!(psbt.finalize(), vec![0]);
+        let input = psbt.input(0).unwrap();
+        assert!(input.tap_leaf_script.is_empty());
+        assert_eq!(input.final_witness.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn sighash_type() {
+        let (master, origin, keypair) = setup("84h/1h/0h/0/3");
+        let pk = CompressedPk::from(keypair.public_key());
+        let mut input = Input::new(0);
+        input.witness_utxo =
+            Some(TxOut::new(ScriptPubkey::p2wpkh(WPubkeyHash::from(pk)), Sats::from_sats(1000u64)));
+        input.bip32_derivation.insert(pk, origin);
+        let mut psbt = psbt_with(input);
+        let sighash_type = SighashType::all_anyone_can_pay();
+
+        assert_eq!(psbt.set_sighash_type(1, sighash_type), Err(SighashError::InvalidInputIndex(1)));
+        psbt.set_sighash_type(0, sighash_type).unwrap();
+        assert_eq!(psbt.sign(&master), Ok(1));
+        let sig = psbt.input(0).unwrap().partial_sigs[&LegacyPk::compressed(*pk)];
+        assert_eq!(sig.sighash_type, sighash_type);
+        assert!(psbt.are_inputs_modifiable());
+        assert!(!psbt.are_outputs_modifiable());
+
+        psbt.set_sighash_type(0, SighashType::all()).unwrap();
+        assert!(psbt.input(0).unwrap().partial_sigs.is_empty());
+        psbt.set_sighash_type(0, sighash_type).unwrap();
+        assert_eq!(psbt.sign(&master), Ok(1));
+        assert_eq!(psbt.finalize(), vec![0]);
+        let witness = psbt.input(0).unwrap().final_witness.as_ref().unwrap();
+        assert_eq!(witness[0].last(), Some(&0x81));
+    }
+
+    #[test]
+    fn foreign_key() {
+        let (_, origin, keypair) = setup("84h/1h/0h/0/3");
+        let other = Xpriv::new_master(true, &[0x5A; 32]);
+        let pk = CompressedPk::from(keypair.public_key());
+        let mut input = Input::new(0);
+        input.witness_utxo =
+            Some(TxOut::new(ScriptPubkey::p2wpkh(WPubkeyHash::from(pk)), Sats::from_sats(1000u64)));
+        input.bip32_derivation.insert(pk, origin);
+        let mut psbt = psbt_with(input);
+
+        assert_eq!(psbt.sign(&other), Ok(0));
+        assert!(psbt.input(0).unwrap().partial_sigs.is_empty());
+    }
+
+    #[test]
+    fn legacy_skipped() {
+        let (master, origin, keypair) = setup("44h/1h/0h/0/0");
+        let pk = CompressedPk::from(keypair.public_key());
+        let mut input = Input::new(0);
+        input.witness_utxo =
+            Some(TxOut::new(ScriptPubkey::p2pkh(WPubkeyHash::from(pk)), Sats::from_sats(1000u64)));
+        input.bip32_derivation.insert(pk, origin);
+        let mut psbt = psbt_with(input);
+
+        assert_eq!(psbt.sign(&master), Ok(0));
+    }
+}
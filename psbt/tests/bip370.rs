@@ -22,13 +22,24 @@
 
 use std::str::FromStr;
 
-use psbt::Psbt;
+use psbt::{Psbt, PsbtVer};
 
 fn parse_roundtrip(s: &str) {
     let psbt = Psbt::from_str(s).unwrap();
     Psbt::from_str(&psbt.to_string()).unwrap();
 }
 
+fn upgrade_roundtrip(s: &str) {
+    let v0 = Psbt::from_str(s).unwrap();
+    assert_eq!(v0.version(), PsbtVer::V0);
+    let v2 = v0.clone().into_v2();
+    assert_eq!(v2.version(), PsbtVer::V2);
+    let reparsed = Psbt::from_str(&v2.to_string()).unwrap();
+    assert_eq!(reparsed, v2);
+    assert_eq!(reparsed.txid(), v0.txid());
+    assert_eq!(format!("{:0}", reparsed), format!("{:0}", v0));
+}
+
 #[test]
 fn base() { parse_roundtrip(include_str!("valid.v2/base.psbt")); }
 
@@ -59,3 +70,19 @@ fn all_modifiable() { parse_roundtrip(include_str!("valid.v2/all_modifiable.psbt
 /// Case: 1 input, 2 output updated PSBTv2, with all PSBTv2 fields
 #[test]
 fn all() { parse_roundtrip(include_str!("valid.v2/all.psbt")); }
+
+#[test]
+fn version() {
+    let psbt = Psbt::from_str(include_str!("valid.v2/all.psbt")).unwrap();
+    assert_eq!(psbt.version(), PsbtVer::V2);
+    assert_eq!(Psbt::from_str(&psbt.to_string()).unwrap(), psbt);
+}
+
+#[test]
+fn upgrade_wsh() { upgrade_roundtrip(include_str!("valid.v0/wsh.psbt")); }
+
+#[test]
+fn upgrade_pkh_sh_wpkh() { upgrade_roundtrip(include_str!("valid.v0/pkh_sh_wpkh.psbt")); }
+
+#[test]
+fn upgrade_xpubs() { upgrade_roundtrip(include_str!("valid.v0/xpubs.psbt")); }